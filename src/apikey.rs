@@ -0,0 +1,139 @@
+// api keys users create with /token. only the sha-256 of a key is stored, the key is shown once.
+// every key carries the scopes it may use, the operator's API_TOKEN has all of them
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::sha256_hex;
+use crate::d1::File;
+use crate::error::Error;
+
+// keys are told apart from API_TOKEN by it
+pub const KEY_PREFIX: &str = "tgk_";
+const KEY_ID_LENGTH: usize = 8;
+const SECRET_LENGTH: usize = 32;
+pub const MAX_KEYS_PER_USER: u64 = 10;
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Scope {
+    Upload,
+    Delete,
+    List,
+    // webhook, export and the other operator routes, only API_TOKEN has it
+    Admin,
+}
+
+impl Scope {
+    // scopes users can give their keys
+    pub const GRANTABLE: [Scope; 3] = [Scope::Upload, Scope::Delete, Scope::List];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::Upload => "upload",
+            Scope::Delete => "delete",
+            Scope::List => "list",
+            Scope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(v: &str) -> Option<Self> {
+        Scope::GRANTABLE
+            .into_iter()
+            .find(|s| s.name().eq_ignore_ascii_case(v))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKey {
+    pub key_id: String,
+    pub user_id: u64,
+    // comma separated names
+    pub scopes: String,
+    // unix time, 0 before the first request
+    pub last_used: i64,
+    pub add_time: i64,
+}
+
+impl ApiKey {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.split(',').any(|v| v == scope.name())
+    }
+}
+
+// who sent a request of the http api
+#[derive(Clone, Debug)]
+pub enum Caller {
+    Operator,
+    User(ApiKey),
+}
+
+impl Caller {
+    // uploads of the operator have no user
+    pub fn user_id(&self) -> u64 {
+        match self {
+            Caller::Operator => 0,
+            Caller::User(key) => key.user_id,
+        }
+    }
+
+    // the operator manages every file, keys only the files of their user
+    pub fn can_manage(&self, file: &File) -> bool {
+        match self {
+            Caller::Operator => true,
+            Caller::User(key) => file.user_id == key.user_id,
+        }
+    }
+}
+
+// `upload delete` or `upload,delete`, every grantable scope when empty
+pub fn parse_scopes(args: &str) -> Option<Vec<Scope>> {
+    let mut scopes = vec![];
+    for v in args.split([',', ' ']).filter(|v| !v.is_empty()) {
+        let scope = Scope::parse(v)?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    if scopes.is_empty() {
+        return Some(Scope::GRANTABLE.to_vec());
+    }
+    Some(scopes)
+}
+
+pub fn scope_names(scopes: &[Scope]) -> String {
+    scopes
+        .iter()
+        .map(|s| s.name())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// the id shown in /token list, the key and the hash it is stored under
+pub fn generate() -> Result<(String, String, String), Error> {
+    let key_id = random_base62(KEY_ID_LENGTH)?;
+    let key = format!("{}{}_{}", KEY_PREFIX, key_id, random_base62(SECRET_LENGTH)?);
+    let hash = hash(&key);
+    Ok((key_id, key, hash))
+}
+
+// keys are random enough that a plain hash can't be reversed
+pub fn hash(key: &str) -> String {
+    sha256_hex(key.as_bytes())
+}
+
+fn random_base62(len: usize) -> Result<String, Error> {
+    // bytes at or above 248 (4 * 62) are dropped so every character is equally likely
+    let limit = 256 - 256 % BASE62.len();
+    let mut out = String::with_capacity(len);
+    let mut bytes = vec![0u8; len];
+    while out.len() < len {
+        getrandom::getrandom(&mut bytes).map_err(|e| Error::Internal(e.to_string()))?;
+        for b in bytes.iter().filter(|b| (**b as usize) < limit) {
+            if out.len() == len {
+                break;
+            }
+            out.push(BASE62[*b as usize % BASE62.len()] as char);
+        }
+    }
+    Ok(out)
+}
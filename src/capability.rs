@@ -0,0 +1,105 @@
+use std::borrow::Cow;
+use worker::Env;
+
+use crate::error::Error;
+
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+    Bucket,
+    Kv,
+    DurableObject,
+    AnalyticsEngine,
+    Ai,
+    D1,
+}
+
+// a feature that only works when its binding is configured in wrangler.toml
+#[derive(Debug)]
+pub struct Feature {
+    pub name: Cow<'static, str>,
+    pub binding: Cow<'static, str>,
+    kind: Kind,
+}
+
+impl Feature {
+    const fn new(name: &'static str, binding: &'static str, kind: Kind) -> Self {
+        Feature {
+            name: Cow::Borrowed(name),
+            binding: Cow::Borrowed(binding),
+            kind,
+        }
+    }
+
+    // the database of a bot of BOTS, named after it
+    pub fn bot_database(name: &str, binding: String) -> Self {
+        Feature {
+            name: Cow::Owned(format!("bot {:?}", name)),
+            binding: Cow::Owned(binding),
+            kind: Kind::D1,
+        }
+    }
+
+    pub fn bound(&self, env: &Env) -> bool {
+        let binding = self.binding.as_ref();
+        match self.kind {
+            Kind::Bucket => env.bucket(binding).is_ok(),
+            Kind::Kv => env.kv(binding).is_ok(),
+            Kind::DurableObject => env.durable_object(binding).is_ok(),
+            Kind::AnalyticsEngine => env.analytics_engine(binding).is_ok(),
+            Kind::Ai => env.ai(binding).is_ok(),
+            Kind::D1 => env.d1(binding).is_ok(),
+        }
+    }
+
+    pub fn missing(&self) -> Error {
+        Error::MissingBinding {
+            feature: self.name.to_string(),
+            binding: self.binding.to_string(),
+        }
+    }
+}
+
+pub const R2_STORAGE: Feature = Feature::new("R2 file storage", "R2", Kind::Bucket);
+// used by deployments without R2
+pub const KV_STORAGE: Feature = Feature::new("KV file storage", "FILE_STORE", Kind::Kv);
+pub const FILE_CACHE: Feature = Feature::new("the download cache", "FILE_CACHE", Kind::Kv);
+pub const RATE_LIMIT: Feature = Feature::new("rate limiting", "RATE_LIMITER", Kind::DurableObject);
+pub const ANALYTICS: Feature =
+    Feature::new("analytics engine metrics", "METRICS", Kind::AnalyticsEngine);
+pub const MODERATION: Feature = Feature::new("MODERATION", "AI", Kind::Ai);
+pub const ALT_TEXT: Feature = Feature::new("ALT_TEXT", "AI", Kind::Ai);
+
+pub const REGISTRY: [&Feature; 7] = [
+    &R2_STORAGE,
+    &KV_STORAGE,
+    &FILE_CACHE,
+    &RATE_LIMIT,
+    &ANALYTICS,
+    &MODERATION,
+    &ALT_TEXT,
+];
+
+// features of the registry whose binding is present in this deployment
+#[derive(Clone, Default, Debug)]
+pub struct Capabilities {
+    bound: Vec<&'static Feature>,
+}
+
+impl Capabilities {
+    pub fn from_env(env: &Env) -> Self {
+        Capabilities {
+            bound: REGISTRY.into_iter().filter(|f| f.bound(env)).collect(),
+        }
+    }
+
+    pub fn has(&self, feature: &Feature) -> bool {
+        self.bound.iter().any(|f| f.name == feature.name)
+    }
+
+    pub fn require(&self, feature: &Feature) -> Result<(), Error> {
+        if self.has(feature) {
+            return Ok(());
+        }
+        Err(feature.missing())
+    }
+}
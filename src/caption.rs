@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use worker::Ai;
+
+use crate::d1::File;
+use crate::error::Error;
+use crate::moderation::{MAX_IMAGE_SIZE, fetch_image};
+
+pub const DEFAULT_MODEL: &str = "@cf/llava-hf/llava-1.5-7b-hf";
+const PROMPT: &str = "Describe this image in one short sentence for people who can't see it.";
+// alt text longer than this is cut, screen readers read all of it
+pub const MAX_ALT_TEXT: usize = 300;
+
+// writes alt text of uploaded images with a vision model of workers ai
+pub struct Captioner {
+    ai: Ai,
+    model: String,
+}
+
+#[derive(Serialize)]
+struct Input<'a> {
+    image: Vec<u8>,
+    prompt: &'a str,
+    max_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct Output {
+    description: String,
+}
+
+impl Captioner {
+    pub fn new(ai: Ai, model: String) -> Self {
+        Self {
+            ai,
+            model: if model.is_empty() {
+                DEFAULT_MODEL.to_string()
+            } else {
+                model
+            },
+        }
+    }
+
+    pub fn checks(&self, file: &File) -> bool {
+        file.is_image() && file.file_size <= MAX_IMAGE_SIZE
+    }
+
+    // `url` is the telegram download link of the file
+    pub async fn caption(&self, url: &str) -> Result<String, Error> {
+        let image = fetch_image(url).await?;
+
+        let output: Output = self
+            .ai
+            .run(
+                &self.model,
+                Input {
+                    image,
+                    prompt: PROMPT,
+                    max_tokens: 96,
+                },
+            )
+            .await
+            .map_err(|e| Error::Internal(format!("caption model: {}", e)))?;
+
+        let alt = clean_alt_text(&output.description);
+        if alt.is_empty() {
+            return Err(Error::Internal("caption model answered nothing".into()));
+        }
+        Ok(alt)
+    }
+}
+
+// one line without surrounding quotes, at most MAX_ALT_TEXT characters
+pub fn clean_alt_text(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let text = text.trim_matches(|c: char| c == '"' || c == '\'').trim();
+    text.chars().take(MAX_ALT_TEXT).collect()
+}
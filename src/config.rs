@@ -0,0 +1,449 @@
+use std::str::FromStr;
+use worker::{Env, Url};
+
+use crate::capability::{ALT_TEXT, Feature, MODERATION, R2_STORAGE, RATE_LIMIT};
+use crate::d1::DEFAULT_PATH_PREFIX;
+use crate::error::Error;
+use crate::handler::DEFAULT_EDGE_CACHE_TTL;
+use crate::i18n::Lang;
+use crate::landing::LandingMode;
+use crate::security::{AttachmentMode, SvgPolicy};
+use crate::tg::ChannelReplyMode;
+use crate::turnstile::DEFAULT_PASS_HOURS;
+
+// first path segments of the other routes
+const RESERVED_PATHS: [&str; 17] = [
+    "api", "admin", "tgbot", "d1", "u", "s", "a", "b", "view", "badge", "report", "terms",
+    "healthz", "metrics", "qr", "delete", "s3",
+];
+// paths under /tgbot/ that are not a bot name
+const RESERVED_BOT_NAMES: [&str; 3] = ["register", "unregister", "info"];
+
+// vars of wrangler.toml, parsed once per request. invalid values fall back to their defaults
+// so downloads keep working, `validate` reports them. no Debug, it holds the tokens
+pub struct Config {
+    pub telegram_token: String,
+    pub maintainer_id: i64,
+    pub storage_chat_id: i64,
+    pub telegram_api_base: String,
+    pub terms: String,
+    pub require_consent: bool,
+    pub log_level: String,
+    pub log_filter: String,
+    pub log_json: bool,
+    pub ip_less: bool,
+    pub allowed_referers: String,
+    pub allow_empty_referer: bool,
+    pub hotlink_placeholder: String,
+    pub rate_limit_per_minute: u32,
+    pub prometheus_metrics: bool,
+    pub channel_reply_mode: ChannelReplyMode,
+    pub summary_size: usize,
+    pub reply_template: String,
+    pub bot_lang: Lang,
+    pub daily_digest: bool,
+    pub r2_public_base_url: String,
+    pub api_token: String,
+    pub api_token_previous: String,
+    pub cache_max_age: u64,
+    pub cache_immutable: bool,
+    pub cache_stale_while_revalidate: u64,
+    pub edge_cache_ttl: i32,
+    pub path_prefix: String,
+    pub short_ids: bool,
+    pub album_zip_max_size: u64,
+    pub image_resizing: bool,
+    pub auto_format: bool,
+    pub strip_exif: bool,
+    pub moderation: bool,
+    pub moderation_model: String,
+    pub moderation_review: f64,
+    pub moderation_reject: f64,
+    pub alt_text: bool,
+    pub alt_text_model: String,
+    pub delete_grace_days: u32,
+    pub hide_banned_files: bool,
+    pub privacy_mode: bool,
+    // name and token of the other bots of BOTS
+    pub bots: Vec<(String, String)>,
+    // host of links and cache keys instead of the one the request came in on
+    pub public_host: String,
+    pub trust_forwarded_host: bool,
+    pub allowed_origins: String,
+    // headers of served files, empty for the defaults of `security`
+    pub content_security_policy: String,
+    pub referrer_policy: String,
+    pub attachment_mode: AttachmentMode,
+    pub svg_policy: SvgPolicy,
+    pub robots_index_files: bool,
+    pub landing_mode: LandingMode,
+    // public uploads shown on the landing page, 0 shows none
+    pub landing_recent: u32,
+    // downloads need a solved challenge when both are set
+    pub turnstile_site_key: String,
+    pub turnstile_secret: String,
+    // passes signed with it are still accepted while the secret is rotated
+    pub turnstile_secret_previous: String,
+    pub turnstile_pass_hours: u64,
+    // bytes, 0 for the telegram download limit
+    pub max_file_size: u64,
+    // comma separated, empty allows every file
+    pub allowed_mime_types: String,
+    pub blocked_extensions: String,
+    // access key id of PUT /s3/:bucket/:key, empty disables it
+    pub s3_access_key_id: String,
+    problems: Vec<String>,
+}
+
+impl Config {
+    pub fn from_env(env: &Env) -> Config {
+        let mut vars = Vars {
+            env,
+            problems: vec![],
+        };
+
+        let telegram_token = vars.string("TELEGRAM_TOKEN");
+        if telegram_token.is_empty() {
+            vars.problems.push("TELEGRAM_TOKEN is not set".to_string());
+        }
+        let maintainer_id = vars.number("MAINTAINER_ID", 0);
+        if maintainer_id == 0 {
+            vars.problems.push("MAINTAINER_ID is not set".to_string());
+        }
+
+        let channel_reply_mode = vars.string("CHANNEL_REPLY_MODE");
+        if !matches!(
+            channel_reply_mode.as_str(),
+            "" | "reply" | "caption" | "comment"
+        ) {
+            vars.problems.push(format!(
+                "CHANNEL_REPLY_MODE {:?} is not reply, caption or comment",
+                channel_reply_mode
+            ));
+        }
+
+        let bot_lang = vars.string("BOT_LANG");
+        let lang = Lang::parse(&bot_lang);
+        if !bot_lang.is_empty() && lang.is_none() {
+            vars.problems
+                .push(format!("BOT_LANG {:?} is not en, zh, ja or ru", bot_lang));
+        }
+
+        let path_prefix = vars.string("PATH_PREFIX").trim_matches('/').to_string();
+        let path_prefix = if path_prefix.is_empty() {
+            DEFAULT_PATH_PREFIX.to_string()
+        } else if !path_prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            || RESERVED_PATHS.contains(&path_prefix.as_str())
+        {
+            vars.problems.push(format!(
+                "PATH_PREFIX {:?} must be letters, digits, - or _ and not one of {}",
+                path_prefix,
+                RESERVED_PATHS.join(", ")
+            ));
+            DEFAULT_PATH_PREFIX.to_string()
+        } else {
+            path_prefix
+        };
+
+        let moderation = vars.flag("MODERATION", false);
+        if moderation && !MODERATION.bound(env) {
+            vars.problems.push(MODERATION.missing().to_string());
+        }
+        let moderation_review = vars.number("MODERATION_REVIEW", 0.5);
+        let moderation_reject = vars.number("MODERATION_REJECT", 0.9);
+        if !(0.0..=1.0).contains(&moderation_review) || !(0.0..=1.0).contains(&moderation_reject) {
+            vars.problems
+                .push("MODERATION_REVIEW and MODERATION_REJECT must be from 0 to 1".to_string());
+        }
+
+        let alt_text = vars.flag("ALT_TEXT", false);
+        if alt_text && !ALT_TEXT.bound(env) {
+            vars.problems.push(ALT_TEXT.missing().to_string());
+        }
+
+        // a FILE_STORE namespace can't be public
+        let r2_public_base_url = vars.string("R2_PUBLIC_BASE_URL");
+        if !r2_public_base_url.is_empty() && !R2_STORAGE.bound(env) {
+            vars.problems.push(R2_STORAGE.missing().to_string());
+        }
+
+        let rate_limit_per_minute = vars.number("RATE_LIMIT_PER_MINUTE", 0);
+        if rate_limit_per_minute > 0 && !RATE_LIMIT.bound(env) {
+            vars.problems.push(RATE_LIMIT.missing().to_string());
+        }
+
+        // consent is recorded per user
+        let privacy_mode = vars.flag("PRIVACY_MODE", false);
+        if privacy_mode && vars.flag("REQUIRE_CONSENT", false) {
+            vars.problems
+                .push("PRIVACY_MODE can't be combined with REQUIRE_CONSENT".to_string());
+        }
+
+        let mut bots = vec![];
+        for name in vars.string("BOTS").split(',').map(str::trim) {
+            if name.is_empty() {
+                continue;
+            }
+            if !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+                || RESERVED_BOT_NAMES.contains(&name)
+            {
+                vars.problems.push(format!(
+                    "bot name {:?} of BOTS must be lowercase letters, digits or _ and not one of {}",
+                    name,
+                    RESERVED_BOT_NAMES.join(", ")
+                ));
+                continue;
+            }
+            let token_var = format!("TELEGRAM_TOKEN_{}", bot_suffix(name));
+            let token = vars.string(&token_var);
+            if token.is_empty() {
+                vars.problems.push(format!("{} is not set", token_var));
+                continue;
+            }
+            let database = Feature::bot_database(name, bot_database(name));
+            if !database.bound(env) {
+                vars.problems.push(database.missing().to_string());
+                continue;
+            }
+            bots.push((name.to_string(), token));
+        }
+
+        let public_host = vars
+            .string("PUBLIC_HOST")
+            .trim()
+            .trim_start_matches("https://")
+            .trim_end_matches('/')
+            .to_ascii_lowercase();
+        let public_host = if public_host.is_empty() || is_host(&public_host) {
+            public_host
+        } else {
+            vars.problems.push(format!(
+                "PUBLIC_HOST {:?} is not a host name like img.example.com",
+                public_host
+            ));
+            String::new()
+        };
+
+        let allowed_origins = vars.string("ALLOWED_ORIGINS");
+        for origin in allowed_origins.split(',').map(str::trim) {
+            if origin.is_empty() || origin == "*" {
+                continue;
+            }
+            let valid = Url::parse(origin).is_ok_and(|u| {
+                matches!(u.scheme(), "http" | "https")
+                    && u.host_str().is_some()
+                    && u.path() == "/"
+                    && u.query().is_none()
+            });
+            if !valid {
+                vars.problems.push(format!(
+                    "origin {:?} of ALLOWED_ORIGINS is not like https://app.example.com",
+                    origin
+                ));
+            }
+        }
+
+        let turnstile_site_key = vars.string("TURNSTILE_SITE_KEY");
+        let turnstile_secret = vars.string("TURNSTILE_SECRET");
+        if turnstile_site_key.is_empty() != turnstile_secret.is_empty() {
+            vars.problems.push(
+                "TURNSTILE_SITE_KEY and the TURNSTILE_SECRET secret are only used together"
+                    .to_string(),
+            );
+        }
+
+        let landing_mode = vars.string("LANDING_PAGE");
+        let landing_mode = match LandingMode::parse(&landing_mode) {
+            Some(v) => v,
+            None => {
+                vars.problems.push(format!(
+                    "LANDING_PAGE {:?} is not page or redirect",
+                    landing_mode
+                ));
+                LandingMode::default()
+            }
+        };
+
+        let allowed_mime_types = vars.string("ALLOWED_MIME_TYPES");
+        for mime in allowed_mime_types.split(',').map(str::trim) {
+            if !mime.is_empty() && !mime.contains('/') {
+                vars.problems.push(format!(
+                    "type {:?} of ALLOWED_MIME_TYPES is not like image/png or image/*",
+                    mime
+                ));
+            }
+        }
+
+        let attachment_mode = vars.string("ATTACHMENT_MODE");
+        let attachment_mode = match AttachmentMode::parse(&attachment_mode) {
+            Some(v) => v,
+            None => {
+                vars.problems.push(format!(
+                    "ATTACHMENT_MODE {:?} is not active or non-media",
+                    attachment_mode
+                ));
+                AttachmentMode::default()
+            }
+        };
+
+        let svg_policy = vars.string("SVG_POLICY");
+        let svg_policy = match SvgPolicy::parse(&svg_policy) {
+            Some(v) => v,
+            None => {
+                vars.problems.push(format!(
+                    "SVG_POLICY {:?} is not attachment, inline or sanitize",
+                    svg_policy
+                ));
+                SvgPolicy::default()
+            }
+        };
+
+        let log_format = vars.string("LOG_FORMAT");
+        if !matches!(log_format.as_str(), "" | "text" | "json") {
+            vars.problems
+                .push(format!("LOG_FORMAT {:?} is not text or json", log_format));
+        }
+
+        Config {
+            telegram_token,
+            maintainer_id,
+            storage_chat_id: vars.number("STORAGE_CHAT_ID", 0),
+            telegram_api_base: vars.string("TELEGRAM_API_BASE"),
+            terms: vars.string("TERMS"),
+            require_consent: vars.flag("REQUIRE_CONSENT", false),
+            log_level: vars.string("LOG_LEVEL"),
+            log_filter: vars.string("LOG_FILTER"),
+            log_json: log_format == "json",
+            ip_less: vars.flag("IP_LESS_MODE", false),
+            allowed_referers: vars.string("ALLOWED_REFERERS"),
+            allow_empty_referer: vars.flag("ALLOW_EMPTY_REFERER", true),
+            hotlink_placeholder: vars.string("HOTLINK_PLACEHOLDER"),
+            rate_limit_per_minute,
+            prometheus_metrics: vars.flag("PROMETHEUS_METRICS", false),
+            channel_reply_mode: ChannelReplyMode::parse(&channel_reply_mode),
+            summary_size: vars.number("SUMMARY_SIZE", 0),
+            reply_template: vars.string("REPLY_TEMPLATE"),
+            bot_lang: lang.unwrap_or_default(),
+            daily_digest: vars.flag("DAILY_DIGEST", false),
+            r2_public_base_url,
+            api_token: vars.string("API_TOKEN"),
+            api_token_previous: vars.string("API_TOKEN_PREVIOUS"),
+            cache_max_age: vars.number("CACHE_MAX_AGE", 31536000),
+            cache_immutable: vars.flag("CACHE_IMMUTABLE", false),
+            cache_stale_while_revalidate: vars.number("CACHE_STALE_WHILE_REVALIDATE", 0),
+            edge_cache_ttl: vars.number("EDGE_CACHE_TTL", DEFAULT_EDGE_CACHE_TTL),
+            path_prefix,
+            short_ids: vars.flag("SHORT_IDS", false),
+            album_zip_max_size: vars
+                .number("ALBUM_ZIP_MAX_MB", 200u64)
+                .saturating_mul(1024 * 1024),
+            image_resizing: vars.flag("IMAGE_RESIZING", false),
+            auto_format: vars.flag("AUTO_FORMAT", false),
+            strip_exif: vars.flag("STRIP_EXIF", false),
+            moderation,
+            moderation_model: vars.string("MODERATION_MODEL"),
+            moderation_review,
+            moderation_reject,
+            alt_text,
+            alt_text_model: vars.string("ALT_TEXT_MODEL"),
+            delete_grace_days: vars.number("DELETE_GRACE_DAYS", 30),
+            hide_banned_files: vars.flag("HIDE_BANNED_FILES", false),
+            privacy_mode,
+            bots,
+            public_host,
+            trust_forwarded_host: vars.flag("TRUST_FORWARDED_HOST", false),
+            allowed_origins,
+            content_security_policy: vars.string("CONTENT_SECURITY_POLICY"),
+            referrer_policy: vars.string("REFERRER_POLICY"),
+            attachment_mode,
+            svg_policy,
+            robots_index_files: vars.flag("ROBOTS_INDEX_FILES", true),
+            landing_mode,
+            landing_recent: vars.number("LANDING_RECENT", 0),
+            turnstile_site_key,
+            turnstile_secret,
+            turnstile_secret_previous: vars.string("TURNSTILE_SECRET_PREVIOUS"),
+            turnstile_pass_hours: vars.number("TURNSTILE_PASS_HOURS", DEFAULT_PASS_HOURS),
+            max_file_size: vars
+                .number("MAX_FILE_SIZE", 0u64)
+                .saturating_mul(1024 * 1024),
+            allowed_mime_types,
+            blocked_extensions: vars.string("BLOCKED_EXTENSIONS"),
+            s3_access_key_id: vars.string("S3_ACCESS_KEY_ID"),
+            problems: vars.problems,
+        }
+    }
+
+    // required values that are missing and values that couldn't be parsed
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.problems.is_empty() {
+            return Ok(());
+        }
+        Err(Error::Config(self.problems.join("; ")))
+    }
+}
+
+// a domain with an optional port, no scheme or path
+pub fn is_host(host: &str) -> bool {
+    !host.is_empty()
+        && host.len() <= 253
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':'))
+}
+
+// `TELEGRAM_TOKEN_<suffix>` and `DB_<suffix>` belong to the bot of BOTS with that name
+pub fn bot_suffix(name: &str) -> String {
+    name.to_ascii_uppercase()
+}
+
+// the rows of every bot are kept in a database of its own
+pub fn bot_database(name: &str) -> String {
+    format!("DB_{}", bot_suffix(name))
+}
+
+struct Vars<'a> {
+    env: &'a Env,
+    problems: Vec<String>,
+}
+
+impl Vars<'_> {
+    fn string(&self, key: &str) -> String {
+        match self.env.var(key) {
+            Ok(v) => v.to_string(),
+            Err(_) => String::new(),
+        }
+    }
+
+    fn number<T: FromStr>(&mut self, key: &str, default: T) -> T {
+        let v = self.string(key).trim().to_string();
+        if v.is_empty() {
+            return default;
+        }
+        match v.parse() {
+            Ok(v) => v,
+            Err(_) => {
+                self.problems
+                    .push(format!("{} {:?} is not a valid number", key, v));
+                default
+            }
+        }
+    }
+
+    fn flag(&mut self, key: &str, default: bool) -> bool {
+        match self.string(key).trim() {
+            "" => default,
+            "true" => true,
+            "false" => false,
+            v => {
+                self.problems
+                    .push(format!("{} {:?} is not true or false", key, v));
+                default
+            }
+        }
+    }
+}
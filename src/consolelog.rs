@@ -1,20 +1,117 @@
-use log::{Level, Log, Metadata, Record, SetLoggerError};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
 use web_sys::console;
 
+use crate::privacy::redact;
+
 static LOGGER: WebConsoleLogger = WebConsoleLogger {};
 
+static FILTER: RwLock<Filter> = RwLock::new(Filter {
+    default: LevelFilter::Info,
+    modules: Vec::new(),
+});
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // id of the request whose future is being polled, set by `WithRequestId`. an isolate handles
+    // requests concurrently, but polls one future at a time
+    static REQUEST_ID: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    level: &'a str,
+    module: &'a str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    request_id: String,
+}
+
+// RUST_LOG like filter, e.g. `info,tg=debug,d1=warn`
+struct Filter {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    fn parse(level: &str, spec: &str) -> Filter {
+        let mut filter = Filter {
+            default: level.parse().unwrap_or(LevelFilter::Info),
+            modules: Vec::new(),
+        };
+
+        for directive in spec.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    if let Ok(level) = level.trim().parse() {
+                        filter.modules.push((module.trim().to_string(), level));
+                    }
+                }
+                None => {
+                    if let Ok(level) = directive.parse() {
+                        filter.default = level;
+                    }
+                }
+            }
+        }
+
+        // the most specific module wins
+        filter.modules.sort_by_key(|v| std::cmp::Reverse(v.0.len()));
+        filter
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, std::cmp::max)
+    }
+
+    fn level(&self, module_path: &str) -> LevelFilter {
+        // modules of this crate can be written without the crate name, `tg` for `tg_image_hosting::tg`
+        let local = module_path
+            .strip_prefix(concat!(env!("CARGO_CRATE_NAME"), "::"))
+            .unwrap_or(module_path);
+
+        self.modules
+            .iter()
+            .find(|(module, _)| {
+                [module_path, local].iter().any(|path| {
+                    *path == module.as_str()
+                        || path
+                            .strip_prefix(module.as_str())
+                            .is_some_and(|rest| rest.starts_with("::"))
+                })
+            })
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+}
+
 struct WebConsoleLogger {}
 
 impl Log for WebConsoleLogger {
     #[inline]
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= log::max_level()
+        if metadata.level() > log::max_level() {
+            return false;
+        }
+
+        match FILTER.read() {
+            Ok(filter) => metadata.level() <= filter.level(metadata.target()),
+            Err(_) => true,
+        }
     }
 
     fn log(&self, record: &Record) {
-        match record.module_path().unwrap_or("") {
-            "html5ever::serialize" => return,
-            _ => {}
+        if record.module_path().unwrap_or("") == "html5ever::serialize" {
+            return;
         }
 
         if !self.enabled(record.metadata()) {
@@ -33,7 +130,26 @@ impl Log for WebConsoleLogger {
         // let file = record.file().unwrap_or("");
         // let line = record.line().unwrap_or(0);
 
-        console_log(&format!("[{}] {}", module_path, record.args()).into());
+        let message = redact(&record.args().to_string()).into_owned();
+
+        if JSON.load(Ordering::Relaxed) {
+            let line = JsonLine {
+                level: record.level().as_str(),
+                module: module_path,
+                message: &message,
+                request_id: current_request_id(),
+            };
+            if let Ok(line) = serde_json::to_string(&line) {
+                return console_log(&line.into());
+            }
+        }
+
+        match current_request_id() {
+            id if !id.is_empty() => {
+                console_log(&format!("[{}] [{}] {}", module_path, id, message).into())
+            }
+            _ => console_log(&format!("[{}] {}", module_path, message).into()),
+        }
     }
 
     fn flush(&self) {}
@@ -45,3 +161,46 @@ pub fn init_with_level(level: Level) -> Result<(), SetLoggerError> {
     log::set_max_level(level.to_level_filter());
     Ok(())
 }
+
+// `level` is the default level, `spec` adds per module levels, applied to every following log
+pub fn set_filter(level: &str, spec: &str) {
+    let filter = Filter::parse(level, spec);
+    log::set_max_level(filter.max_level());
+    if let Ok(mut v) = FILTER.write() {
+        *v = filter;
+    }
+}
+
+// one json object per line instead of `[module] message`, for logpush and tail workers
+pub fn set_json(enabled: bool) {
+    JSON.store(enabled, Ordering::Relaxed);
+}
+
+fn current_request_id() -> String {
+    REQUEST_ID.with(|v| v.borrow().clone())
+}
+
+// logs while `future` is polled carry `request_id`, other requests interleaved with it keep theirs
+pub fn with_request_id<F: Future>(request_id: String, future: F) -> WithRequestId<F> {
+    WithRequestId {
+        request_id,
+        future: Box::pin(future),
+    }
+}
+
+pub struct WithRequestId<F> {
+    request_id: String,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for WithRequestId<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let outer = REQUEST_ID.with(|v| v.replace(this.request_id.clone()));
+        let result = this.future.as_mut().poll(cx);
+        REQUEST_ID.with(|v| *v.borrow_mut() = outer);
+        result
+    }
+}
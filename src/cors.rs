@@ -0,0 +1,46 @@
+use worker::{Headers, Result};
+
+// readable by scripts of an allowed origin besides the cors safelisted ones
+const EXPOSE_HEADERS: &str = "Content-Disposition, Content-Length, Content-Range, X-Request-Id";
+
+// which web apps may read responses with fetch or draw the images into a readable canvas
+#[derive(Clone, Default, Debug)]
+pub struct Cors {
+    // origins like `https://app.example.com`, empty allows any origin without credentials
+    origins: Vec<String>,
+}
+
+impl Cors {
+    pub fn new(allowed_origins: &str) -> Self {
+        let origins = allowed_origins
+            .split(',')
+            .map(|v| v.trim().trim_end_matches('/').to_ascii_lowercase())
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<_>>();
+        // `*` in the list is the same as no list
+        if origins.iter().any(|v| v == "*") {
+            return Self::default();
+        }
+        Cors { origins }
+    }
+
+    // headers of preflights and of the actual responses, `origin` is the Origin of the request
+    pub fn apply(&self, origin: Option<&str>, headers: &Headers) -> Result<()> {
+        if self.origins.is_empty() {
+            headers.set("Access-Control-Allow-Origin", "*")?;
+        } else {
+            // the answer depends on the origin, caches keep one per origin
+            headers.append("Vary", "Origin")?;
+            match origin {
+                Some(v) if self.origins.contains(&v.to_ascii_lowercase()) => {
+                    headers.set("Access-Control-Allow-Origin", v)?;
+                    headers.set("Access-Control-Allow-Credentials", "true")?;
+                }
+                // no allow headers, the browser keeps the response from the script
+                _ => return Ok(()),
+            }
+        }
+        headers.set("Access-Control-Expose-Headers", EXPOSE_HEADERS)?;
+        Ok(())
+    }
+}
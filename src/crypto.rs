@@ -0,0 +1,102 @@
+// hashes and hmacs shared by the modules that sign or check something, hex encoded the way they
+// end up in urls, cookies and d1
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key).map_err(|e| Error::Internal(e.to_string()))?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+// pbkdf2 with hmac-sha256, one block of 32 bytes
+pub fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> Result<[u8; 32], Error> {
+    let prf =
+        Hmac::<Sha256>::new_from_slice(password).map_err(|e| Error::Internal(e.to_string()))?;
+    let mut mac = prf.clone();
+    mac.update(salt);
+    mac.update(&1u32.to_be_bytes());
+    let mut u: [u8; 32] = mac.finalize().into_bytes().into();
+    let mut key = u;
+    for _ in 1..iterations {
+        let mut mac = prf.clone();
+        mac.update(&u);
+        u = mac.finalize().into_bytes().into();
+        key.iter_mut().zip(&u).for_each(|(k, v)| *k ^= v);
+    }
+    Ok(key)
+}
+
+// compares secrets without returning early at the first differing byte
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// whether `signature` is the hex encoded hmac-sha256 of `data`, checked by `Mac::verify_slice`
+pub fn verify_hmac_hex(key: &[u8], data: &[u8], signature: &str) -> Result<bool, Error> {
+    let Some(signature) = unhex(signature) else {
+        return Ok(false);
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key).map_err(|e| Error::Internal(e.to_string()))?;
+    mac.update(data);
+    Ok(mac.verify_slice(&signature).is_ok())
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// `bytes` random bytes, hex encoded
+pub fn random_hex(bytes: usize) -> Result<String, Error> {
+    let mut data = vec![0u8; bytes];
+    getrandom::getrandom(&mut data).map_err(|e| Error::Internal(e.to_string()))?;
+    Ok(hex(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // rfc 7914 section 11
+    #[test]
+    fn pbkdf2_vectors() {
+        assert_eq!(
+            hex(&pbkdf2_sha256(b"password", b"salt", 1).unwrap()),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            hex(&pbkdf2_sha256(b"password", b"salt", 4096).unwrap()),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn hmac_hex_roundtrip() {
+        let signature = hex(&hmac_sha256(b"key", b"data").unwrap());
+        assert!(verify_hmac_hex(b"key", b"data", &signature).unwrap());
+        assert!(!verify_hmac_hex(b"key", b"other", &signature).unwrap());
+        assert!(!verify_hmac_hex(b"key", b"data", "zz").unwrap());
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+}
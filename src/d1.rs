@@ -1,10 +1,14 @@
-use frankenstein::types::{Document, Message, PhotoSize, Video};
+use frankenstein::types::{Chat, Document, Message, MessageOrigin, PhotoSize, Video};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
 use std::{ops::Deref, sync::Arc};
 use wasm_bindgen::JsValue;
-use worker::{D1Database, D1PreparedStatement};
+use worker::{D1Database, D1PreparedStatement, D1Result, Date};
 
+use crate::apikey::ApiKey;
 use crate::error::Error;
+use crate::filestore::FileStore;
 
 pub static CREATE_TABLE: &str = r#"
 CREATE TABLE IF NOT EXISTS [files](
@@ -19,17 +23,223 @@ CREATE TABLE IF NOT EXISTS [files](
     "mime_type" TEXT,
     "add_time" INTEGER,
     "update_time" INTEGER,
-    "file_path" TEXT
+    "file_path" TEXT,
+    "file_hash" TEXT NOT NULL DEFAULT '',
+    "password_hash" TEXT NOT NULL DEFAULT '',
+    "password_salt" TEXT NOT NULL DEFAULT '',
+    "upload_locale" TEXT NOT NULL DEFAULT '',
+    "download_name" TEXT NOT NULL DEFAULT '',
+    "custom_headers" TEXT NOT NULL DEFAULT '',
+    "forward_chat_id" TEXT NOT NULL DEFAULT '',
+    "forward_message_id" INTEGER NOT NULL DEFAULT 0,
+    "forward_from" TEXT NOT NULL DEFAULT '',
+    "chat_id" INTEGER NOT NULL DEFAULT 0,
+    "replaced_by" TEXT NOT NULL DEFAULT '',
+    "downloads" INTEGER NOT NULL DEFAULT 0
 )
 ;
 "#;
 
+// columns added after the first release, applied by `D1::init` on existing databases
+pub static MIGRATIONS: &[&str] = &[
+    r#"ALTER TABLE files ADD COLUMN "file_hash" TEXT NOT NULL DEFAULT ''"#,
+    r#"CREATE INDEX IF NOT EXISTS files_file_hash ON files(file_hash)"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [users](
+    "user_id" INTEGER PRIMARY KEY,
+    "consent_time" INTEGER NOT NULL DEFAULT 0,
+    "add_time" INTEGER,
+    "update_time" INTEGER
+)
+"#,
+    r#"ALTER TABLE files ADD COLUMN "password_hash" TEXT NOT NULL DEFAULT ''"#,
+    r#"ALTER TABLE files ADD COLUMN "password_salt" TEXT NOT NULL DEFAULT ''"#,
+    r#"ALTER TABLE files ADD COLUMN "upload_locale" TEXT NOT NULL DEFAULT ''"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [settings](
+    "key" TEXT PRIMARY KEY,
+    "value" TEXT NOT NULL,
+    "update_time" INTEGER
+)
+"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [counters](
+    "name" TEXT PRIMARY KEY,
+    "value" INTEGER NOT NULL DEFAULT 0
+)
+"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [events](
+    "seq" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "kind" TEXT NOT NULL,
+    "file_id" TEXT NOT NULL,
+    "file_unique_id" TEXT NOT NULL,
+    "file_name" TEXT NOT NULL DEFAULT '',
+    "mime_type" TEXT NOT NULL DEFAULT '',
+    "add_time" INTEGER
+)
+"#,
+    r#"ALTER TABLE files ADD COLUMN "download_name" TEXT NOT NULL DEFAULT ''"#,
+    r#"ALTER TABLE files ADD COLUMN "custom_headers" TEXT NOT NULL DEFAULT ''"#,
+    r#"ALTER TABLE users ADD COLUMN "namespace" TEXT NOT NULL DEFAULT ''"#,
+    r#"CREATE UNIQUE INDEX IF NOT EXISTS users_namespace ON users(namespace) WHERE namespace != ''"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [pending_tasks](
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "kind" TEXT NOT NULL,
+    "file_id" TEXT NOT NULL,
+    "key" TEXT NOT NULL,
+    "error" TEXT NOT NULL DEFAULT '',
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "add_time" INTEGER,
+    "update_time" INTEGER,
+    UNIQUE(kind, key)
+)
+"#,
+    r#"ALTER TABLE files ADD COLUMN "forward_chat_id" TEXT NOT NULL DEFAULT ''"#,
+    r#"ALTER TABLE files ADD COLUMN "forward_message_id" INTEGER NOT NULL DEFAULT 0"#,
+    r#"ALTER TABLE files ADD COLUMN "forward_from" TEXT NOT NULL DEFAULT ''"#,
+    r#"ALTER TABLE files ADD COLUMN "chat_id" INTEGER NOT NULL DEFAULT 0"#,
+    r#"ALTER TABLE files ADD COLUMN "replaced_by" TEXT NOT NULL DEFAULT ''"#,
+    r#"CREATE INDEX IF NOT EXISTS files_chat_message ON files(chat_id, message_id)"#,
+    r#"ALTER TABLE files ADD COLUMN "downloads" INTEGER NOT NULL DEFAULT 0"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [aliases](
+    "alias" TEXT PRIMARY KEY,
+    "file_unique_id" TEXT NOT NULL,
+    "kind" TEXT NOT NULL,
+    "add_time" INTEGER
+)
+"#,
+    r#"CREATE UNIQUE INDEX IF NOT EXISTS aliases_short_id ON aliases(file_unique_id) WHERE kind = 'short'"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [albums](
+    "album_id" TEXT PRIMARY KEY,
+    "user_id" INTEGER NOT NULL,
+    "name" TEXT NOT NULL,
+    "add_time" INTEGER,
+    UNIQUE(user_id, name)
+)
+"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [album_files](
+    "album_id" TEXT NOT NULL,
+    "file_unique_id" TEXT NOT NULL,
+    "add_time" INTEGER,
+    PRIMARY KEY(album_id, file_unique_id)
+)
+"#,
+    r#"ALTER TABLE files ADD COLUMN "moderation_score" REAL"#,
+    r#"ALTER TABLE files ADD COLUMN "blocked" TEXT NOT NULL DEFAULT ''"#,
+    r#"ALTER TABLE files ADD COLUMN "alt_text" TEXT NOT NULL DEFAULT ''"#,
+    r#"ALTER TABLE files ADD COLUMN "deleted_at" INTEGER NOT NULL DEFAULT 0"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [banned_users](
+    "user_id" INTEGER PRIMARY KEY,
+    "add_time" INTEGER
+)
+"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [reports](
+    "report_id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "file_unique_id" TEXT NOT NULL,
+    "reason" TEXT NOT NULL,
+    "status" TEXT NOT NULL DEFAULT 'open',
+    "add_time" INTEGER
+)
+"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [api_keys](
+    "key_id" TEXT PRIMARY KEY,
+    "key_hash" TEXT NOT NULL UNIQUE,
+    "user_id" INTEGER NOT NULL,
+    "scopes" TEXT NOT NULL,
+    "last_used" INTEGER NOT NULL DEFAULT 0,
+    "add_time" INTEGER
+)
+"#,
+    r#"CREATE INDEX IF NOT EXISTS api_keys_user_id ON api_keys(user_id)"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [tags](
+    "tag_id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "user_id" INTEGER NOT NULL,
+    "name" TEXT NOT NULL,
+    "add_time" INTEGER,
+    UNIQUE(user_id, name)
+)
+"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [file_tags](
+    "tag_id" INTEGER NOT NULL,
+    "file_unique_id" TEXT NOT NULL,
+    "add_time" INTEGER,
+    PRIMARY KEY(tag_id, file_unique_id)
+)
+"#,
+    r#"CREATE INDEX IF NOT EXISTS file_tags_file_unique_id ON file_tags(file_unique_id)"#,
+    r#"
+CREATE TABLE IF NOT EXISTS [channel_collections](
+    "chat_id" TEXT PRIMARY KEY,
+    "album_id" TEXT NOT NULL,
+    "tags" TEXT NOT NULL DEFAULT '',
+    "add_time" INTEGER
+)
+"#,
+    r#"ALTER TABLE users ADD COLUMN "show_location" INTEGER NOT NULL DEFAULT 0"#,
+];
+
+pub const EVENT_UPLOAD: &str = "upload";
+pub const EVENT_PROTECT: &str = "protect";
+pub const EVENT_UNPROTECT: &str = "unprotect";
+pub const EVENT_RENAME: &str = "rename";
+pub const EVENT_REPLACE: &str = "replace";
+pub const EVENT_DELETE: &str = "delete";
+pub const EVENT_UNDELETE: &str = "undelete";
+
+// reasons of `File::blocked`
+pub const BLOCKED_MODERATION: &str = "moderation";
+pub const BLOCKED_REPORT: &str = "report";
+
+// decisions of the maintainer about reports
+pub const REPORT_OPEN: &str = "open";
+pub const REPORT_TAKEN_DOWN: &str = "taken_down";
+pub const REPORT_DISMISSED: &str = "dismissed";
+
+pub const ALIAS_SHORT: &str = "short";
+pub const ALIAS_SLUG: &str = "slug";
+const SHORT_ID_LENGTH: usize = 6;
+// 62^6 ids, a collision is rare enough that a few retries always find a free one
+pub(crate) const SHORT_ID_ATTEMPTS: u32 = 5;
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+// first segment of download links, PATH_PREFIX
+static PATH_PREFIX: RwLock<String> = RwLock::new(String::new());
+pub const DEFAULT_PATH_PREFIX: &str = "f";
+
+pub fn set_path_prefix(prefix: &str) {
+    if let Ok(mut v) = PATH_PREFIX.write() {
+        *v = prefix.to_string();
+    }
+}
+
+pub fn path_prefix() -> String {
+    match PATH_PREFIX.read() {
+        Ok(v) if !v.is_empty() => v.clone(),
+        _ => DEFAULT_PATH_PREFIX.to_string(),
+    }
+}
+
+// headers uploaders may set on their files, anything that changes how browsers execute content is left out
+pub const CUSTOM_HEADERS: [&str; 2] = ["Content-Language", "X-Robots-Tag"];
+
 pub static INSERT_FILE: &str = r#"
 INSERT INTO files(
   file_id, file_unique_id, thumbnail_file_id, 
   thumbnail_file_unique_id, message_id, 
   user_id, file_name, file_size, mime_type, 
-  add_time, update_time, file_path
+  add_time, update_time, file_path, file_hash, 
+  upload_locale, forward_chat_id, forward_message_id, 
+  forward_from, chat_id
 ) 
 VALUES 
   (
@@ -44,6 +254,12 @@ VALUES
     ?, 
     strftime('%s', 'now'), 
     strftime('%s', 'now'), 
+    ?, 
+    ?, 
+    ?, 
+    ?, 
+    ?, 
+    ?, 
     ?
   ) ON CONFLICT(file_unique_id) DO 
 UPDATE 
@@ -51,12 +267,18 @@ SET
   thumbnail_file_id = excluded.thumbnail_file_id, 
   thumbnail_file_unique_id = excluded.thumbnail_file_unique_id, 
   message_id = excluded.message_id, 
-  user_id = excluded.user_id, 
+  user_id = CASE WHEN files.user_id = 0 THEN excluded.user_id ELSE files.user_id END, 
   file_name = excluded.file_name, 
   file_size = excluded.file_size, 
   mime_type = excluded.mime_type, 
   update_time = strftime('%s', 'now'), 
-  file_path = excluded.file_path
+  file_path = excluded.file_path, 
+  file_hash = CASE WHEN excluded.file_hash = '' THEN files.file_hash ELSE excluded.file_hash END, 
+  upload_locale = excluded.upload_locale, 
+  forward_chat_id = excluded.forward_chat_id, 
+  forward_message_id = excluded.forward_message_id, 
+  forward_from = excluded.forward_from, 
+  chat_id = excluded.chat_id
 "#;
 
 pub static SAVE_FILE_PATH: &str = r#"
@@ -69,6 +291,122 @@ WHERE
     file_unique_id = ?
 "#;
 
+pub static SAVE_PASSWORD: &str = r#"
+UPDATE
+    files
+SET
+    password_hash = ?, 
+    password_salt = ?, 
+    update_time = strftime('%s', 'now')
+WHERE
+    file_unique_id = ?
+"#;
+
+pub static SAVE_MODERATION: &str = r#"
+UPDATE
+    files
+SET
+    moderation_score = ?, 
+    blocked = CASE WHEN ? = '' THEN blocked ELSE ? END, 
+    update_time = strftime('%s', 'now')
+WHERE
+    file_unique_id = ?
+"#;
+
+pub static SAVE_BLOCKED: &str = r#"
+UPDATE
+    files
+SET
+    blocked = ?, 
+    update_time = strftime('%s', 'now')
+WHERE
+    file_unique_id = ?
+"#;
+
+pub static SAVE_ALT_TEXT: &str = r#"
+UPDATE
+    files
+SET
+    alt_text = ?, 
+    update_time = strftime('%s', 'now')
+WHERE
+    file_unique_id = ?
+"#;
+
+// generated text doesn't replace what the uploader wrote with /alt
+pub static SAVE_GENERATED_ALT_TEXT: &str = r#"
+UPDATE
+    files
+SET
+    alt_text = ?, 
+    update_time = strftime('%s', 'now')
+WHERE
+    file_unique_id = ?
+    AND alt_text = ''
+"#;
+
+// unix time of the deletion, 0 brings the file back
+pub static SAVE_DELETED: &str = r#"
+UPDATE
+    files
+SET
+    deleted_at = ?, 
+    update_time = strftime('%s', 'now')
+WHERE
+    file_unique_id = ?
+"#;
+
+pub static SELECT_EXPIRED_FILES: &str = r#"
+SELECT
+    *
+FROM
+    files
+WHERE
+    deleted_at > 0
+AND deleted_at <= ?
+LIMIT ?
+"#;
+
+// the file and everything pointing at it, events and reports stay as a record
+pub static DELETE_FILE: &str = r#"
+DELETE FROM
+    files
+WHERE
+    file_unique_id = ?
+"#;
+
+pub static DELETE_FILE_ALIASES: &str = r#"
+DELETE FROM
+    aliases
+WHERE
+    file_unique_id = ?
+"#;
+
+pub static DELETE_FILE_ALBUM_ENTRIES: &str = r#"
+DELETE FROM
+    album_files
+WHERE
+    file_unique_id = ?
+"#;
+
+pub static DELETE_FILE_TAG_ENTRIES: &str = r#"
+DELETE FROM
+    file_tags
+WHERE
+    file_unique_id = ?
+"#;
+
+pub static SAVE_DOWNLOAD_OPTIONS: &str = r#"
+UPDATE
+    files
+SET
+    download_name = ?, 
+    custom_headers = ?, 
+    update_time = strftime('%s', 'now')
+WHERE
+    file_unique_id = ?
+"#;
+
 pub static SELECT_FILE: &str = r#"
 SELECT
     *
@@ -79,229 +417,2756 @@ WHERE
 OR  file_unique_id = ?
 "#;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct File {
-    pub file_id: String,
-    pub file_unique_id: String,
-    pub thumbnail_file_id: String,
-    pub thumbnail_file_unique_id: String,
-    pub message_id: i32,
-    pub user_id: u64,
-    pub file_name: String,
-    pub file_size: u64,
-    pub mime_type: String,
-    pub add_time: i64,
-    pub update_time: i64,
-    pub file_path: String,
-}
+// files of a message that were not replaced by an edit yet
+pub static SELECT_MESSAGE_FILES: &str = r#"
+SELECT
+    *
+FROM
+    files
+WHERE
+    chat_id = ?
+AND message_id = ?
+AND replaced_by = ''
+AND deleted_at = 0
+"#;
 
-impl File {
-    pub fn with_message_id(mut self, message_id: i32) -> Self {
-        self.message_id = message_id;
-        self
-    }
+pub static REPLACE_FILE: &str = r#"
+UPDATE
+    files
+SET
+    replaced_by = ?, 
+    update_time = strftime('%s', 'now')
+WHERE
+    file_unique_id = ?
+"#;
 
-    pub fn with_user_id(mut self, user_id: u64) -> Self {
-        self.user_id = user_id;
-        self
-    }
+pub static INSERT_ALIAS: &str = r#"
+INSERT INTO aliases(alias, file_unique_id, kind, add_time)
+VALUES
+  (?, ?, ?, strftime('%s', 'now'))
+"#;
 
-    pub fn with_file_path(mut self, file_path: String) -> Self {
-        self.file_path = file_path;
-        self
-    }
+pub static SELECT_ALIAS_FILE: &str = r#"
+SELECT
+    file_unique_id
+FROM
+    aliases
+WHERE
+    alias = ?
+"#;
 
-    pub async fn from_message<F, Fut>(
-        msg: Box<Message>,
-        get_file_path: F,
-    ) -> Result<Vec<File>, Error>
-    where
-        F: Fn(String) -> Fut,
-        Fut: Future<Output = Result<String, Error>>,
-    {
-        let user_id = match msg.from {
-            Some(u) => u.id,
-            None => 0,
-        };
-        let msg_id = msg.message_id;
+pub static SELECT_SHORT_ID: &str = r#"
+SELECT
+    alias
+FROM
+    aliases
+WHERE
+    file_unique_id = ?
+AND kind = 'short'
+"#;
 
-        let mut files = Vec::new();
-        if let Some(doc) = msg.document {
-            files.push(
-                File::from(doc.deref())
-                    .with_message_id(msg_id)
-                    .with_user_id(user_id)
-                    .with_file_path(get_file_path(doc.file_id).await?),
-            );
-        }
-        if let Some(photos) = msg.photo {
-            match photos.last() {
-                Some(photo) => {
-                    files.push(
-                        File::from(photo)
-                            .with_message_id(msg_id)
-                            .with_user_id(user_id)
-                            .with_file_path(get_file_path(photo.file_id.clone()).await?),
-                    );
-                }
-                _ => {}
-            };
-        }
-        if let Some(video) = msg.video {
-            files.push(
-                File::from(video.deref())
-                    .with_message_id(msg_id)
-                    .with_user_id(user_id)
-                    .with_file_path(get_file_path(video.file_id).await?),
-            );
-        }
-        Ok(files)
-    }
-}
+pub static INSERT_ALBUM: &str = r#"
+INSERT INTO albums(album_id, user_id, name, add_time)
+VALUES
+  (?, ?, ?, strftime('%s', 'now'))
+"#;
 
-impl From<&Video> for File {
-    fn from(v: &Video) -> Self {
-        let (thumbnail_file_id, thumbnail_file_unique_id) = match &v.thumbnail {
-            Some(t) => (&t.file_id, &t.file_unique_id),
-            None => (&String::new(), &String::new()),
-        };
-        File {
-            file_id: v.file_id.clone(),
-            file_unique_id: v.file_unique_id.clone(),
-            thumbnail_file_id: thumbnail_file_id.clone(),
-            thumbnail_file_unique_id: thumbnail_file_unique_id.clone(),
-            file_size: v.file_size.unwrap_or_default(),
-            mime_type: v.mime_type.clone().unwrap_or_default(),
-            file_name: v.file_name.clone().unwrap_or_default(),
-            add_time: 0,
-            update_time: 0,
-            message_id: 0,
-            user_id: 0,
-            file_path: "".to_string(),
-        }
-    }
-}
+pub static SELECT_ALBUM: &str = r#"
+SELECT
+    *
+FROM
+    albums
+WHERE
+    album_id = ?
+"#;
 
-impl From<&Document> for File {
-    fn from(value: &Document) -> Self {
-        let (thumbnail_file_id, thumbnail_file_unique_id) = match &value.thumbnail {
-            Some(t) => (&t.file_id, &t.file_unique_id),
-            None => (&String::new(), &String::new()),
-        };
-        File {
-            file_id: value.file_id.clone(),
-            file_unique_id: value.file_unique_id.clone(),
-            thumbnail_file_id: thumbnail_file_id.clone(),
-            thumbnail_file_unique_id: thumbnail_file_unique_id.clone(),
-            file_size: value.file_size.unwrap_or_default(),
-            mime_type: value.mime_type.clone().unwrap_or_default(),
-            file_name: value.file_name.clone().unwrap_or_default(),
-            add_time: 0,
-            update_time: 0,
-            message_id: 0,
-            user_id: 0,
-            file_path: "".to_string(),
-        }
-    }
-}
+pub static SELECT_USER_ALBUM: &str = r#"
+SELECT
+    *
+FROM
+    albums
+WHERE
+    user_id = ?
+AND name = ?
+"#;
 
-impl From<&PhotoSize> for File {
-    fn from(value: &PhotoSize) -> Self {
-        File {
-            file_id: value.file_id.clone(),
-            file_unique_id: value.file_unique_id.clone(),
-            thumbnail_file_id: "".to_string(),
-            thumbnail_file_unique_id: "".to_string(),
-            file_size: value.file_size.unwrap_or_default(),
-            mime_type: "".to_string(),
-            file_name: "".to_string(),
-            add_time: 0,
+pub static INSERT_ALBUM_FILE: &str = r#"
+INSERT OR IGNORE INTO album_files(album_id, file_unique_id, add_time)
+VALUES
+  (?, ?, strftime('%s', 'now'))
+"#;
+
+pub static UPSERT_CHANNEL_COLLECTION: &str = r#"
+INSERT INTO channel_collections(chat_id, album_id, tags, add_time)
+VALUES
+  (?, ?, ?, strftime('%s', 'now'))
+ON CONFLICT(chat_id) DO UPDATE SET
+  album_id = excluded.album_id,
+  tags = excluded.tags
+"#;
+
+pub static SELECT_CHANNEL_COLLECTION: &str = r#"
+SELECT
+    *
+FROM
+    channel_collections
+WHERE
+    chat_id = ?
+"#;
+
+pub static SELECT_CHANNEL_COLLECTIONS: &str = r#"
+SELECT
+    *
+FROM
+    channel_collections
+ORDER BY
+    add_time
+"#;
+
+pub static DELETE_CHANNEL_COLLECTION: &str = r#"
+DELETE FROM
+    channel_collections
+WHERE
+    chat_id = ?
+"#;
+
+// files of an album in the order they were added, protected ones stay out of the public gallery
+pub static SELECT_ALBUM_FILES: &str = r#"
+SELECT
+    files.*
+FROM
+    album_files
+    JOIN files ON files.file_unique_id = album_files.file_unique_id
+WHERE
+    album_files.album_id = ?
+AND files.password_hash = ''
+AND files.blocked = ''
+AND files.deleted_at = 0
+ORDER BY
+    album_files.add_time, album_files.rowid
+LIMIT ?
+"#;
+
+pub static INSERT_REPORT: &str = r#"
+INSERT INTO reports(file_unique_id, reason, add_time)
+VALUES
+  (?, ?, strftime('%s', 'now'))
+RETURNING report_id
+"#;
+
+// one decision answers every open report of the file
+pub static CLOSE_REPORTS: &str = r#"
+UPDATE
+    reports
+SET
+    status = ?
+WHERE
+    file_unique_id = ?
+AND status = 'open'
+"#;
+
+// files of GET /admin/files, empty values don't filter. photos count as image/jpeg
+pub static SELECT_FILTERED_FILES: &str = r#"
+SELECT
+    *
+FROM
+    files
+WHERE
+    (? = '' OR user_id = ?)
+AND (? = '' OR (CASE WHEN mime_type = '' THEN 'image/jpeg' ELSE mime_type END) LIKE ? || '%')
+AND (? = '' OR file_name LIKE '%' || ? || '%' OR download_name LIKE '%' || ? || '%')
+AND CASE ?
+        WHEN 'active' THEN deleted_at = 0 AND blocked = ''
+        WHEN 'deleted' THEN deleted_at > 0
+        WHEN 'blocked' THEN blocked != ''
+        ELSE 1
+    END
+"#;
+
+// storage of files that aren't deleted by the part of the type before the slash
+pub static SELECT_USAGE_BY_TYPE: &str = r#"
+SELECT
+    CASE
+        WHEN mime_type = '' THEN 'image'
+        ELSE substr(mime_type, 1, instr(mime_type || '/', '/') - 1)
+    END AS type,
+    COUNT(*) AS files,
+    IFNULL(SUM(file_size), 0) AS bytes
+FROM
+    files
+WHERE
+    deleted_at = 0
+GROUP BY
+    type
+ORDER BY
+    bytes DESC
+"#;
+
+pub static SELECT_USAGE_BY_DAY: &str = r#"
+SELECT
+    date(add_time, 'unixepoch') AS day,
+    COUNT(*) AS files,
+    IFNULL(SUM(file_size), 0) AS bytes
+FROM
+    files
+WHERE
+    add_time >= ?
+GROUP BY
+    day
+ORDER BY
+    day
+"#;
+
+// uploaders with the most stored first, banned_at is 0 for users who aren't banned
+pub static SELECT_UPLOADERS: &str = r#"
+SELECT
+    files.user_id AS user_id,
+    COUNT(*) AS files,
+    IFNULL(SUM(files.file_size), 0) AS bytes,
+    MAX(files.add_time) AS last_upload,
+    IFNULL(banned_users.add_time, 0) AS banned_at
+FROM
+    files
+    LEFT JOIN banned_users ON banned_users.user_id = files.user_id
+WHERE
+    files.user_id != 0
+AND (? = '' OR files.user_id = ?)
+GROUP BY
+    files.user_id
+ORDER BY
+    bytes DESC
+LIMIT ? OFFSET ?
+"#;
+
+pub static INSERT_API_KEY: &str = r#"
+INSERT INTO api_keys(key_id, key_hash, user_id, scopes, add_time)
+VALUES
+  (?, ?, ?, ?, strftime('%s', 'now'))
+"#;
+
+pub static SELECT_API_KEY: &str = r#"
+SELECT
+    key_id, user_id, scopes, last_used, add_time
+FROM
+    api_keys
+WHERE
+    key_hash = ?
+"#;
+
+pub static SELECT_USER_API_KEYS: &str = r#"
+SELECT
+    key_id, user_id, scopes, last_used, add_time
+FROM
+    api_keys
+WHERE
+    user_id = ?
+ORDER BY
+    add_time
+"#;
+
+pub static DELETE_API_KEY: &str = r#"
+DELETE FROM
+    api_keys
+WHERE
+    key_id = ?
+AND user_id = ?
+"#;
+
+pub static TOUCH_API_KEY: &str = r#"
+UPDATE
+    api_keys
+SET
+    last_used = strftime('%s', 'now')
+WHERE
+    key_id = ?
+"#;
+
+pub static SELECT_FILE_BY_HASH: &str = r#"
+SELECT
+    *
+FROM
+    files
+WHERE
+    file_hash = ?
+AND deleted_at = 0
+AND password_hash = ''
+AND blocked = ''
+LIMIT 1
+"#;
+
+pub static INSERT_BANNED_USER: &str = r#"
+INSERT OR IGNORE INTO banned_users(user_id, add_time)
+VALUES
+  (?, strftime('%s', 'now'))
+"#;
+
+pub static DELETE_BANNED_USER: &str = r#"
+DELETE FROM
+    banned_users
+WHERE
+    user_id = ?
+"#;
+
+pub static SELECT_BANNED_USER: &str = r#"
+SELECT
+    user_id
+FROM
+    banned_users
+WHERE
+    user_id = ?
+"#;
+
+// every file of a user, deleted and blocked ones included
+pub static SELECT_USER_FILES: &str = r#"
+SELECT
+    *
+FROM
+    files
+WHERE
+    user_id = ?
+"#;
+
+// keyset pages of /api/export
+pub static SELECT_FILES_AFTER: &str = r#"
+SELECT
+    *
+FROM
+    files
+WHERE
+    file_unique_id > ?
+ORDER BY
+    file_unique_id
+LIMIT
+    ?
+"#;
+
+// rows of /api/import, files this instance already knows are kept as they are
+pub static IMPORT_FILE: &str = r#"
+INSERT OR IGNORE INTO files(
+  file_id, file_unique_id, thumbnail_file_id, 
+  thumbnail_file_unique_id, message_id, 
+  user_id, file_name, file_size, mime_type, 
+  add_time, update_time, file_path, file_hash, 
+  password_hash, password_salt, upload_locale, 
+  download_name, custom_headers, forward_chat_id, 
+  forward_message_id, forward_from, chat_id, 
+  replaced_by, downloads, moderation_score, 
+  blocked, alt_text, deleted_at
+) 
+VALUES 
+  (
+    ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 
+    ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
+  )
+"#;
+
+pub static COUNT_USER_ALBUMS: &str = r#"
+SELECT
+    COUNT(*) AS count
+FROM
+    albums
+WHERE
+    user_id = ?
+"#;
+
+// everything stored about a user for /forgetme, each statement is bound to the user id.
+// files go last, the others find their rows through them. a ban is kept
+pub static FORGET_USER: [&str; 12] = [
+    "DELETE FROM events WHERE file_unique_id IN (SELECT file_unique_id FROM files WHERE user_id = ?)",
+    "DELETE FROM aliases WHERE file_unique_id IN (SELECT file_unique_id FROM files WHERE user_id = ?)",
+    "DELETE FROM reports WHERE file_unique_id IN (SELECT file_unique_id FROM files WHERE user_id = ?)",
+    "DELETE FROM pending_tasks WHERE file_id IN (SELECT file_id FROM files WHERE user_id = ?)",
+    "DELETE FROM album_files WHERE file_unique_id IN (SELECT file_unique_id FROM files WHERE user_id = ?) OR album_id IN (SELECT album_id FROM albums WHERE user_id = ?)",
+    "DELETE FROM channel_collections WHERE album_id IN (SELECT album_id FROM albums WHERE user_id = ?)",
+    "DELETE FROM albums WHERE user_id = ?",
+    "DELETE FROM file_tags WHERE tag_id IN (SELECT tag_id FROM tags WHERE user_id = ?)",
+    "DELETE FROM tags WHERE user_id = ?",
+    "DELETE FROM api_keys WHERE user_id = ?",
+    "DELETE FROM users WHERE user_id = ?",
+    "DELETE FROM files WHERE user_id = ?",
+];
+
+pub static SELECT_CONSENT: &str = r#"
+SELECT
+    consent_time
+FROM
+    users
+WHERE
+    user_id = ?
+"#;
+
+pub static SELECT_NAMESPACE: &str = r#"
+SELECT
+    namespace
+FROM
+    users
+WHERE
+    user_id = ?
+"#;
+
+pub static SAVE_NAMESPACE: &str = r#"
+INSERT INTO users(user_id, namespace, add_time, update_time)
+VALUES
+  (
+    ?, 
+    ?, 
+    strftime('%s', 'now'), 
+    strftime('%s', 'now')
+  ) ON CONFLICT(user_id) DO 
+UPDATE 
+SET 
+  namespace = excluded.namespace, 
+  update_time = excluded.update_time
+"#;
+
+// public files of a namespace, the order is appended from `ListingSort`
+pub static SELECT_NAMESPACE_FILES: &str = r#"
+SELECT
+    files.*
+FROM
+    files
+    JOIN users ON files.user_id = users.user_id
+WHERE
+    users.namespace = ?
+AND files.password_hash = ''
+AND files.blocked = ''
+AND files.deleted_at = 0
+"#;
+
+pub static SELECT_SHOW_LOCATION: &str = r#"
+SELECT
+    show_location
+FROM
+    users
+WHERE
+    user_id = ?
+"#;
+
+pub static SAVE_SHOW_LOCATION: &str = r#"
+INSERT INTO users(user_id, show_location, add_time, update_time)
+VALUES
+  (
+    ?, 
+    ?, 
+    strftime('%s', 'now'), 
+    strftime('%s', 'now')
+  ) ON CONFLICT(user_id) DO 
+UPDATE 
+SET 
+  show_location = excluded.show_location, 
+  update_time = excluded.update_time
+"#;
+
+// newest files of users with a public listing, for the landing page
+pub static SELECT_RECENT_PUBLIC_FILES: &str = r#"
+SELECT
+    files.*
+FROM
+    files
+    JOIN users ON files.user_id = users.user_id
+WHERE
+    users.namespace != ''
+AND files.password_hash = ''
+AND files.blocked = ''
+AND files.deleted_at = 0
+ORDER BY
+    files.add_time DESC
+LIMIT ?
+"#;
+
+// the order is appended with TAGGED_FILE when a tag is searched
+pub static SEARCH_USER_FILES: &str = r#"
+SELECT
+    *
+FROM
+    files
+WHERE
+    user_id = ?
+AND blocked = ''
+AND deleted_at = 0
+AND (file_name LIKE ? ESCAPE '\' OR download_name LIKE ? ESCAPE '\')
+"#;
+
+// appended to a query of files to keep the ones with the tag of the bound name, only when a tag is
+// asked for, databases without the tag tables still answer the other queries
+pub static TAGGED_FILE: &str = r#"
+AND files.file_unique_id IN (
+    SELECT
+        file_tags.file_unique_id
+    FROM
+        file_tags
+        JOIN tags ON tags.tag_id = file_tags.tag_id
+    WHERE
+        tags.user_id = files.user_id
+    AND tags.name = ?
+)
+"#;
+
+pub static INSERT_TAG: &str = r#"
+INSERT OR IGNORE INTO tags(user_id, name, add_time)
+VALUES
+  (?, ?, strftime('%s', 'now'))
+"#;
+
+pub static INSERT_FILE_TAG: &str = r#"
+INSERT OR IGNORE INTO file_tags(tag_id, file_unique_id, add_time)
+SELECT
+    tag_id, ?, strftime('%s', 'now')
+FROM
+    tags
+WHERE
+    user_id = ?
+AND name = ?
+"#;
+
+pub static DELETE_FILE_TAG: &str = r#"
+DELETE FROM
+    file_tags
+WHERE
+    file_unique_id = ?
+AND tag_id IN (SELECT tag_id FROM tags WHERE user_id = ? AND name = ?)
+"#;
+
+// tags of a user that no file has anymore
+pub static DELETE_UNUSED_TAGS: &str = r#"
+DELETE FROM
+    tags
+WHERE
+    user_id = ?
+AND tag_id NOT IN (SELECT tag_id FROM file_tags)
+"#;
+
+// tags of the files of a json array of file_unique_id
+pub static SELECT_FILES_TAGS: &str = r#"
+SELECT
+    file_tags.file_unique_id AS file_unique_id,
+    tags.name AS name
+FROM
+    file_tags
+    JOIN tags ON tags.tag_id = file_tags.tag_id
+WHERE
+    file_tags.file_unique_id IN (SELECT value FROM json_each(?))
+ORDER BY
+    tags.name
+"#;
+
+// tags of a user with their files that aren't deleted
+pub static SELECT_USER_TAGS: &str = r#"
+SELECT
+    tags.name AS name,
+    COUNT(files.file_unique_id) AS files
+FROM
+    tags
+    JOIN file_tags ON file_tags.tag_id = tags.tag_id
+    JOIN files ON files.file_unique_id = file_tags.file_unique_id AND files.deleted_at = 0
+WHERE
+    tags.user_id = ?
+GROUP BY
+    tags.name
+ORDER BY
+    tags.name
+"#;
+
+pub static SAVE_TASK: &str = r#"
+INSERT INTO pending_tasks(kind, file_id, key, error, add_time, update_time)
+VALUES
+  (
+    ?, 
+    ?, 
+    ?, 
+    ?, 
+    strftime('%s', 'now'), 
+    strftime('%s', 'now')
+  ) ON CONFLICT(kind, key) DO 
+UPDATE 
+SET 
+  error = excluded.error, 
+  update_time = excluded.update_time
+"#;
+
+pub static SELECT_TASKS: &str = r#"
+SELECT
+    id, kind, file_id, key, error, attempts
+FROM
+    pending_tasks
+WHERE
+    attempts < ?
+ORDER BY
+    id
+LIMIT ?
+"#;
+
+pub static FAIL_TASK: &str = r#"
+UPDATE
+    pending_tasks
+SET
+    error = ?, 
+    attempts = attempts + 1, 
+    update_time = strftime('%s', 'now')
+WHERE
+    id = ?
+"#;
+
+pub static DELETE_TASK: &str = r#"
+DELETE FROM
+    pending_tasks
+WHERE
+    id = ?
+"#;
+
+pub static SAVE_CONSENT: &str = r#"
+INSERT INTO users(user_id, consent_time, add_time, update_time)
+VALUES
+  (
+    ?, 
+    strftime('%s', 'now'), 
+    strftime('%s', 'now'), 
+    strftime('%s', 'now')
+  ) ON CONFLICT(user_id) DO 
+UPDATE 
+SET 
+  consent_time = excluded.consent_time, 
+  update_time = excluded.update_time
+"#;
+
+pub static COUNT_FILES: &str = r#"
+SELECT
+    COUNT(*) AS count
+FROM
+    files
+"#;
+
+pub static COUNT_USERS: &str = r#"
+SELECT
+    COUNT(*) AS count
+FROM
+    users
+"#;
+
+pub static SELECT_SETTING: &str = r#"
+SELECT
+    value
+FROM
+    settings
+WHERE
+    key = ?
+"#;
+
+pub static SAVE_SETTING: &str = r#"
+INSERT INTO settings(key, value, update_time)
+VALUES
+  (?, ?, strftime('%s', 'now')) ON CONFLICT(key) DO 
+UPDATE 
+SET 
+  value = excluded.value, 
+  update_time = excluded.update_time
+"#;
+
+pub static INCREMENT_COUNTER: &str = r#"
+INSERT INTO counters(name, value)
+VALUES
+  (?, 1) ON CONFLICT(name) DO 
+UPDATE 
+SET 
+  value = value + 1
+"#;
+
+pub static INCREMENT_DOWNLOADS: &str = r#"
+UPDATE
+    files
+SET
+    downloads = downloads + 1
+WHERE
+    file_unique_id = ?
+"#;
+
+// activity since a unix time, new users are uploaders whose first file is that recent
+pub static SELECT_DIGEST: &str = r#"
+SELECT
+    (SELECT COUNT(*) FROM events WHERE kind = 'upload' AND add_time >= ?) AS uploads,
+    (
+        SELECT COUNT(*) FROM (
+            SELECT user_id FROM files WHERE user_id != 0 GROUP BY user_id HAVING MIN(add_time) >= ?
+        )
+    ) AS new_users,
+    (SELECT IFNULL(SUM(file_size), 0) FROM files WHERE add_time >= ?) AS bytes
+"#;
+
+pub static SELECT_TOP_DOWNLOADS: &str = r#"
+SELECT
+    *
+FROM
+    files
+WHERE
+    downloads > 0
+AND deleted_at = 0
+ORDER BY
+    downloads DESC
+LIMIT ?
+"#;
+
+pub static SELECT_COUNTERS: &str = r#"
+SELECT
+    name, value
+FROM
+    counters
+"#;
+
+// appended in the same batch as the change it records, from the current row of the file
+pub static INSERT_EVENT: &str = r#"
+INSERT INTO events(
+  kind, file_id, file_unique_id, file_name, 
+  mime_type, add_time
+) 
+SELECT 
+  ?, 
+  file_id, 
+  file_unique_id, 
+  file_name, 
+  mime_type, 
+  strftime('%s', 'now') 
+FROM 
+  files 
+WHERE 
+  file_unique_id = ?
+"#;
+
+pub static SELECT_EVENTS: &str = r#"
+SELECT
+    seq, kind, file_id, file_unique_id, file_name, mime_type, add_time
+FROM
+    events
+WHERE
+    seq > ?
+ORDER BY
+    seq
+LIMIT ?
+"#;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileEvent {
+    pub seq: u64,
+    pub kind: String,
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub file_name: String,
+    pub mime_type: String,
+    pub add_time: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EventPage {
+    pub events: Vec<FileEvent>,
+    // pass as `since` to get the following events
+    pub next: u64,
+}
+
+// background work that failed and is retried by the cron and /admin/tasks/retry
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingTask {
+    pub id: u64,
+    pub kind: String,
+    pub file_id: String,
+    pub key: String,
+    pub error: String,
+    pub attempts: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Digest {
+    pub uploads: u64,
+    pub new_users: u64,
+    // size of the files added
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Album {
+    // a short id, the album is shared as /a/<album_id>
+    pub album_id: String,
+    pub user_id: u64,
+    // only unique per user
+    pub name: String,
+    pub add_time: i64,
+}
+
+impl Album {
+    pub fn url(&self, host: &str) -> String {
+        format!("https://{}/a/{}", host, self.album_id)
+    }
+}
+
+// files posted in or forwarded from the channel `chat_id` go to the album and get the tags
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelCollection {
+    pub chat_id: String,
+    pub album_id: String,
+    // comma separated
+    #[serde(default)]
+    pub tags: String,
+    #[serde(default)]
+    pub add_time: i64,
+}
+
+impl ChannelCollection {
+    pub fn tags(&self) -> Vec<String> {
+        self.tags
+            .split(',')
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Counter {
+    pub name: String,
+    pub value: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct File {
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub thumbnail_file_id: String,
+    pub thumbnail_file_unique_id: String,
+    pub message_id: i32,
+    pub user_id: u64,
+    pub file_name: String,
+    pub file_size: u64,
+    pub mime_type: String,
+    pub add_time: i64,
+    pub update_time: i64,
+    pub file_path: String,
+    #[serde(default)]
+    pub file_hash: String,
+    #[serde(default)]
+    pub password_hash: String,
+    #[serde(default)]
+    pub password_salt: String,
+    // telegram language code of the uploader, the only thing stored about them besides the user id
+    #[serde(default)]
+    pub upload_locale: String,
+    // filename of Content-Disposition instead of the telegram one
+    #[serde(default)]
+    pub download_name: String,
+    // json object of CUSTOM_HEADERS
+    #[serde(default)]
+    pub custom_headers: String,
+    // where a forwarded file was originally posted, people are never identified
+    #[serde(default)]
+    pub forward_chat_id: String,
+    #[serde(default)]
+    pub forward_message_id: i32,
+    #[serde(default)]
+    pub forward_from: String,
+    // chat of the message, 0 for files saved before it was recorded
+    #[serde(default)]
+    pub chat_id: i64,
+    // file_id of the file that replaced this one when the message was edited
+    #[serde(default)]
+    pub replaced_by: String,
+    // successful downloads, only counted with PROMETHEUS_METRICS
+    #[serde(default)]
+    pub downloads: u64,
+    // short id of the links, not a column, only set on uploads with SHORT_IDS
+    #[serde(default)]
+    pub short_id: String,
+    // from 0 to 1, None when the file wasn't checked
+    #[serde(default)]
+    pub moderation_score: Option<f64>,
+    // why the file isn't served anymore, empty for available files
+    #[serde(default)]
+    pub blocked: String,
+    // description of an image for screen readers, written by the caption model or /alt
+    #[serde(default)]
+    pub alt_text: String,
+    // unix time of /delete, 0 for files that weren't deleted. the row is removed after DELETE_GRACE_DAYS
+    #[serde(default)]
+    pub deleted_at: i64,
+}
+
+impl File {
+    pub fn is_protected(&self) -> bool {
+        !self.password_hash.is_empty()
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        !self.blocked.is_empty()
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at > 0
+    }
+
+    pub fn headers(&self) -> BTreeMap<String, String> {
+        serde_json::from_str::<BTreeMap<String, String>>(&self.custom_headers)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(k, v)| Some((custom_header(&k)?.to_string(), v)))
+            .collect()
+    }
+
+    pub fn ext(&self) -> String {
+        self.file_path
+            .rsplit('.')
+            .next()
+            .map(|e| format!(".{}", e))
+            .unwrap_or_default()
+    }
+
+    // photos have neither a mime type nor a name
+    pub fn is_image(&self) -> bool {
+        self.mime_type.starts_with("image/")
+            || (self.mime_type.is_empty() && self.file_name.is_empty())
+    }
+
+    pub fn url(&self, host: &str) -> String {
+        let id = if self.short_id.is_empty() {
+            &self.file_id
+        } else {
+            &self.short_id
+        };
+        format!("https://{}/{}/{}{}", host, path_prefix(), id, self.ext())
+    }
+
+    pub fn unique_url(&self, host: &str) -> String {
+        format!(
+            "https://{}/{}/{}{}",
+            host,
+            path_prefix(),
+            self.file_unique_id,
+            self.ext()
+        )
+    }
+
+    // png of the link for GET /qr/:file_id
+    pub fn qr_url(&self, host: &str) -> String {
+        format!("https://{}/qr/{}.png", host, self.file_unique_id)
+    }
+
+    pub fn with_message_id(mut self, message_id: i32) -> Self {
+        self.message_id = message_id;
+        self
+    }
+
+    pub fn with_chat_id(mut self, chat_id: i64) -> Self {
+        self.chat_id = chat_id;
+        self
+    }
+
+    pub fn with_user_id(mut self, user_id: u64) -> Self {
+        self.user_id = user_id;
+        self
+    }
+
+    pub fn with_file_path(mut self, file_path: String) -> Self {
+        self.file_path = file_path;
+        self
+    }
+
+    pub fn with_upload_locale(mut self, upload_locale: String) -> Self {
+        self.upload_locale = upload_locale;
+        self
+    }
+
+    // PRIVACY_MODE, the uploader can't be told from the row. the chat of a private message is the user
+    pub fn anonymize(&mut self) {
+        self.user_id = 0;
+        self.upload_locale = String::new();
+        self.chat_id = 0;
+    }
+
+    pub fn with_forward_origin(mut self, origin: Option<&MessageOrigin>) -> Self {
+        let (chat_id, message_id, from) = match origin {
+            Some(MessageOrigin::Channel(v)) => {
+                (v.chat.id.to_string(), v.message_id, chat_title(&v.chat))
+            }
+            Some(MessageOrigin::Chat(v)) => {
+                (v.sender_chat.id.to_string(), 0, chat_title(&v.sender_chat))
+            }
+            Some(MessageOrigin::User(_)) | Some(MessageOrigin::HiddenUser(_)) => {
+                (String::new(), 0, "a user".to_string())
+            }
+            None => return self,
+        };
+        self.forward_chat_id = chat_id;
+        self.forward_message_id = message_id;
+        self.forward_from = from;
+        self
+    }
+
+    // a t.me link to the original post of public channels
+    pub fn forward_url(&self) -> Option<String> {
+        let username = self.forward_from.strip_prefix('@')?;
+        if self.forward_message_id == 0 {
+            return None;
+        }
+        Some(format!(
+            "https://t.me/{}/{}",
+            username, self.forward_message_id
+        ))
+    }
+
+    pub fn with_file_hash(mut self, file_hash: String) -> Self {
+        self.file_hash = file_hash;
+        self
+    }
+
+    // `get_file_paths` resolves the (file_id, file_unique_id, file_size) of all attachments at once,
+    // returning their paths in the same order, empty for files too big to download
+    // a document, photo or video, the attachments from_message saves
+    pub fn has_files(msg: &Message) -> bool {
+        msg.document.is_some()
+            || msg.photo.as_ref().is_some_and(|v| !v.is_empty())
+            || msg.video.is_some()
+    }
+
+    pub async fn from_message<F, Fut>(
+        msg: Box<Message>,
+        get_file_paths: F,
+    ) -> Result<Vec<File>, Error>
+    where
+        F: FnOnce(Vec<(String, String, u64)>) -> Fut,
+        Fut: Future<Output = Result<Vec<String>, Error>>,
+    {
+        let (user_id, locale) = match msg.from {
+            Some(u) => (u.id, u.language_code.unwrap_or_default()),
+            None => (0, String::new()),
+        };
+        let msg_id = msg.message_id;
+        let chat_id = msg.chat.id;
+        let origin = msg.forward_origin.as_deref();
+
+        let mut files = Vec::new();
+        if let Some(doc) = &msg.document {
+            files.push(File::from(doc.deref()));
+        }
+        if let Some(photo) = msg.photo.as_ref().and_then(|v| v.last()) {
+            files.push(File::from(photo));
+        }
+        if let Some(video) = &msg.video {
+            files.push(File::from(video.deref()));
+        }
+
+        if files.is_empty() {
+            return Ok(files);
+        }
+
+        let ids = files
+            .iter()
+            .map(|f| (f.file_id.clone(), f.file_unique_id.clone(), f.file_size))
+            .collect::<Vec<_>>();
+        let paths = get_file_paths(ids).await?;
+        if paths.len() != files.len() {
+            return Err(Error::Internal(format!(
+                "resolved {} file paths for {} files",
+                paths.len(),
+                files.len()
+            )));
+        }
+
+        Ok(files
+            .into_iter()
+            .zip(paths)
+            .map(|(f, path)| {
+                f.with_message_id(msg_id)
+                    .with_chat_id(chat_id)
+                    .with_user_id(user_id)
+                    .with_upload_locale(locale.clone())
+                    .with_forward_origin(origin)
+                    .with_file_path(path)
+            })
+            .collect())
+    }
+}
+
+// @username of public chats, the title otherwise
+fn chat_title(chat: &Chat) -> String {
+    match (&chat.username, &chat.title) {
+        (Some(username), _) => format!("@{}", username),
+        (None, Some(title)) => title.clone(),
+        (None, None) => chat.id.to_string(),
+    }
+}
+
+impl From<&Video> for File {
+    fn from(v: &Video) -> Self {
+        let (thumbnail_file_id, thumbnail_file_unique_id) = match &v.thumbnail {
+            Some(t) => (&t.file_id, &t.file_unique_id),
+            None => (&String::new(), &String::new()),
+        };
+        File {
+            file_id: v.file_id.clone(),
+            file_unique_id: v.file_unique_id.clone(),
+            thumbnail_file_id: thumbnail_file_id.clone(),
+            thumbnail_file_unique_id: thumbnail_file_unique_id.clone(),
+            file_size: v.file_size.unwrap_or_default(),
+            mime_type: v.mime_type.clone().unwrap_or_default(),
+            file_name: v.file_name.clone().unwrap_or_default(),
+            add_time: 0,
+            update_time: 0,
+            message_id: 0,
+            user_id: 0,
+            file_path: "".to_string(),
+            file_hash: "".to_string(),
+            password_hash: "".to_string(),
+            password_salt: "".to_string(),
+            upload_locale: "".to_string(),
+            download_name: "".to_string(),
+            custom_headers: "".to_string(),
+            forward_chat_id: "".to_string(),
+            forward_message_id: 0,
+            forward_from: "".to_string(),
+            chat_id: 0,
+            replaced_by: "".to_string(),
+            downloads: 0,
+            short_id: String::new(),
+            moderation_score: None,
+            blocked: String::new(),
+            alt_text: String::new(),
+            deleted_at: 0,
+        }
+    }
+}
+
+impl From<&Document> for File {
+    fn from(value: &Document) -> Self {
+        let (thumbnail_file_id, thumbnail_file_unique_id) = match &value.thumbnail {
+            Some(t) => (&t.file_id, &t.file_unique_id),
+            None => (&String::new(), &String::new()),
+        };
+        File {
+            file_id: value.file_id.clone(),
+            file_unique_id: value.file_unique_id.clone(),
+            thumbnail_file_id: thumbnail_file_id.clone(),
+            thumbnail_file_unique_id: thumbnail_file_unique_id.clone(),
+            file_size: value.file_size.unwrap_or_default(),
+            mime_type: value.mime_type.clone().unwrap_or_default(),
+            file_name: value.file_name.clone().unwrap_or_default(),
+            add_time: 0,
+            update_time: 0,
+            message_id: 0,
+            user_id: 0,
+            file_path: "".to_string(),
+            file_hash: "".to_string(),
+            password_hash: "".to_string(),
+            password_salt: "".to_string(),
+            upload_locale: "".to_string(),
+            download_name: "".to_string(),
+            custom_headers: "".to_string(),
+            forward_chat_id: "".to_string(),
+            forward_message_id: 0,
+            forward_from: "".to_string(),
+            chat_id: 0,
+            replaced_by: "".to_string(),
+            downloads: 0,
+            short_id: String::new(),
+            moderation_score: None,
+            blocked: String::new(),
+            alt_text: String::new(),
+            deleted_at: 0,
+        }
+    }
+}
+
+impl From<&PhotoSize> for File {
+    fn from(value: &PhotoSize) -> Self {
+        File {
+            file_id: value.file_id.clone(),
+            file_unique_id: value.file_unique_id.clone(),
+            thumbnail_file_id: "".to_string(),
+            thumbnail_file_unique_id: "".to_string(),
+            file_size: value.file_size.unwrap_or_default(),
+            mime_type: "".to_string(),
+            file_name: "".to_string(),
+            add_time: 0,
             update_time: 0,
             message_id: 0,
             user_id: 0,
             file_path: "".to_string(),
+            file_hash: "".to_string(),
+            password_hash: "".to_string(),
+            password_salt: "".to_string(),
+            upload_locale: "".to_string(),
+            download_name: "".to_string(),
+            custom_headers: "".to_string(),
+            forward_chat_id: "".to_string(),
+            forward_message_id: 0,
+            forward_from: "".to_string(),
+            chat_id: 0,
+            replaced_by: "".to_string(),
+            downloads: 0,
+            short_id: String::new(),
+            moderation_score: None,
+            blocked: String::new(),
+            alt_text: String::new(),
+            deleted_at: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ListingField {
+    Name,
+    Size,
+    Date,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ListingSort {
+    pub field: ListingField,
+    pub descending: bool,
+}
+
+impl ListingSort {
+    // autoindex style `?sort=name|size|date&order=asc|desc`, newest first by default
+    pub fn parse(sort: Option<&str>, order: Option<&str>) -> Self {
+        let field = match sort {
+            Some("name") => ListingField::Name,
+            Some("size") => ListingField::Size,
+            _ => ListingField::Date,
+        };
+        let descending = match order {
+            Some("asc") => false,
+            Some("desc") => true,
+            _ => field == ListingField::Date,
+        };
+        ListingSort { field, descending }
+    }
+
+    // `sort=date&order=desc`, to keep the order in other links of a listing
+    pub fn query(&self) -> String {
+        let sort = match self.field {
+            ListingField::Name => "name",
+            ListingField::Size => "size",
+            ListingField::Date => "date",
+        };
+        let order = if self.descending { "desc" } else { "asc" };
+        format!("sort={}&order={}", sort, order)
+    }
+
+    // only ever one of these, the column can't be a bound parameter
+    fn column(&self) -> &'static str {
+        match self.field {
+            ListingField::Name => "file_name",
+            ListingField::Size => "file_size",
+            ListingField::Date => "add_time",
+        }
+    }
+}
+
+// canonical name of an allowed custom header
+pub fn custom_header(name: &str) -> Option<&'static str> {
+    CUSTOM_HEADERS
+        .iter()
+        .find(|h| h.eq_ignore_ascii_case(name))
+        .copied()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Usage {
+    // `image` of SELECT_USAGE_BY_TYPE or `2024-05-01` of SELECT_USAGE_BY_DAY
+    #[serde(default, alias = "type", alias = "day")]
+    pub key: String,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Uploader {
+    pub user_id: u64,
+    pub files: u64,
+    pub bytes: u64,
+    pub last_upload: i64,
+    pub banned_at: i64,
+}
+
+// filters of GET /admin/files
+#[derive(Clone, Debug, Default)]
+pub struct FileFilter {
+    pub user_id: Option<u64>,
+    // a type or its start, `image/`
+    pub mime_type: String,
+    // part of the file or download name
+    pub name: String,
+    // active, deleted or blocked, empty for all files
+    pub status: String,
+    // a tag of the uploader, empty for any file
+    pub tag: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileTag {
+    pub file_unique_id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TagCount {
+    pub name: String,
+    pub files: u64,
+}
+
+#[derive(Clone)]
+pub struct D1 {
+    db: Arc<D1Database>,
+}
+
+impl D1 {
+    pub fn new(db: Arc<D1Database>) -> D1 {
+        D1 { db }
+    }
+
+    fn event_statement(
+        &self,
+        kind: &str,
+        file_unique_id: &str,
+    ) -> Result<D1PreparedStatement, Error> {
+        Ok(self
+            .db
+            .prepare(INSERT_EVENT)
+            .bind(&[kind.into(), file_unique_id.into()])?)
+    }
+
+    fn save_statements(&self, files: &[File]) -> Result<Vec<D1PreparedStatement>, Error> {
+        let statement = self.db.prepare(INSERT_FILE);
+
+        let mut statements = vec![];
+
+        for f in files {
+            let values: Vec<JsValue> = vec![
+                (&f.file_id).into(),
+                (&f.file_unique_id).into(),
+                (&f.thumbnail_file_id).into(),
+                (&f.thumbnail_file_unique_id).into(),
+                f.message_id.into(),
+                // u64 will be converted to bigint in JS which is not supported by D1
+                // so we need to convert it to string first
+                f.user_id.to_string().into(),
+                (&f.file_name).into(),
+                // i64 will be converted to bigint in JS which is not supported by D1
+                // so we need to convert it to string first
+                f.file_size.to_string().into(),
+                (&f.mime_type).into(),
+                (&f.file_path).into(),
+                (&f.file_hash).into(),
+                (&f.upload_locale).into(),
+                (&f.forward_chat_id).into(),
+                f.forward_message_id.into(),
+                (&f.forward_from).into(),
+                // i64 will be converted to bigint too
+                f.chat_id.to_string().into(),
+            ];
+
+            statements.push(statement.clone().bind(&values)?);
+            statements.push(self.event_statement(EVENT_UPLOAD, &f.file_unique_id)?);
+        }
+
+        Ok(statements)
+    }
+
+    async fn count(&self, query: &str) -> Result<u64, Error> {
+        match self.db.prepare(query).first::<u64>(Some("count")).await {
+            Ok(v) => Ok(v.unwrap_or_default()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn find_file(&self, file_id: &str) -> Result<Option<File>, Error> {
+        Ok(self
+            .db
+            .prepare(SELECT_FILE)
+            .bind(&[file_id.into(), file_id.into()])?
+            .first::<File>(None)
+            .await?)
+    }
+}
+
+impl FileStore for D1 {
+    async fn init(&self) -> Result<(), Error> {
+        self.db.prepare(CREATE_TABLE).run().await?;
+        for m in MIGRATIONS.iter().copied() {
+            match self.db.prepare(m).run().await {
+                Ok(_) => {}
+                Err(worker::Error::D1(e)) if e.cause().contains("duplicate column name") => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    async fn save_file_path(&self, file_unique_id: &str, file_path: &str) -> Result<(), Error> {
+        self.db
+            .prepare(SAVE_FILE_PATH)
+            .bind(&[file_path.into(), file_unique_id.into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn save_password(
+        &self,
+        file_unique_id: &str,
+        password_hash: &str,
+        password_salt: &str,
+    ) -> Result<(), Error> {
+        let kind = if password_hash.is_empty() {
+            EVENT_UNPROTECT
+        } else {
+            EVENT_PROTECT
+        };
+
+        let statements = || -> Result<Vec<D1PreparedStatement>, Error> {
+            Ok(vec![
+                self.db.prepare(SAVE_PASSWORD).bind(&[
+                    password_hash.into(),
+                    password_salt.into(),
+                    file_unique_id.into(),
+                ])?,
+                self.event_statement(kind, file_unique_id)?,
+            ])
+        };
+
+        match self.db.batch(statements()?).await {
+            Ok(_) => Ok(()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                self.db.batch(statements()?).await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save_moderation(
+        &self,
+        file_unique_id: &str,
+        score: f64,
+        blocked: &str,
+    ) -> Result<(), Error> {
+        self.db
+            .prepare(SAVE_MODERATION)
+            .bind(&[
+                score.into(),
+                blocked.into(),
+                blocked.into(),
+                file_unique_id.into(),
+            ])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    // an empty reason serves the file again
+    async fn save_blocked(&self, file_unique_id: &str, blocked: &str) -> Result<(), Error> {
+        self.db
+            .prepare(SAVE_BLOCKED)
+            .bind(&[blocked.into(), file_unique_id.into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    // `generated` keeps alt text the uploader already set
+    async fn save_alt_text(
+        &self,
+        file_unique_id: &str,
+        alt_text: &str,
+        generated: bool,
+    ) -> Result<(), Error> {
+        self.db
+            .prepare(if generated {
+                SAVE_GENERATED_ALT_TEXT
+            } else {
+                SAVE_ALT_TEXT
+            })
+            .bind(&[alt_text.into(), file_unique_id.into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn save_download_options(
+        &self,
+        file_unique_id: &str,
+        download_name: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<(), Error> {
+        let headers = if headers.is_empty() {
+            String::new()
+        } else {
+            serde_json::to_string(headers).map_err(|e| Error::Internal(e.to_string()))?
+        };
+
+        let statements = || -> Result<Vec<D1PreparedStatement>, Error> {
+            Ok(vec![
+                self.db.prepare(SAVE_DOWNLOAD_OPTIONS).bind(&[
+                    download_name.into(),
+                    (&headers).into(),
+                    file_unique_id.into(),
+                ])?,
+                self.event_statement(EVENT_RENAME, file_unique_id)?,
+            ])
+        };
+
+        match self.db.batch(statements()?).await {
+            Ok(_) => Ok(()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                self.db.batch(statements()?).await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // tombstones the file, it can be restored until the scheduled job removes it
+    async fn delete_file(&self, file_unique_id: &str) -> Result<(), Error> {
+        let now = Date::now().as_millis() / 1000;
+        self.db
+            .batch(vec![
+                self.db
+                    .prepare(SAVE_DELETED)
+                    .bind(&[now.to_string().into(), file_unique_id.into()])?,
+                self.event_statement(EVENT_DELETE, file_unique_id)?,
+            ])
+            .await?;
+        Ok(())
+    }
+
+    // tombstones every file in one batch, either all of them are deleted or none
+    async fn delete_files(&self, file_unique_ids: &[String]) -> Result<(), Error> {
+        if file_unique_ids.is_empty() {
+            return Ok(());
+        }
+
+        let now = Date::now().as_millis() / 1000;
+        let mut statements = vec![];
+        for id in file_unique_ids {
+            statements.push(
+                self.db
+                    .prepare(SAVE_DELETED)
+                    .bind(&[now.to_string().into(), id.into()])?,
+            );
+            statements.push(self.event_statement(EVENT_DELETE, id)?);
+        }
+        self.db.batch(statements).await?;
+        Ok(())
+    }
+
+    async fn undelete_file(&self, file_unique_id: &str) -> Result<(), Error> {
+        self.db
+            .batch(vec![
+                self.db
+                    .prepare(SAVE_DELETED)
+                    .bind(&[0.into(), file_unique_id.into()])?,
+                self.event_statement(EVENT_UNDELETE, file_unique_id)?,
+            ])
+            .await?;
+        Ok(())
+    }
+
+    // files deleted at or before `before`, a unix time
+    async fn expired_files(&self, before: i64, limit: u32) -> Result<Vec<File>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_EXPIRED_FILES)
+            .bind(&[before.to_string().into(), limit.into()])?
+            .all()
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.results::<File>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such column") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // removes the row for good, the stored copies are deleted by the caller
+    async fn purge_file(&self, file_unique_id: &str) -> Result<(), Error> {
+        let statements = || -> Result<Vec<D1PreparedStatement>, Error> {
+            Ok([
+                DELETE_FILE_ALIASES,
+                DELETE_FILE_ALBUM_ENTRIES,
+                DELETE_FILE_TAG_ENTRIES,
+                DELETE_FILE,
+            ]
+            .into_iter()
+            .map(|q| self.db.prepare(q).bind(&[file_unique_id.into()]))
+            .collect::<Result<Vec<_>, _>>()?)
+        };
+
+        match self.db.batch(statements()?).await {
+            Ok(_) => Ok(()),
+            // no aliases or albums were ever made
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                self.db.batch(statements()?).await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // marks the file as replaced by an edit of its message, its links redirect to the new file
+    async fn replace_file(&self, file_unique_id: &str, replaced_by: &str) -> Result<(), Error> {
+        self.db
+            .batch(vec![
+                self.db
+                    .prepare(REPLACE_FILE)
+                    .bind(&[replaced_by.into(), file_unique_id.into()])?,
+                self.event_statement(EVENT_REPLACE, file_unique_id)?,
+            ])
+            .await?;
+        Ok(())
+    }
+
+    async fn message_files(&self, chat_id: i64, message_id: i32) -> Result<Vec<File>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_MESSAGE_FILES)
+            .bind(&[chat_id.to_string().into(), message_id.into()])?
+            .all()
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.results::<File>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn events(&self, since: u64, limit: u32) -> Result<Vec<FileEvent>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_EVENTS)
+            .bind(&[since.to_string().into(), limit.into()])?
+            .all()
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.results::<FileEvent>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save(&self, files: &[File]) -> Result<(), Error> {
+        if files.is_empty() {
+            return Ok(());
+        }
+        // columns added since the table was created come with `init`, uploads keep working after an upgrade
+        match self.db.batch(self.save_statements(files)?).await {
+            Ok(_) => Ok(()),
+            Err(worker::Error::D1(e))
+                if e.cause().contains("no such table")
+                    || e.cause().contains("no such column")
+                    || e.cause().contains("has no column named") =>
+            {
+                self.init().await?;
+                self.db.batch(self.save_statements(files)?).await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        self.db.prepare("SELECT 1").run().await?;
+        Ok(())
+    }
+
+    async fn count_files(&self) -> Result<u64, Error> {
+        self.count(COUNT_FILES).await
+    }
+
+    async fn count_users(&self) -> Result<u64, Error> {
+        self.count(COUNT_USERS).await
+    }
+
+    // `downloaded` is the file_unique_id of a successful download
+    async fn increment(&self, names: &[&str], downloaded: Option<&str>) -> Result<(), Error> {
+        if names.is_empty() && downloaded.is_none() {
+            return Ok(());
+        }
+
+        let statements = || -> Result<Vec<D1PreparedStatement>, Error> {
+            let mut statements = names
+                .iter()
+                .map(|name| self.db.prepare(INCREMENT_COUNTER).bind(&[(*name).into()]))
+                .collect::<Result<Vec<_>, _>>()?;
+            if let Some(file_unique_id) = downloaded {
+                statements.push(
+                    self.db
+                        .prepare(INCREMENT_DOWNLOADS)
+                        .bind(&[file_unique_id.into()])?,
+                );
+            }
+            Ok(statements)
+        };
+
+        match self.db.batch(statements()?).await {
+            Ok(_) => Ok(()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                self.db.batch(statements()?).await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn digest(&self, since: u64) -> Result<Digest, Error> {
+        let since = since.to_string();
+        let result = self
+            .db
+            .prepare(SELECT_DIGEST)
+            .bind(&[(&since).into(), (&since).into(), (&since).into()])?
+            .first::<Digest>(None)
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.unwrap_or_default()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                Ok(Digest::default())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn top_downloads(&self, limit: u32) -> Result<Vec<File>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_TOP_DOWNLOADS)
+            .bind(&[limit.into()])?
+            .all()
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.results::<File>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn counters(&self) -> Result<Vec<Counter>, Error> {
+        match self.db.prepare(SELECT_COUNTERS).all().await {
+            Ok(v) => Ok(v.results::<Counter>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save_task(
+        &self,
+        kind: &str,
+        file_id: &str,
+        key: &str,
+        error: &str,
+    ) -> Result<(), Error> {
+        let statement = self.db.prepare(SAVE_TASK).bind(&[
+            kind.into(),
+            file_id.into(),
+            key.into(),
+            error.into(),
+        ])?;
+
+        match statement.run().await {
+            Ok(_) => Ok(()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                statement.run().await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // tasks that were tried less than `max_attempts` times
+    async fn pending_tasks(
+        &self,
+        max_attempts: u32,
+        limit: u32,
+    ) -> Result<Vec<PendingTask>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_TASKS)
+            .bind(&[max_attempts.into(), limit.into()])?
+            .all()
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.results::<PendingTask>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn fail_task(&self, id: u64, error: &str) -> Result<(), Error> {
+        self.db
+            .prepare(FAIL_TASK)
+            .bind(&[error.into(), id.to_string().into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_task(&self, id: u64) -> Result<(), Error> {
+        self.db
+            .prepare(DELETE_TASK)
+            .bind(&[id.to_string().into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn get_setting(&self, key: &str) -> Result<String, Error> {
+        let value = self
+            .db
+            .prepare(SELECT_SETTING)
+            .bind(&[key.into()])?
+            .first::<String>(Some("value"))
+            .await;
+
+        match value {
+            Ok(v) => Ok(v.unwrap_or_default()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(String::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn set_setting(&self, key: &str, value: &str) -> Result<(), Error> {
+        let statement = self
+            .db
+            .prepare(SAVE_SETTING)
+            .bind(&[key.into(), value.into()])?;
+
+        match statement.run().await {
+            Ok(_) => Ok(()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                statement.run().await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn is_banned(&self, user_id: u64) -> Result<bool, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_BANNED_USER)
+            .bind(&[user_id.to_string().into()])?
+            .first::<i64>(Some("user_id"))
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.is_some()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // false when the user was banned already
+    async fn ban_user(&self, user_id: u64) -> Result<bool, Error> {
+        let statement = self
+            .db
+            .prepare(INSERT_BANNED_USER)
+            .bind(&[user_id.to_string().into()])?;
+
+        let result = match statement.run().await {
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                statement.run().await
+            }
+            v => v,
+        };
+
+        Ok(changes(&result?) > 0)
+    }
+
+    // false when the user wasn't banned
+    async fn unban_user(&self, user_id: u64) -> Result<bool, Error> {
+        let result = self
+            .db
+            .prepare(DELETE_BANNED_USER)
+            .bind(&[user_id.to_string().into()])?
+            .run()
+            .await;
+
+        match result {
+            Ok(v) => Ok(changes(&v) > 0),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn user_files(&self, user_id: u64) -> Result<Vec<File>, Error> {
+        Ok(self
+            .db
+            .prepare(SELECT_USER_FILES)
+            .bind(&[user_id.to_string().into()])?
+            .all()
+            .await?
+            .results::<File>()?)
+    }
+
+    async fn count_user_albums(&self, user_id: u64) -> Result<u64, Error> {
+        let result = self
+            .db
+            .prepare(COUNT_USER_ALBUMS)
+            .bind(&[user_id.to_string().into()])?
+            .first::<u64>(Some("count"))
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.unwrap_or_default()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // removes the rows of FORGET_USER in one batch, so nothing is left half deleted
+    async fn forget_user(&self, user_id: u64) -> Result<(), Error> {
+        let user_id = user_id.to_string();
+        let statements = || -> Result<Vec<D1PreparedStatement>, Error> {
+            Ok(FORGET_USER
+                .iter()
+                .map(|q| {
+                    let params = vec![JsValue::from(user_id.as_str()); q.matches('?').count()];
+                    self.db.prepare(*q).bind(&params)
+                })
+                .collect::<Result<Vec<_>, _>>()?)
+        };
+
+        match self.db.batch(statements()?).await {
+            Ok(_) => Ok(()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                self.db.batch(statements()?).await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
         }
     }
-}
 
-#[derive(Clone)]
-pub struct D1 {
-    db: Arc<D1Database>,
-}
+    // all rows including deleted and blocked files, ordered by file_unique_id
+    async fn files_after(&self, after: &str, limit: u32) -> Result<Vec<File>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_FILES_AFTER)
+            .bind(&[after.into(), limit.into()])?
+            .all()
+            .await;
 
-impl D1 {
-    pub fn new(db: Arc<D1Database>) -> D1 {
-        D1 { db }
+        match result {
+            Ok(v) => Ok(v.results::<File>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    pub async fn init(&self) -> Result<(), Error> {
-        self.db.prepare(CREATE_TABLE).run().await?;
-        Ok(())
+    // the number of files inserted, the others were known already
+    async fn import_files(&self, files: &[File]) -> Result<usize, Error> {
+        if files.is_empty() {
+            return Ok(0);
+        }
+        let statements = || -> Result<Vec<D1PreparedStatement>, Error> {
+            let statement = self.db.prepare(IMPORT_FILE);
+            files
+                .iter()
+                .map(|f| {
+                    let values: Vec<JsValue> = vec![
+                        (&f.file_id).into(),
+                        (&f.file_unique_id).into(),
+                        (&f.thumbnail_file_id).into(),
+                        (&f.thumbnail_file_unique_id).into(),
+                        f.message_id.into(),
+                        f.user_id.to_string().into(),
+                        (&f.file_name).into(),
+                        f.file_size.to_string().into(),
+                        (&f.mime_type).into(),
+                        f.add_time.to_string().into(),
+                        f.update_time.to_string().into(),
+                        (&f.file_path).into(),
+                        (&f.file_hash).into(),
+                        (&f.password_hash).into(),
+                        (&f.password_salt).into(),
+                        (&f.upload_locale).into(),
+                        (&f.download_name).into(),
+                        (&f.custom_headers).into(),
+                        (&f.forward_chat_id).into(),
+                        f.forward_message_id.into(),
+                        (&f.forward_from).into(),
+                        f.chat_id.to_string().into(),
+                        (&f.replaced_by).into(),
+                        f.downloads.to_string().into(),
+                        f.moderation_score.map_or(JsValue::NULL, JsValue::from),
+                        (&f.blocked).into(),
+                        (&f.alt_text).into(),
+                        f.deleted_at.to_string().into(),
+                    ];
+                    Ok(statement.clone().bind(&values)?)
+                })
+                .collect()
+        };
+
+        let results = match self.db.batch(statements()?).await {
+            Ok(v) => v,
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                self.db.batch(statements()?).await?
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(results.iter().map(changes).sum())
+    }
+
+    async fn has_consented(&self, user_id: u64) -> Result<bool, Error> {
+        let consent_time = self
+            .db
+            .prepare(SELECT_CONSENT)
+            .bind(&[user_id.to_string().into()])?
+            .first::<i64>(Some("consent_time"))
+            .await;
+
+        match consent_time {
+            Ok(v) => Ok(v.unwrap_or_default() > 0),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save_consent(&self, user_id: u64) -> Result<(), Error> {
+        let statement = self
+            .db
+            .prepare(SAVE_CONSENT)
+            .bind(&[user_id.to_string().into()])?;
+
+        match statement.run().await {
+            Ok(_) => Ok(()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                statement.run().await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_namespace(&self, user_id: u64) -> Result<String, Error> {
+        let namespace = self
+            .db
+            .prepare(SELECT_NAMESPACE)
+            .bind(&[user_id.to_string().into()])?
+            .first::<String>(Some("namespace"))
+            .await;
+
+        match namespace {
+            Ok(v) => Ok(v.unwrap_or_default()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(String::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // false when another user already took the namespace
+    async fn save_namespace(&self, user_id: u64, namespace: &str) -> Result<bool, Error> {
+        let statement = self
+            .db
+            .prepare(SAVE_NAMESPACE)
+            .bind(&[user_id.to_string().into(), namespace.into()])?;
+
+        let result = match statement.run().await {
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                statement.run().await
+            }
+            v => v,
+        };
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(worker::Error::D1(e)) if e.cause().contains("UNIQUE constraint failed") => {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // the user chose to publish the GPS location of their photos
+    async fn shows_location(&self, user_id: u64) -> Result<bool, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_SHOW_LOCATION)
+            .bind(&[user_id.to_string().into()])?
+            .first::<i64>(Some("show_location"))
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.unwrap_or_default() != 0),
+            Err(worker::Error::D1(e))
+                if e.cause().contains("no such table") || e.cause().contains("no such column") =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save_show_location(&self, user_id: u64, show: bool) -> Result<(), Error> {
+        let statement = self
+            .db
+            .prepare(SAVE_SHOW_LOCATION)
+            .bind(&[user_id.to_string().into(), (show as i32).into()])?;
+
+        match statement.run().await {
+            Ok(_) => Ok(()),
+            Err(worker::Error::D1(e))
+                if e.cause().contains("no such table") || e.cause().contains("no such column") =>
+            {
+                self.init().await?;
+                statement.run().await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn namespace_files(
+        &self,
+        namespace: &str,
+        sort: &ListingSort,
+        limit: u32,
+    ) -> Result<Vec<File>, Error> {
+        let query = format!(
+            "{} ORDER BY files.{} {} LIMIT ?",
+            SELECT_NAMESPACE_FILES,
+            sort.column(),
+            if sort.descending { "DESC" } else { "ASC" }
+        );
+
+        let result = self
+            .db
+            .prepare(&query)
+            .bind(&[namespace.into(), limit.into()])?
+            .all()
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.results::<File>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn recent_public_files(&self, limit: u32) -> Result<Vec<File>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_RECENT_PUBLIC_FILES)
+            .bind(&[limit.into()])?
+            .all()
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.results::<File>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // newest files of a user whose name contains `query`, all of them for an empty query.
+    // only files with `tag` unless it's empty
+    async fn search_user_files(
+        &self,
+        user_id: u64,
+        query: &str,
+        tag: &str,
+        limit: u32,
+    ) -> Result<Vec<File>, Error> {
+        let escaped = query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let pattern = format!("%{}%", escaped);
+
+        let mut params: Vec<JsValue> = vec![
+            user_id.to_string().into(),
+            (&pattern).into(),
+            (&pattern).into(),
+        ];
+        let mut query = SEARCH_USER_FILES.to_string();
+        if !tag.is_empty() {
+            query.push_str(TAGGED_FILE);
+            params.push(tag.into());
+        }
+        query.push_str("ORDER BY add_time DESC LIMIT ?");
+        params.push(limit.into());
+
+        let result = self.db.prepare(&query).bind(&params)?.all().await;
+
+        match result {
+            Ok(v) => Ok(v.results::<File>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // by file_id, file_unique_id or alias
+    async fn find(&self, file_id: &str) -> Result<Option<File>, Error> {
+        if let Some(f) = self.find_file(file_id).await? {
+            return Ok(Some(f));
+        }
+        match self.find_alias(file_id).await? {
+            Some(v) => self.find_file(&v).await,
+            None => Ok(None),
+        }
+    }
+
+    // file_unique_id of a short id or slug
+    async fn find_alias(&self, alias: &str) -> Result<Option<String>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_ALIAS_FILE)
+            .bind(&[alias.into()])?
+            .first::<String>(Some("file_unique_id"))
+            .await;
+
+        match result {
+            Ok(v) => Ok(v),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // false when the alias is taken
+    async fn save_alias(
+        &self,
+        alias: &str,
+        file_unique_id: &str,
+        kind: &str,
+    ) -> Result<bool, Error> {
+        let statement = self.db.prepare(INSERT_ALIAS).bind(&[
+            alias.into(),
+            file_unique_id.into(),
+            kind.into(),
+        ])?;
+
+        let result = match statement.run().await {
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                statement.run().await
+            }
+            v => v,
+        };
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(worker::Error::D1(e)) if e.cause().contains("UNIQUE constraint failed") => {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // the short id of a file, created on first use
+    async fn short_id(&self, file_unique_id: &str) -> Result<String, Error> {
+        let existing = self
+            .db
+            .prepare(SELECT_SHORT_ID)
+            .bind(&[file_unique_id.into()])?
+            .first::<String>(Some("alias"))
+            .await;
+        match existing {
+            Ok(Some(v)) => return Ok(v),
+            Ok(None) => {}
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        for _ in 0..SHORT_ID_ATTEMPTS {
+            let id = random_short_id()?;
+            if self.save_alias(&id, file_unique_id, ALIAS_SHORT).await? {
+                return Ok(id);
+            }
+        }
+        Err(Error::Internal("no free short id found".into()))
+    }
+
+    // None when the user already has an album with this name
+    async fn create_album(&self, user_id: u64, name: &str) -> Result<Option<Album>, Error> {
+        if self.find_album(user_id, name).await?.is_some() {
+            return Ok(None);
+        }
+
+        for _ in 0..SHORT_ID_ATTEMPTS {
+            let album_id = random_short_id()?;
+            let statement = self.db.prepare(INSERT_ALBUM).bind(&[
+                album_id.clone().into(),
+                user_id.to_string().into(),
+                name.into(),
+            ])?;
+
+            let result = match statement.run().await {
+                Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                    self.init().await?;
+                    statement.run().await
+                }
+                v => v,
+            };
+
+            match result {
+                Ok(_) => return self.album(&album_id).await,
+                // the id was taken, or the same name was created in between
+                Err(worker::Error::D1(e)) if e.cause().contains("UNIQUE constraint failed") => {
+                    if self.find_album(user_id, name).await?.is_some() {
+                        return Ok(None);
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(Error::Internal("no free album id found".into()))
+    }
+
+    async fn album(&self, album_id: &str) -> Result<Option<Album>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_ALBUM)
+            .bind(&[album_id.into()])?
+            .first::<Album>(None)
+            .await;
+
+        match result {
+            Ok(v) => Ok(v),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    pub async fn save_file_path(
+    async fn find_album(&self, user_id: u64, name: &str) -> Result<Option<Album>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_USER_ALBUM)
+            .bind(&[user_id.to_string().into(), name.into()])?
+            .first::<Album>(None)
+            .await;
+
+        match result {
+            Ok(v) => Ok(v),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // adding a file twice keeps its place
+    async fn add_album_files(
         &self,
-        file_unique_id: &String,
-        file_path: &String,
+        album_id: &str,
+        file_unique_ids: &[String],
     ) -> Result<(), Error> {
-        self.db
-            .prepare(SAVE_FILE_PATH)
-            .bind(&vec![file_path.into(), file_unique_id.into()])?
-            .run()
-            .await?;
+        let statements = file_unique_ids
+            .iter()
+            .map(|id| {
+                self.db
+                    .prepare(INSERT_ALBUM_FILE)
+                    .bind(&[album_id.into(), id.into()])
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.db.batch(statements).await?;
         Ok(())
     }
 
-    fn save_statements(&self, files: &Vec<File>) -> Result<Vec<D1PreparedStatement>, Error> {
-        let statement = self.db.prepare(INSERT_FILE);
+    async fn album_files(&self, album_id: &str, limit: u32) -> Result<Vec<File>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_ALBUM_FILES)
+            .bind(&[album_id.into(), limit.into()])?
+            .all()
+            .await;
 
-        let mut statements = vec![];
+        match result {
+            Ok(v) => Ok(v.results::<File>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
 
-        for f in files {
-            let values: Vec<JsValue> = vec![
-                (&f.file_id).into(),
-                (&f.file_unique_id).into(),
-                (&f.thumbnail_file_id).into(),
-                (&f.thumbnail_file_unique_id).into(),
-                f.message_id.into(),
-                // u64 will be converted to bigint in JS which is not supported by D1
-                // so we need to convert it to string first
-                f.user_id.to_string().into(),
-                (&f.file_name).into(),
-                // i64 will be converted to bigint in JS which is not supported by D1
-                // so we need to convert it to string first
-                f.file_size.to_string().into(),
-                (&f.mime_type).into(),
-                (&f.file_path).into(),
-            ];
+    async fn save_channel_collection(&self, collection: &ChannelCollection) -> Result<(), Error> {
+        let statement = || {
+            self.db.prepare(UPSERT_CHANNEL_COLLECTION).bind(&[
+                collection.chat_id.as_str().into(),
+                collection.album_id.as_str().into(),
+                collection.tags.as_str().into(),
+            ])
+        };
 
-            statements.push(statement.clone().bind(&values)?);
+        match statement()?.run().await {
+            Ok(_) => Ok(()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                statement()?.run().await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
         }
+    }
 
-        Ok(statements)
+    async fn channel_collection(&self, chat_id: &str) -> Result<Option<ChannelCollection>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_CHANNEL_COLLECTION)
+            .bind(&[chat_id.into()])?
+            .first::<ChannelCollection>(None)
+            .await;
+
+        match result {
+            Ok(v) => Ok(v),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    pub async fn save(&self, files: &Vec<File>) -> Result<(), Error> {
-        if files.is_empty() {
-            return Ok(());
+    async fn channel_collections(&self) -> Result<Vec<ChannelCollection>, Error> {
+        let result = self.db.prepare(SELECT_CHANNEL_COLLECTIONS).all().await;
+
+        match result {
+            Ok(v) => Ok(v.results::<ChannelCollection>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
         }
-        match self.db.batch(self.save_statements(files)?).await {
+    }
+
+    // false when the channel had no album
+    async fn delete_channel_collection(&self, chat_id: &str) -> Result<bool, Error> {
+        let result = self
+            .db
+            .prepare(DELETE_CHANNEL_COLLECTION)
+            .bind(&[chat_id.into()])?
+            .run()
+            .await;
+
+        match result {
+            Ok(v) => Ok(changes(&v) > 0),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save_report(&self, file_unique_id: &str, reason: &str) -> Result<i64, Error> {
+        let statement = self
+            .db
+            .prepare(INSERT_REPORT)
+            .bind(&[file_unique_id.into(), reason.into()])?;
+
+        let result = match statement.first::<i64>(Some("report_id")).await {
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                statement.first::<i64>(Some("report_id")).await
+            }
+            v => v,
+        };
+
+        result?.ok_or(Error::Internal("report id is missing".into()))
+    }
+
+    async fn close_reports(&self, file_unique_id: &str, status: &str) -> Result<(), Error> {
+        let result = self
+            .db
+            .prepare(CLOSE_REPORTS)
+            .bind(&[status.into(), file_unique_id.into()])?
+            .run()
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn filtered_files(
+        &self,
+        filter: &FileFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<File>, Error> {
+        let user_id = filter.user_id.map(|v| v.to_string()).unwrap_or_default();
+        let mut params: Vec<JsValue> = vec![
+            user_id.as_str().into(),
+            user_id.as_str().into(),
+            filter.mime_type.as_str().into(),
+            filter.mime_type.as_str().into(),
+            filter.name.as_str().into(),
+            filter.name.as_str().into(),
+            filter.name.as_str().into(),
+            filter.status.as_str().into(),
+        ];
+        let mut query = SELECT_FILTERED_FILES.to_string();
+        if !filter.tag.is_empty() {
+            query.push_str(TAGGED_FILE);
+            params.push(filter.tag.as_str().into());
+        }
+        query.push_str("ORDER BY add_time DESC LIMIT ? OFFSET ?");
+        params.push(limit.into());
+        params.push(offset.into());
+
+        let result = self.db.prepare(&query).bind(&params)?.all().await;
+        match result {
+            Ok(v) => Ok(v.results::<File>()?),
+            // nothing was ever tagged
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // adds and removes tags of the file, the tags belong to its uploader
+    async fn save_tags(&self, file: &File, add: &[String], remove: &[String]) -> Result<(), Error> {
+        let user_id = file.user_id.to_string();
+        let statements = || -> Result<Vec<D1PreparedStatement>, Error> {
+            let mut statements = vec![];
+            for name in add {
+                statements.push(
+                    self.db
+                        .prepare(INSERT_TAG)
+                        .bind(&[user_id.as_str().into(), name.into()])?,
+                );
+                statements.push(self.db.prepare(INSERT_FILE_TAG).bind(&[
+                    (&file.file_unique_id).into(),
+                    user_id.as_str().into(),
+                    name.into(),
+                ])?);
+            }
+            for name in remove {
+                statements.push(self.db.prepare(DELETE_FILE_TAG).bind(&[
+                    (&file.file_unique_id).into(),
+                    user_id.as_str().into(),
+                    name.into(),
+                ])?);
+            }
+            if !remove.is_empty() {
+                statements.push(
+                    self.db
+                        .prepare(DELETE_UNUSED_TAGS)
+                        .bind(&[user_id.as_str().into()])?,
+                );
+            }
+            Ok(statements)
+        };
+
+        match self.db.batch(statements()?).await {
             Ok(_) => Ok(()),
             Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
                 self.init().await?;
-                self.db.batch(self.save_statements(files)?).await?;
+                self.db.batch(statements()?).await?;
                 Ok(())
             }
-            Err(e) => Err(Error(e.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // names of the tags of each file by file_unique_id, files without tags are left out
+    async fn files_tags(&self, files: &[File]) -> Result<HashMap<String, Vec<String>>, Error> {
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        if files.is_empty() {
+            return Ok(tags);
+        }
+
+        let ids = serde_json::to_string(
+            &files
+                .iter()
+                .map(|f| f.file_unique_id.as_str())
+                .collect::<Vec<_>>(),
+        )
+        .map_err(|e| Error::Internal(e.to_string()))?;
+        let result = self
+            .db
+            .prepare(SELECT_FILES_TAGS)
+            .bind(&[ids.into()])?
+            .all()
+            .await;
+
+        let rows = match result {
+            Ok(v) => v.results::<FileTag>()?,
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        for row in rows {
+            tags.entry(row.file_unique_id).or_default().push(row.name);
+        }
+        Ok(tags)
+    }
+
+    async fn user_tags(&self, user_id: u64) -> Result<Vec<TagCount>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_USER_TAGS)
+            .bind(&[user_id.to_string().into()])?
+            .all()
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.results::<TagCount>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn usage_by_type(&self) -> Result<Vec<Usage>, Error> {
+        Ok(self
+            .db
+            .prepare(SELECT_USAGE_BY_TYPE)
+            .all()
+            .await?
+            .results::<Usage>()?)
+    }
+
+    // uploads per day since a unix time
+    async fn usage_by_day(&self, since: u64) -> Result<Vec<Usage>, Error> {
+        Ok(self
+            .db
+            .prepare(SELECT_USAGE_BY_DAY)
+            .bind(&[since.to_string().into()])?
+            .all()
+            .await?
+            .results::<Usage>()?)
+    }
+
+    async fn uploaders(
+        &self,
+        user_id: Option<u64>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Uploader>, Error> {
+        let user_id = user_id.map(|v| v.to_string()).unwrap_or_default();
+        let result = self
+            .db
+            .prepare(SELECT_UPLOADERS)
+            .bind(&[
+                user_id.as_str().into(),
+                user_id.as_str().into(),
+                limit.into(),
+                offset.into(),
+            ])?
+            .all()
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.results::<Uploader>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save_api_key(
+        &self,
+        key_id: &str,
+        key_hash: &str,
+        user_id: u64,
+        scopes: &str,
+    ) -> Result<(), Error> {
+        let statement = self.db.prepare(INSERT_API_KEY).bind(&[
+            key_id.into(),
+            key_hash.into(),
+            user_id.to_string().into(),
+            scopes.into(),
+        ])?;
+
+        let result = match statement.run().await {
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => {
+                self.init().await?;
+                statement.run().await
+            }
+            v => v,
+        };
+
+        result?;
+        Ok(())
+    }
+
+    // the key stored under the hash of a bearer token
+    async fn find_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_API_KEY)
+            .bind(&[key_hash.into()])?
+            .first::<ApiKey>(None)
+            .await;
+
+        match result {
+            Ok(v) => Ok(v),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn user_api_keys(&self, user_id: u64) -> Result<Vec<ApiKey>, Error> {
+        let result = self
+            .db
+            .prepare(SELECT_USER_API_KEYS)
+            .bind(&[user_id.to_string().into()])?
+            .all()
+            .await;
+
+        match result {
+            Ok(v) => Ok(v.results::<ApiKey>()?),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(vec![]),
+            Err(e) => Err(e.into()),
         }
     }
 
-    pub async fn get(&self, file_id: &String) -> Result<File, Error> {
+    // false when the user has no key with this id
+    async fn delete_api_key(&self, key_id: &str, user_id: u64) -> Result<bool, Error> {
+        let result = self
+            .db
+            .prepare(DELETE_API_KEY)
+            .bind(&[key_id.into(), user_id.to_string().into()])?
+            .run()
+            .await;
+
+        match result {
+            Ok(v) => Ok(changes(&v) > 0),
+            Err(worker::Error::D1(e)) if e.cause().contains("no such table") => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn touch_api_key(&self, key_id: &str) -> Result<(), Error> {
         self.db
-            .prepare(SELECT_FILE)
-            .bind(&vec![file_id.into(), file_id.into()])?
+            .prepare(TOUCH_API_KEY)
+            .bind(&[key_id.into()])?
+            .run()
+            .await?;
+        Ok(())
+    }
+
+    async fn find_by_hash(&self, file_hash: &str) -> Result<Option<File>, Error> {
+        Ok(self
+            .db
+            .prepare(SELECT_FILE_BY_HASH)
+            .bind(&[file_hash.into()])?
             .first::<File>(None)
-            .await?
-            .ok_or(Error("File not found".to_string()))
+            .await?)
     }
 }
+
+// rows written by a statement
+fn changes(result: &D1Result) -> usize {
+    result
+        .meta()
+        .ok()
+        .flatten()
+        .and_then(|m| m.changes)
+        .unwrap_or_default()
+}
+
+pub(crate) fn random_short_id() -> Result<String, Error> {
+    let mut bytes = [0u8; SHORT_ID_LENGTH];
+    getrandom::getrandom(&mut bytes).map_err(|e| Error::Internal(e.to_string()))?;
+    Ok(bytes
+        .iter()
+        .map(|b| BASE62[*b as usize % BASE62.len()] as char)
+        .collect())
+}
@@ -0,0 +1,104 @@
+use frankenstein::methods::SendMessageParams;
+use frankenstein::types::{ChatId, LinkPreviewOptions};
+use log::info;
+use serde::{Deserialize, Serialize};
+use worker::Date;
+
+use crate::error::Error;
+use crate::filestore::FileStore;
+use crate::listing::human_size;
+use crate::metrics::DOWNLOADS;
+use crate::tg::TgBot;
+
+const DIGEST_SETTING: &str = "digest";
+const INTERVAL_SECS: u64 = 24 * 60 * 60;
+// crons fire a little early or late, don't skip a day because of it
+const INTERVAL_SLACK_SECS: u64 = 5 * 60;
+const TOP_FILES: u32 = 5;
+
+// when the last digest was sent and the download counter at that time
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct State {
+    time: u64,
+    downloads: u64,
+}
+
+// messages the maintainer a summary of the last day, called by every cron run
+pub async fn send<D: FileStore>(bot: &TgBot<D>) -> Result<(), Error> {
+    if bot.matainer == 0 {
+        return Ok(());
+    }
+
+    let now = Date::now().as_millis() / 1000;
+    let state: State =
+        serde_json::from_str(&bot.d1.get_setting(DIGEST_SETTING).await?).unwrap_or_default();
+    if now + INTERVAL_SLACK_SECS < state.time + INTERVAL_SECS {
+        return Ok(());
+    }
+
+    let downloads = bot
+        .d1
+        .counters()
+        .await?
+        .iter()
+        .find(|c| c.name == DOWNLOADS)
+        .map(|c| c.value)
+        .unwrap_or_default();
+
+    // the first run only records where the next digest starts
+    if state.time != 0 {
+        let text = render(bot, &state, downloads).await?;
+        bot.send_message(
+            &SendMessageParams::builder()
+                .chat_id(ChatId::Integer(bot.matainer))
+                .text(text)
+                .link_preview_options(LinkPreviewOptions::DISABLED)
+                .build(),
+        )
+        .await?;
+        info!("daily digest sent");
+    }
+
+    let state = State {
+        time: now,
+        downloads,
+    };
+    let value = serde_json::to_string(&state).map_err(|e| Error::Internal(e.to_string()))?;
+    bot.d1.set_setting(DIGEST_SETTING, &value).await
+}
+
+async fn render<D: FileStore>(
+    bot: &TgBot<D>,
+    state: &State,
+    downloads: u64,
+) -> Result<String, Error> {
+    let digest = bot.d1.digest(state.time).await?;
+
+    let mut lines = vec![
+        "daily digest".to_string(),
+        format!("uploads: {}", digest.uploads),
+        // the counter only moves with PROMETHEUS_METRICS
+        if downloads == 0 {
+            "downloads: not counted, set PROMETHEUS_METRICS".to_string()
+        } else {
+            format!("downloads: {}", downloads.saturating_sub(state.downloads))
+        },
+        format!("new users: {}", digest.new_users),
+        format!("storage: +{}", human_size(digest.bytes)),
+    ];
+
+    let top = bot.d1.top_downloads(TOP_FILES).await?;
+    if !top.is_empty() {
+        lines.push("most downloaded:".to_string());
+        for (i, f) in top.iter().enumerate() {
+            let name = if f.file_name.is_empty() {
+                &f.file_unique_id
+            } else {
+                &f.file_name
+            };
+            lines.push(format!("{}. {} ({})", i + 1, name, f.downloads));
+        }
+    }
+
+    Ok(lines.join("\n"))
+}
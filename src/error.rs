@@ -1,49 +1,142 @@
 use frankenstein::reqwest;
-use std::fmt::Display;
+use serde::Serialize;
 use wasm_bindgen::JsValue;
 use worker::Response;
 
-#[derive(Debug)]
-pub struct Error(pub String);
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    #[error("{0}")]
+    Gone(String),
+    #[error("{0}")]
+    Blocked(String),
+    #[error("too many requests, retry after {0} seconds")]
+    TooManyRequests(u64),
+    #[error("telegram api: {0}")]
+    TelegramApi(String),
+    #[error("database: {0}")]
+    Database(String),
+    #[error("{feature} requires the {binding} binding, add it to wrangler.toml")]
+    MissingBinding { feature: String, binding: String },
+    #[error("storage: {0}")]
+    Storage(String),
+    #[error("configuration: {0}")]
+    Config(String),
+    #[error("{0}")]
+    Internal(String),
+}
 
-impl Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorDetail<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail<'a> {
+    code: &'a str,
+    message: &'a str,
 }
 
 impl From<String> for Error {
     fn from(err: String) -> Self {
-        Error(err)
+        Error::Internal(err)
     }
 }
 
 impl From<worker::Error> for Error {
     fn from(err: worker::Error) -> Self {
-        Error(err.to_string())
+        match err {
+            worker::Error::D1(e) => Error::Database(e.cause()),
+            e => Error::Internal(e.to_string()),
+        }
     }
 }
 
 impl From<frankenstein::Error> for Error {
     fn from(err: frankenstein::Error) -> Self {
-        Error(err.to_string())
+        Error::TelegramApi(err.to_string())
     }
 }
 
+// reqwest is only used to talk to the bot api
 impl From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
-        Error(err.to_string())
+        Error::TelegramApi(err.to_string())
     }
 }
 
 impl From<JsValue> for Error {
     fn from(err: JsValue) -> Self {
-        Error(err.as_string().unwrap_or_default())
+        Error::Internal(err.as_string().unwrap_or_default())
     }
 }
 
 impl Error {
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Error::BadRequest(_) => 400,
+            Error::Unauthorized(_) => 401,
+            Error::Forbidden(_) => 403,
+            Error::NotFound(_) => 404,
+            Error::PayloadTooLarge(_) => 413,
+            Error::Gone(_) => 410,
+            Error::Blocked(_) => 451,
+            Error::TooManyRequests(_) => 429,
+            Error::TelegramApi(_) => 502,
+            Error::MissingBinding { .. } => 501,
+            Error::Database(_) | Error::Storage(_) | Error::Config(_) | Error::Internal(_) => 500,
+        }
+    }
+
+    // failures of this instance or its dependencies, not of the request or the configuration
+    pub fn is_unexpected(&self) -> bool {
+        self.status_code() >= 500
+            && !matches!(self, Error::MissingBinding { .. } | Error::Config(_))
+    }
+
+    // stable identifiers api clients can branch on, don't rename them
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::BadRequest(_) => "bad_request",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::Forbidden(_) => "forbidden",
+            Error::NotFound(_) => "not_found",
+            Error::PayloadTooLarge(_) => "payload_too_large",
+            Error::Gone(_) => "gone",
+            Error::Blocked(_) => "blocked",
+            Error::TooManyRequests(_) => "rate_limited",
+            Error::TelegramApi(_) => "telegram_api_error",
+            Error::Database(_) => "database_error",
+            Error::MissingBinding { .. } => "feature_unavailable",
+            Error::Storage(_) => "storage_error",
+            Error::Config(_) => "configuration_error",
+            Error::Internal(_) => "internal_error",
+        }
+    }
+
     pub fn to_response(&self) -> worker::Result<Response> {
-        Response::error(&self.0, 500)
+        let mut resp = Response::from_json(&ErrorBody {
+            error: ErrorDetail {
+                code: self.code(),
+                message: &self.to_string(),
+            },
+        })?
+        .with_status(self.status_code());
+
+        if let Error::TooManyRequests(retry_after) = self {
+            resp.headers_mut()
+                .set("Retry-After", &retry_after.to_string())?;
+        }
+
+        Ok(resp)
     }
 }
@@ -0,0 +1,563 @@
+// removes EXIF, XMP and IPTC segments from the header of a jpeg as it streams through.
+// the image data after the start of scan is passed on untouched. phones store the
+// rotation in EXIF, it's kept in a minimal EXIF segment so photos don't turn sideways.
+// files that keep their EXIF show what the camera recorded on /view/ and in /info
+
+use crate::d1::File;
+
+const SOI: u8 = 0xd8;
+const SOS: u8 = 0xda;
+// EXIF and XMP
+const APP1: u8 = 0xe1;
+// IPTC of photoshop
+const APP13: u8 = 0xed;
+const ORIENTATION: u16 = 0x0112;
+// tags of the first IFD
+const MAKE: u16 = 0x010f;
+const MODEL: u16 = 0x0110;
+const DATE_TIME: u16 = 0x0132;
+const EXIF_IFD: u16 = 0x8769;
+const GPS_IFD: u16 = 0x8825;
+// tags of the EXIF IFD
+const EXPOSURE_TIME: u16 = 0x829a;
+const F_NUMBER: u16 = 0x829d;
+const ISO: u16 = 0x8827;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+const FOCAL_LENGTH: u16 = 0x920a;
+const LENS_MODEL: u16 = 0xa434;
+// tags of the GPS IFD
+const GPS_LATITUDE_REF: u16 = 1;
+const GPS_LATITUDE: u16 = 2;
+const GPS_LONGITUDE_REF: u16 = 3;
+const GPS_LONGITUDE: u16 = 4;
+
+// header segments are at most 64 KiB each, a header larger than this isn't a photo
+const MAX_HEADER: usize = 1024 * 1024;
+// bytes read of a file for its metadata, EXIF is one segment near the start
+pub const METADATA_PREFIX: usize = 128 * 1024;
+
+#[derive(PartialEq)]
+enum State {
+    Start,
+    Header,
+    Passthrough,
+}
+
+pub struct ExifStripper {
+    state: State,
+    // bytes of the header not parsed yet
+    buf: Vec<u8>,
+}
+
+impl Default for ExifStripper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExifStripper {
+    pub fn new() -> Self {
+        Self {
+            state: State::Start,
+            buf: vec![],
+        }
+    }
+
+    // the bytes that can be sent on, segments split across chunks are held back
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        if self.state == State::Passthrough {
+            return chunk.to_vec();
+        }
+        self.buf.extend_from_slice(chunk);
+
+        let mut out = vec![];
+        let mut pos = 0;
+
+        if self.state == State::Start {
+            if self.buf.len() < 2 {
+                return out;
+            }
+            // not a jpeg, leave it alone
+            if self.buf[0] != 0xff || self.buf[1] != SOI {
+                self.state = State::Passthrough;
+                return std::mem::take(&mut self.buf);
+            }
+            out.extend_from_slice(&self.buf[..2]);
+            pos = 2;
+            self.state = State::Header;
+        }
+
+        loop {
+            if self.buf.len() < pos + 4 {
+                break;
+            }
+            // anything unexpected ends the parsing, the rest goes out as it is
+            if self.buf[pos] != 0xff || self.buf[pos + 1] == SOS {
+                self.state = State::Passthrough;
+                break;
+            }
+            let marker = self.buf[pos + 1];
+            // fill bytes before a marker
+            if marker == 0xff {
+                pos += 1;
+                continue;
+            }
+
+            let length = u16::from_be_bytes([self.buf[pos + 2], self.buf[pos + 3]]) as usize;
+            if length < 2 {
+                self.state = State::Passthrough;
+                break;
+            }
+            if self.buf.len() < pos + 2 + length {
+                break;
+            }
+            let segment = &self.buf[pos..pos + 2 + length];
+            let data = &segment[4..];
+
+            match marker {
+                APP1 if data.starts_with(b"Exif\0\0") => {
+                    if let Some(v) = orientation(&data[6..])
+                        && v != 1
+                    {
+                        out.extend(orientation_segment(v));
+                    }
+                }
+                APP1 | APP13 => {}
+                _ => out.extend_from_slice(segment),
+            }
+            pos += 2 + length;
+        }
+
+        let rest = self.buf.split_off(pos);
+        self.buf = rest;
+        if self.state == State::Passthrough || self.buf.len() > MAX_HEADER {
+            self.state = State::Passthrough;
+            out.append(&mut self.buf);
+        }
+        out
+    }
+
+    // whatever is left of a truncated file
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.state = State::Passthrough;
+        std::mem::take(&mut self.buf)
+    }
+}
+
+// jpeg documents keep what the camera wrote, photos were re-encoded by telegram
+pub fn applies(file: Option<&File>, ext: &str) -> bool {
+    match file {
+        Some(f) => f.mime_type == "image/jpeg",
+        None => matches!(ext, "jpg" | "jpeg"),
+    }
+}
+
+// orientation tag of the first IFD of the tiff structure in EXIF
+fn orientation(data: &[u8]) -> Option<u16> {
+    let tiff = Tiff::new(data)?;
+    tiff.short(tiff.first_ifd()?, ORIENTATION)
+        .filter(|v| (1..=8).contains(v))
+}
+
+// what the camera recorded about a photo, empty fields weren't recorded
+#[derive(Debug, Default, PartialEq)]
+pub struct Metadata {
+    // make and model
+    pub camera: String,
+    pub lens: String,
+    // `1/250 s, f/2.8, ISO 100, 35 mm`
+    pub exposure: String,
+    // by the clock of the camera, which has no time zone
+    pub taken: String,
+    // latitude and longitude in degrees
+    pub location: Option<(f64, f64)>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Metadata::default()
+    }
+
+    pub fn location(&self) -> Option<String> {
+        self.location
+            .map(|(lat, lon)| format!("{:.5}, {:.5}", lat, lon))
+    }
+}
+
+// the EXIF metadata of the header of a jpeg, `METADATA_PREFIX` bytes are enough
+pub fn metadata(jpeg: &[u8]) -> Option<Metadata> {
+    if jpeg.get(..2)? != [0xff, SOI] {
+        return None;
+    }
+    let mut pos = 2;
+    loop {
+        let header = jpeg.get(pos..pos + 4)?;
+        if header[0] != 0xff || header[1] == SOS {
+            return None;
+        }
+        if header[1] == 0xff {
+            pos += 1;
+            continue;
+        }
+        let length = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let data = jpeg.get(pos + 4..pos + 2 + length.max(2))?;
+        if header[1] == APP1
+            && let Some(tiff) = data.strip_prefix(b"Exif\0\0")
+        {
+            return Some(read_metadata(&Tiff::new(tiff)?)).filter(|v| !v.is_empty());
+        }
+        pos += 2 + length;
+    }
+}
+
+fn read_metadata(tiff: &Tiff) -> Metadata {
+    let Some(ifd) = tiff.first_ifd() else {
+        return Metadata::default();
+    };
+    let make = tiff.ascii(ifd, MAKE).unwrap_or_default();
+    let model = tiff.ascii(ifd, MODEL).unwrap_or_default();
+    // models usually start with the make, `Canon EOS R5`
+    let camera = if model.to_lowercase().starts_with(&make.to_lowercase()) {
+        model
+    } else {
+        format!("{} {}", make, model).trim().to_string()
+    };
+
+    let exif = tiff.long(ifd, EXIF_IFD).map(|v| v as usize);
+    let exposure = exif
+        .map(|exif| {
+            let mut parts = vec![];
+            if let Some((n, d)) = tiff.rational(exif, EXPOSURE_TIME, 0) {
+                parts.push(match n as f64 / d as f64 {
+                    v if v < 1.0 => format!("1/{} s", (d as f64 / n as f64).round()),
+                    v => format!("{} s", trim_float(v)),
+                });
+            }
+            if let Some((n, d)) = tiff.rational(exif, F_NUMBER, 0) {
+                parts.push(format!("f/{}", trim_float(n as f64 / d as f64)));
+            }
+            if let Some(v) = tiff.short(exif, ISO) {
+                parts.push(format!("ISO {}", v));
+            }
+            if let Some((n, d)) = tiff.rational(exif, FOCAL_LENGTH, 0) {
+                parts.push(format!("{} mm", trim_float(n as f64 / d as f64)));
+            }
+            parts.join(", ")
+        })
+        .unwrap_or_default();
+    let taken = exif
+        .and_then(|exif| tiff.ascii(exif, DATE_TIME_ORIGINAL))
+        .or_else(|| tiff.ascii(ifd, DATE_TIME))
+        .unwrap_or_default();
+    let location = tiff.long(ifd, GPS_IFD).and_then(|gps| {
+        let lat = coordinate(tiff, gps as usize, GPS_LATITUDE, GPS_LATITUDE_REF, "S")?;
+        let lon = coordinate(tiff, gps as usize, GPS_LONGITUDE, GPS_LONGITUDE_REF, "W")?;
+        Some((lat, lon))
+    });
+
+    Metadata {
+        camera,
+        lens: exif
+            .and_then(|exif| tiff.ascii(exif, LENS_MODEL))
+            .unwrap_or_default(),
+        exposure,
+        taken,
+        location,
+    }
+}
+
+// degrees, minutes and seconds as degrees, negative in the south and west
+fn coordinate(tiff: &Tiff, gps: usize, tag: u16, reference: u16, negative: &str) -> Option<f64> {
+    let mut degrees = 0.0;
+    for (i, unit) in [1.0, 60.0, 3600.0].into_iter().enumerate() {
+        let (n, d) = tiff.rational(gps, tag, i)?;
+        degrees += n as f64 / d as f64 / unit;
+    }
+    if tiff.ascii(gps, reference)? == negative {
+        degrees = -degrees;
+    }
+    Some(degrees)
+}
+
+// `2.8` and `35` instead of `2.80` and `35.0`
+fn trim_float(v: f64) -> String {
+    let s = format!("{:.1}", v);
+    s.strip_suffix(".0").map(str::to_string).unwrap_or(s)
+}
+
+// the tiff structure inside an EXIF segment, offsets are from its start
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let big_endian = match data.get(..2)? {
+            b"MM" => true,
+            b"II" => false,
+            _ => return None,
+        };
+        Some(Self { data, big_endian })
+    }
+
+    fn u16_at(&self, at: usize) -> Option<u16> {
+        let b: [u8; 2] = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+
+    fn u32_at(&self, at: usize) -> Option<u32> {
+        let b: [u8; 4] = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        Some(self.u32_at(4)? as usize)
+    }
+
+    // type, count and where the values of `tag` are, in place when they fit into 4 bytes
+    fn entry(&self, ifd: usize, tag: u16) -> Option<(u16, usize, usize)> {
+        let entries = self.u16_at(ifd)? as usize;
+        let at = (0..entries)
+            .map(|i| ifd + 2 + i * 12)
+            .find(|at| self.u16_at(*at) == Some(tag))?;
+        let kind = self.u16_at(at + 2)?;
+        let count = self.u32_at(at + 4)? as usize;
+        let size = match kind {
+            // BYTE, ASCII, UNDEFINED
+            1 | 2 | 7 => 1,
+            // SHORT
+            3 => 2,
+            // LONG, SLONG
+            4 | 9 => 4,
+            // RATIONAL, SRATIONAL
+            5 | 10 => 8,
+            _ => return None,
+        };
+        let values = match size * count {
+            ..=4 => at + 8,
+            _ => self.u32_at(at + 8)? as usize,
+        };
+        Some((kind, count, values))
+    }
+
+    fn short(&self, ifd: usize, tag: u16) -> Option<u16> {
+        match self.entry(ifd, tag)? {
+            (3, _, at) => self.u16_at(at),
+            (4, _, at) => self.u32_at(at).and_then(|v| u16::try_from(v).ok()),
+            _ => None,
+        }
+    }
+
+    fn long(&self, ifd: usize, tag: u16) -> Option<u32> {
+        match self.entry(ifd, tag)? {
+            (3, _, at) => self.u16_at(at).map(u32::from),
+            (4, _, at) => self.u32_at(at),
+            _ => None,
+        }
+    }
+
+    fn ascii(&self, ifd: usize, tag: u16) -> Option<String> {
+        let (2, count, at) = self.entry(ifd, tag)? else {
+            return None;
+        };
+        let bytes = self.data.get(at..at + count)?;
+        let bytes = bytes.split(|b| *b == 0).next().unwrap_or_default();
+        let value = String::from_utf8_lossy(bytes).trim().to_string();
+        Some(value).filter(|v| !v.is_empty())
+    }
+
+    // the `index`th numerator and denominator, None for a zero denominator
+    fn rational(&self, ifd: usize, tag: u16, index: usize) -> Option<(u32, u32)> {
+        let (5, count, at) = self.entry(ifd, tag)? else {
+            return None;
+        };
+        if index >= count {
+            return None;
+        }
+        let at = at + index * 8;
+        let (n, d) = (self.u32_at(at)?, self.u32_at(at + 4)?);
+        Some((n, d)).filter(|_| d != 0)
+    }
+}
+
+// an APP1 segment with nothing but the orientation
+fn orientation_segment(orientation: u16) -> Vec<u8> {
+    let mut tiff = vec![];
+    tiff.extend_from_slice(b"MM\0\x2a");
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    // tag, SHORT, one value, the value padded to 4 bytes
+    tiff.extend_from_slice(&ORIENTATION.to_be_bytes());
+    tiff.extend_from_slice(&3u16.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&orientation.to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    // no next IFD
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+
+    let mut segment = vec![0xff, APP1];
+    segment.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend(tiff);
+    segment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // tag, type, count and value bytes
+    type Entry = (u16, u16, u32, Vec<u8>);
+
+    // a big endian tiff of IFDs, values over 4 bytes go after the IFDs. IFD pointers are filled
+    // with the offsets of the IFDs that follow
+    fn tiff(ifds: &[Vec<Entry>]) -> Vec<u8> {
+        let sizes = ifds
+            .iter()
+            .map(|v| 2 + v.len() * 12 + 4)
+            .collect::<Vec<_>>();
+        let mut offsets = vec![8];
+        for size in &sizes {
+            offsets.push(offsets.last().unwrap() + size);
+        }
+        let mut extra_at = *offsets.last().unwrap();
+        let mut out = b"MM\0\x2a".to_vec();
+        out.extend_from_slice(&8u32.to_be_bytes());
+        let mut extra = vec![];
+        for (i, ifd) in ifds.iter().enumerate() {
+            out.extend_from_slice(&(ifd.len() as u16).to_be_bytes());
+            for (tag, kind, count, value) in ifd {
+                let value = match *tag {
+                    EXIF_IFD => (offsets[1] as u32).to_be_bytes().to_vec(),
+                    GPS_IFD => (offsets[2] as u32).to_be_bytes().to_vec(),
+                    _ => value.clone(),
+                };
+                out.extend_from_slice(&tag.to_be_bytes());
+                out.extend_from_slice(&kind.to_be_bytes());
+                out.extend_from_slice(&count.to_be_bytes());
+                if value.len() <= 4 {
+                    let mut v = value.clone();
+                    v.resize(4, 0);
+                    out.extend(v);
+                } else {
+                    out.extend_from_slice(&(extra_at as u32).to_be_bytes());
+                    extra_at += value.len();
+                    extra.extend(value);
+                }
+            }
+            assert_eq!(out.len(), offsets[i] + sizes[i] - 4);
+            out.extend_from_slice(&0u32.to_be_bytes());
+        }
+        out.extend(extra);
+        out
+    }
+
+    fn ascii(tag: u16, v: &str) -> (u16, u16, u32, Vec<u8>) {
+        let mut value = v.as_bytes().to_vec();
+        value.push(0);
+        (tag, 2, value.len() as u32, value)
+    }
+
+    fn rationals(tag: u16, v: &[(u32, u32)]) -> (u16, u16, u32, Vec<u8>) {
+        let value = v
+            .iter()
+            .flat_map(|(n, d)| [n.to_be_bytes(), d.to_be_bytes()].concat())
+            .collect();
+        (tag, 5, v.len() as u32, value)
+    }
+
+    fn jpeg(tiff: &[u8]) -> Vec<u8> {
+        let mut out = vec![0xff, SOI];
+        // a JFIF segment before the EXIF one
+        out.extend_from_slice(&[0xff, 0xe0, 0, 4, 0, 0]);
+        out.extend_from_slice(&[0xff, APP1]);
+        out.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        out.extend_from_slice(b"Exif\0\0");
+        out.extend_from_slice(tiff);
+        out.extend_from_slice(&[0xff, SOS, 0, 2, 1, 2, 3]);
+        out
+    }
+
+    #[test]
+    fn reads_metadata() {
+        let data = jpeg(&tiff(&[
+            vec![
+                ascii(MAKE, "Canon"),
+                ascii(MODEL, "Canon EOS R5"),
+                (ORIENTATION, 3, 1, vec![0, 6]),
+                (EXIF_IFD, 4, 1, vec![]),
+                (GPS_IFD, 4, 1, vec![]),
+            ],
+            vec![
+                rationals(EXPOSURE_TIME, &[(1, 250)]),
+                rationals(F_NUMBER, &[(28, 10)]),
+                (ISO, 3, 1, vec![0, 100]),
+                ascii(DATE_TIME_ORIGINAL, "2024:05:01 12:30:00"),
+                rationals(FOCAL_LENGTH, &[(35, 1)]),
+                ascii(LENS_MODEL, "RF24-70mm F2.8 L IS USM"),
+            ],
+            vec![
+                ascii(GPS_LATITUDE_REF, "N"),
+                rationals(GPS_LATITUDE, &[(48, 1), (51, 1), (2964, 100)]),
+                ascii(GPS_LONGITUDE_REF, "W"),
+                rationals(GPS_LONGITUDE, &[(2, 1), (17, 1), (4008, 100)]),
+            ],
+        ]));
+
+        let metadata = metadata(&data).unwrap();
+        assert_eq!(metadata.camera, "Canon EOS R5");
+        assert_eq!(metadata.lens, "RF24-70mm F2.8 L IS USM");
+        assert_eq!(metadata.exposure, "1/250 s, f/2.8, ISO 100, 35 mm");
+        assert_eq!(metadata.taken, "2024:05:01 12:30:00");
+        assert_eq!(metadata.location().unwrap(), "48.85823, -2.29447");
+        assert_eq!(orientation(&data[2 + 6 + 4 + 6..]), Some(6));
+    }
+
+    #[test]
+    fn partial_and_missing_metadata() {
+        let data = jpeg(&tiff(&[vec![
+            ascii(MAKE, "Fujifilm"),
+            ascii(MODEL, "X-T5"),
+        ]]));
+        let metadata = metadata(&data).unwrap();
+        assert_eq!(metadata.camera, "Fujifilm X-T5");
+        assert_eq!(metadata.exposure, "");
+        assert_eq!(metadata.location, None);
+
+        // only the rotation
+        let data = jpeg(&tiff(&[vec![(ORIENTATION, 3, 1, vec![0, 1])]]));
+        assert_eq!(super::metadata(&data), None);
+        // cut inside the EXIF segment
+        assert_eq!(super::metadata(&data[..20]), None);
+        assert_eq!(super::metadata(b"\x89PNG\r\n"), None);
+    }
+
+    #[test]
+    fn strips_metadata_but_keeps_rotation() {
+        let data = jpeg(&tiff(&[vec![
+            ascii(MAKE, "Canon"),
+            (ORIENTATION, 3, 1, vec![0, 6]),
+        ]]));
+        let mut stripper = ExifStripper::new();
+        let mut out = vec![];
+        for chunk in data.chunks(7) {
+            out.extend(stripper.push(chunk));
+        }
+        out.extend(stripper.finish());
+
+        let mut expected = vec![0xff, SOI, 0xff, 0xe0, 0, 4, 0, 0];
+        expected.extend(orientation_segment(6));
+        expected.extend_from_slice(&[0xff, SOS, 0, 2, 1, 2, 3]);
+        assert_eq!(out, expected);
+        assert_eq!(metadata(&out), None);
+    }
+}
@@ -0,0 +1,137 @@
+// rows of the files table for GET /api/export. json is what POST /api/import reads back,
+// csv is meant for spreadsheets and scripts
+
+use serde_json::Value;
+
+use crate::d1::File;
+
+// columns of the files table in the order of the csv header
+pub const COLUMNS: [&str; 28] = [
+    "file_id",
+    "file_unique_id",
+    "thumbnail_file_id",
+    "thumbnail_file_unique_id",
+    "message_id",
+    "user_id",
+    "file_name",
+    "file_size",
+    "mime_type",
+    "add_time",
+    "update_time",
+    "file_path",
+    "file_hash",
+    "password_hash",
+    "password_salt",
+    "upload_locale",
+    "download_name",
+    "custom_headers",
+    "forward_chat_id",
+    "forward_message_id",
+    "forward_from",
+    "chat_id",
+    "replaced_by",
+    "downloads",
+    "moderation_score",
+    "blocked",
+    "alt_text",
+    "deleted_at",
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Option<Self> {
+        match format {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Csv => "csv",
+        }
+    }
+
+    // everything before the first row
+    pub fn start(&self) -> String {
+        match self {
+            Format::Json => "[".to_string(),
+            Format::Csv => format!("{}\r\n", COLUMNS.join(",")),
+        }
+    }
+
+    pub fn row(&self, file: &File, first: bool) -> Result<String, serde_json::Error> {
+        match self {
+            Format::Json => Ok(format!(
+                "{}\n{}",
+                if first { "" } else { "," },
+                serde_json::to_string(file)?
+            )),
+            Format::Csv => {
+                let value = serde_json::to_value(file)?;
+                let fields = COLUMNS
+                    .iter()
+                    .map(|c| csv_field(value.get(c).unwrap_or(&Value::Null)))
+                    .collect::<Vec<_>>();
+                Ok(format!("{}\r\n", fields.join(",")))
+            }
+        }
+    }
+
+    pub fn end(&self) -> &'static str {
+        match self {
+            Format::Json => "\n]\n",
+            Format::Csv => "",
+        }
+    }
+}
+
+// quoted when it contains a separator, a quote or a line break.
+// strings that a spreadsheet would run as a formula get a leading '
+fn csv_field(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::String(v) if v.starts_with(['=', '+', '-', '@', '\t', '\r']) => format!("'{v}"),
+        Value::String(v) => v.clone(),
+        v => v.to_string(),
+    };
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn csv_fields() {
+        assert_eq!(csv_field(&json!("a.png")), "a.png");
+        assert_eq!(csv_field(&json!("a,b")), "\"a,b\"");
+        assert_eq!(
+            csv_field(&json!("=HYPERLINK(\"x\")")),
+            "\"'=HYPERLINK(\"\"x\"\")\""
+        );
+        assert_eq!(csv_field(&json!("+1")), "'+1");
+        assert_eq!(csv_field(&json!("@SUM(A1)")), "'@SUM(A1)");
+        assert_eq!(csv_field(&json!(-1)), "-1");
+        assert_eq!(csv_field(&Value::Null), "");
+    }
+}
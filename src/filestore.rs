@@ -0,0 +1,1510 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::apikey::ApiKey;
+use crate::d1::{
+    ALIAS_SHORT, Album, ChannelCollection, Counter, Digest, EVENT_DELETE, EVENT_PROTECT,
+    EVENT_RENAME, EVENT_REPLACE, EVENT_UNDELETE, EVENT_UNPROTECT, EVENT_UPLOAD, File, FileEvent,
+    FileFilter, ListingField, ListingSort, PendingTask, REPORT_OPEN, SHORT_ID_ATTEMPTS, TagCount,
+    Uploader, Usage, random_short_id,
+};
+use crate::error::Error;
+
+// the rows of files and of everything around them, users, albums, tags, keys and tasks.
+// D1 in production, MemoryStore keeps TgBot and Handler testable off the workers runtime
+#[allow(async_fn_in_trait)]
+pub trait FileStore: Clone + 'static {
+    // creates the tables and applies MIGRATIONS
+    async fn init(&self) -> Result<(), Error>;
+    async fn ping(&self) -> Result<(), Error>;
+
+    // uploads, a file that is known already keeps its password, name and counters
+    async fn save(&self, files: &[File]) -> Result<(), Error>;
+    // the number of files inserted, the others were known already
+    async fn import_files(&self, files: &[File]) -> Result<usize, Error>;
+    async fn save_file_path(&self, file_unique_id: &str, file_path: &str) -> Result<(), Error>;
+    // an empty hash removes the password
+    async fn save_password(
+        &self,
+        file_unique_id: &str,
+        password_hash: &str,
+        password_salt: &str,
+    ) -> Result<(), Error>;
+    // an empty reason keeps the file blocked, only save_blocked serves it again
+    async fn save_moderation(
+        &self,
+        file_unique_id: &str,
+        score: f64,
+        blocked: &str,
+    ) -> Result<(), Error>;
+    // an empty reason serves the file again
+    async fn save_blocked(&self, file_unique_id: &str, blocked: &str) -> Result<(), Error>;
+    // `generated` keeps alt text the uploader already set
+    async fn save_alt_text(
+        &self,
+        file_unique_id: &str,
+        alt_text: &str,
+        generated: bool,
+    ) -> Result<(), Error>;
+    async fn save_download_options(
+        &self,
+        file_unique_id: &str,
+        download_name: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<(), Error>;
+    // tombstones the file, it can be restored until the scheduled job removes it
+    async fn delete_file(&self, file_unique_id: &str) -> Result<(), Error>;
+    // either all of them are deleted or none
+    async fn delete_files(&self, file_unique_ids: &[String]) -> Result<(), Error>;
+    async fn undelete_file(&self, file_unique_id: &str) -> Result<(), Error>;
+    // files deleted at or before `before`, a unix time
+    async fn expired_files(&self, before: i64, limit: u32) -> Result<Vec<File>, Error>;
+    // removes the row for good, the stored copies are deleted by the caller
+    async fn purge_file(&self, file_unique_id: &str) -> Result<(), Error>;
+    // marks the file as replaced by an edit of its message, its links redirect to the new file
+    async fn replace_file(&self, file_unique_id: &str, replaced_by: &str) -> Result<(), Error>;
+
+    // by file_id, file_unique_id or alias
+    async fn find(&self, file_id: &str) -> Result<Option<File>, Error>;
+    async fn find_by_hash(&self, file_hash: &str) -> Result<Option<File>, Error>;
+    async fn message_files(&self, chat_id: i64, message_id: i32) -> Result<Vec<File>, Error>;
+    // every file of a user, deleted and blocked ones included
+    async fn user_files(&self, user_id: u64) -> Result<Vec<File>, Error>;
+    // all rows including deleted and blocked files, ordered by file_unique_id
+    async fn files_after(&self, after: &str, limit: u32) -> Result<Vec<File>, Error>;
+    async fn namespace_files(
+        &self,
+        namespace: &str,
+        sort: &ListingSort,
+        limit: u32,
+    ) -> Result<Vec<File>, Error>;
+    async fn recent_public_files(&self, limit: u32) -> Result<Vec<File>, Error>;
+    // newest files of a user whose name contains `query`, all of them for an empty query.
+    // only files with `tag` unless it's empty
+    async fn search_user_files(
+        &self,
+        user_id: u64,
+        query: &str,
+        tag: &str,
+        limit: u32,
+    ) -> Result<Vec<File>, Error>;
+    async fn filtered_files(
+        &self,
+        filter: &FileFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<File>, Error>;
+    async fn top_downloads(&self, limit: u32) -> Result<Vec<File>, Error>;
+
+    async fn get(&self, file_id: &str) -> Result<File, Error> {
+        self.find(file_id)
+            .await?
+            .ok_or(Error::NotFound("file not found".to_string()))
+    }
+
+    async fn events(&self, since: u64, limit: u32) -> Result<Vec<FileEvent>, Error>;
+    async fn count_files(&self) -> Result<u64, Error>;
+    async fn count_users(&self) -> Result<u64, Error>;
+    // `downloaded` is the file_unique_id of a successful download
+    async fn increment(&self, names: &[&str], downloaded: Option<&str>) -> Result<(), Error>;
+    async fn counters(&self) -> Result<Vec<Counter>, Error>;
+    async fn digest(&self, since: u64) -> Result<Digest, Error>;
+    async fn usage_by_type(&self) -> Result<Vec<Usage>, Error>;
+    // uploads per day since a unix time
+    async fn usage_by_day(&self, since: u64) -> Result<Vec<Usage>, Error>;
+    async fn uploaders(
+        &self,
+        user_id: Option<u64>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Uploader>, Error>;
+
+    async fn save_task(
+        &self,
+        kind: &str,
+        file_id: &str,
+        key: &str,
+        error: &str,
+    ) -> Result<(), Error>;
+    // tasks that were tried less than `max_attempts` times
+    async fn pending_tasks(&self, max_attempts: u32, limit: u32)
+    -> Result<Vec<PendingTask>, Error>;
+    async fn fail_task(&self, id: u64, error: &str) -> Result<(), Error>;
+    async fn delete_task(&self, id: u64) -> Result<(), Error>;
+
+    async fn get_setting(&self, key: &str) -> Result<String, Error>;
+    async fn set_setting(&self, key: &str, value: &str) -> Result<(), Error>;
+
+    async fn is_banned(&self, user_id: u64) -> Result<bool, Error>;
+    // false when the user was banned already
+    async fn ban_user(&self, user_id: u64) -> Result<bool, Error>;
+    // false when the user wasn't banned
+    async fn unban_user(&self, user_id: u64) -> Result<bool, Error>;
+    // everything stored about a user except a ban
+    async fn forget_user(&self, user_id: u64) -> Result<(), Error>;
+    async fn has_consented(&self, user_id: u64) -> Result<bool, Error>;
+    async fn save_consent(&self, user_id: u64) -> Result<(), Error>;
+    async fn get_namespace(&self, user_id: u64) -> Result<String, Error>;
+    // false when another user already took the namespace
+    async fn save_namespace(&self, user_id: u64, namespace: &str) -> Result<bool, Error>;
+    // the user chose to publish the GPS location of their photos
+    async fn shows_location(&self, user_id: u64) -> Result<bool, Error>;
+    async fn save_show_location(&self, user_id: u64, show: bool) -> Result<(), Error>;
+
+    // file_unique_id of a short id or slug
+    async fn find_alias(&self, alias: &str) -> Result<Option<String>, Error>;
+    // false when the alias is taken
+    async fn save_alias(
+        &self,
+        alias: &str,
+        file_unique_id: &str,
+        kind: &str,
+    ) -> Result<bool, Error>;
+    // the short id of a file, created on first use
+    async fn short_id(&self, file_unique_id: &str) -> Result<String, Error>;
+
+    // None when the user already has an album with this name
+    async fn create_album(&self, user_id: u64, name: &str) -> Result<Option<Album>, Error>;
+    async fn album(&self, album_id: &str) -> Result<Option<Album>, Error>;
+    async fn find_album(&self, user_id: u64, name: &str) -> Result<Option<Album>, Error>;
+    async fn count_user_albums(&self, user_id: u64) -> Result<u64, Error>;
+    // adding a file twice keeps its place
+    async fn add_album_files(
+        &self,
+        album_id: &str,
+        file_unique_ids: &[String],
+    ) -> Result<(), Error>;
+    // public files of the album in the order they were added
+    async fn album_files(&self, album_id: &str, limit: u32) -> Result<Vec<File>, Error>;
+    async fn save_channel_collection(&self, collection: &ChannelCollection) -> Result<(), Error>;
+    async fn channel_collection(&self, chat_id: &str) -> Result<Option<ChannelCollection>, Error>;
+    async fn channel_collections(&self) -> Result<Vec<ChannelCollection>, Error>;
+    // false when the channel had no album
+    async fn delete_channel_collection(&self, chat_id: &str) -> Result<bool, Error>;
+
+    async fn save_report(&self, file_unique_id: &str, reason: &str) -> Result<i64, Error>;
+    // one decision answers every open report of the file
+    async fn close_reports(&self, file_unique_id: &str, status: &str) -> Result<(), Error>;
+
+    // adds and removes tags of the file, the tags belong to its uploader
+    async fn save_tags(&self, file: &File, add: &[String], remove: &[String]) -> Result<(), Error>;
+    // names of the tags of each file by file_unique_id, files without tags are left out
+    async fn files_tags(&self, files: &[File]) -> Result<HashMap<String, Vec<String>>, Error>;
+    // tags of a user with their files that aren't deleted
+    async fn user_tags(&self, user_id: u64) -> Result<Vec<TagCount>, Error>;
+
+    async fn save_api_key(
+        &self,
+        key_id: &str,
+        key_hash: &str,
+        user_id: u64,
+        scopes: &str,
+    ) -> Result<(), Error>;
+    // the key stored under the hash of a bearer token
+    async fn find_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, Error>;
+    async fn user_api_keys(&self, user_id: u64) -> Result<Vec<ApiKey>, Error>;
+    // false when the user has no key with this id
+    async fn delete_api_key(&self, key_id: &str, user_id: u64) -> Result<bool, Error>;
+    async fn touch_api_key(&self, key_id: &str) -> Result<(), Error>;
+}
+
+// the tables of D1 in memory for tests, nothing is kept after the store is dropped.
+// clones share the rows like handles of the same database
+#[derive(Clone, Default)]
+pub struct MemoryStore(Rc<RefCell<Tables>>);
+
+#[derive(Default)]
+struct Tables {
+    // in insert order, the rowid order of D1
+    files: Vec<File>,
+    events: Vec<FileEvent>,
+    users: BTreeMap<u64, User>,
+    settings: HashMap<String, String>,
+    counters: BTreeMap<String, u64>,
+    tasks: Vec<PendingTask>,
+    banned_users: BTreeMap<u64, i64>,
+    aliases: Vec<Alias>,
+    albums: Vec<Album>,
+    album_files: Vec<(String, String)>,
+    channel_collections: Vec<ChannelCollection>,
+    // file_unique_id and status, the reasons aren't read back
+    reports: Vec<(String, String)>,
+    api_keys: Vec<(String, ApiKey)>,
+    tags: Vec<Tag>,
+    file_tags: Vec<(u64, String)>,
+    last_id: u64,
+}
+
+#[derive(Default)]
+struct User {
+    consent_time: i64,
+    namespace: String,
+    show_location: bool,
+}
+
+struct Alias {
+    alias: String,
+    file_unique_id: String,
+    kind: String,
+}
+
+struct Tag {
+    tag_id: u64,
+    user_id: u64,
+    name: String,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Tables {
+    fn next_id(&mut self) -> u64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn file(&self, file_unique_id: &str) -> Option<&File> {
+        self.files
+            .iter()
+            .find(|f| f.file_unique_id == file_unique_id)
+    }
+
+    fn file_mut(&mut self, file_unique_id: &str) -> Option<&mut File> {
+        self.files
+            .iter_mut()
+            .find(|f| f.file_unique_id == file_unique_id)
+    }
+
+    // UPDATE files SET ... WHERE file_unique_id = ?, a missing row changes nothing
+    fn update(&mut self, file_unique_id: &str, change: impl FnOnce(&mut File)) {
+        if let Some(f) = self.file_mut(file_unique_id) {
+            change(f);
+            f.update_time = now();
+        }
+    }
+
+    // INSERT_EVENT, from the current row of the file
+    fn event(&mut self, kind: &str, file_unique_id: &str) {
+        let Some(f) = self.file(file_unique_id) else {
+            return;
+        };
+        let event = FileEvent {
+            seq: 0,
+            kind: kind.to_string(),
+            file_id: f.file_id.clone(),
+            file_unique_id: f.file_unique_id.clone(),
+            file_name: f.file_name.clone(),
+            mime_type: f.mime_type.clone(),
+            add_time: now(),
+        };
+        let seq = self.next_id();
+        self.events.push(FileEvent { seq, ..event });
+    }
+
+    fn tagged(&self, file: &File, tag: &str) -> bool {
+        self.tags
+            .iter()
+            .filter(|t| t.user_id == file.user_id && t.name == tag)
+            .any(|t| {
+                self.file_tags
+                    .iter()
+                    .any(|(id, uid)| *id == t.tag_id && *uid == file.file_unique_id)
+            })
+    }
+
+    fn namespace(&self, user_id: u64) -> &str {
+        self.users
+            .get(&user_id)
+            .map(|u| u.namespace.as_str())
+            .unwrap_or_default()
+    }
+}
+
+impl FileStore for MemoryStore {
+    async fn init(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn save(&self, files: &[File]) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        let now = now();
+        for f in files {
+            match t.file_mut(&f.file_unique_id) {
+                // the columns of the ON CONFLICT of INSERT_FILE
+                Some(v) => {
+                    v.thumbnail_file_id = f.thumbnail_file_id.clone();
+                    v.thumbnail_file_unique_id = f.thumbnail_file_unique_id.clone();
+                    v.message_id = f.message_id;
+                    if v.user_id == 0 {
+                        v.user_id = f.user_id;
+                    }
+                    v.file_name = f.file_name.clone();
+                    v.file_size = f.file_size;
+                    v.mime_type = f.mime_type.clone();
+                    v.update_time = now;
+                    v.file_path = f.file_path.clone();
+                    if !f.file_hash.is_empty() {
+                        v.file_hash = f.file_hash.clone();
+                    }
+                    v.upload_locale = f.upload_locale.clone();
+                    v.forward_chat_id = f.forward_chat_id.clone();
+                    v.forward_message_id = f.forward_message_id;
+                    v.forward_from = f.forward_from.clone();
+                    v.chat_id = f.chat_id;
+                }
+                None => t.files.push(File {
+                    add_time: now,
+                    update_time: now,
+                    password_hash: String::new(),
+                    password_salt: String::new(),
+                    download_name: String::new(),
+                    custom_headers: String::new(),
+                    replaced_by: String::new(),
+                    downloads: 0,
+                    short_id: String::new(),
+                    moderation_score: None,
+                    blocked: String::new(),
+                    alt_text: String::new(),
+                    deleted_at: 0,
+                    ..f.clone()
+                }),
+            }
+            t.event(EVENT_UPLOAD, &f.file_unique_id);
+        }
+        Ok(())
+    }
+
+    async fn import_files(&self, files: &[File]) -> Result<usize, Error> {
+        let mut t = self.0.borrow_mut();
+        let mut count = 0;
+        for f in files {
+            if t.files
+                .iter()
+                .any(|v| v.file_unique_id == f.file_unique_id || v.file_id == f.file_id)
+            {
+                continue;
+            }
+            t.files.push(File {
+                short_id: String::new(),
+                ..f.clone()
+            });
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    async fn save_file_path(&self, file_unique_id: &str, file_path: &str) -> Result<(), Error> {
+        self.0
+            .borrow_mut()
+            .update(file_unique_id, |f| f.file_path = file_path.to_string());
+        Ok(())
+    }
+
+    async fn save_password(
+        &self,
+        file_unique_id: &str,
+        password_hash: &str,
+        password_salt: &str,
+    ) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        t.update(file_unique_id, |f| {
+            f.password_hash = password_hash.to_string();
+            f.password_salt = password_salt.to_string();
+        });
+        let kind = if password_hash.is_empty() {
+            EVENT_UNPROTECT
+        } else {
+            EVENT_PROTECT
+        };
+        t.event(kind, file_unique_id);
+        Ok(())
+    }
+
+    async fn save_moderation(
+        &self,
+        file_unique_id: &str,
+        score: f64,
+        blocked: &str,
+    ) -> Result<(), Error> {
+        self.0.borrow_mut().update(file_unique_id, |f| {
+            f.moderation_score = Some(score);
+            if !blocked.is_empty() {
+                f.blocked = blocked.to_string();
+            }
+        });
+        Ok(())
+    }
+
+    async fn save_blocked(&self, file_unique_id: &str, blocked: &str) -> Result<(), Error> {
+        self.0
+            .borrow_mut()
+            .update(file_unique_id, |f| f.blocked = blocked.to_string());
+        Ok(())
+    }
+
+    async fn save_alt_text(
+        &self,
+        file_unique_id: &str,
+        alt_text: &str,
+        generated: bool,
+    ) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        if generated
+            && t.file(file_unique_id)
+                .is_some_and(|f| !f.alt_text.is_empty())
+        {
+            return Ok(());
+        }
+        t.update(file_unique_id, |f| f.alt_text = alt_text.to_string());
+        Ok(())
+    }
+
+    async fn save_download_options(
+        &self,
+        file_unique_id: &str,
+        download_name: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> Result<(), Error> {
+        let headers = if headers.is_empty() {
+            String::new()
+        } else {
+            serde_json::to_string(headers).map_err(|e| Error::Internal(e.to_string()))?
+        };
+
+        let mut t = self.0.borrow_mut();
+        t.update(file_unique_id, |f| {
+            f.download_name = download_name.to_string();
+            f.custom_headers = headers;
+        });
+        t.event(EVENT_RENAME, file_unique_id);
+        Ok(())
+    }
+
+    async fn delete_file(&self, file_unique_id: &str) -> Result<(), Error> {
+        self.delete_files(&[file_unique_id.to_string()]).await
+    }
+
+    async fn delete_files(&self, file_unique_ids: &[String]) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        let now = now();
+        for id in file_unique_ids {
+            t.update(id, |f| f.deleted_at = now);
+            t.event(EVENT_DELETE, id);
+        }
+        Ok(())
+    }
+
+    async fn undelete_file(&self, file_unique_id: &str) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        t.update(file_unique_id, |f| f.deleted_at = 0);
+        t.event(EVENT_UNDELETE, file_unique_id);
+        Ok(())
+    }
+
+    async fn expired_files(&self, before: i64, limit: u32) -> Result<Vec<File>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .files
+            .iter()
+            .filter(|f| f.deleted_at > 0 && f.deleted_at <= before)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn purge_file(&self, file_unique_id: &str) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        t.aliases.retain(|a| a.file_unique_id != *file_unique_id);
+        t.album_files.retain(|(_, uid)| uid != file_unique_id);
+        t.file_tags.retain(|(_, uid)| uid != file_unique_id);
+        t.files.retain(|f| f.file_unique_id != *file_unique_id);
+        Ok(())
+    }
+
+    async fn replace_file(&self, file_unique_id: &str, replaced_by: &str) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        t.update(file_unique_id, |f| f.replaced_by = replaced_by.to_string());
+        t.event(EVENT_REPLACE, file_unique_id);
+        Ok(())
+    }
+
+    async fn find(&self, file_id: &str) -> Result<Option<File>, Error> {
+        let t = self.0.borrow();
+        let find = |id: &str| {
+            t.files
+                .iter()
+                .find(|f| f.file_id == id || f.file_unique_id == id)
+                .cloned()
+        };
+        if let Some(f) = find(file_id) {
+            return Ok(Some(f));
+        }
+        Ok(t.aliases
+            .iter()
+            .find(|a| a.alias == *file_id)
+            .and_then(|a| find(&a.file_unique_id)))
+    }
+
+    async fn find_by_hash(&self, file_hash: &str) -> Result<Option<File>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .files
+            .iter()
+            .find(|f| {
+                f.file_hash == *file_hash && !f.is_deleted() && !f.is_protected() && !f.is_blocked()
+            })
+            .cloned())
+    }
+
+    async fn message_files(&self, chat_id: i64, message_id: i32) -> Result<Vec<File>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .files
+            .iter()
+            .filter(|f| {
+                f.chat_id == chat_id
+                    && f.message_id == message_id
+                    && f.replaced_by.is_empty()
+                    && !f.is_deleted()
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn user_files(&self, user_id: u64) -> Result<Vec<File>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .files
+            .iter()
+            .filter(|f| f.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn files_after(&self, after: &str, limit: u32) -> Result<Vec<File>, Error> {
+        let mut files = self
+            .0
+            .borrow()
+            .files
+            .iter()
+            .filter(|f| f.file_unique_id.as_str() > after)
+            .cloned()
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| a.file_unique_id.cmp(&b.file_unique_id));
+        files.truncate(limit as usize);
+        Ok(files)
+    }
+
+    async fn namespace_files(
+        &self,
+        namespace: &str,
+        sort: &ListingSort,
+        limit: u32,
+    ) -> Result<Vec<File>, Error> {
+        let t = self.0.borrow();
+        let mut files = t
+            .files
+            .iter()
+            .filter(|f| !namespace.is_empty() && t.namespace(f.user_id) == namespace)
+            .filter(|f| is_public(f))
+            .cloned()
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| {
+            let order = match sort.field {
+                ListingField::Name => a.file_name.cmp(&b.file_name),
+                ListingField::Size => a.file_size.cmp(&b.file_size),
+                ListingField::Date => a.add_time.cmp(&b.add_time),
+            };
+            if sort.descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+        files.truncate(limit as usize);
+        Ok(files)
+    }
+
+    async fn recent_public_files(&self, limit: u32) -> Result<Vec<File>, Error> {
+        let t = self.0.borrow();
+        let files = t
+            .files
+            .iter()
+            .filter(|f| !t.namespace(f.user_id).is_empty() && is_public(f))
+            .cloned()
+            .collect();
+        Ok(newest(files, limit, 0))
+    }
+
+    async fn search_user_files(
+        &self,
+        user_id: u64,
+        query: &str,
+        tag: &str,
+        limit: u32,
+    ) -> Result<Vec<File>, Error> {
+        let t = self.0.borrow();
+        let files = t
+            .files
+            .iter()
+            .filter(|f| f.user_id == user_id && !f.is_blocked() && !f.is_deleted())
+            .filter(|f| contains(&f.file_name, query) || contains(&f.download_name, query))
+            .filter(|f| tag.is_empty() || t.tagged(f, tag))
+            .cloned()
+            .collect();
+        Ok(newest(files, limit, 0))
+    }
+
+    async fn filtered_files(
+        &self,
+        filter: &FileFilter,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<File>, Error> {
+        let t = self.0.borrow();
+        let files = t
+            .files
+            .iter()
+            .filter(|f| filter.user_id.is_none_or(|v| f.user_id == v))
+            .filter(|f| {
+                // photos count as image/jpeg
+                let mime_type = match f.mime_type.as_str() {
+                    "" => "image/jpeg",
+                    v => v,
+                };
+                mime_type
+                    .to_ascii_lowercase()
+                    .starts_with(&filter.mime_type.to_ascii_lowercase())
+            })
+            .filter(|f| {
+                filter.name.is_empty()
+                    || contains(&f.file_name, &filter.name)
+                    || contains(&f.download_name, &filter.name)
+            })
+            .filter(|f| match filter.status.as_str() {
+                "active" => !f.is_deleted() && !f.is_blocked(),
+                "deleted" => f.is_deleted(),
+                "blocked" => f.is_blocked(),
+                _ => true,
+            })
+            .filter(|f| filter.tag.is_empty() || t.tagged(f, &filter.tag))
+            .cloned()
+            .collect();
+        Ok(newest(files, limit, offset))
+    }
+
+    async fn top_downloads(&self, limit: u32) -> Result<Vec<File>, Error> {
+        let mut files = self
+            .0
+            .borrow()
+            .files
+            .iter()
+            .filter(|f| f.downloads > 0 && !f.is_deleted())
+            .cloned()
+            .collect::<Vec<_>>();
+        files.sort_by_key(|f| std::cmp::Reverse(f.downloads));
+        files.truncate(limit as usize);
+        Ok(files)
+    }
+
+    async fn events(&self, since: u64, limit: u32) -> Result<Vec<FileEvent>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .events
+            .iter()
+            .filter(|e| e.seq > since)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn count_files(&self) -> Result<u64, Error> {
+        Ok(self.0.borrow().files.len() as u64)
+    }
+
+    async fn count_users(&self) -> Result<u64, Error> {
+        Ok(self.0.borrow().users.len() as u64)
+    }
+
+    async fn increment(&self, names: &[&str], downloaded: Option<&str>) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        for name in names {
+            *t.counters.entry(name.to_string()).or_default() += 1;
+        }
+        if let Some(f) = downloaded.and_then(|id| t.file_mut(id)) {
+            f.downloads += 1;
+        }
+        Ok(())
+    }
+
+    async fn counters(&self) -> Result<Vec<Counter>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .counters
+            .iter()
+            .map(|(name, value)| Counter {
+                name: name.clone(),
+                value: *value,
+            })
+            .collect())
+    }
+
+    async fn digest(&self, since: u64) -> Result<Digest, Error> {
+        let t = self.0.borrow();
+        let since = since as i64;
+
+        let mut first_uploads: BTreeMap<u64, i64> = BTreeMap::new();
+        for f in t.files.iter().filter(|f| f.user_id != 0) {
+            let first = first_uploads.entry(f.user_id).or_insert(f.add_time);
+            *first = (*first).min(f.add_time);
+        }
+
+        Ok(Digest {
+            uploads: t
+                .events
+                .iter()
+                .filter(|e| e.kind == EVENT_UPLOAD && e.add_time >= since)
+                .count() as u64,
+            new_users: first_uploads.values().filter(|v| **v >= since).count() as u64,
+            bytes: t
+                .files
+                .iter()
+                .filter(|f| f.add_time >= since)
+                .map(|f| f.file_size)
+                .sum(),
+        })
+    }
+
+    async fn usage_by_type(&self) -> Result<Vec<Usage>, Error> {
+        let t = self.0.borrow();
+        let files = t.files.iter().filter(|f| !f.is_deleted());
+        let mut usage = usage(files, |f| match f.mime_type.split('/').next() {
+            Some("") | None => "image".to_string(),
+            Some(v) => v.to_string(),
+        });
+        usage.sort_by_key(|f| std::cmp::Reverse(f.bytes));
+        Ok(usage)
+    }
+
+    async fn usage_by_day(&self, since: u64) -> Result<Vec<Usage>, Error> {
+        let t = self.0.borrow();
+        let files = t.files.iter().filter(|f| f.add_time >= since as i64);
+        // BTreeMap order, the days are sorted
+        Ok(usage(files, |f| day(f.add_time)))
+    }
+
+    async fn uploaders(
+        &self,
+        user_id: Option<u64>,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<Uploader>, Error> {
+        let t = self.0.borrow();
+        let mut uploaders: BTreeMap<u64, Uploader> = BTreeMap::new();
+        for f in t
+            .files
+            .iter()
+            .filter(|f| f.user_id != 0 && user_id.is_none_or(|v| f.user_id == v))
+        {
+            let v = uploaders.entry(f.user_id).or_insert(Uploader {
+                user_id: f.user_id,
+                files: 0,
+                bytes: 0,
+                last_upload: 0,
+                banned_at: t.banned_users.get(&f.user_id).copied().unwrap_or_default(),
+            });
+            v.files += 1;
+            v.bytes += f.file_size;
+            v.last_upload = v.last_upload.max(f.add_time);
+        }
+
+        let mut uploaders = uploaders.into_values().collect::<Vec<_>>();
+        uploaders.sort_by_key(|f| std::cmp::Reverse(f.bytes));
+        Ok(uploaders
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn save_task(
+        &self,
+        kind: &str,
+        file_id: &str,
+        key: &str,
+        error: &str,
+    ) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        if let Some(v) = t.tasks.iter_mut().find(|v| v.kind == kind && v.key == key) {
+            v.error = error.to_string();
+            return Ok(());
+        }
+        let id = t.next_id();
+        t.tasks.push(PendingTask {
+            id,
+            kind: kind.to_string(),
+            file_id: file_id.to_string(),
+            key: key.to_string(),
+            error: error.to_string(),
+            attempts: 0,
+        });
+        Ok(())
+    }
+
+    async fn pending_tasks(
+        &self,
+        max_attempts: u32,
+        limit: u32,
+    ) -> Result<Vec<PendingTask>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .tasks
+            .iter()
+            .filter(|v| v.attempts < max_attempts)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn fail_task(&self, id: u64, error: &str) -> Result<(), Error> {
+        if let Some(v) = self.0.borrow_mut().tasks.iter_mut().find(|v| v.id == id) {
+            v.error = error.to_string();
+            v.attempts += 1;
+        }
+        Ok(())
+    }
+
+    async fn delete_task(&self, id: u64) -> Result<(), Error> {
+        self.0.borrow_mut().tasks.retain(|v| v.id != id);
+        Ok(())
+    }
+
+    async fn get_setting(&self, key: &str) -> Result<String, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .settings
+            .get(key)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn set_setting(&self, key: &str, value: &str) -> Result<(), Error> {
+        self.0
+            .borrow_mut()
+            .settings
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    async fn is_banned(&self, user_id: u64) -> Result<bool, Error> {
+        Ok(self.0.borrow().banned_users.contains_key(&user_id))
+    }
+
+    async fn ban_user(&self, user_id: u64) -> Result<bool, Error> {
+        let mut t = self.0.borrow_mut();
+        if t.banned_users.contains_key(&user_id) {
+            return Ok(false);
+        }
+        t.banned_users.insert(user_id, now());
+        Ok(true)
+    }
+
+    async fn unban_user(&self, user_id: u64) -> Result<bool, Error> {
+        Ok(self.0.borrow_mut().banned_users.remove(&user_id).is_some())
+    }
+
+    async fn forget_user(&self, user_id: u64) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        let (files, others): (Vec<File>, Vec<File>) =
+            t.files.drain(..).partition(|f| f.user_id == user_id);
+        t.files = others;
+        let owned = |id: &String| files.iter().any(|f| f.file_unique_id == *id);
+        let albums = t
+            .albums
+            .iter()
+            .filter(|a| a.user_id == user_id)
+            .map(|a| a.album_id.clone())
+            .collect::<Vec<_>>();
+        let tags = t
+            .tags
+            .iter()
+            .filter(|v| v.user_id == user_id)
+            .map(|v| v.tag_id)
+            .collect::<Vec<_>>();
+
+        t.events.retain(|e| !owned(&e.file_unique_id));
+        t.aliases.retain(|a| !owned(&a.file_unique_id));
+        t.reports.retain(|(id, _)| !owned(id));
+        t.tasks
+            .retain(|v| !files.iter().any(|f| f.file_id == v.file_id));
+        t.album_files
+            .retain(|(album_id, uid)| !owned(uid) && !albums.contains(album_id));
+        t.channel_collections
+            .retain(|c| !albums.contains(&c.album_id));
+        t.albums.retain(|a| a.user_id != user_id);
+        t.file_tags.retain(|(tag_id, _)| !tags.contains(tag_id));
+        t.tags.retain(|v| v.user_id != user_id);
+        t.api_keys.retain(|(_, k)| k.user_id != user_id);
+        t.users.remove(&user_id);
+        Ok(())
+    }
+
+    async fn has_consented(&self, user_id: u64) -> Result<bool, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .users
+            .get(&user_id)
+            .is_some_and(|u| u.consent_time > 0))
+    }
+
+    async fn save_consent(&self, user_id: u64) -> Result<(), Error> {
+        self.0
+            .borrow_mut()
+            .users
+            .entry(user_id)
+            .or_default()
+            .consent_time = now();
+        Ok(())
+    }
+
+    async fn get_namespace(&self, user_id: u64) -> Result<String, Error> {
+        Ok(self.0.borrow().namespace(user_id).to_string())
+    }
+
+    async fn save_namespace(&self, user_id: u64, namespace: &str) -> Result<bool, Error> {
+        let mut t = self.0.borrow_mut();
+        if !namespace.is_empty()
+            && t.users
+                .iter()
+                .any(|(id, u)| *id != user_id && u.namespace == namespace)
+        {
+            return Ok(false);
+        }
+        t.users.entry(user_id).or_default().namespace = namespace.to_string();
+        Ok(true)
+    }
+
+    async fn shows_location(&self, user_id: u64) -> Result<bool, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .users
+            .get(&user_id)
+            .is_some_and(|u| u.show_location))
+    }
+
+    async fn save_show_location(&self, user_id: u64, show: bool) -> Result<(), Error> {
+        self.0
+            .borrow_mut()
+            .users
+            .entry(user_id)
+            .or_default()
+            .show_location = show;
+        Ok(())
+    }
+
+    async fn find_alias(&self, alias: &str) -> Result<Option<String>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .aliases
+            .iter()
+            .find(|a| a.alias == alias)
+            .map(|a| a.file_unique_id.clone()))
+    }
+
+    async fn save_alias(
+        &self,
+        alias: &str,
+        file_unique_id: &str,
+        kind: &str,
+    ) -> Result<bool, Error> {
+        let mut t = self.0.borrow_mut();
+        // the primary key and the unique index of short ids
+        if t.aliases.iter().any(|a| {
+            a.alias == alias
+                || (kind == ALIAS_SHORT
+                    && a.kind == ALIAS_SHORT
+                    && a.file_unique_id == file_unique_id)
+        }) {
+            return Ok(false);
+        }
+        t.aliases.push(Alias {
+            alias: alias.to_string(),
+            file_unique_id: file_unique_id.to_string(),
+            kind: kind.to_string(),
+        });
+        Ok(true)
+    }
+
+    async fn short_id(&self, file_unique_id: &str) -> Result<String, Error> {
+        if let Some(a) = self
+            .0
+            .borrow()
+            .aliases
+            .iter()
+            .find(|a| a.file_unique_id == file_unique_id && a.kind == ALIAS_SHORT)
+        {
+            return Ok(a.alias.clone());
+        }
+
+        for _ in 0..SHORT_ID_ATTEMPTS {
+            let id = random_short_id()?;
+            if self.save_alias(&id, file_unique_id, ALIAS_SHORT).await? {
+                return Ok(id);
+            }
+        }
+        Err(Error::Internal("no free short id found".into()))
+    }
+
+    async fn create_album(&self, user_id: u64, name: &str) -> Result<Option<Album>, Error> {
+        if self.find_album(user_id, name).await?.is_some() {
+            return Ok(None);
+        }
+
+        let mut t = self.0.borrow_mut();
+        for _ in 0..SHORT_ID_ATTEMPTS {
+            let album_id = random_short_id()?;
+            if t.albums.iter().any(|a| a.album_id == album_id) {
+                continue;
+            }
+            let album = Album {
+                album_id,
+                user_id,
+                name: name.to_string(),
+                add_time: now(),
+            };
+            t.albums.push(album.clone());
+            return Ok(Some(album));
+        }
+        Err(Error::Internal("no free album id found".into()))
+    }
+
+    async fn album(&self, album_id: &str) -> Result<Option<Album>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .albums
+            .iter()
+            .find(|a| a.album_id == album_id)
+            .cloned())
+    }
+
+    async fn find_album(&self, user_id: u64, name: &str) -> Result<Option<Album>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .albums
+            .iter()
+            .find(|a| a.user_id == user_id && a.name == name)
+            .cloned())
+    }
+
+    async fn count_user_albums(&self, user_id: u64) -> Result<u64, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .albums
+            .iter()
+            .filter(|a| a.user_id == user_id)
+            .count() as u64)
+    }
+
+    async fn add_album_files(
+        &self,
+        album_id: &str,
+        file_unique_ids: &[String],
+    ) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        for id in file_unique_ids {
+            let entry = (album_id.to_string(), id.clone());
+            if !t.album_files.contains(&entry) {
+                t.album_files.push(entry);
+            }
+        }
+        Ok(())
+    }
+
+    async fn album_files(&self, album_id: &str, limit: u32) -> Result<Vec<File>, Error> {
+        let t = self.0.borrow();
+        Ok(t.album_files
+            .iter()
+            .filter(|(id, _)| id == album_id)
+            .filter_map(|(_, uid)| t.file(uid))
+            .filter(|f| is_public(f))
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn save_channel_collection(&self, collection: &ChannelCollection) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        match t
+            .channel_collections
+            .iter_mut()
+            .find(|c| c.chat_id == collection.chat_id)
+        {
+            Some(v) => {
+                v.album_id = collection.album_id.clone();
+                v.tags = collection.tags.clone();
+            }
+            None => t.channel_collections.push(ChannelCollection {
+                add_time: now(),
+                ..collection.clone()
+            }),
+        }
+        Ok(())
+    }
+
+    async fn channel_collection(&self, chat_id: &str) -> Result<Option<ChannelCollection>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .channel_collections
+            .iter()
+            .find(|c| c.chat_id == chat_id)
+            .cloned())
+    }
+
+    async fn channel_collections(&self) -> Result<Vec<ChannelCollection>, Error> {
+        Ok(self.0.borrow().channel_collections.clone())
+    }
+
+    async fn delete_channel_collection(&self, chat_id: &str) -> Result<bool, Error> {
+        let mut t = self.0.borrow_mut();
+        let count = t.channel_collections.len();
+        t.channel_collections.retain(|c| c.chat_id != chat_id);
+        Ok(t.channel_collections.len() != count)
+    }
+
+    async fn save_report(&self, file_unique_id: &str, _reason: &str) -> Result<i64, Error> {
+        let mut t = self.0.borrow_mut();
+        t.reports
+            .push((file_unique_id.to_string(), REPORT_OPEN.to_string()));
+        Ok(t.next_id() as i64)
+    }
+
+    async fn close_reports(&self, file_unique_id: &str, status: &str) -> Result<(), Error> {
+        self.0
+            .borrow_mut()
+            .reports
+            .iter_mut()
+            .filter(|(id, v)| id == file_unique_id && v == REPORT_OPEN)
+            .for_each(|(_, v)| *v = status.to_string());
+        Ok(())
+    }
+
+    async fn save_tags(&self, file: &File, add: &[String], remove: &[String]) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        let user_id = file.user_id;
+        let tag_id = |t: &Tables, name: &str| {
+            t.tags
+                .iter()
+                .find(|v| v.user_id == user_id && v.name == name)
+                .map(|v| v.tag_id)
+        };
+
+        for name in add {
+            let id = match tag_id(&t, name) {
+                Some(v) => v,
+                None => {
+                    let tag_id = t.next_id();
+                    t.tags.push(Tag {
+                        tag_id,
+                        user_id,
+                        name: name.clone(),
+                    });
+                    tag_id
+                }
+            };
+            let entry = (id, file.file_unique_id.clone());
+            if !t.file_tags.contains(&entry) {
+                t.file_tags.push(entry);
+            }
+        }
+        for name in remove {
+            if let Some(id) = tag_id(&t, name) {
+                t.file_tags
+                    .retain(|(tag_id, uid)| *tag_id != id || *uid != file.file_unique_id);
+            }
+        }
+        if !remove.is_empty() {
+            let used = t.file_tags.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+            t.tags
+                .retain(|v| v.user_id != user_id || used.contains(&v.tag_id));
+        }
+        Ok(())
+    }
+
+    async fn files_tags(&self, files: &[File]) -> Result<HashMap<String, Vec<String>>, Error> {
+        let t = self.0.borrow();
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for (tag_id, uid) in &t.file_tags {
+            if !files.iter().any(|f| f.file_unique_id == *uid) {
+                continue;
+            }
+            if let Some(tag) = t.tags.iter().find(|v| v.tag_id == *tag_id) {
+                tags.entry(uid.clone()).or_default().push(tag.name.clone());
+            }
+        }
+        tags.values_mut().for_each(|v| v.sort());
+        Ok(tags)
+    }
+
+    async fn user_tags(&self, user_id: u64) -> Result<Vec<TagCount>, Error> {
+        let t = self.0.borrow();
+        let mut counts: BTreeMap<String, u64> = BTreeMap::new();
+        for tag in t.tags.iter().filter(|v| v.user_id == user_id) {
+            let files = t
+                .file_tags
+                .iter()
+                .filter(|(id, uid)| {
+                    *id == tag.tag_id && t.file(uid).is_some_and(|f| !f.is_deleted())
+                })
+                .count() as u64;
+            if files > 0 {
+                *counts.entry(tag.name.clone()).or_default() += files;
+            }
+        }
+        Ok(counts
+            .into_iter()
+            .map(|(name, files)| TagCount { name, files })
+            .collect())
+    }
+
+    async fn save_api_key(
+        &self,
+        key_id: &str,
+        key_hash: &str,
+        user_id: u64,
+        scopes: &str,
+    ) -> Result<(), Error> {
+        let mut t = self.0.borrow_mut();
+        if t.api_keys
+            .iter()
+            .any(|(hash, k)| hash == key_hash || k.key_id == key_id)
+        {
+            return Err(Error::Database(
+                "UNIQUE constraint failed: api_keys".to_string(),
+            ));
+        }
+        t.api_keys.push((
+            key_hash.to_string(),
+            ApiKey {
+                key_id: key_id.to_string(),
+                user_id,
+                scopes: scopes.to_string(),
+                last_used: 0,
+                add_time: now(),
+            },
+        ));
+        Ok(())
+    }
+
+    async fn find_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .api_keys
+            .iter()
+            .find(|(hash, _)| hash == key_hash)
+            .map(|(_, k)| k.clone()))
+    }
+
+    async fn user_api_keys(&self, user_id: u64) -> Result<Vec<ApiKey>, Error> {
+        Ok(self
+            .0
+            .borrow()
+            .api_keys
+            .iter()
+            .filter(|(_, k)| k.user_id == user_id)
+            .map(|(_, k)| k.clone())
+            .collect())
+    }
+
+    async fn delete_api_key(&self, key_id: &str, user_id: u64) -> Result<bool, Error> {
+        let mut t = self.0.borrow_mut();
+        let count = t.api_keys.len();
+        t.api_keys
+            .retain(|(_, k)| k.key_id != key_id || k.user_id != user_id);
+        Ok(t.api_keys.len() != count)
+    }
+
+    async fn touch_api_key(&self, key_id: &str) -> Result<(), Error> {
+        if let Some((_, k)) = self
+            .0
+            .borrow_mut()
+            .api_keys
+            .iter_mut()
+            .find(|(_, k)| k.key_id == key_id)
+        {
+            k.last_used = now();
+        }
+        Ok(())
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+// the files galleries and listings show
+fn is_public(f: &File) -> bool {
+    !f.is_protected() && !f.is_blocked() && !f.is_deleted()
+}
+
+// LIKE '%query%', case insensitive for ascii like sqlite
+fn contains(value: &str, query: &str) -> bool {
+    value
+        .to_ascii_lowercase()
+        .contains(&query.to_ascii_lowercase())
+}
+
+// ORDER BY add_time DESC LIMIT ? OFFSET ?
+fn newest(mut files: Vec<File>, limit: u32, offset: u32) -> Vec<File> {
+    files.sort_by_key(|f| std::cmp::Reverse(f.add_time));
+    files
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .collect()
+}
+
+fn usage<'a>(files: impl Iterator<Item = &'a File>, key: impl Fn(&File) -> String) -> Vec<Usage> {
+    let mut usage: BTreeMap<String, Usage> = BTreeMap::new();
+    for f in files {
+        let key = key(f);
+        let v = usage.entry(key.clone()).or_insert(Usage {
+            key,
+            ..Default::default()
+        });
+        v.files += 1;
+        v.bytes += f.file_size;
+    }
+    usage.into_values().collect()
+}
+
+// date(add_time, 'unixepoch'), days to a civil date of the proleptic gregorian calendar
+fn day(unix: i64) -> String {
+    let z = unix.div_euclid(86400) + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    // the store never awaits anything, a single poll finishes every call
+    pub(crate) fn block_on<T>(fut: impl Future<Output = T>) -> T {
+        let mut cx = Context::from_waker(Waker::noop());
+        match pin!(fut).poll(&mut cx) {
+            Poll::Ready(v) => v,
+            Poll::Pending => panic!("the store awaited"),
+        }
+    }
+
+    pub(crate) fn file(file_unique_id: &str, user_id: u64) -> File {
+        serde_json::from_value(serde_json::json!({
+            "file_id": format!("id-{file_unique_id}"),
+            "file_unique_id": file_unique_id,
+            "thumbnail_file_id": "",
+            "thumbnail_file_unique_id": "",
+            "message_id": 1,
+            "user_id": user_id,
+            "file_name": "a.png",
+            "file_size": 3,
+            "mime_type": "image/png",
+            "add_time": 0,
+            "update_time": 0,
+            "file_path": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn save_upserts() {
+        let store = MemoryStore::new();
+        block_on(store.save(&[file("a", 1)])).unwrap();
+        let mut renamed = file("a", 1);
+        renamed.file_name = "b.png".to_string();
+        block_on(store.save(&[renamed])).unwrap();
+
+        assert_eq!(block_on(store.count_files()).unwrap(), 1);
+        assert_eq!(block_on(store.get("id-a")).unwrap().file_name, "b.png");
+        assert!(block_on(store.get("missing")).is_err());
+    }
+
+    #[test]
+    fn deleted_files_are_restored() {
+        let store = MemoryStore::new();
+        let mut f = file("a", 1);
+        f.file_hash = "h".to_string();
+        block_on(store.save(&[f])).unwrap();
+        block_on(store.delete_file("a")).unwrap();
+
+        assert!(block_on(store.get("a")).unwrap().is_deleted());
+        assert!(block_on(store.find_by_hash("h")).unwrap().is_none());
+        // sending the file again doesn't restore it
+        block_on(store.save(&[file("a", 2)])).unwrap();
+        assert!(block_on(store.get("a")).unwrap().is_deleted());
+        block_on(store.undelete_file("a")).unwrap();
+        assert!(block_on(store.find_by_hash("h")).unwrap().is_some());
+    }
+
+    #[test]
+    fn scores_keep_blocks() {
+        let store = MemoryStore::new();
+        block_on(store.save(&[file("a", 1)])).unwrap();
+        block_on(store.save_blocked("a", "report")).unwrap();
+        block_on(store.save_moderation("a", 0.1, "")).unwrap();
+
+        let f = block_on(store.get("a")).unwrap();
+        assert_eq!(f.blocked, "report");
+        assert_eq!(f.moderation_score, Some(0.1));
+    }
+
+    #[test]
+    fn forget_user_keeps_ban() {
+        let store = MemoryStore::new();
+        block_on(store.save(&[file("a", 1), file("b", 2)])).unwrap();
+        assert!(block_on(store.ban_user(1)).unwrap());
+        assert!(!block_on(store.ban_user(1)).unwrap());
+        block_on(store.forget_user(1)).unwrap();
+
+        assert!(block_on(store.user_files(1)).unwrap().is_empty());
+        assert_eq!(block_on(store.user_files(2)).unwrap().len(), 1);
+        assert!(block_on(store.is_banned(1)).unwrap());
+    }
+}
@@ -0,0 +1,98 @@
+// which files are accepted and served. ALLOWED_MIME_TYPES keeps a deployment to images or
+// media instead of a generic file dump, BLOCKED_EXTENSIONS refuses names like `.exe` whatever
+// type they claim to be
+
+use std::fmt;
+
+use crate::d1::File;
+
+#[derive(Clone, Default, Debug)]
+pub struct FileTypes {
+    // `image/png` or `image/*`, empty allows every type
+    allowed_mime_types: Vec<String>,
+    // lowercase without the dot
+    blocked_extensions: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Refusal {
+    MimeType(String),
+    Extension(String),
+}
+
+impl Refusal {
+    // `image/x-icon` or `.exe`, shown to users
+    pub fn kind(&self) -> String {
+        match self {
+            Refusal::MimeType(v) => v.clone(),
+            Refusal::Extension(v) => format!(".{}", v),
+        }
+    }
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} files are not allowed", self.kind())
+    }
+}
+
+impl FileTypes {
+    // comma separated lists of the env vars
+    pub fn new(allowed_mime_types: &str, blocked_extensions: &str) -> Self {
+        let list = |v: &str, trim: &[char]| {
+            v.split(',')
+                .map(|v| v.trim().trim_start_matches(trim).to_ascii_lowercase())
+                .filter(|v| !v.is_empty())
+                .collect::<Vec<_>>()
+        };
+        FileTypes {
+            allowed_mime_types: list(allowed_mime_types, &[]),
+            blocked_extensions: list(blocked_extensions, &['.']),
+        }
+    }
+
+    // `mime` without parameters, `name` is a file name or path with the extension
+    pub fn check(&self, mime: &str, name: &str) -> Result<(), Refusal> {
+        self.check_extension(name)?;
+
+        let mime = mime.to_ascii_lowercase();
+        if self.allowed_mime_types.is_empty()
+            || self
+                .allowed_mime_types
+                .iter()
+                .any(|v| match v.strip_suffix('*') {
+                    Some(prefix) => mime.starts_with(prefix),
+                    None => *v == mime,
+                })
+        {
+            return Ok(());
+        }
+        Err(Refusal::MimeType(mime))
+    }
+
+    pub fn check_extension(&self, name: &str) -> Result<(), Refusal> {
+        match name.rsplit_once('.') {
+            Some((_, ext)) if self.blocked_extensions.contains(&ext.to_ascii_lowercase()) => {
+                Err(Refusal::Extension(ext.to_ascii_lowercase()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_file(&self, file: &File) -> Result<(), Refusal> {
+        let mime = if !file.mime_type.is_empty() {
+            file.mime_type.as_str()
+        } else if file.is_image() {
+            // telegram photos are jpegs
+            "image/jpeg"
+        } else {
+            "application/octet-stream"
+        };
+        let name = if file.file_name.is_empty() {
+            &file.file_path
+        } else {
+            &file.file_name
+        };
+        self.check(mime, name)
+    }
+}
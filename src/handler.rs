@@ -1,48 +1,458 @@
+use crate::apikey;
+use crate::apikey::{Caller, KEY_PREFIX, Scope};
+use crate::consolelog;
+use crate::crypto::{constant_time_eq, sha256_hex};
+use crate::d1::{
+    Album, BLOCKED_REPORT, CUSTOM_HEADERS, ChannelCollection, D1, EventPage, File, FileFilter,
+    ListingSort, REPORT_TAKEN_DOWN, custom_header,
+};
+use crate::exif;
+use crate::exif::ExifStripper;
+use crate::export::Format;
+use crate::filestore::FileStore;
+use crate::hotlink::Hotlink;
+use crate::image;
+use crate::image::Transform;
+use crate::landing::{LandingMode, REPOSITORY_URL};
+use crate::listing::html_escape;
+use crate::metrics::{Event, Metrics};
+use crate::password::verify_password;
+use crate::privacy::client_key;
+use crate::qr;
+use crate::ratelimit;
+use crate::s3;
+use crate::security::{SecurityHeaders, SvgPolicy};
+use crate::state::{
+    BanReport, BatchDeleteReport, CleanupReport, DeleteItem, DeleteReport, DeleteStatus, Health,
+    InstanceState, KeyRotation, PurgeReport, SignedState, StateDiff, Stats, UndeleteReport,
+};
+use crate::storage::Storage;
+use crate::svg;
+use crate::tag;
+use crate::tasks;
 use crate::tg::TgBot;
+use crate::turnstile;
+use crate::turnstile::Turnstile;
+use crate::upload::{
+    BatchDeleteRequest, ChannelCollectionRequest, DownloadOptions, Imported, ListedFile,
+    PicGoUploaded, ReportRequest, Reported, UploadMeta, Uploaded, Validation, delete_token,
+    sanitize_file_name, verify_delete_token,
+};
+use crate::zip;
+use crate::zip::ZipWriter;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use frankenstein::updates::Update;
+use futures_util::StreamExt;
+use futures_util::future::join_all;
 use log::error;
 use log::info;
 use log::warn;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::path::Path;
 use std::sync::Arc;
 use wasm_bindgen::JsCast;
 use web_sys::ReadableStream;
 use worker::*;
 
-pub struct Handler {
+const CACHE_CONTROL: &str = "public, max-age=31536000";
+pub const DEFAULT_EDGE_CACHE_TTL: i32 = 31536000;
+const EVENTS_PAGE_SIZE: u32 = 1000;
+// ids of one POST /api/files:batchDelete and one /delete
+pub const MAX_BATCH_DELETE: usize = 100;
+// files of GET /admin/files without ?limit=
+const ADMIN_PAGE_SIZE: u32 = 100;
+// days of the uploads chart of GET /admin/stats
+const USAGE_DAYS: u64 = 30;
+// custom metadata of stored copies, the value of `cleaning`
+pub const CLEANED_METADATA: &str = "cleaned";
+const ADMIN_PAGE: &str = include_str!("admin.html");
+const EXIF_PAGE: &str = include_str!("exif.html");
+// the dashboard only talks to this origin and can't be framed
+const ADMIN_CSP: &str = "default-src 'none'; script-src 'unsafe-inline'; style-src 'unsafe-inline'; connect-src 'self'; frame-ancestors 'none'";
+// rows per D1 query of an export and per batch of an import
+const EXPORT_PAGE_SIZE: u32 = 500;
+const IMPORT_BATCH_SIZE: usize = 100;
+// no scripts, no requests back to this origin, an opaque origin for whatever is left
+const VIEW_CSP: &str = "sandbox; default-src 'none'; img-src https: data:; style-src 'unsafe-inline'; media-src https:";
+const LISTING_LIMIT: u32 = 1000;
+const FAVICON: &[u8] = include_bytes!("favicon.ico");
+// pixels of the thumbnail_url of uploads
+const THUMBNAIL_WIDTH: u32 = 320;
+// boundaries and part headers of an upload form around the file
+const MAX_FORM_OVERHEAD: u64 = 64 * 1024;
+// characters of an abuse report, longer ones are refused
+const MAX_REPORT_REASON: usize = 2000;
+const PREVIOUS_API_TOKEN_USED_SETTING: &str = "previous_api_token_used";
+
+pub struct Handler<S: Storage, D: FileStore = D1> {
     host: String,
-    pub r2: Option<Bucket>,
-    bot: Arc<TgBot>,
+    // of a bot of BOTS, its links start with /b/<name>
+    bot_name: String,
+    // names of all BOTS and PUBLIC_HOST, only exported with the state
+    bot_names: Vec<String>,
+    public_host: String,
+    // of the request being handled, in its logs, alerts and X-Request-Id
+    request_id: String,
+    pub storage: Option<S>,
+    // files stored in r2 are redirected to the public bucket instead of proxied, empty disables it
+    r2_public_base_url: String,
+    bot: Arc<TgBot<D>>,
     ctx: Arc<Context>,
     pub cache: Arc<Cache>,
+    api_token: String,
+    // still accepted while clients move to the new API_TOKEN
+    previous_api_token: String,
+    terms: String,
+    hotlink: Hotlink,
+    rate_limiter: Option<ObjectNamespace>,
+    // requests per minute per client, 0 disables rate limiting
+    rate_limit: u32,
+    metrics: Metrics,
+    // of files served to clients
+    cache_control: String,
+    // seconds the edge keeps files and telegram responses, negative disables caching
+    edge_cache_ttl: i32,
+    // bytes of an album zip, 0 disables them
+    album_zip_max_size: u64,
+    // ?w=, ?h= and ?fit= of downloads, needs image resizing on the zone
+    image_resizing: bool,
+    // avif and webp variants for clients that accept them, also needs image resizing
+    auto_format: bool,
+    // files of banned uploaders answer 410
+    hide_banned_files: bool,
+    security: SecurityHeaders,
+    // downloads need a solved challenge, None without TURNSTILE_SITE_KEY
+    turnstile: Option<Turnstile>,
+    landing_mode: LandingMode,
+    // public uploads shown on the landing page
+    landing_recent: u32,
+    // robots.txt lets crawlers index the files
+    index_files: bool,
+    // of PUT /s3/:bucket/:key, empty disables it
+    s3_access_key_id: String,
 }
 
-impl Handler {
-    pub fn new(host: String, r2: Option<Bucket>, bot: Arc<TgBot>, ctx: Arc<Context>) -> Self {
+impl<S: Storage, D: FileStore> Handler<S, D> {
+    pub fn new(host: String, storage: Option<S>, bot: Arc<TgBot<D>>, ctx: Arc<Context>) -> Self {
         Self {
             host,
-            r2,
+            bot_name: String::new(),
+            bot_names: vec![],
+            public_host: String::new(),
+            request_id: String::new(),
+            storage,
+            r2_public_base_url: String::new(),
             bot,
             ctx,
             cache: Arc::new(Cache::default()),
+            api_token: String::new(),
+            previous_api_token: String::new(),
+            terms: String::new(),
+            hotlink: Hotlink::default(),
+            rate_limiter: None,
+            rate_limit: 0,
+            metrics: Metrics::default(),
+            cache_control: CACHE_CONTROL.to_string(),
+            edge_cache_ttl: DEFAULT_EDGE_CACHE_TTL,
+            album_zip_max_size: 0,
+            image_resizing: false,
+            auto_format: false,
+            hide_banned_files: false,
+            security: SecurityHeaders::default(),
+            turnstile: None,
+            landing_mode: LandingMode::default(),
+            landing_recent: 0,
+            index_files: true,
+            s3_access_key_id: String::new(),
+        }
+    }
+
+    pub fn with_bot_name(mut self, bot_name: String) -> Self {
+        self.bot_name = bot_name;
+        self
+    }
+
+    pub fn with_routing(mut self, bot_names: Vec<String>, public_host: String) -> Self {
+        self.bot_names = bot_names;
+        self.public_host = public_host;
+        self
+    }
+
+    pub fn with_request_id(mut self, request_id: String) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    // work that outlives the response, logging with the id of its request
+    fn wait_until<F: Future<Output = ()> + 'static>(&self, future: F) {
+        self.ctx
+            .wait_until(consolelog::with_request_id(self.request_id.clone(), future));
+    }
+
+    // what links and the webhook url start with after `https://`
+    fn base(&self) -> String {
+        match self.bot_name.as_str() {
+            "" => self.host.clone(),
+            name => format!("{}/b/{}", self.host, name),
+        }
+    }
+
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    fn record(
+        &self,
+        mut event: Event,
+        result: &std::result::Result<Response, crate::error::Error>,
+    ) {
+        event.status = match result {
+            Ok(v) => v.status_code(),
+            Err(e) => e.status_code(),
+        };
+        self.metrics.write(&event);
+
+        let counters = self.metrics.counters(&event);
+        let downloaded = self.metrics.downloaded(&event).map(str::to_string);
+        if counters.is_empty() && downloaded.is_none() {
+            return;
+        }
+
+        let d1 = self.bot.d1.clone();
+        self.wait_until(async move {
+            if let Err(e) = d1.increment(&counters, downloaded.as_deref()).await {
+                warn!("increment counters failed: {}", e);
+            }
+        });
+    }
+
+    pub fn with_rate_limit(
+        mut self,
+        rate_limiter: Option<ObjectNamespace>,
+        rate_limit: u32,
+    ) -> Self {
+        self.rate_limiter = rate_limiter;
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    async fn check_rate_limit(
+        &self,
+        req: &Request,
+    ) -> std::result::Result<(), crate::error::Error> {
+        if self.rate_limit == 0 {
+            return Ok(());
+        }
+
+        if let (Some(namespace), Some(key)) = (&self.rate_limiter, client_key(req))
+            && let Some(retry_after) = ratelimit::check(namespace, &key, self.rate_limit).await?
+        {
+            return Err(crate::error::Error::TooManyRequests(retry_after));
+        }
+
+        Ok(())
+    }
+
+    pub fn with_r2_public_base_url(mut self, r2_public_base_url: String) -> Self {
+        self.r2_public_base_url = r2_public_base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_cache_control(
+        mut self,
+        max_age: u64,
+        immutable: bool,
+        stale_while_revalidate: u64,
+    ) -> Self {
+        let mut cache_control = format!("public, max-age={}", max_age);
+        if immutable {
+            cache_control.push_str(", immutable");
+        }
+        if stale_while_revalidate > 0 {
+            cache_control.push_str(&format!(
+                ", stale-while-revalidate={}",
+                stale_while_revalidate
+            ));
+        }
+        self.cache_control = cache_control;
+        self
+    }
+
+    pub fn with_edge_cache_ttl(mut self, edge_cache_ttl: i32) -> Self {
+        self.edge_cache_ttl = edge_cache_ttl;
+        self
+    }
+
+    pub fn with_album_zip_max_size(mut self, album_zip_max_size: u64) -> Self {
+        self.album_zip_max_size = album_zip_max_size;
+        self
+    }
+
+    pub fn with_image_resizing(mut self, image_resizing: bool) -> Self {
+        self.image_resizing = image_resizing;
+        self
+    }
+
+    pub fn with_auto_format(mut self, auto_format: bool) -> Self {
+        self.auto_format = auto_format;
+        self
+    }
+
+    pub fn with_hide_banned_files(mut self, hide_banned_files: bool) -> Self {
+        self.hide_banned_files = hide_banned_files;
+        self
+    }
+
+    pub fn with_security_headers(mut self, security: SecurityHeaders) -> Self {
+        self.security = security;
+        self
+    }
+
+    pub fn with_landing(mut self, landing_mode: LandingMode, landing_recent: u32) -> Self {
+        self.landing_mode = landing_mode;
+        self.landing_recent = landing_recent.min(LISTING_LIMIT);
+        self
+    }
+
+    pub fn with_index_files(mut self, index_files: bool) -> Self {
+        self.index_files = index_files;
+        self
+    }
+
+    pub fn with_s3_access_key_id(mut self, s3_access_key_id: String) -> Self {
+        self.s3_access_key_id = s3_access_key_id;
+        self
+    }
+
+    pub fn with_turnstile(mut self, turnstile: Option<Turnstile>) -> Self {
+        self.turnstile = turnstile;
+        self
+    }
+
+    pub fn with_hotlink(mut self, hotlink: Hotlink) -> Self {
+        self.hotlink = hotlink;
+        self
+    }
+
+    pub fn with_terms(mut self, terms: String) -> Self {
+        self.terms = terms;
+        self
+    }
+
+    pub fn with_api_token(mut self, api_token: String) -> Self {
+        self.api_token = api_token;
+        self
+    }
+
+    pub fn with_previous_api_token(mut self, previous_api_token: String) -> Self {
+        self.previous_api_token = previous_api_token;
+        self
+    }
+
+    // the operator with API_TOKEN or API_TOKEN_PREVIOUS, or a user with a key of /token
+    async fn authenticate(
+        &self,
+        req: &Request,
+        scope: Scope,
+    ) -> std::result::Result<Caller, crate::error::Error> {
+        let unauthorized = || crate::error::Error::Unauthorized("unauthorized".into());
+        let authorization = req.headers().get("Authorization")?.unwrap_or_default();
+        let token = authorization
+            .strip_prefix("Bearer ")
+            .ok_or_else(unauthorized)?;
+
+        if !token.starts_with(KEY_PREFIX) {
+            return match self.is_operator_token(token) {
+                true => Ok(Caller::Operator),
+                false => Err(unauthorized()),
+            };
+        }
+
+        let key = self
+            .bot
+            .d1
+            .find_api_key(&apikey::hash(token))
+            .await?
+            .ok_or_else(unauthorized)?;
+        if !key.allows(scope) {
+            return Err(crate::error::Error::Forbidden(format!(
+                "the key has no {} scope",
+                scope.name()
+            )));
+        }
+        if self.bot.d1.is_banned(key.user_id).await? {
+            return Err(crate::error::Error::Forbidden("user is banned".into()));
+        }
+
+        let d1 = self.bot.d1.clone();
+        let key_id = key.key_id.clone();
+        self.wait_until(async move {
+            if let Err(e) = d1.touch_api_key(&key_id).await {
+                warn!("save api key use failed: {}", e);
+            }
+        });
+        Ok(Caller::User(key))
+    }
+
+    fn is_operator_token(&self, token: &str) -> bool {
+        if self.api_token.is_empty() {
+            return false;
+        }
+
+        let token = token.as_bytes();
+        match token {
+            v if constant_time_eq(v, self.api_token.as_bytes()) => true,
+            v if !self.previous_api_token.is_empty()
+                && constant_time_eq(v, self.previous_api_token.as_bytes()) =>
+            {
+                // remembered so operators can tell when the previous token is safe to remove
+                let d1 = self.bot.d1.clone();
+                self.wait_until(async move {
+                    let now = Date::now().as_millis().to_string();
+                    if let Err(e) = d1.set_setting(PREVIOUS_API_TOKEN_USED_SETTING, &now).await {
+                        warn!("save previous api token use failed: {}", e);
+                    }
+                });
+                true
+            }
+            _ => false,
         }
     }
 
-    pub async fn put_to_r2(
+    pub async fn put_to_storage(
         &self,
+        file_id: &str,
         key: &str,
         data: ReadableStream,
+        (http_metadata, custom_metadata): (HttpMetadata, HashMap<String, String>),
+        overwrite: bool,
     ) -> std::result::Result<ReadableStream, crate::error::Error> {
-        if let Some(v) = &self.r2 {
+        if let Some(v) = &self.storage {
+            // another request already stored it while this one was downloading from telegram
+            if !overwrite && let Ok(true) = v.head(key).await {
+                info!("stored object {} exists, skip put", key);
+                return Ok(data);
+            }
+
             let (s1, s2) = splite_readable_stream(data)?;
 
+            let file_id = file_id.to_string();
             let key = key.to_string();
             let v = v.clone();
+            let d1 = self.bot.d1.clone();
 
-            self.ctx.wait_until(async move {
-                if let Err(e) = v.put(key, s2).execute().await {
+            self.wait_until(async move {
+                if let Err(e) = v.put(&key, s2, http_metadata, custom_metadata).await {
                     error!("Put file error: {:#?}", e);
+                    // retried by the cron, otherwise the file is fetched from telegram on every cache miss
+                    if let Err(e) = d1
+                        .save_task(tasks::R2_PUT, &file_id, &key, &e.to_string())
+                        .await
+                    {
+                        error!("save pending task failed: {}", e);
+                    }
                 }
             });
 
@@ -53,26 +463,35 @@ impl Handler {
     }
 
     pub async fn get_cache(&self, key: &Request) -> Option<Response> {
-        if let Ok(v) = self.cache.get(CacheKey::from(key), true).await {
-            v
-        } else {
-            None
-        }
+        self.cache
+            .get(CacheKey::from(key), true)
+            .await
+            .unwrap_or_default()
     }
 
     pub async fn put_cache(
         &self,
         key: Request,
         data: ReadableStream,
+        http_metadata: &HttpMetadata,
     ) -> std::result::Result<ReadableStream, crate::error::Error> {
         let (s1, s2) = splite_readable_stream(data)?;
 
         let cache = self.cache.clone();
+        let headers = typed_headers(http_metadata)?;
+        // the edge keeps the copy for s-maxage, clients see the rest on a hit
+        headers.set(
+            "Cache-Control",
+            &format!(
+                "{}, s-maxage={}",
+                self.cache_control,
+                self.edge_cache_ttl.max(0)
+            ),
+        )?;
 
-        self.ctx.wait_until(async move {
+        self.wait_until(async move {
             let resp = ResponseBuilder::new()
-                .with_header("Cache-Control", "public, max-age=31536000")
-                .unwrap_or_else(|_| ResponseBuilder::new())
+                .with_headers(headers)
                 .body(ResponseBody::Stream(s2));
 
             if let Err(e) = cache.put(CacheKey::from(&key), resp).await {
@@ -83,164 +502,2871 @@ impl Handler {
         Ok(s1)
     }
 
+    // the public bucket serves objects without the headers of the worker, so only media that
+    // needs none of them is sent there, and only copies cleaned like the worker would clean them
+    async fn public_r2_url(&self, file: &File, ext: &str) -> Option<Url> {
+        if self.r2_public_base_url.is_empty()
+            || file.is_protected()
+            || is_active_content(file)
+            || self.security.is_attachment(&file_mime(file))
+            || !file.headers().is_empty()
+        {
+            return None;
+        }
+
+        let key = format!("{}.{}", file.file_unique_id, ext);
+        match self.storage.as_ref()?.public_metadata(&key).await {
+            Ok(Some(v))
+                if v.get(CLEANED_METADATA).map(String::as_str)
+                    == Some(cleaning(&self.bot, Some(file), ext)) =>
+            {
+                Url::parse(&format!("{}/{}", self.r2_public_base_url, key)).ok()
+            }
+            _ => None,
+        }
+    }
+
+    // tees the stream at most once: a file fetched from telegram is copied to the storage when it's bound,
+    // otherwise to the edge cache under `cache_key`, the cache is filled from the storage on the next miss.
+    // also returns the content type and disposition, the ones stored with the object if any.
+    // `refresh` skips the stored copy and overwrites it with the one from telegram
     async fn get_file(
         &self,
         file_id: &str,
         ext: &str,
-    ) -> std::result::Result<ReadableStream, crate::error::Error> {
-        let (url, file_uniq_id) = self.bot.get_file_url(file_id, false).await?;
+        file: Option<&File>,
+        cache_key: Option<Request>,
+        refresh: bool,
+    ) -> std::result::Result<(ReadableStream, bool, HttpMetadata), crate::error::Error> {
+        let (url, file_uniq_id) = self.bot.get_file_url(file_id, refresh).await?;
 
         let r2_key = format!("{}.{}", file_uniq_id, ext);
 
-        // get from r2 cache first
-        if let Some(r2) = self.r2.as_ref()
-            && let Ok(Some(v)) = r2.get(&r2_key).execute().await
-            && let Some(body) = v.body()
-            && let Ok(ResponseBody::Stream(s)) = body.response_body()
+        // the public bucket would serve protected files to anyone who guesses the key
+        let storage = self.storage.as_ref().filter(|_| {
+            self.r2_public_base_url.is_empty() || !file.is_some_and(File::is_protected)
+        });
+
+        // get from the storage first
+        if !refresh
+            && let Some(storage) = storage
+            && let Ok(Some(v)) = storage.get(&r2_key).await
         {
-            info!("use r2 cache");
-            return Ok(s);
+            info!("use stored copy");
+            // copies stored before STRIP_EXIF or SVG_POLICY were set still have it
+            let s = cleaned(&self.bot, v.body, file, ext)?;
+            // objects stored before metadata was written have none
+            let mut http_metadata = v.http_metadata;
+            if http_metadata.content_type.is_none() {
+                http_metadata = r2_metadata(file, ext).0;
+            }
+            let s = match cache_key {
+                Some(key) => self.put_cache(key, s, &http_metadata).await?,
+                None => s,
+            };
+            return Ok((s, true, http_metadata));
         }
 
         info!("download from raw");
 
-        let stream = match download(url).await? {
+        let stream = match download(url, self.edge_cache_ttl).await? {
             DownloadResult::Stream(v) => v,
             DownloadResult::NotFound => {
                 // retry to get path
                 warn!("file not found, retry to get new path");
                 let (url, _) = self.bot.get_file_url(file_id, true).await?;
-                match download(url).await? {
+                match download(url, self.edge_cache_ttl).await? {
                     DownloadResult::Stream(v) => v,
                     DownloadResult::NotFound => {
-                        return Err(crate::error::Error("file not found".into()));
+                        return Err(crate::error::Error::NotFound("file not found".into()));
                     }
                 }
             }
         };
 
-        self.put_to_r2(&r2_key, stream).await
+        let stream = cleaned(&self.bot, stream, file, ext)?;
+        let mut metadata = r2_metadata(file, ext);
+        metadata.1.insert(
+            CLEANED_METADATA.to_string(),
+            cleaning(&self.bot, file, ext).to_string(),
+        );
+        let http_metadata = metadata.0.clone();
+        let stream = match cache_key {
+            Some(key) if self.storage.is_none() => {
+                self.put_cache(key, stream, &http_metadata).await?
+            }
+            _ if storage.is_none() => stream,
+            _ => {
+                self.put_to_storage(file_id, &r2_key, stream, metadata, refresh)
+                    .await?
+            }
+        };
+        Ok((stream, false, http_metadata))
     }
 
     pub async fn download(
         &self,
-        _req: Request,
+        req: Request,
         ctx: RouteContext<()>,
     ) -> std::result::Result<Response, crate::error::Error> {
-        let file_name = match ctx.param("file_id") {
-            Some(v) => v,
-            None => return Err(crate::error::Error("file name is not found".into())),
+        let mut event = Event::new("download");
+        let result = self.download_file(req, ctx, &mut event).await;
+        self.record(event, &result);
+
+        if let Err(e) = &result
+            && e.is_unexpected()
+        {
+            let bot = self.bot.clone();
+            let request_id = self.request_id.clone();
+            let error = e.to_string();
+            self.wait_until(async move {
+                bot.alert("download", &request_id, &error).await;
+            });
+        }
+
+        result
+    }
+
+    // the hotlink and turnstile checks of every route that hands out the content of a file,
+    // Some is the response sent instead
+    fn guard(&self, req: &Request) -> std::result::Result<Option<Response>, crate::error::Error> {
+        let referer = req.headers().get("Referer")?;
+        if !self.hotlink.allows(&self.host, referer.as_deref()) {
+            if self.hotlink.placeholder.is_empty() {
+                return Err(crate::error::Error::Forbidden(
+                    "hotlinking is not allowed".into(),
+                ));
+            }
+            let placeholder = Url::parse(&self.hotlink.placeholder)
+                .map_err(|e| crate::error::Error::Internal(e.to_string()))?;
+            return Ok(Some(Response::redirect(placeholder)?));
+        }
+
+        if let Some(turnstile) = &self.turnstile
+            && !turnstile.passed(req)
+        {
+            return Ok(Some(challenge(turnstile)?));
+        }
+        Ok(None)
+    }
+
+    async fn download_file(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+        event: &mut Event,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.check_rate_limit(&req).await?;
+
+        let (file_id, ext) = file_param(&ctx)?;
+
+        if let Some(resp) = self.guard(&req)? {
+            return Ok(resp);
+        }
+
+        let file = self.bot.find_file(&file_id).await?;
+        if let Some(f) = &file {
+            check_available(f)?;
+            self.check_uploader(f).await?;
+        }
+        self.check_type(file.as_ref(), &ext)?;
+        event.mime_type = mime_from_ext(&ext).to_string();
+        if let Some(f) = &file {
+            event.user_id = f.user_id;
+            event.file_unique_id = f.file_unique_id.clone();
+            if !f.mime_type.is_empty() {
+                event.mime_type = f.mime_type.clone();
+            }
+        }
+
+        let protected = match &file {
+            Some(f) if f.is_protected() => {
+                if !unlocked(&req, f) {
+                    return Ok(password_required()?);
+                }
+                true
+            }
+            _ => false,
         };
 
-        let p = Path::new(file_name);
-        let file_id = p.file_stem().unwrap_or_default().to_string_lossy();
-        let ext = p.extension().unwrap_or_default().to_string_lossy();
+        // the message was edited to another file, the cached copy of this one is stale
+        if let Some(f) = &file
+            && !f.replaced_by.is_empty()
+            && let Some(new) = self.bot.d1.find(&f.replaced_by).await?
+        {
+            let url = Url::parse(&new.url(&self.base()))
+                .map_err(|e| crate::error::Error::Internal(e.to_string()))?;
+            return Ok(Response::redirect(url)?);
+        }
 
-        let url = format!("https://{}/f/{}.{}", self.host, file_id, ext);
+        let query = req.query::<HashMap<String, String>>().unwrap_or_default();
 
-        let cache_key = Request::new(&url, Method::Get)?;
+        // scaled variants, the parameters are ignored where image resizing isn't enabled
+        let mut transform = if self.image_resizing {
+            Transform::from_query(&query)?
+        } else {
+            None
+        };
+        // the same link serves avif, webp or the original depending on the Accept header
+        let negotiated = self.auto_format && file.as_ref().is_some_and(image::negotiable);
+        if negotiated
+            && let Some(format) =
+                image::negotiate(&req.headers().get("Accept")?.unwrap_or_default())
+        {
+            transform.get_or_insert_default().format = Some(format);
+        }
+        if let Some(transform) = transform {
+            let Some(f) = &file else {
+                return Err(crate::error::Error::NotFound("file not found".into()));
+            };
+            let resp = self
+                .download_transformed(f, &ext, protected, &transform, event)
+                .await?;
+            return vary_accept(resp, negotiated);
+        }
 
-        // let no_cache = req
-        //     .query::<HashMap<String, String>>()
-        //     .unwrap_or_default()
-        //     .get("no_cache")
-        //     .unwrap_or(&"false".to_string())
-        //     .parse::<bool>()
-        //     .unwrap_or_default();
+        // re-fetches a corrupt copy from telegram, only for the holder of API_TOKEN
+        let refresh = match query.get("refresh") {
+            Some(token) if self.is_operator_token(token) => true,
+            Some(_) => {
+                return Err(crate::error::Error::Unauthorized(
+                    "invalid refresh token".into(),
+                ));
+            }
+            None => false,
+        };
 
-        // if !no_cache {
-        if let Some(v) = self.get_cache(&cache_key).await {
-            return Ok(v);
+        // the public bucket has no password check
+        if !protected
+            && !refresh
+            && let Some(f) = &file
+            && let Some(url) = self.public_r2_url(f, &ext).await
+        {
+            event.cache = "r2";
+            return Ok(Response::redirect(url)?);
         }
-        // }
 
-        let stream = self.get_file(file_id.as_ref(), ext.as_ref()).await?;
+        // short ids, aliases and file ids of a known file share the entry of its file_unique_id,
+        // the one purge_copies removes
+        let cached_id = file.as_ref().map_or(&file_id, |f| &f.file_unique_id);
+        let url = format!("https://{}/f/{}.{}", self.base(), cached_id, ext);
 
-        let stream = self.put_cache(cache_key, stream).await?;
+        let cache_key = Request::new(&url, Method::Get)?;
 
-        Ok(ResponseBuilder::new()
-            .with_header("Cache-Control", "public, max-age=31536000")?
-            .body(ResponseBody::Stream(stream)))
-    }
+        // the refreshed copy is only written to the edge cache without a storage,
+        // otherwise the next miss fills it from the storage
+        if refresh
+            && self.storage.is_some()
+            && let Err(e) = self.cache.delete(CacheKey::from(&cache_key), true).await
+        {
+            warn!("purge cache of {} failed: {}", url, e);
+        }
 
-    pub async fn telegram(
-        &self,
-        mut req: Request,
-        _ctx: RouteContext<()>,
-    ) -> std::result::Result<(), crate::error::Error> {
-        let update = req.json::<Update>().await?;
-        info!("body: {:?}", update);
-        self.bot.handle(&self.host, update).await?;
-        Ok(())
+        // protected files skip the edge cache, it would serve them without the password check
+        if !protected
+            && !refresh
+            && let Some(v) = self.get_cache(&cache_key).await
+        {
+            event.cache = "hit";
+            return vary_accept(decorate(v, file.as_ref(), &self.security)?, negotiated);
+        }
+        let (stream, from_r2, http_metadata) = self
+            .get_file(
+                &file_id,
+                &ext,
+                file.as_ref(),
+                (!protected).then_some(cache_key),
+                refresh,
+            )
+            .await?;
+        event.cache = if from_r2 {
+            "r2"
+        } else if protected {
+            "bypass"
+        } else {
+            "miss"
+        };
+
+        let headers = typed_headers(&http_metadata)?;
+        headers.set(
+            "Cache-Control",
+            if protected || refresh {
+                "private"
+            } else {
+                &self.cache_control
+            },
+        )?;
+        let resp = ResponseBuilder::new()
+            .with_headers(headers)
+            .body(ResponseBody::Stream(stream));
+
+        vary_accept(decorate(resp, file.as_ref(), &self.security)?, negotiated)
     }
 
-    pub async fn register(
+    // variants aren't stored, the edge caches each one under its own query
+    async fn download_transformed(
         &self,
-        _: Request,
-        _ctx: RouteContext<()>,
-    ) -> std::result::Result<(), crate::error::Error> {
-        let url = format!("https://{}/tgbot", self.host);
-        self.bot.set_webhook(url.as_ref()).await?;
-        Ok(())
+        file: &File,
+        ext: &str,
+        protected: bool,
+        transform: &Transform,
+        event: &mut Event,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        if !file.is_image() {
+            return Err(crate::error::Error::BadRequest(
+                "only images can be resized".into(),
+            ));
+        }
+
+        let cache_key = Request::new(
+            &format!(
+                "https://{}/f/{}.{}?{}",
+                self.base(),
+                file.file_unique_id,
+                ext,
+                transform.query()
+            ),
+            Method::Get,
+        )?;
+        if !protected && let Some(v) = self.get_cache(&cache_key).await {
+            event.cache = "hit";
+            return decorate(v, Some(file), &self.security);
+        }
+
+        let (url, _) = self.bot.get_file_url(&file.file_id, false).await?;
+        let stream = match download_transformed(url, self.edge_cache_ttl, transform).await? {
+            DownloadResult::Stream(v) => v,
+            DownloadResult::NotFound => {
+                let (url, _) = self.bot.get_file_url(&file.file_id, true).await?;
+                match download_transformed(url, self.edge_cache_ttl, transform).await? {
+                    DownloadResult::Stream(v) => v,
+                    DownloadResult::NotFound => {
+                        return Err(crate::error::Error::NotFound("file not found".into()));
+                    }
+                }
+            }
+        };
+
+        let mut http_metadata = r2_metadata(Some(file), ext).0;
+        if let Some(v) = transform.content_type() {
+            http_metadata.content_type = Some(v);
+        }
+        let stream = if protected {
+            event.cache = "bypass";
+            stream
+        } else {
+            event.cache = "miss";
+            self.put_cache(cache_key, stream, &http_metadata).await?
+        };
+
+        let headers = typed_headers(&http_metadata)?;
+        headers.set(
+            "Cache-Control",
+            if protected {
+                "private"
+            } else {
+                &self.cache_control
+            },
+        )?;
+        let resp = ResponseBuilder::new()
+            .with_headers(headers)
+            .body(ResponseBody::Stream(stream));
+
+        decorate(resp, Some(file), &self.security)
     }
 
-    pub async fn init_database(
+    // html and text documents rendered under a sandboxing CSP instead of being downloaded
+    pub async fn view(
         &self,
-        _: Request,
-        _ctx: RouteContext<()>,
-    ) -> std::result::Result<(), crate::error::Error> {
-        self.bot.d1.init().await?;
-        Ok(())
-    }
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.check_rate_limit(&req).await?;
 
-    pub fn github_page(_: Request, _: RouteContext<()>) -> Result<Response> {
-        return Response::redirect(
-            Url::parse("https://github.com/Asutorufa/tg-image-hosting").unwrap(),
-        );
-    }
-}
+        let (file_id, ext) = file_param(&ctx)?;
+        if let Some(resp) = self.guard(&req)? {
+            return Ok(resp);
+        }
+        let file = self.bot.d1.get(&file_id).await?;
+        check_available(&file)?;
+        self.check_uploader(&file).await?;
+        self.check_type(Some(&file), &ext)?;
 
-fn splite_readable_stream(
-    r: ReadableStream,
-) -> std::result::Result<(ReadableStream, ReadableStream), crate::error::Error> {
-    let tee_off = r.tee();
-    Ok((tee_off.get(0).dyn_into()?, tee_off.get(1).dyn_into()?))
-}
+        if file.is_protected() && !unlocked(&req, &file) {
+            return Ok(password_required()?);
+        }
+        // jpeg documents that keep their EXIF are shown with what the camera recorded
+        if !self.bot.strip_exif && exif::applies(Some(&file), &ext) {
+            return self.exif_page(&file).await;
+        }
+        if !is_viewable(&file) {
+            return Err(crate::error::Error::BadRequest(
+                "only html, text and jpeg documents can be viewed".into(),
+            ));
+        }
 
-pub enum DownloadResult {
-    Stream(ReadableStream),
-    NotFound,
-}
-async fn download(url: String) -> std::result::Result<DownloadResult, crate::error::Error> {
-    let request = Request::new_with_init(
-        url.as_str(),
-        &RequestInit {
-            method: Method::Get,
-            cf: CfProperties {
-                cache_ttl_by_status: Some(HashMap::from([("200-299".to_string(), 31536000)])),
-                ..CfProperties::default()
-            },
-            ..RequestInit::default()
-        },
-    )?;
+        let (stream, _, _) = self
+            .get_file(&file_id, &ext, Some(&file), None, false)
+            .await?;
 
-    let mut response = Fetch::Request(request).send().await?;
+        let mime = file_mime(&file);
+        let content_type = if mime.contains("charset") {
+            mime
+        } else {
+            format!("{}; charset=utf-8", mime)
+        };
 
-    if response.status_code() == 404 {
-        return Ok(DownloadResult::NotFound);
+        let headers = Headers::new();
+        headers.set("Content-Type", &content_type)?;
+        headers.set("Content-Security-Policy", VIEW_CSP)?;
+        headers.set("X-Content-Type-Options", "nosniff")?;
+        headers.set(
+            "Cache-Control",
+            if file.is_protected() {
+                "private"
+            } else {
+                &self.cache_control
+            },
+        )?;
+
+        Ok(ResponseBuilder::new()
+            .with_headers(headers)
+            .body(ResponseBody::Stream(stream)))
     }
 
-    if response.status_code() != 200 {
-        return Err(crate::error::Error(format!(
-            "status code is not 200, but {}, {}",
-            response.status_code(),
-            response.text().await?
-        )));
+    // the location only when the uploader enabled it with /location on
+    async fn exif_page(&self, file: &File) -> std::result::Result<Response, crate::error::Error> {
+        let mut metadata = match self.bot.exif(file).await {
+            Ok(v) => v.unwrap_or_default(),
+            Err(e) => {
+                warn!("Read EXIF of {} failed: {}", file.file_unique_id, e);
+                exif::Metadata::default()
+            }
+        };
+        if metadata.location.is_some() && !self.bot.d1.shows_location(file.user_id).await? {
+            metadata.location = None;
+        }
+
+        let rows = [
+            ("Camera", metadata.camera.clone()),
+            ("Lens", metadata.lens.clone()),
+            ("Exposure", metadata.exposure.clone()),
+            ("Taken", metadata.taken.clone()),
+            ("Location", metadata.location().unwrap_or_default()),
+        ]
+        .into_iter()
+        .filter(|(_, v)| !v.is_empty())
+        .map(|(k, v)| format!("<tr><th>{}</th><td>{}</td></tr>\n", k, html_escape(&v)))
+        .collect::<String>();
+        let name = match file.download_name.as_str() {
+            "" if file.file_name.is_empty() => &file.file_unique_id,
+            "" => &file.file_name,
+            v => v,
+        };
+        let body = EXIF_PAGE
+            .replace("{title}", &html_escape(name))
+            .replace("{alt}", &html_escape(&file.alt_text))
+            .replace("{image}", &html_escape(&file.unique_url(&self.base())))
+            .replace("{rows}", &rows);
+
+        let headers = Headers::new();
+        headers.set("Content-Type", "text/html; charset=utf-8")?;
+        headers.set("Content-Security-Policy", VIEW_CSP)?;
+        headers.set("X-Content-Type-Options", "nosniff")?;
+        // short, /location changes what the page shows
+        headers.set(
+            "Cache-Control",
+            if file.is_protected() {
+                "private"
+            } else {
+                "public, max-age=300"
+            },
+        )?;
+        Ok(Response::ok(body)?.with_headers(headers))
     }
 
-    let stream = match &response.body() {
+    pub async fn head(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let (file_id, ext) = file_param(&ctx)?;
+
+        let file = match self.bot.d1.find(&file_id).await? {
+            Some(v) => v,
+            None => return Err(crate::error::Error::NotFound("file not found".into())),
+        };
+        check_available(&file)?;
+        self.check_uploader(&file).await?;
+        self.check_type(Some(&file), &ext)?;
+
+        if file.is_protected() && !unlocked(&req, &file) {
+            return Ok(password_required()?);
+        }
+
+        let mime_type = if file.mime_type.is_empty() {
+            mime_from_ext(&ext)
+        } else {
+            file.mime_type.as_str()
+        };
+
+        let headers = Headers::new();
+        headers.set("Content-Type", mime_type)?;
+        // the served file is smaller than the one telegram reported
+        if file.file_size > 0
+            && !(self.bot.strip_exif && exif::applies(Some(&file), &ext))
+            && !(self.bot.svg_policy == SvgPolicy::Sanitize && svg::applies(Some(&file), &ext))
+        {
+            headers.set("Content-Length", &file.file_size.to_string())?;
+        }
+        headers.set("ETag", &format!("\"{}\"", file.file_unique_id))?;
+        for (k, v) in file_headers(&file, &self.security) {
+            headers.set(&k, &v)?;
+        }
+        headers.set(
+            "Cache-Control",
+            if file.is_protected() {
+                "private"
+            } else {
+                &self.cache_control
+            },
+        )?;
+
+        Ok(Response::empty()?.with_headers(headers))
+    }
+
+    // emergency takedown of the cached and stored copies, the edge would keep serving them until they expire
+    pub async fn purge(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let caller = self.authenticate(&req, Scope::Delete).await?;
+
+        let file_unique_id = match ctx.param("file_unique_id") {
+            Some(v) => v.to_string(),
+            None => {
+                return Err(crate::error::Error::BadRequest(
+                    "file unique id is empty".into(),
+                ));
+            }
+        };
+        let file = self.bot.d1.get(&file_unique_id).await?;
+        if !caller.can_manage(&file) {
+            return Err(crate::error::Error::Forbidden("not your file".into()));
+        }
+
+        let report = purge_copies(&self.base(), &self.cache, self.storage.as_ref(), &file).await?;
+
+        info!(
+            "purged {}: {} cache entries, {} stored objects",
+            file.file_unique_id, report.cache_entries, report.stored_objects
+        );
+        Ok(Response::from_json(&report)?)
+    }
+
+    pub async fn update_file(
+        &self,
+        mut req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let caller = self.authenticate(&req, Scope::Upload).await?;
+
+        let (file_id, _) = file_param(&ctx)?;
+        let file = self.bot.d1.get(&file_id).await?;
+        if !caller.can_manage(&file) {
+            return Err(crate::error::Error::Forbidden("not your file".into()));
+        }
+        let options = req.json::<DownloadOptions>().await?;
+
+        let download_name = match options.download_name {
+            Some(v) => sanitize_file_name(&v),
+            None => file.download_name.clone(),
+        };
+        let headers = match options.headers {
+            Some(v) => v
+                .into_iter()
+                .map(|(k, v)| match custom_header(&k) {
+                    Some(name) => Ok((name.to_string(), v)),
+                    None => Err(crate::error::Error::BadRequest(format!(
+                        "header {} is not allowed, allowed are {}",
+                        k,
+                        CUSTOM_HEADERS.join(", ")
+                    ))),
+                })
+                .collect::<std::result::Result<BTreeMap<_, _>, _>>()?,
+            None => file.headers(),
+        };
+
+        self.bot
+            .d1
+            .save_download_options(&file.file_unique_id, &download_name, &headers)
+            .await?;
+        self.bot.uncache(&file.file_unique_id).await;
+        // like /rename, the next download stores a copy with the new name
+        if download_name != file.download_name
+            && let Some(storage) = self.storage.as_ref()
+        {
+            delete_stored_copies(storage, &file).await?;
+        }
+
+        Ok(Response::from_json(&DownloadOptions {
+            download_name: Some(download_name),
+            headers: Some(headers),
+        })?)
+    }
+
+    // files of the caller that aren't deleted, API_TOKEN lists the uploads of the api.
+    // ?tag= keeps the files with that tag
+    pub async fn files(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let caller = self.authenticate(&req, Scope::List).await?;
+        let tag = tag_param(&req)?;
+
+        let files = self
+            .bot
+            .d1
+            .user_files(caller.user_id())
+            .await?
+            .into_iter()
+            .filter(|f| !f.is_deleted())
+            .collect::<Vec<_>>();
+        let tags = self.bot.d1.files_tags(&files).await?;
+        let files = files
+            .iter()
+            .filter(|f| has_tag(&tags, f, &tag))
+            .map(|f| ListedFile::new(f, &self.base(), &tags))
+            .collect::<Vec<_>>();
+        Ok(Response::from_json(&files)?)
+    }
+
+    pub async fn validate_upload(
+        &self,
+        mut req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Upload).await?;
+
+        let meta = req.json::<UploadMeta>().await?;
+
+        let validation = if let Err(reason) = meta.check_policy(self.bot.max_file_size) {
+            Validation::rejected(reason)
+        } else if let Err(e) = self.bot.file_types.check(&meta.mime_type, "") {
+            Validation::rejected(e.to_string())
+        } else if !meta.hash.is_empty()
+            && let Some(f) = self
+                .bot
+                .d1
+                .find_by_hash(&meta.hash.to_ascii_lowercase())
+                .await?
+        {
+            Validation::deduplicated(f.url(&self.base()))
+        } else {
+            Validation::accepted()
+        };
+
+        Ok(Response::from_json(&validation)?)
+    }
+
+    pub async fn upload(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let mut event = Event::new("upload");
+        let result = self.upload_file(req, ctx, &mut event).await;
+        self.record(event, &result);
+        result
+    }
+
+    async fn upload_file(
+        &self,
+        mut req: Request,
+        _ctx: RouteContext<()>,
+        event: &mut Event,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.check_rate_limit(&req).await?;
+
+        let caller = self.authenticate(&req, Scope::Upload).await?;
+
+        // the form is read into memory, bodies that can't hold an allowed file aren't
+        let max_size = self.bot.max_file_size;
+        if req
+            .headers()
+            .get("Content-Length")?
+            .and_then(|v| v.parse::<u64>().ok())
+            .is_some_and(|v| v > max_size + MAX_FORM_OVERHEAD)
+        {
+            return Err(too_large(max_size));
+        }
+
+        let query = req.query::<HashMap<String, String>>().unwrap_or_default();
+        let picgo = match query.get("format").map(String::as_str) {
+            None | Some("") | Some("json") => false,
+            Some("picgo") => true,
+            Some(_) => {
+                return Err(crate::error::Error::BadRequest(
+                    "format must be json or picgo".into(),
+                ));
+            }
+        };
+
+        let form = req.form_data().await?;
+        let file = match form.get("file") {
+            Some(FormEntry::File(v)) => v,
+            _ => {
+                return Err(crate::error::Error::BadRequest(
+                    "file field is required".into(),
+                ));
+            }
+        };
+
+        let data = file.bytes().await?;
+        let meta = UploadMeta {
+            size: data.len() as u64,
+            mime_type: file.type_(),
+            hash: sha256_hex(&data),
+        };
+        event.mime_type = meta.mime_type.clone();
+
+        if meta.size > max_size {
+            return Err(too_large(max_size));
+        }
+        if let Err(reason) = meta.check_policy(max_size) {
+            return Err(crate::error::Error::BadRequest(reason));
+        }
+        if let Err(e) = self.bot.file_types.check(&meta.mime_type, &file.name()) {
+            return Err(crate::error::Error::BadRequest(e.to_string()));
+        }
+
+        if let Some(f) = self.bot.d1.find_by_hash(&meta.hash).await? {
+            info!("upload deduplicated: {}", f.file_unique_id);
+            event.user_id = f.user_id;
+            return self.uploaded(&f, true, picgo, &caller);
+        }
+
+        let f = self
+            .bot
+            .upload_document(file.name(), &meta, data, caller.user_id())
+            .await?;
+        event.user_id = f.user_id;
+
+        self.uploaded(&f, false, picgo, &caller)
+    }
+
+    fn uploaded(
+        &self,
+        file: &File,
+        deduplicated: bool,
+        picgo: bool,
+        caller: &Caller,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let base = self.base();
+        if picgo {
+            return Ok(Response::from_json(&PicGoUploaded {
+                success: true,
+                result: vec![file.url(&base)],
+            })?);
+        }
+
+        let mut uploaded = Uploaded::new(file, &base, deduplicated);
+        // a deduplicated file may belong to someone else
+        if !self.api_token.is_empty() && caller.can_manage(file) {
+            uploaded = uploaded.with_delete_url(format!(
+                "https://{}/delete/{}?token={}",
+                base,
+                file.file_unique_id,
+                delete_token(&self.api_token, &file.file_unique_id)?
+            ));
+        }
+        if self.image_resizing && file.is_image() {
+            uploaded =
+                uploaded.with_thumbnail_url(format!("{}?w={}", file.url(&base), THUMBNAIL_WIDTH));
+        }
+        Ok(Response::from_json(&uploaded)?)
+    }
+
+    // PUT /s3/:bucket/:key of s3 clients, signed with API_TOKEN as the secret access key
+    pub async fn s3_put(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let mut event = Event::new("upload");
+        let result = self.s3_upload(req, ctx, &mut event).await;
+        self.record(event, &result);
+        result
+    }
+
+    async fn s3_upload(
+        &self,
+        mut req: Request,
+        ctx: RouteContext<()>,
+        event: &mut Event,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        if self.s3_access_key_id.is_empty() || self.api_token.is_empty() {
+            return Err(crate::error::Error::NotFound("s3 api is disabled".into()));
+        }
+        self.check_rate_limit(&req).await?;
+
+        let max_size = self.bot.max_file_size;
+        if req
+            .headers()
+            .get("Content-Length")?
+            .and_then(|v| v.parse::<u64>().ok())
+            .is_some_and(|v| v > max_size)
+        {
+            return Err(too_large(max_size));
+        }
+
+        // the signature may cover the body, only the headers are checked before it is read
+        let authorization = s3::authorization(&req, &self.s3_access_key_id)?;
+        let data = req.bytes().await?;
+        s3::verify(
+            &req,
+            &authorization,
+            &[&self.api_token, &self.previous_api_token],
+            &data,
+        )?;
+        s3::check_content_md5(&req, &data)?;
+
+        let name = s3::file_name(&ctx.param("key").cloned().unwrap_or_default());
+        if name.is_empty() {
+            return Err(crate::error::Error::BadRequest(
+                "the key must end with a file name".into(),
+            ));
+        }
+        let mime_type = req
+            .headers()
+            .get("Content-Type")?
+            .and_then(|v| v.split(';').next().map(|v| v.trim().to_string()))
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        let meta = UploadMeta {
+            size: data.len() as u64,
+            mime_type,
+            hash: sha256_hex(&data),
+        };
+        event.mime_type = meta.mime_type.clone();
+
+        if meta.size > max_size {
+            return Err(too_large(max_size));
+        }
+        if let Err(reason) = meta.check_policy(max_size) {
+            return Err(crate::error::Error::BadRequest(reason));
+        }
+        if let Err(e) = self.bot.file_types.check(&meta.mime_type, &name) {
+            return Err(crate::error::Error::BadRequest(e.to_string()));
+        }
+
+        let etag = s3::etag(&data);
+        let f = match self.bot.d1.find_by_hash(&meta.hash).await? {
+            Some(f) => {
+                info!("s3 upload deduplicated: {}", f.file_unique_id);
+                f
+            }
+            None => self.bot.upload_document(name, &meta, data, 0).await?,
+        };
+        event.user_id = f.user_id;
+
+        // the key isn't where the file is served, the link is in Location
+        let headers = Headers::new();
+        headers.set("ETag", &etag)?;
+        headers.set("Location", &f.url(&self.base()))?;
+        Ok(Response::empty()?.with_headers(headers))
+    }
+
+    // a custom uploader for sharex, the token of the request is written into it
+    pub async fn sharex_config(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Upload).await?;
+        let authorization = req.headers().get("Authorization")?.unwrap_or_default();
+
+        let base = self.base();
+        let config = serde_json::json!({
+            "Version": "15.0.0",
+            "Name": base,
+            "DestinationType": "ImageUploader, FileUploader",
+            "RequestMethod": "POST",
+            "RequestURL": format!("https://{}/api/upload", base),
+            "Headers": { "Authorization": authorization },
+            "Body": "MultipartFormData",
+            "FileFormName": "file",
+            "URL": "{json:url}",
+            "ThumbnailURL": "{json:thumbnail_url}",
+            "DeletionURL": "{json:delete_url}",
+            "ErrorMessage": "{json:error.message}",
+        });
+
+        let headers = Headers::new();
+        headers.set("Content-Type", "application/json")?;
+        headers.set(
+            "Content-Disposition",
+            &content_disposition("attachment", &format!("{}.sxcu", base.replace('/', "_"))),
+        )?;
+        headers.set("Cache-Control", "no-store")?;
+        Ok(Response::ok(config.to_string())?.with_headers(headers))
+    }
+
+    // the delete url of an upload, a page to confirm on get so link previews don't delete anything
+    pub async fn delete_page(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.check_rate_limit(&req).await?;
+
+        let file = self.deletable_file(&req, &ctx).await?;
+        Ok(Response::from_html(crate::listing::delete_form(
+            &self.base(),
+            &file,
+        ))?)
+    }
+
+    pub async fn delete(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.check_rate_limit(&req).await?;
+
+        let file = self.deletable_file(&req, &ctx).await?;
+        self.bot.d1.delete_file(&file.file_unique_id).await?;
+        self.bot.uncache(&file.file_unique_id).await;
+        info!("deleted {} with its delete url", file.file_unique_id);
+        // a public bucket keeps serving stored copies, the cron would only remove them after the grace period
+        if let Err(e) = purge_copies(&self.base(), &self.cache, self.storage.as_ref(), &file).await
+        {
+            warn!(
+                "purge copies of deleted {} failed: {}",
+                file.file_unique_id, e
+            );
+        }
+
+        Ok(Response::from_html(crate::listing::deleted(
+            self.bot.delete_grace_days,
+        ))?)
+    }
+
+    // at most MAX_BATCH_DELETE ids of any link of the files, the report has an item for every id
+    pub async fn batch_delete(
+        &self,
+        mut req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let caller = self.authenticate(&req, Scope::Delete).await?;
+
+        let ids = req.json::<BatchDeleteRequest>().await?.ids;
+        if ids.is_empty() || ids.len() > MAX_BATCH_DELETE {
+            return Err(crate::error::Error::BadRequest(format!(
+                "ids must have 1 to {} entries",
+                MAX_BATCH_DELETE
+            )));
+        }
+
+        let items = delete_files(
+            &self.bot,
+            &self.base(),
+            &self.cache,
+            self.storage.as_ref(),
+            &ids,
+            |f| caller.can_manage(f),
+        )
+        .await?;
+        let deleted = items
+            .iter()
+            .filter(|v| v.status == DeleteStatus::Deleted)
+            .count();
+        info!("batch deleted {} of {} files", deleted, items.len());
+
+        Ok(Response::from_json(&BatchDeleteReport {
+            deleted,
+            failed: items.len() - deleted,
+            grace_days: self.bot.delete_grace_days,
+            items,
+        })?)
+    }
+
+    // tokens of the previous api token stay valid while it is accepted
+    async fn deletable_file(
+        &self,
+        req: &Request,
+        ctx: &RouteContext<()>,
+    ) -> std::result::Result<File, crate::error::Error> {
+        let file_unique_id = ctx.param("file_unique_id").cloned().unwrap_or_default();
+        let query = req.query::<HashMap<String, String>>().unwrap_or_default();
+        let token = query.get("token").cloned().unwrap_or_default();
+
+        let mut valid = false;
+        for key in [&self.api_token, &self.previous_api_token] {
+            if !key.is_empty() && verify_delete_token(key, &file_unique_id, &token)? {
+                valid = true;
+            }
+        }
+        if !valid {
+            return Err(crate::error::Error::Forbidden(
+                "invalid delete token".into(),
+            ));
+        }
+
+        let file = self.bot.d1.get(&file_unique_id).await?;
+        if file.is_deleted() {
+            return Err(crate::error::Error::Gone("file was deleted".into()));
+        }
+        Ok(file)
+    }
+
+    pub async fn events(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let query = req.query::<HashMap<String, String>>()?;
+        let since = match query.get("since").map(|v| v.parse::<u64>()) {
+            Some(Ok(v)) => v,
+            Some(Err(_)) => {
+                return Err(crate::error::Error::BadRequest(
+                    "since must be a sequence number".into(),
+                ));
+            }
+            None => 0,
+        };
+        let limit = query
+            .get("limit")
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(EVENTS_PAGE_SIZE)
+            .clamp(1, EVENTS_PAGE_SIZE);
+
+        let events = self.bot.d1.events(since, limit).await?;
+        let next = events.last().map(|e| e.seq).unwrap_or(since);
+
+        Ok(Response::from_json(&EventPage { events, next })?)
+    }
+
+    // every file with its full row, filtered by ?user_id=, ?type=, ?q=, ?status= and ?tag=
+    pub async fn admin_files(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let query = req.query::<HashMap<String, String>>()?;
+        let user_id = match query.get("user_id").map(|v| v.parse::<u64>()) {
+            Some(Ok(v)) => Some(v),
+            Some(Err(_)) => {
+                return Err(crate::error::Error::BadRequest(
+                    "user_id must be a number".into(),
+                ));
+            }
+            None => None,
+        };
+        let status = query.get("status").cloned().unwrap_or_default();
+        if !matches!(status.as_str(), "" | "active" | "deleted" | "blocked") {
+            return Err(crate::error::Error::BadRequest(
+                "status must be active, deleted or blocked".into(),
+            ));
+        }
+        let filter = FileFilter {
+            user_id,
+            mime_type: query.get("type").cloned().unwrap_or_default(),
+            name: query.get("q").cloned().unwrap_or_default(),
+            status,
+            tag: tag_param(&req)?,
+        };
+        let limit = query
+            .get("limit")
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(ADMIN_PAGE_SIZE)
+            .clamp(1, LISTING_LIMIT);
+        let offset = query
+            .get("offset")
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or_default();
+
+        Ok(Response::from_json(
+            &self.bot.d1.filtered_files(&filter, limit, offset).await?,
+        )?)
+    }
+
+    // works like /delete of the bot, the file can be restored with admin_undelete_file
+    // until DELETE_GRACE_DAYS are over
+    pub async fn admin_delete_file(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let file_unique_id = ctx.param("file_unique_id").cloned().unwrap_or_default();
+        let file = self.bot.d1.get(&file_unique_id).await?;
+        if !file.is_deleted() {
+            self.bot.d1.delete_file(&file.file_unique_id).await?;
+            self.bot.uncache(&file.file_unique_id).await;
+            purge_copies(&self.base(), &self.cache, self.storage.as_ref(), &file).await?;
+            info!("deleted {} with the admin api", file.file_unique_id);
+        }
+
+        Ok(Response::from_json(&DeleteReport {
+            file_unique_id: file.file_unique_id,
+            deleted: true,
+            grace_days: self.bot.delete_grace_days,
+        })?)
+    }
+
+    pub async fn admin_undelete_file(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let file_unique_id = ctx.param("file_unique_id").cloned().unwrap_or_default();
+        let file = self.bot.d1.get(&file_unique_id).await?;
+        let restored = file.is_deleted();
+        if restored {
+            self.bot.d1.undelete_file(&file.file_unique_id).await?;
+            self.bot.uncache(&file.file_unique_id).await;
+            info!("restored {} with the admin api", file.file_unique_id);
+        }
+
+        Ok(Response::from_json(&UndeleteReport {
+            file_unique_id: file.file_unique_id,
+            restored,
+        })?)
+    }
+
+    // GET lists the channels with an album, PUT maps one to an album and DELETE removes it
+    pub async fn admin_channels(
+        &self,
+        mut req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let Some(chat_id) = ctx.param("chat_id") else {
+            return Ok(Response::from_json(
+                &self.bot.d1.channel_collections().await?,
+            )?);
+        };
+        if chat_id.parse::<i64>().is_err() {
+            return Err(crate::error::Error::BadRequest(
+                "chat id must be a number".into(),
+            ));
+        }
+        if req.method() == Method::Delete {
+            if !self.bot.d1.delete_channel_collection(chat_id).await? {
+                return Err(crate::error::Error::NotFound(
+                    "the channel has no album".into(),
+                ));
+            }
+            return Ok(Response::empty()?.with_status(204));
+        }
+
+        let body = req.json::<ChannelCollectionRequest>().await?;
+        if self.bot.d1.album(&body.album_id).await?.is_none() {
+            return Err(crate::error::Error::NotFound("album not found".into()));
+        }
+        let mut tags = vec![];
+        for v in &body.tags {
+            let name = tag::normalize(v)
+                .ok_or_else(|| crate::error::Error::BadRequest(format!("invalid tag: {}", v)))?;
+            if !tags.contains(&name) {
+                tags.push(name);
+            }
+        }
+        if tags.len() > tag::MAX_TAGS_PER_FILE {
+            return Err(crate::error::Error::BadRequest(format!(
+                "at most {} tags",
+                tag::MAX_TAGS_PER_FILE
+            )));
+        }
+
+        let collection = ChannelCollection {
+            chat_id: chat_id.clone(),
+            album_id: body.album_id,
+            tags: tags.join(","),
+            add_time: 0,
+        };
+        self.bot.d1.save_channel_collection(&collection).await?;
+        Ok(Response::from_json(&collection)?)
+    }
+
+    // PUT bans the user, DELETE lifts the ban, like /ban and /unban
+    pub async fn admin_ban(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let user_id = ctx
+            .param("user_id")
+            .and_then(|v| v.parse::<u64>().ok())
+            .ok_or(crate::error::Error::BadRequest(
+                "user id must be a number".into(),
+            ))?;
+        let banned = req.method() == Method::Put;
+        if banned && user_id as i64 == self.bot.matainer {
+            return Err(crate::error::Error::BadRequest(
+                "the maintainer can't be banned".into(),
+            ));
+        }
+        let changed = match banned {
+            true => self.bot.d1.ban_user(user_id).await?,
+            false => self.bot.d1.unban_user(user_id).await?,
+        };
+        if banned && changed {
+            self.bot.purge_user_copies(&self.base(), user_id).await?;
+        }
+
+        Ok(Response::from_json(&BanReport {
+            user_id,
+            banned,
+            changed,
+        })?)
+    }
+
+    pub async fn admin_stats(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let now = Date::now().as_millis() / 1000;
+        let since = now - 24 * 60 * 60;
+        Ok(Response::from_json(&Stats {
+            files: self.bot.d1.count_files().await?,
+            users: self.bot.d1.count_users().await?,
+            counters: self
+                .bot
+                .d1
+                .counters()
+                .await?
+                .into_iter()
+                .map(|c| (c.name, c.value))
+                .collect(),
+            last_day: self.bot.d1.digest(since).await?,
+            by_type: self.bot.d1.usage_by_type().await?,
+            by_day: self
+                .bot
+                .d1
+                .usage_by_day(now - USAGE_DAYS * 24 * 60 * 60)
+                .await?,
+        })?)
+    }
+
+    // everyone who uploaded, with their storage and ban, filtered by ?user_id=
+    pub async fn admin_users(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let query = req.query::<HashMap<String, String>>()?;
+        let user_id = match query.get("user_id").map(|v| v.parse::<u64>()) {
+            Some(Ok(v)) => Some(v),
+            Some(Err(_)) => {
+                return Err(crate::error::Error::BadRequest(
+                    "user_id must be a number".into(),
+                ));
+            }
+            None => None,
+        };
+        let limit = query
+            .get("limit")
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(ADMIN_PAGE_SIZE)
+            .clamp(1, LISTING_LIMIT);
+        let offset = query
+            .get("offset")
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or_default();
+
+        Ok(Response::from_json(
+            &self.bot.d1.uploaders(user_id, limit, offset).await?,
+        )?)
+    }
+
+    // like the take down button of a report: blocks the file and purges its copies
+    pub async fn admin_takedown(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let file_unique_id = ctx.param("file_unique_id").cloned().unwrap_or_default();
+        let file = self.bot.d1.get(&file_unique_id).await?;
+        self.bot
+            .d1
+            .save_blocked(&file.file_unique_id, BLOCKED_REPORT)
+            .await?;
+        self.bot.uncache(&file.file_unique_id).await;
+        self.bot
+            .d1
+            .close_reports(&file.file_unique_id, REPORT_TAKEN_DOWN)
+            .await?;
+        let report = purge_copies(&self.base(), &self.cache, self.storage.as_ref(), &file).await?;
+
+        info!(
+            "took down {} with the admin api: {} cache entries, {} stored objects",
+            file.file_unique_id, report.cache_entries, report.stored_objects
+        );
+        Ok(Response::from_json(&report)?)
+    }
+
+    // the dashboard on top of the admin api, it asks for API_TOKEN itself
+    pub fn admin_page(
+        &self,
+        _: Request,
+        _: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let body = ADMIN_PAGE
+            .replace("{title}", &html_escape(&self.host))
+            .replace("{prefix}", &crate::d1::path_prefix());
+
+        let headers = Headers::new();
+        headers.set("Content-Type", "text/html; charset=utf-8")?;
+        headers.set("Cache-Control", "no-store")?;
+        headers.set("Content-Security-Policy", ADMIN_CSP)?;
+        headers.set("X-Robots-Tag", "noindex")?;
+        Ok(Response::ok(body)?.with_headers(headers))
+    }
+
+    // the removal of expired deleted files the cron runs, without waiting for it
+    pub async fn admin_cleanup(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let purged =
+            tasks::purge_deleted(&self.bot, self.storage.as_ref(), self.bot.delete_grace_days)
+                .await?;
+        Ok(Response::from_json(&CleanupReport { purged })?)
+    }
+
+    pub async fn telegram(
+        &self,
+        mut req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<(), crate::error::Error> {
+        // anyone can post to /tgbot, callbacks and commands trust the sender in the update
+        let token = req
+            .headers()
+            .get("X-Telegram-Bot-Api-Secret-Token")?
+            .unwrap_or_default();
+        if !self.bot.is_webhook_secret(&token).await? {
+            return Err(crate::error::Error::Unauthorized(
+                "invalid webhook secret token".into(),
+            ));
+        }
+
+        let update = req.json::<Update>().await?;
+        info!("body: {:?}", update);
+        if let Err(e) = self.bot.handle(&self.base(), update).await {
+            if e.is_unexpected() {
+                self.bot
+                    .alert("telegram update", &self.request_id, &e.to_string())
+                    .await;
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub async fn register(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<(), crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        // skips the backlog that piled up while the webhook was down
+        let drop_pending_updates = req
+            .query::<HashMap<String, String>>()
+            .unwrap_or_default()
+            .get("drop_pending_updates")
+            .is_some_and(|v| v == "true");
+
+        let url = format!("https://{}/tgbot", self.base());
+        self.bot
+            .set_webhook(url.as_ref(), drop_pending_updates)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn unregister(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<(), crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let drop_pending_updates = req
+            .query::<HashMap<String, String>>()
+            .unwrap_or_default()
+            .get("drop_pending_updates")
+            .is_some_and(|v| v == "true");
+        self.bot.delete_webhook(drop_pending_updates).await?;
+        Ok(())
+    }
+
+    pub async fn retry_tasks(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        Ok(Response::from_json(
+            &tasks::retry_pending(&self.bot, self.storage.as_ref()).await?,
+        )?)
+    }
+
+    pub async fn drain_updates(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        Ok(Response::from_json(
+            &self.bot.drain_updates(&self.base()).await?,
+        )?)
+    }
+
+    pub async fn webhook_info(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        Ok(Response::from_json(&self.bot.webhook_info().await?)?)
+    }
+
+    pub async fn init_database(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<(), crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        self.bot.d1.init().await?;
+        Ok(())
+    }
+
+    async fn instance_state(&self) -> std::result::Result<InstanceState, crate::error::Error> {
+        let features = BTreeMap::from([
+            ("api".to_string(), !self.api_token.is_empty()),
+            ("r2".to_string(), self.storage.is_some()),
+            ("terms".to_string(), !self.terms.is_empty()),
+            ("require_consent".to_string(), self.bot.require_consent),
+            ("ip_less".to_string(), crate::privacy::is_ip_less()),
+            ("privacy_mode".to_string(), self.bot.privacy_mode),
+            ("hotlink".to_string(), self.hotlink.enabled()),
+            (
+                "rate_limit".to_string(),
+                self.rate_limiter.is_some() && self.rate_limit > 0,
+            ),
+        ]);
+
+        Ok(InstanceState {
+            schema_version: crate::d1::MIGRATIONS.len(),
+            host: self.base(),
+            features,
+            bots: self.bot_names.clone(),
+            path_prefix: crate::d1::path_prefix(),
+            public_host: self.public_host.clone(),
+            files: self.bot.d1.count_files().await?,
+            users: self.bot.d1.count_users().await?,
+        })
+    }
+
+    // the whole files table, streamed page by page
+    pub async fn export(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let query = req.query::<HashMap<String, String>>()?;
+        let format = match query.get("format") {
+            None => Format::Json,
+            Some(v) => Format::parse(v).ok_or(crate::error::Error::BadRequest(
+                "format must be json or csv".into(),
+            ))?,
+        };
+
+        let d1 = self.bot.d1.clone();
+        // the last exported file_unique_id, None once the table is done
+        let start = futures_util::stream::once(async move { Ok(format.start().into_bytes()) });
+        let rows = futures_util::stream::unfold(Some(String::new()), move |after| {
+            let d1 = d1.clone();
+            async move {
+                let after = after?;
+                let files = match d1.files_after(&after, EXPORT_PAGE_SIZE).await {
+                    Ok(v) => v,
+                    // the client sees a truncated export, there is no way to report it after the headers
+                    Err(e) => {
+                        error!("export: {}", e);
+                        return Some((Err(Error::RustError(e.to_string())), None));
+                    }
+                };
+                let last = files.last()?.file_unique_id.clone();
+                let mut chunk = String::new();
+                for f in &files {
+                    match format.row(f, after.is_empty() && chunk.is_empty()) {
+                        Ok(v) => chunk.push_str(&v),
+                        Err(e) => return Some((Err(Error::RustError(e.to_string())), None)),
+                    }
+                }
+                let next = (files.len() as u32 == EXPORT_PAGE_SIZE).then_some(last);
+                Some((Ok(chunk.into_bytes()), next))
+            }
+        });
+        let end = futures_util::stream::once(async move { Ok(format.end().as_bytes().to_vec()) });
+
+        let headers = Headers::new();
+        headers.set("Content-Type", format.content_type())?;
+        headers.set(
+            "Content-Disposition",
+            &content_disposition("attachment", &format!("files.{}", format.extension())),
+        )?;
+        headers.set("Cache-Control", "no-store")?;
+
+        Ok(Response::from_stream(start.chain(rows).chain(end))?.with_headers(headers))
+    }
+
+    // a json export of this or another instance, known files are skipped
+    pub async fn import(
+        &self,
+        mut req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let files = req.json::<Vec<File>>().await.map_err(|e| {
+            crate::error::Error::BadRequest(format!("body is not a json export: {}", e))
+        })?;
+        if let Some(f) = files
+            .iter()
+            .find(|f| f.file_id.is_empty() || f.file_unique_id.is_empty())
+        {
+            return Err(crate::error::Error::BadRequest(format!(
+                "file {:?} has no file_id or file_unique_id",
+                f.file_name
+            )));
+        }
+
+        let mut imported = 0;
+        for batch in files.chunks(IMPORT_BATCH_SIZE) {
+            imported += self.bot.d1.import_files(batch).await?;
+        }
+
+        Ok(Response::from_json(&Imported {
+            imported,
+            skipped: files.len() - imported,
+        })?)
+    }
+
+    pub async fn export_state(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let state = SignedState::sign(self.instance_state().await?, &self.api_token)?;
+        Ok(Response::from_json(&state)?)
+    }
+
+    pub async fn diff_state(
+        &self,
+        mut req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let exported = req.json::<SignedState>().await?;
+        let valid = exported.verify(&self.api_token)?
+            || (!self.previous_api_token.is_empty()
+                && exported.verify(&self.previous_api_token)?);
+        if !valid {
+            return Ok(Response::from_json(&StateDiff {
+                valid: false,
+                differences: vec![],
+            })?
+            .with_status(400));
+        }
+
+        let local = self.instance_state().await?;
+        Ok(Response::from_json(&StateDiff {
+            valid: true,
+            differences: exported.state.differences(&local),
+        })?)
+    }
+
+    pub async fn key_rotation(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let last_used = self
+            .bot
+            .d1
+            .get_setting(PREVIOUS_API_TOKEN_USED_SETTING)
+            .await?
+            .parse::<u64>()
+            .unwrap_or_default();
+
+        Ok(Response::from_json(&KeyRotation {
+            rotating: !self.previous_api_token.is_empty(),
+            previous_api_token_last_used: last_used,
+            webhook_secret_rotated: self.bot.webhook_secret_rotated().await?,
+            turnstile_rotating: self.turnstile.as_ref().is_some_and(Turnstile::rotating),
+        })?)
+    }
+
+    pub async fn rotate_webhook_secret(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        self.bot.rotate_webhook_secret().await?;
+        self.key_rotation(req, ctx).await
+    }
+
+    pub async fn metrics(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.authenticate(&req, Scope::Admin).await?;
+
+        let body = crate::metrics::prometheus(&self.bot.d1.counters().await?);
+
+        let headers = Headers::new();
+        headers.set("Content-Type", "text/plain; version=0.0.4")?;
+        Ok(Response::ok(body)?.with_headers(headers))
+    }
+
+    pub async fn health(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let mut health = Health::new();
+
+        health.check("d1", self.bot.d1.ping().await);
+        match &self.storage {
+            Some(_) => health.check::<String>("storage", Ok(())),
+            None => {
+                health.checks.insert("storage", "not bound".to_string());
+            }
+        }
+
+        // an api call per probe, so only on request
+        let telegram = req
+            .query::<HashMap<String, String>>()
+            .unwrap_or_default()
+            .get("telegram")
+            .is_some_and(|v| v == "true");
+        if telegram {
+            health.check("telegram", self.bot.get_me().await);
+        }
+
+        let status = if health.healthy { 200 } else { 503 };
+        Ok(Response::from_json(&health)?.with_status(status))
+    }
+
+    pub async fn listing(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let namespace = match ctx.param("name") {
+            Some(v) => v.to_ascii_lowercase(),
+            None => return Err(crate::error::Error::BadRequest("name is required".into())),
+        };
+
+        let query = req.query::<HashMap<String, String>>().unwrap_or_default();
+        let sort = ListingSort::parse(
+            query.get("sort").map(String::as_str),
+            query.get("order").map(String::as_str),
+        );
+
+        let tag = tag_param(&req)?;
+
+        let files = self
+            .bot
+            .d1
+            .namespace_files(&namespace, &sort, LISTING_LIMIT)
+            .await?;
+        if files.is_empty() {
+            return Err(crate::error::Error::NotFound("namespace not found".into()));
+        }
+        let tags = self.bot.d1.files_tags(&files).await?;
+        let files = files
+            .into_iter()
+            .filter(|f| has_tag(&tags, f, &tag))
+            .collect::<Vec<_>>();
+
+        Ok(Response::from_html(crate::listing::render(
+            &self.base(),
+            &namespace,
+            &files,
+            &sort,
+            &crate::tag::counts(&tags),
+            &tag,
+        ))?)
+    }
+
+    // /qr/<file_id>.png or .svg, the code of the link the bot replied with
+    pub async fn qr(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.check_rate_limit(&req).await?;
+
+        let (file_id, ext) = file_param(&ctx)?;
+        let format = match qr::Format::parse(&ext) {
+            Some(v) => v,
+            None => {
+                return Err(crate::error::Error::BadRequest(
+                    "qr codes are png or svg".into(),
+                ));
+            }
+        };
+        let file = match self.bot.d1.find(&file_id).await? {
+            Some(v) => v,
+            None => return Err(crate::error::Error::NotFound("file not found".into())),
+        };
+        check_available(&file)?;
+
+        let query = req.query::<HashMap<String, String>>().unwrap_or_default();
+        let scale = query
+            .get("scale")
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(qr::DEFAULT_SCALE);
+        let url = file.url(&self.base());
+        let body = match format {
+            qr::Format::Png => qr::png(&url, scale)?,
+            qr::Format::Svg => qr::svg(&url)?,
+        };
+
+        let headers = Headers::new();
+        headers.set("Content-Type", format.content_type())?;
+        // the link of a file doesn't change, deleting it does
+        headers.set("Cache-Control", "public, max-age=86400")?;
+        headers.set("X-Content-Type-Options", "nosniff")?;
+        Ok(Response::from_bytes(body)?.with_headers(headers))
+    }
+
+    // /s/<slug> redirects to the unique link, so the file is served and cached in one place
+    pub async fn slug(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let slug = match ctx.param("slug") {
+            Some(v) => v.to_ascii_lowercase(),
+            None => return Err(crate::error::Error::BadRequest("slug is required".into())),
+        };
+
+        let file = match self.bot.d1.find_alias(&slug).await? {
+            Some(v) => self.bot.d1.get(&v).await?,
+            None => return Err(crate::error::Error::NotFound("alias not found".into())),
+        };
+
+        let mut url = Url::parse(&file.unique_url(&self.base()))
+            .map_err(|e| crate::error::Error::Internal(e.to_string()))?;
+        // keeps ?key= of protected files
+        url.set_query(req.url()?.query());
+        Ok(Response::redirect(url)?)
+    }
+
+    pub async fn album(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.check_rate_limit(&req).await?;
+
+        // /a/<album_id>.zip downloads the files instead
+        let (album_id, as_zip) = match ctx.param("album_id") {
+            Some(v) => match v.strip_suffix(".zip") {
+                Some(id) => (id.to_string(), true),
+                None => (v.to_string(), false),
+            },
+            None => {
+                return Err(crate::error::Error::BadRequest(
+                    "album id is required".into(),
+                ));
+            }
+        };
+        let album = match self.bot.d1.album(&album_id).await? {
+            Some(v) => v,
+            None => return Err(crate::error::Error::NotFound("album not found".into())),
+        };
+
+        let files = self
+            .bot
+            .d1
+            .album_files(&album.album_id, LISTING_LIMIT)
+            .await?;
+
+        if as_zip {
+            return self.album_zip(&album, files);
+        }
+
+        let tag = tag_param(&req)?;
+        let tags = self.bot.d1.files_tags(&files).await?;
+        let files = files
+            .into_iter()
+            .filter(|f| has_tag(&tags, f, &tag))
+            .collect::<Vec<_>>();
+
+        Ok(Response::from_html(crate::listing::gallery(
+            &self.base(),
+            &album,
+            &files,
+            &crate::tag::counts(&tags),
+            &tag,
+        ))?)
+    }
+
+    fn album_zip(
+        &self,
+        album: &Album,
+        files: Vec<File>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        if self.album_zip_max_size == 0 {
+            return Err(crate::error::Error::Forbidden(
+                "zip downloads are disabled".into(),
+            ));
+        }
+        let max_size = self.album_zip_max_size.min(zip::MAX_ARCHIVE_SIZE);
+        // sizes telegram didn't report are caught while streaming
+        if files.iter().map(|f| f.file_size).sum::<u64>() > max_size {
+            return Err(crate::error::Error::PayloadTooLarge(format!(
+                "album is larger than {} bytes, download the files one by one",
+                max_size
+            )));
+        }
+
+        let mut names: Vec<String> = vec![];
+        let entries = files
+            .into_iter()
+            .map(|f| {
+                let mut name = crate::listing::display_name(&f).replace(['/', '\\'], "_");
+                if names.contains(&name) {
+                    name = format!("{}-{}", f.file_unique_id, name);
+                }
+                names.push(name.clone());
+                (name, f)
+            })
+            .collect();
+
+        let archive = Archive {
+            bot: self.bot.clone(),
+            storage: self.storage.clone(),
+            edge_cache_ttl: self.edge_cache_ttl,
+            entries,
+            body: None,
+            writer: ZipWriter::new(),
+            max_size,
+            done: false,
+        };
+
+        let headers = Headers::new();
+        headers.set("Content-Type", "application/zip")?;
+        headers.set(
+            "Content-Disposition",
+            &content_disposition("attachment", &format!("{}.zip", album.name)),
+        )?;
+        // members can be added at any time
+        headers.set("Cache-Control", "no-cache")?;
+
+        Ok(Response::from_stream(archive.stream())?.with_headers(headers))
+    }
+
+    // the number of hosted files, or with `?file=` the downloads of that file
+    pub async fn badge(
+        &self,
+        req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let query = req.query::<HashMap<String, String>>().unwrap_or_default();
+        let svg = match query.get("file").filter(|v| !v.is_empty()) {
+            Some(file_id) => {
+                // protected and unavailable files look like unknown ids, as in the public listings
+                let file = match self.bot.d1.find(file_id).await? {
+                    Some(v) if !v.is_protected() && check_available(&v).is_ok() => v,
+                    _ => return Err(crate::error::Error::NotFound("file not found".into())),
+                };
+                crate::badge::render("views", &file.downloads.to_string(), "#007ec6")
+            }
+            None => {
+                let files = self.bot.d1.count_files().await?;
+                crate::badge::render("uploads", &files.to_string(), "#4c1")
+            }
+        };
+
+        let headers = Headers::new();
+        headers.set("Content-Type", "image/svg+xml")?;
+        // short enough to stay live, long enough that embedding readmes don't hit D1 on every view
+        headers.set("Cache-Control", "public, max-age=300")?;
+
+        Ok(Response::ok(svg)?.with_headers(headers))
+    }
+
+    // only asks d1 when HIDE_BANNED_FILES is set
+    async fn check_uploader(&self, file: &File) -> std::result::Result<(), crate::error::Error> {
+        if self.hide_banned_files
+            && file.user_id != 0
+            && self.bot.d1.is_banned(file.user_id).await?
+        {
+            return Err(crate::error::Error::Gone(
+                "the uploader of the file is banned".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    // files uploaded before ALLOWED_MIME_TYPES or BLOCKED_EXTENSIONS were set aren't served either
+    fn check_type(
+        &self,
+        file: Option<&File>,
+        ext: &str,
+    ) -> std::result::Result<(), crate::error::Error> {
+        let types = &self.bot.file_types;
+        // the extension of the link can differ from the one of the file
+        let name = format!(".{}", ext);
+        let checked = match file {
+            Some(f) => types
+                .check_file(f)
+                .and_then(|_| types.check_extension(&name)),
+            None => types.check(mime_from_ext(ext), &name),
+        };
+        checked.map_err(|e| crate::error::Error::Forbidden(e.to_string()))
+    }
+
+    // the interstitial posts the solved widget here, a valid one gets the pass cookie
+    pub async fn pass_challenge(
+        &self,
+        mut req: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.check_rate_limit(&req).await?;
+
+        let Some(turnstile) = &self.turnstile else {
+            return Err(crate::error::Error::NotFound("no challenge to pass".into()));
+        };
+        let token = match req.form_data().await?.get(turnstile::RESPONSE_FIELD) {
+            Some(FormEntry::Field(v)) => v,
+            _ => String::new(),
+        };
+        if token.is_empty() || !turnstile.verify(&req, &token).await? {
+            return challenge(turnstile);
+        }
+
+        // back to the file, as a get
+        let headers = Headers::new();
+        headers.set("Location", req.url()?.as_str())?;
+        headers.set("Set-Cookie", &turnstile.pass_cookie(&req)?)?;
+        headers.set("Cache-Control", "no-store")?;
+        Ok(Response::empty()?.with_status(303).with_headers(headers))
+    }
+
+    // form for visitors to report a file to the maintainer
+    pub async fn report_form(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.check_rate_limit(&req).await?;
+
+        let file = self.reported_file(&ctx).await?;
+        Ok(Response::from_html(crate::listing::report_form(
+            &self.base(),
+            &file,
+        ))?)
+    }
+
+    // takes the form or a json body with `reason`, the maintainer decides with the buttons of the notification
+    pub async fn report(
+        &self,
+        mut req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        self.check_rate_limit(&req).await?;
+
+        let file = self.reported_file(&ctx).await?;
+
+        let json = req
+            .headers()
+            .get("Content-Type")?
+            .is_some_and(|v| v.starts_with("application/json"));
+        let reason = if json {
+            req.json::<ReportRequest>().await?.reason
+        } else {
+            match req.form_data().await?.get("reason") {
+                Some(FormEntry::Field(v)) => v,
+                _ => String::new(),
+            }
+        };
+        let reason = reason.trim();
+        if reason.is_empty() || reason.chars().count() > MAX_REPORT_REASON {
+            return Err(crate::error::Error::BadRequest(format!(
+                "reason must be 1 to {} characters",
+                MAX_REPORT_REASON
+            )));
+        }
+
+        let report_id = self
+            .bot
+            .d1
+            .save_report(&file.file_unique_id, reason)
+            .await?;
+        info!("report {} of {}", report_id, file.file_unique_id);
+        // the report is stored, the maintainer can still find it in d1
+        if let Err(e) = self
+            .bot
+            .notify_report(&self.base(), &file, report_id, reason)
+            .await
+        {
+            warn!("notify report {} failed: {}", report_id, e);
+        }
+
+        if json {
+            return Ok(Response::from_json(&Reported { report_id })?);
+        }
+        Ok(Response::from_html(crate::listing::report_sent())?)
+    }
+
+    async fn reported_file(
+        &self,
+        ctx: &RouteContext<()>,
+    ) -> std::result::Result<File, crate::error::Error> {
+        let file_unique_id = match ctx.param("file_unique_id") {
+            Some(v) => v.to_string(),
+            None => {
+                return Err(crate::error::Error::BadRequest(
+                    "file unique id is empty".into(),
+                ));
+            }
+        };
+        let file = self.bot.d1.get(&file_unique_id).await?;
+        // already taken down or deleted
+        check_available(&file)?;
+        Ok(file)
+    }
+
+    pub async fn terms(
+        &self,
+        _: Request,
+        _ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        if self.terms.is_empty() {
+            return Err(crate::error::Error::NotFound("terms not configured".into()));
+        }
+
+        Ok(Response::from_html(&self.terms)?)
+    }
+
+    // `/`, how to use the bot or the repository with LANDING_PAGE = "redirect"
+    pub async fn landing(
+        &self,
+        req: Request,
+        ctx: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        if self.landing_mode == LandingMode::Redirect {
+            return Ok(Self::github_page(req, ctx)?);
+        }
+
+        // the page is still useful without the name of the bot
+        let username = match self.bot.username().await {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("get bot username failed: {}", e);
+                String::new()
+            }
+        };
+        let recent = if self.landing_recent > 0 {
+            self.bot.d1.recent_public_files(self.landing_recent).await?
+        } else {
+            vec![]
+        };
+
+        let headers = Headers::new();
+        headers.set("Content-Type", "text/html; charset=utf-8")?;
+        headers.set("Cache-Control", "public, max-age=300")?;
+        Ok(Response::ok(crate::landing::render(
+            &self.host,
+            &username,
+            self.bot.max_file_size,
+            &recent,
+        ))?
+        .with_headers(headers))
+    }
+
+    // crawlers stay out of the api, files are only indexed with ROBOTS_INDEX_FILES
+    pub fn robots(
+        &self,
+        _: Request,
+        _: RouteContext<()>,
+    ) -> std::result::Result<Response, crate::error::Error> {
+        let mut disallow = vec![
+            "/api/".to_string(),
+            "/admin".to_string(),
+            "/tgbot".to_string(),
+            "/d1/".to_string(),
+            "/report/".to_string(),
+            "/delete/".to_string(),
+        ];
+        if !self.index_files {
+            let prefix = format!("/{}/", crate::d1::path_prefix());
+            disallow.push("/f/".to_string());
+            if prefix != "/f/" {
+                disallow.push(prefix);
+            }
+            disallow.extend(["/view/", "/s/", "/a/", "/u/"].map(String::from));
+        }
+        let body = format!(
+            "User-agent: *\n{}",
+            disallow
+                .iter()
+                .map(|v| format!("Disallow: {}\n", v))
+                .collect::<String>()
+        );
+
+        let headers = Headers::new();
+        headers.set("Content-Type", "text/plain; charset=utf-8")?;
+        headers.set("Cache-Control", "public, max-age=3600")?;
+        Ok(Response::ok(body)?.with_headers(headers))
+    }
+
+    pub fn favicon(_: Request, _: RouteContext<()>) -> Result<Response> {
+        let headers = Headers::new();
+        headers.set("Content-Type", "image/x-icon")?;
+        headers.set("Cache-Control", "public, max-age=86400")?;
+        Ok(Response::from_bytes(FAVICON.to_vec())?.with_headers(headers))
+    }
+
+    pub fn github_page(_: Request, _: RouteContext<()>) -> Result<Response> {
+        Response::redirect(Url::parse(REPOSITORY_URL).unwrap())
+    }
+}
+
+// ?tag= of a listing, empty when it's not set
+fn tag_param(req: &Request) -> std::result::Result<String, crate::error::Error> {
+    let query = req.query::<HashMap<String, String>>().unwrap_or_default();
+    match query.get("tag").filter(|v| !v.is_empty()) {
+        Some(v) => crate::tag::normalize(v).ok_or(crate::error::Error::BadRequest(
+            "tag must be letters, digits, - and _".into(),
+        )),
+        None => Ok(String::new()),
+    }
+}
+
+// every file has the empty tag
+fn has_tag(tags: &HashMap<String, Vec<String>>, file: &File, tag: &str) -> bool {
+    tag.is_empty()
+        || tags
+            .get(&file.file_unique_id)
+            .is_some_and(|v| v.iter().any(|t| t == tag))
+}
+
+fn file_param(
+    ctx: &RouteContext<()>,
+) -> std::result::Result<(String, String), crate::error::Error> {
+    let file_name = match ctx.param("file_id") {
+        Some(v) => v,
+        None => {
+            return Err(crate::error::Error::BadRequest(
+                "file name is not found".into(),
+            ));
+        }
+    };
+
+    let p = Path::new(file_name);
+    Ok((
+        p.file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        p.extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+    ))
+}
+
+// the password part of basic auth, or `?key=` of links shared before
+fn request_password(req: &Request) -> Option<String> {
+    let basic = req
+        .headers()
+        .get("Authorization")
+        .ok()
+        .flatten()
+        .and_then(|v| BASE64.decode(v.strip_prefix("Basic ")?).ok())
+        .and_then(|v| String::from_utf8(v).ok())
+        .and_then(|v| v.split_once(':').map(|(_, p)| p.to_string()));
+    if basic.is_some() {
+        return basic;
+    }
+
+    let url = req.url().ok()?;
+    url.query_pairs()
+        .find(|(k, _)| k == "key")
+        .map(|(_, v)| v.into_owned())
+}
+
+// headers of a file that uploaders control and the ones that keep it from running as a page
+fn file_headers(file: &File, security: &SecurityHeaders) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = file.headers().into_iter().collect();
+    headers.extend(security.headers());
+
+    // documents browsers would execute on this origin are only downloaded, /view/ shows them sandboxed
+    let attachment = security.is_attachment(&file_mime(file));
+    let disposition = if attachment { "attachment" } else { "inline" };
+    let name = if file.download_name.is_empty() {
+        &file.file_name
+    } else {
+        &file.download_name
+    };
+    if !file.download_name.is_empty() || attachment {
+        headers.push((
+            "Content-Disposition".to_string(),
+            content_disposition(disposition, name),
+        ));
+    }
+
+    headers
+}
+
+// stored with r2 objects, so files served from r2 or the edge cache aren't untyped streams
+pub fn r2_metadata(file: Option<&File>, ext: &str) -> (HttpMetadata, HashMap<String, String>) {
+    let Some(file) = file else {
+        let http_metadata = HttpMetadata {
+            content_type: Some(mime_from_ext(ext).to_string()),
+            ..Default::default()
+        };
+        return (http_metadata, HashMap::new());
+    };
+
+    let disposition = if is_active_content(file) {
+        "attachment"
+    } else {
+        "inline"
+    };
+    // the name of /rename, a public bucket serves the object without file_headers
+    let name = if file.download_name.is_empty() {
+        &file.file_name
+    } else {
+        &file.download_name
+    };
+    let http_metadata = HttpMetadata {
+        content_type: Some(file_mime(file)),
+        content_disposition: (!name.is_empty()).then(|| content_disposition(disposition, name)),
+        ..Default::default()
+    };
+    let custom_metadata = HashMap::from([
+        ("file_name".to_string(), file.file_name.clone()),
+        ("file_id".to_string(), file.file_id.clone()),
+    ]);
+    (http_metadata, custom_metadata)
+}
+
+fn typed_headers(http_metadata: &HttpMetadata) -> Result<Headers> {
+    let headers = Headers::new();
+    if let Some(v) = &http_metadata.content_type {
+        headers.set("Content-Type", v)?;
+    }
+    if let Some(v) = &http_metadata.content_disposition {
+        headers.set("Content-Disposition", v)?;
+    }
+    Ok(headers)
+}
+
+fn file_mime(file: &File) -> String {
+    if !file.mime_type.is_empty() {
+        return file.mime_type.to_ascii_lowercase();
+    }
+    let ext = file.ext();
+    mime_from_ext(ext.trim_start_matches('.')).to_string()
+}
+
+fn is_active_content(file: &File) -> bool {
+    crate::security::is_active(&file_mime(file))
+}
+
+fn is_viewable(file: &File) -> bool {
+    is_active_content(file) || file_mime(file).starts_with("text/")
+}
+
+// cached and stored copies of the file, the edge cache only of this data center, others expire on their own.
+// resized and converted variants are cached per query and can't be listed, they expire after EDGE_CACHE_TTL.
+// downloads check the row before the edge cache, so neither serves a deleted or blocked file
+pub async fn purge_copies<S: Storage>(
+    host: &str,
+    cache: &Cache,
+    storage: Option<&S>,
+    file: &File,
+) -> std::result::Result<PurgeReport, crate::error::Error> {
+    let mut report = PurgeReport::default();
+    for ext in known_extensions(file) {
+        for id in [&file.file_id, &file.file_unique_id] {
+            let url = format!("https://{}/f/{}.{}", host, id, ext);
+            if let Ok(CacheDeletionOutcome::Success) = cache.delete(url, true).await {
+                report.cache_entries += 1;
+            }
+        }
+    }
+    if let Some(storage) = storage {
+        report.stored_objects = delete_stored_copies(storage, file).await?;
+    }
+
+    Ok(report)
+}
+
+// the files of `ids` that `can_manage` allows are tombstoned in one D1 batch, then their copies are purged
+// in parallel. a failed purge is only logged, the file is deleted anyway and the cron removes its copies
+pub async fn delete_files<S: Storage, D: FileStore>(
+    bot: &TgBot<D>,
+    host: &str,
+    cache: &Cache,
+    storage: Option<&S>,
+    ids: &[String],
+    can_manage: impl Fn(&File) -> bool,
+) -> std::result::Result<Vec<DeleteItem>, crate::error::Error> {
+    let mut items = vec![];
+    let mut files: Vec<(usize, File)> = vec![];
+    for id in ids {
+        let (file_unique_id, status) = match bot.d1.find(id).await? {
+            None => (String::new(), DeleteStatus::NotFound),
+            Some(f)
+                if files
+                    .iter()
+                    .any(|(_, v)| v.file_unique_id == f.file_unique_id) =>
+            {
+                (f.file_unique_id, DeleteStatus::Duplicate)
+            }
+            Some(f) if f.is_deleted() => (f.file_unique_id, DeleteStatus::AlreadyDeleted),
+            Some(f) if !can_manage(&f) => (f.file_unique_id, DeleteStatus::Forbidden),
+            Some(f) => {
+                files.push((items.len(), f.clone()));
+                (f.file_unique_id, DeleteStatus::Deleted)
+            }
+        };
+        items.push(DeleteItem {
+            id: id.clone(),
+            file_unique_id,
+            status,
+            cache_entries: 0,
+            stored_objects: 0,
+        });
+    }
+
+    bot.d1
+        .delete_files(
+            &files
+                .iter()
+                .map(|(_, f)| f.file_unique_id.clone())
+                .collect::<Vec<_>>(),
+        )
+        .await?;
+    for (_, f) in &files {
+        bot.uncache(&f.file_unique_id).await;
+    }
+
+    let purged = join_all(
+        files
+            .iter()
+            .map(|(_, f)| purge_copies(host, cache, storage, f)),
+    )
+    .await;
+    for ((i, f), report) in files.iter().zip(purged) {
+        match report {
+            Ok(v) => {
+                items[*i].cache_entries = v.cache_entries;
+                items[*i].stored_objects = v.stored_objects;
+            }
+            Err(e) => warn!("purge copies of deleted {} failed: {}", f.file_unique_id, e),
+        }
+    }
+    Ok(items)
+}
+
+// objects of the file in R2 or KV, returns how many there were
+pub async fn delete_stored_copies<S: Storage>(
+    storage: &S,
+    file: &File,
+) -> std::result::Result<u32, crate::error::Error> {
+    let mut deleted = 0;
+    for key in stored_copies(storage, file).await? {
+        storage.delete(&key).await?;
+        deleted += 1;
+    }
+    Ok(deleted)
+}
+
+// keys of the copies of a file in the storage binding
+pub async fn stored_copies<S: Storage>(
+    storage: &S,
+    file: &File,
+) -> std::result::Result<Vec<String>, crate::error::Error> {
+    let mut keys = vec![];
+    for ext in known_extensions(file) {
+        let key = format!("{}.{}", file.file_unique_id, ext);
+        if storage.head(&key).await? {
+            keys.push(key);
+        }
+    }
+    Ok(keys)
+}
+
+// any extension can be requested, the file's own and the ones with a known type
+fn known_extensions(file: &File) -> Vec<String> {
+    let mut exts: Vec<String> = KNOWN_EXTENSIONS.iter().map(|v| v.to_string()).collect();
+    let ext = file.ext().trim_start_matches('.').to_string();
+    if !ext.is_empty() && !exts.contains(&ext) {
+        exts.push(ext);
+    }
+    exts
+}
+
+fn too_large(max_size: u64) -> crate::error::Error {
+    crate::error::Error::PayloadTooLarge(format!(
+        "file is larger than {} MB, the limit of this host",
+        max_size / 1024 / 1024
+    ))
+}
+
+// the edge cache still holds blocked and deleted files, it's never consulted for them
+fn check_available(file: &File) -> std::result::Result<(), crate::error::Error> {
+    if file.is_deleted() {
+        return Err(crate::error::Error::Gone("file was deleted".into()));
+    }
+    if file.is_blocked() {
+        return Err(crate::error::Error::Blocked(format!(
+            "file is not available, blocked by {}",
+            file.blocked
+        )));
+    }
+    Ok(())
+}
+
+// headers of cached responses are immutable, so they are copied into a new response
+fn decorate(
+    resp: Response,
+    file: Option<&File>,
+    security: &SecurityHeaders,
+) -> std::result::Result<Response, crate::error::Error> {
+    let extra = match file {
+        Some(f) => file_headers(f, security),
+        // files without a row only have the type of their extension
+        None => {
+            let mut extra = security.headers();
+            let mime = resp.headers().get("Content-Type")?.unwrap_or_default();
+            if security.is_attachment(&mime) {
+                extra.push(("Content-Disposition".to_string(), "attachment".to_string()));
+            }
+            extra
+        }
+    };
+
+    let headers = Headers::new();
+    for (k, v) in resp.headers().entries() {
+        headers.append(&k, &v)?;
+    }
+    for (k, v) in extra {
+        headers.set(&k, &v)?;
+    }
+    Ok(resp.with_headers(headers))
+}
+
+// caches in front of the worker must not hand an avif variant to a client without avif support
+fn vary_accept(
+    resp: Response,
+    negotiated: bool,
+) -> std::result::Result<Response, crate::error::Error> {
+    if !negotiated {
+        return Ok(resp);
+    }
+
+    let headers = Headers::new();
+    for (k, v) in resp.headers().entries() {
+        headers.append(&k, &v)?;
+    }
+    headers.append("Vary", "Accept")?;
+    Ok(resp.with_headers(headers))
+}
+
+// ascii fallback for old clients plus the RFC 5987 utf-8 name
+fn content_disposition(disposition: &str, name: &str) -> String {
+    if name.is_empty() {
+        return disposition.to_string();
+    }
+
+    let fallback: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let encoded: String =
+        name.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => (b as char).to_string(),
+                b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|'
+                | b'~' => (b as char).to_string(),
+                _ => format!("%{:02X}", b),
+            })
+            .collect();
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        disposition, fallback, encoded
+    )
+}
+
+fn unlocked(req: &Request, file: &File) -> bool {
+    match request_password(req) {
+        Some(p) => verify_password(&p, &file.password_salt, &file.password_hash),
+        None => false,
+    }
+}
+
+fn challenge(turnstile: &Turnstile) -> std::result::Result<Response, crate::error::Error> {
+    let headers = Headers::new();
+    headers.set("Content-Type", "text/html; charset=utf-8")?;
+    // each visitor solves their own
+    headers.set("Cache-Control", "no-store")?;
+    Ok(
+        Response::from_html(crate::listing::challenge(&turnstile.site_key))?
+            .with_status(403)
+            .with_headers(headers),
+    )
+}
+
+fn password_required() -> Result<Response> {
+    let mut resp = crate::error::Error::Unauthorized("password required".into()).to_response()?;
+    resp.headers_mut()
+        .set("WWW-Authenticate", "Basic realm=\"protected file\"")?;
+    Ok(resp)
+}
+
+// extensions of `mime_from_ext`
+const KNOWN_EXTENSIONS: [&str; 13] = [
+    "jpg", "jpeg", "png", "gif", "webp", "mp4", "webm", "html", "htm", "xhtml", "svg", "xml", "txt",
+];
+
+fn mime_from_ext(ext: &str) -> &'static str {
+    match ext.to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "html" | "htm" => "text/html",
+        "xhtml" => "application/xhtml+xml",
+        "svg" => "image/svg+xml",
+        "xml" => "application/xml",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+fn splite_readable_stream(
+    r: ReadableStream,
+) -> std::result::Result<(ReadableStream, ReadableStream), crate::error::Error> {
+    let tee_off = r.tee();
+    Ok((tee_off.get(0).dyn_into()?, tee_off.get(1).dyn_into()?))
+}
+
+// the body as it is served, stored copies are cleaned again in case the settings changed
+// what `cleaned` did to a stored copy, kept in its custom metadata
+pub fn cleaning<D: FileStore>(bot: &TgBot<D>, file: Option<&File>, ext: &str) -> &'static str {
+    if bot.strip_exif && exif::applies(file, ext) {
+        "exif"
+    } else {
+        "none"
+    }
+}
+
+pub fn cleaned<D: FileStore>(
+    bot: &TgBot<D>,
+    body: ReadableStream,
+    file: Option<&File>,
+    ext: &str,
+) -> std::result::Result<ReadableStream, crate::error::Error> {
+    let body = without_exif(bot, body, file, ext)?;
+    without_scripts(bot, body, file, ext)
+}
+
+// the body without location and camera metadata when STRIP_EXIF is set
+fn without_exif<D: FileStore>(
+    bot: &TgBot<D>,
+    body: ReadableStream,
+    file: Option<&File>,
+    ext: &str,
+) -> std::result::Result<ReadableStream, crate::error::Error> {
+    if !bot.strip_exif || !exif::applies(file, ext) {
+        return Ok(body);
+    }
+
+    let bytes = Response::from_body(ResponseBody::Stream(body))?.stream()?;
+    let stream =
+        futures_util::stream::unfold(Some((bytes, ExifStripper::new())), |state| async move {
+            let (mut bytes, mut stripper) = state?;
+            loop {
+                match bytes.next().await {
+                    Some(Ok(chunk)) => {
+                        let out = stripper.push(&chunk);
+                        if !out.is_empty() {
+                            return Some((Ok(out), Some((bytes, stripper))));
+                        }
+                    }
+                    Some(Err(e)) => return Some((Err(e), None)),
+                    None => return Some((Ok(stripper.finish()), None)),
+                }
+            }
+        });
+
+    match Response::from_stream(stream)?.body() {
+        ResponseBody::Stream(v) => Ok(v.clone()),
+        _ => Err(crate::error::Error::Internal(
+            "body is not streamable".into(),
+        )),
+    }
+}
+
+// svgs without scripts when SVG_POLICY is sanitize, the whole file is read first
+fn without_scripts<D: FileStore>(
+    bot: &TgBot<D>,
+    body: ReadableStream,
+    file: Option<&File>,
+    ext: &str,
+) -> std::result::Result<ReadableStream, crate::error::Error> {
+    if bot.svg_policy != SvgPolicy::Sanitize || !svg::applies(file, ext) {
+        return Ok(body);
+    }
+
+    let bytes = Response::from_body(ResponseBody::Stream(body))?.stream()?;
+    let stream = futures_util::stream::unfold(Some((bytes, vec![])), |state| async move {
+        let (mut bytes, mut data) = state?;
+        loop {
+            match bytes.next().await {
+                Some(Ok(chunk)) => {
+                    data.extend_from_slice(&chunk);
+                    // no need to read the rest, sanitize replaces it anyway
+                    if data.len() > svg::MAX_SVG_SIZE {
+                        return Some((Ok(svg::EMPTY_SVG.as_bytes().to_vec()), None));
+                    }
+                }
+                Some(Err(e)) => return Some((Err(e), None)),
+                None => return Some((Ok(svg::sanitize(&data)), None)),
+            }
+        }
+    });
+
+    match Response::from_stream(stream)?.body() {
+        ResponseBody::Stream(v) => Ok(v.clone()),
+        _ => Err(crate::error::Error::Internal(
+            "body is not streamable".into(),
+        )),
+    }
+}
+
+pub enum DownloadResult {
+    Stream(ReadableStream),
+    NotFound,
+}
+
+// an album zip, its files are fetched one after another while the archive streams
+struct Archive<S: Storage, D: FileStore> {
+    bot: Arc<TgBot<D>>,
+    storage: Option<S>,
+    edge_cache_ttl: i32,
+    entries: VecDeque<(String, File)>,
+    // of the entry being written
+    body: Option<ByteStream>,
+    writer: ZipWriter,
+    max_size: u64,
+    done: bool,
+}
+
+impl<S: Storage, D: FileStore> Archive<S, D> {
+    fn stream(self) -> impl futures_util::Stream<Item = Result<Vec<u8>>> {
+        futures_util::stream::unfold(self, |mut archive| async move {
+            if archive.done {
+                return None;
+            }
+            match archive.next_chunk().await {
+                Ok(v) => Some((Ok(v), archive)),
+                // the client sees a truncated archive, there is no way to report it after the headers
+                Err(e) => {
+                    error!("album zip: {}", e);
+                    archive.done = true;
+                    Some((Err(e), archive))
+                }
+            }
+        })
+    }
+
+    async fn next_chunk(&mut self) -> Result<Vec<u8>> {
+        if let Some(body) = self.body.as_mut() {
+            return match body.next().await {
+                Some(chunk) => {
+                    let chunk = chunk?;
+                    self.writer.write(&chunk);
+                    if self.writer.written() > self.max_size {
+                        return Err(Error::RustError(
+                            "album is larger than the zip size limit".into(),
+                        ));
+                    }
+                    Ok(chunk)
+                }
+                None => {
+                    self.body = None;
+                    Ok(self.writer.end_entry())
+                }
+            };
+        }
+
+        match self.entries.pop_front() {
+            Some((name, file)) => {
+                let body =
+                    member_body(&self.bot, self.storage.as_ref(), self.edge_cache_ttl, &file)
+                        .await
+                        .map_err(|e| Error::RustError(e.to_string()))?;
+                self.body = Some(Response::from_body(ResponseBody::Stream(body))?.stream()?);
+                Ok(self.writer.start_entry(&name, file.add_time))
+            }
+            None => {
+                self.done = true;
+                Ok(self.writer.finish())
+            }
+        }
+    }
+}
+
+// the stored copy or the telegram file, nothing is cached or stored for a zip
+async fn member_body<S: Storage, D: FileStore>(
+    bot: &TgBot<D>,
+    storage: Option<&S>,
+    edge_cache_ttl: i32,
+    file: &File,
+) -> std::result::Result<ReadableStream, crate::error::Error> {
+    let key = format!("{}{}", file.file_unique_id, file.ext());
+    let ext = file.ext();
+    let ext = ext.trim_start_matches('.');
+    if let Some(storage) = storage
+        && let Some(v) = storage.get(&key).await?
+    {
+        return cleaned(bot, v.body, Some(file), ext);
+    }
+
+    // the second attempt gets a new path, like `get_file` does
+    for refresh in [false, true] {
+        let (url, _) = bot.get_file_url(&file.file_id, refresh).await?;
+        if let DownloadResult::Stream(v) = download(url, edge_cache_ttl).await? {
+            return cleaned(bot, v, Some(file), ext);
+        }
+    }
+    Err(crate::error::Error::NotFound("file not found".into()))
+}
+
+pub async fn download(
+    url: String,
+    edge_cache_ttl: i32,
+) -> std::result::Result<DownloadResult, crate::error::Error> {
+    fetch_file(
+        url,
+        CfProperties {
+            cache_ttl_by_status: Some(HashMap::from([("200-299".to_string(), edge_cache_ttl)])),
+            ..CfProperties::default()
+        },
+    )
+    .await
+}
+
+// resized by cloudflare on the way, the zone needs image resizing from any origin
+pub async fn download_transformed(
+    url: String,
+    edge_cache_ttl: i32,
+    transform: &Transform,
+) -> std::result::Result<DownloadResult, crate::error::Error> {
+    fetch_file(
+        url,
+        CfProperties {
+            cache_ttl_by_status: Some(HashMap::from([("200-299".to_string(), edge_cache_ttl)])),
+            image: Some(transform.resize_config()),
+            ..CfProperties::default()
+        },
+    )
+    .await
+}
+
+async fn fetch_file(
+    url: String,
+    cf: CfProperties,
+) -> std::result::Result<DownloadResult, crate::error::Error> {
+    let request = Request::new_with_init(
+        url.as_str(),
+        &RequestInit {
+            method: Method::Get,
+            cf,
+            ..RequestInit::default()
+        },
+    )?;
+
+    let mut response = Fetch::Request(request).send().await?;
+
+    if response.status_code() == 404 {
+        return Ok(DownloadResult::NotFound);
+    }
+
+    if response.status_code() != 200 {
+        return Err(crate::error::Error::TelegramApi(format!(
+            "status code is not 200, but {}, {}",
+            response.status_code(),
+            response.text().await?
+        )));
+    }
+
+    let stream = match &response.body() {
         ResponseBody::Stream(edge_request) => edge_request,
-        _ => return Err(crate::error::Error("body is not streamable".into())),
+        _ => {
+            return Err(crate::error::Error::TelegramApi(
+                "body is not streamable".into(),
+            ));
+        }
     };
 
     Ok(DownloadResult::Stream(stream.clone()))
@@ -0,0 +1,52 @@
+use worker::Url;
+
+#[derive(Clone, Default)]
+pub struct Hotlink {
+    // hosts allowed to embed files, `example.com` also allows its subdomains
+    pub allowed_referers: Vec<String>,
+    pub allow_empty_referer: bool,
+    // image served to rejected requests instead of a 403
+    pub placeholder: String,
+}
+
+impl Hotlink {
+    pub fn new(allowed_referers: &str, allow_empty_referer: bool, placeholder: String) -> Self {
+        Hotlink {
+            allowed_referers: allowed_referers
+                .split(',')
+                .map(|v| v.trim().trim_start_matches("*.").to_ascii_lowercase())
+                .filter(|v| !v.is_empty())
+                .collect(),
+            allow_empty_referer,
+            placeholder,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.allowed_referers.is_empty()
+    }
+
+    pub fn allows(&self, host: &str, referer: Option<&str>) -> bool {
+        if !self.enabled() {
+            return true;
+        }
+
+        let referer = match referer {
+            Some(v) if !v.is_empty() => v,
+            _ => return self.allow_empty_referer,
+        };
+
+        let referer_host = match Url::parse(referer)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_ascii_lowercase()))
+        {
+            Some(v) => v,
+            None => return false,
+        };
+
+        referer_host == host
+            || self.allowed_referers.iter().any(|allowed| {
+                referer_host == *allowed || referer_host.ends_with(&format!(".{}", allowed))
+            })
+    }
+}
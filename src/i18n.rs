@@ -0,0 +1,1043 @@
+// message catalogs of the bot, placeholders like `{count}` are filled by `tg::render_template`
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    Zh,
+    Ja,
+    Ru,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 4] = [Lang::En, Lang::Zh, Lang::Ja, Lang::Ru];
+
+    // telegram sends IETF tags like `en`, `zh-hans` or `pt-br`
+    pub fn parse(code: &str) -> Option<Lang> {
+        let code = code.split(['-', '_']).next().unwrap_or_default();
+        match code.to_ascii_lowercase().as_str() {
+            "en" => Some(Lang::En),
+            "zh" => Some(Lang::Zh),
+            "ja" => Some(Lang::Ja),
+            "ru" => Some(Lang::Ru),
+            _ => None,
+        }
+    }
+
+    // the sender's language when there is a catalog for it, the deployment's otherwise
+    pub fn select(code: Option<&str>, default: Lang) -> Lang {
+        code.and_then(Lang::parse).unwrap_or(default)
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Zh => "zh",
+            Lang::Ja => "ja",
+            Lang::Ru => "ru",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Msg {
+    ConsentButton,
+    ConsentRequest,
+    ConsentThanks,
+    ReplyToFile,
+    NotYourFiles,
+    NotYourPreview,
+    UsageProtect,
+    UsageRename,
+    Unprotected,
+    Protected,
+    Renamed,
+    UsageAlias,
+    AliasInvalid,
+    AliasReserved,
+    AliasSaved,
+    UsageAlt,
+    AltSaved,
+    UsageFetch,
+    FetchInvalidUrl,
+    FetchFailed,
+    FetchTooLarge,
+    FetchNotMedia,
+    UsageInfo,
+    InfoNotFound,
+    InfoSize,
+    InfoUploaded,
+    InfoUploader,
+    InfoUploaderUnknown,
+    InfoDownloads,
+    InfoStored,
+    InfoNotStored,
+    InfoDeleted,
+    InfoBlocked,
+    InfoCamera,
+    InfoLens,
+    InfoExposure,
+    InfoTaken,
+    InfoLocation,
+    InfoLocationHidden,
+    Deleted,
+    UsageDelete,
+    DeleteTooMany,
+    DeleteSkipped,
+    DeleteNotFound,
+    DeleteAlreadyDeleted,
+    DeleteForbidden,
+    DeleteDuplicate,
+    UsageUndelete,
+    UndeleteNotFound,
+    Undeleted,
+    UsageBan,
+    UsageUnban,
+    Banned,
+    AlreadyBanned,
+    Unbanned,
+    NotBanned,
+    YouAreBanned,
+    MaintainerOnly,
+    ForgetMeConfirm,
+    ForgetMeButton,
+    Forgotten,
+    PrivacyModeOnly,
+    UsageAlbum,
+    AlbumPrivate,
+    AlbumCreated,
+    AlbumExists,
+    AlbumNotFound,
+    AlbumAdded,
+    UsageTag,
+    TagInvalid,
+    TagTooMany,
+    TagsSaved,
+    TagNoTags,
+    TagList,
+    TagNone,
+    PreviewProtected,
+    PreviewPublic,
+    PreviewNeverExpires,
+    PreviewNoName,
+    PreviewDownloadsAs,
+    PreviewExifStripped,
+    PreviewExifStrippedOnServe,
+    PreviewExifKept,
+    PreviewForwardedFrom,
+    PreviewNoThumbnail,
+    PreviewThumbnail,
+    SummaryTitle,
+    NamespacePrivate,
+    NamespaceUsage,
+    NamespaceCurrent,
+    NamespaceOff,
+    NamespaceInvalid,
+    NamespaceTaken,
+    NamespaceSaved,
+    LocationPrivate,
+    LocationShown,
+    LocationHidden,
+    LocationUsage,
+    TokenPrivate,
+    UsageToken,
+    TokenCreated,
+    TokenLimit,
+    TokenNone,
+    TokenList,
+    TokenLine,
+    TokenNeverUsed,
+    TokenRevoked,
+    TokenNotFound,
+    SetupTitle,
+    SetupStepDb,
+    SetupStepWebhook,
+    SetupStepR2,
+    SetupStepChannel,
+    SetupDb,
+    SetupWebhook,
+    SetupR2,
+    SetupR2Kv,
+    SetupR2Missing,
+    SetupChannel,
+    SetupUnknown,
+    SetupChannelId,
+    SetupMaintainerOnly,
+    SetupFailed,
+    SetupDone,
+    SendFileHint,
+    UploadError,
+    TooBig,
+    TooLarge,
+    Refused,
+    TypeNotAllowed,
+    MissingBinding,
+    Help,
+    CmdHelp,
+    CmdPreview,
+    CmdProtect,
+    CmdUnprotect,
+    CmdRename,
+    CmdAlias,
+    CmdAlt,
+    CmdInfo,
+    CmdFetch,
+    CmdDelete,
+    CmdUndelete,
+    CmdBan,
+    CmdUnban,
+    CmdForgetMe,
+    CmdAlbum,
+    CmdTag,
+    CmdNamespace,
+    CmdToken,
+    CmdLocation,
+    CmdSetup,
+}
+
+pub fn tr(lang: Lang, msg: Msg) -> &'static str {
+    match lang {
+        Lang::En => en(msg),
+        Lang::Zh => zh(msg),
+        Lang::Ja => ja(msg),
+        Lang::Ru => ru(msg),
+    }
+}
+
+fn en(msg: Msg) -> &'static str {
+    match msg {
+        Msg::ConsentButton => "I agree",
+        Msg::ConsentRequest => {
+            "Please read the terms of service at {terms} and press \"I agree\" before uploading files."
+        }
+        Msg::ConsentThanks => "Thanks, you can upload files now.",
+        Msg::ReplyToFile => "Reply to an uploaded file or its links to use this command.",
+        Msg::NotYourFiles => "You can only change files you uploaded.",
+        Msg::NotYourPreview => "You can only preview files you uploaded.",
+        Msg::UsageProtect => "Usage: /protect <password>",
+        Msg::UsageRename => "Usage: /rename <file name>",
+        Msg::Unprotected => "Removed password from {count} file(s).",
+        Msg::Protected => {
+            "Protected {count} file(s), the browser asks for the password when they are opened."
+        }
+        Msg::Renamed => "{count} file(s) now download as {name}.",
+        Msg::UsageAlias => "Usage: /alias <name>",
+        Msg::AliasInvalid => "Use 1 to 64 letters, digits, - or _ for the alias.",
+        Msg::AliasReserved => "{name} is reserved, pick another alias.",
+        Msg::AliasSaved => "The file is now also at {url}",
+        Msg::UsageAlt => "Usage: /alt <description of the image>, at most {max} characters",
+        Msg::AltSaved => "Alt text of {count} file(s) updated.",
+        Msg::UsageFetch => "Usage: /fetch <url of an image, video or audio file up to {size} MB>",
+        Msg::FetchInvalidUrl => "That is not an http or https link.",
+        Msg::FetchFailed => "Download failed: {reason}",
+        Msg::FetchTooLarge => "The file is larger than {size} MB.",
+        Msg::FetchNotMedia => "The link is not an image, video or audio file but {mime}.",
+        Msg::UsageInfo => "Usage: /info <file id, unique id or short id>, or reply /info to a file",
+        Msg::InfoNotFound => "No file {id} was found.",
+        Msg::InfoSize => "📦 {size}, {mime}",
+        Msg::InfoUploaded => "🕒 uploaded {time} UTC",
+        Msg::InfoUploader => "👤 uploaded by user {id}",
+        Msg::InfoUploaderUnknown => "👤 the uploader isn't stored",
+        Msg::InfoDownloads => "👁️ {count} downloads",
+        Msg::InfoStored => "💾 stored copy: {keys}",
+        Msg::InfoNotStored => "💾 no stored copy, served from Telegram",
+        Msg::InfoDeleted => "🗑️ deleted {time} UTC",
+        Msg::InfoBlocked => "⛔ not served: {reason}",
+        Msg::InfoCamera => "📷 {camera}",
+        Msg::InfoLens => "🔭 {lens}",
+        Msg::InfoExposure => "⚙️ {exposure}",
+        Msg::InfoTaken => "📅 taken {time}",
+        Msg::InfoLocation => "📍 {location}",
+        Msg::InfoLocationHidden => "📍 location hidden, /location on shows it",
+        Msg::Deleted => {
+            "Deleted {count} file(s), undo within {days} days with /undelete <id>:\n{ids}"
+        }
+        Msg::UsageDelete => "Reply to a file with /delete, or give its ids: /delete <id> <id> ...",
+        Msg::DeleteTooMany => "At most {max} files can be deleted at once.",
+        Msg::DeleteSkipped => "Not deleted:\n{lines}",
+        Msg::DeleteNotFound => "not found",
+        Msg::DeleteAlreadyDeleted => "already deleted",
+        Msg::DeleteForbidden => "not your file",
+        Msg::DeleteDuplicate => "listed twice",
+        Msg::UsageUndelete => "Usage: /undelete <id>",
+        Msg::UndeleteNotFound => {
+            "No deleted file {id} was found, it may have been removed for good."
+        }
+        Msg::Undeleted => "{id} is back online.",
+        Msg::UsageBan => "Usage: /ban <user id>",
+        Msg::UsageUnban => "Usage: /unban <user id>",
+        Msg::Banned => "User {id} is banned, their uploads are refused.",
+        Msg::AlreadyBanned => "User {id} is banned already.",
+        Msg::Unbanned => "User {id} can upload again.",
+        Msg::NotBanned => "User {id} isn't banned.",
+        Msg::YouAreBanned => "You can't upload files to this bot.",
+        Msg::MaintainerOnly => "Only the maintainer can run this command.",
+        Msg::ForgetMeConfirm => {
+            "This deletes every file you uploaded, your albums and everything else stored about you. Links stop working and it can't be undone."
+        }
+        Msg::ForgetMeButton => "Delete everything",
+        Msg::Forgotten => {
+            "Deleted {files} file(s), {albums} album(s) and {objects} stored copies. Nothing about you is stored anymore."
+        }
+        Msg::PrivacyModeOnly => {
+            "This bot doesn't store who uploaded a file, so only the maintainer can use this command."
+        }
+        Msg::UsageAlbum => "Usage: /album create <name>, or reply to a file with /album add <name>",
+        Msg::AlbumPrivate => "Albums belong to users, run this in a private chat.",
+        Msg::AlbumCreated => "Created album {name}, share it with {url}",
+        Msg::AlbumExists => "You already have an album named {name}.",
+        Msg::AlbumNotFound => "You have no album named {name}, create it with /album create {name}",
+        Msg::AlbumAdded => "Added {count} file(s) to {name}: {url}",
+        Msg::UsageTag => {
+            "Reply to a file with /tag <name> ... to tag it, -<name> removes a tag. /tag alone lists your tags."
+        }
+        Msg::TagInvalid => "{name} is not a tag, use up to {max} letters, digits, - and _.",
+        Msg::TagTooMany => "A file can have at most {max} tags.",
+        Msg::TagsSaved => "Tags of {count} file(s) updated:\n{lines}",
+        Msg::TagNoTags => "no tags",
+        Msg::TagList => "Your tags:\n{tags}",
+        Msg::TagNone => "You have no tags yet, reply to a file with /tag <name> to add one.",
+        Msg::PreviewProtected => "🔒 password protected, only people with the password can open it",
+        Msg::PreviewPublic => "🌐 public, anyone with the link can open it",
+        Msg::PreviewNeverExpires => "♾️ never expires",
+        Msg::PreviewNoName => "📄 downloads without a file name",
+        Msg::PreviewDownloadsAs => "📄 downloads as {name}",
+        Msg::PreviewExifStripped => "✅ EXIF metadata was stripped by Telegram",
+        Msg::PreviewExifStrippedOnServe => "✅ EXIF metadata is stripped when the file is served",
+        Msg::PreviewExifKept => "⚠️ EXIF metadata like location is published as uploaded",
+        Msg::PreviewForwardedFrom => "↪️ forwarded from {from}",
+        Msg::PreviewNoThumbnail => "🖼 no thumbnail",
+        Msg::PreviewThumbnail => "🖼 has a thumbnail",
+        Msg::SummaryTitle => "Recent uploads",
+        Msg::NamespacePrivate => "Namespaces belong to users, run this in a private chat.",
+        Msg::NamespaceUsage => {
+            "Usage: /namespace <name>, your public files are listed at /u/<name>/."
+        }
+        Msg::NamespaceCurrent => "Your public files are listed at {url}",
+        Msg::NamespaceOff => "Your files are no longer listed.",
+        Msg::NamespaceInvalid => "Use 1 to 32 letters, digits, - or _ for the name.",
+        Msg::NamespaceTaken => "{name} is already taken.",
+        Msg::NamespaceSaved => "Your files without a password are now listed at {url}",
+        Msg::LocationPrivate => {
+            "The location setting belongs to users, run this in a private chat."
+        }
+        Msg::LocationShown => {
+            "The GPS location of your jpeg documents is shown on their /view/ pages and in /info."
+        }
+        Msg::LocationHidden => "The GPS location of your jpeg documents is hidden.",
+        Msg::LocationUsage => "Usage: /location on|off",
+        Msg::TokenPrivate => {
+            "API keys are shown in the chat, manage them in a private chat with the bot."
+        }
+        Msg::UsageToken => {
+            "Usage: /token new [upload delete list], /token list or /token revoke <id>"
+        }
+        Msg::TokenCreated => {
+            "Key {id} with {scopes}:\n\n{key}\n\nSend it as Authorization: Bearer <key>. It is shown only this once, delete this message after copying it."
+        }
+        Msg::TokenLimit => "You already have {max} keys, revoke one first.",
+        Msg::TokenNone => "You have no API keys, create one with /token new.",
+        Msg::TokenList => "Your API keys:\n{keys}",
+        Msg::TokenLine => "{id}: {scopes}, last used {used}",
+        Msg::TokenNeverUsed => "never",
+        Msg::TokenRevoked => "Key {id} was revoked.",
+        Msg::TokenNotFound => "You have no key {id}.",
+        Msg::SetupTitle => "Instance setup, run the steps in order:",
+        Msg::SetupStepDb => "Initialize database",
+        Msg::SetupStepWebhook => "Register webhook",
+        Msg::SetupStepR2 => "Check R2",
+        Msg::SetupStepChannel => "Storage channel",
+        Msg::SetupDb => "Database initialized.",
+        Msg::SetupWebhook => "Webhook registered.",
+        Msg::SetupR2 => "R2 bucket is reachable.",
+        Msg::SetupR2Kv => {
+            "R2 is not bound, files are stored in the FILE_STORE KV namespace instead. Add the R2 binding to wrangler.toml for files over 25 MiB."
+        }
+        Msg::SetupR2Missing => {
+            "R2 is not bound, files are fetched from Telegram on every cache miss. Add the R2 binding to wrangler.toml to store them."
+        }
+        Msg::SetupChannel => {
+            "Create a private channel, add this bot as an admin, \
+            then post /setup channel in it to get the id for STORAGE_CHAT_ID."
+        }
+        Msg::SetupUnknown => "Unknown setup step.",
+        Msg::SetupChannelId => {
+            "The id of this chat is {id}, set STORAGE_CHAT_ID to it to keep uploads from the http api here."
+        }
+        Msg::SetupMaintainerOnly => "Only the maintainer can run /setup.",
+        Msg::SetupFailed => "Setup step {step} failed: {error}",
+        Msg::SetupDone => "Done.",
+        Msg::SendFileHint => "Send a photo, video or file to host it, /help for more.",
+        Msg::UploadError => "Error: {error}",
+        Msg::TooBig => {
+            "{name} is larger than {size} MB, the Telegram Bot API download limit, so it can't be hosted."
+        }
+        Msg::TooLarge => {
+            "{name} is larger than {size} MB, the file size limit of this bot, so it wasn't hosted."
+        }
+        Msg::Refused => "{name} was refused by the content moderation and won't be hosted.",
+        Msg::TypeNotAllowed => "{name} can't be hosted, {type} files are not accepted here.",
+        Msg::MissingBinding => "{feature} requires the {binding} binding, add it to wrangler.toml",
+        Msg::Help => {
+            "Send a photo, video or file to this chat, or to a channel where this bot is an admin, \
+            and it replies with links to share it.\n\n\
+            Photos are re-encoded by Telegram, send images as files to keep the original. \
+            Files up to {size} MB can be hosted.\n\n\
+            Commands:\n{commands}"
+        }
+        Msg::CmdHelp => "How to use this bot",
+        Msg::CmdPreview => "Show what the replied file's links publish",
+        Msg::CmdProtect => "Require a password for the replied file",
+        Msg::CmdUnprotect => "Remove the password of the replied file",
+        Msg::CmdRename => "Set the download name of the replied file",
+        Msg::CmdAlias => "Serve the replied file at /s/<name>",
+        Msg::CmdAlt => "Describe the replied image for screen readers",
+        Msg::CmdInfo => "Show what is stored about a file",
+        Msg::CmdFetch => "Host a file downloaded from a link",
+        Msg::CmdDelete => {
+            "Delete the replied file or the given ids, they can be restored for a while"
+        }
+        Msg::CmdUndelete => "Restore a deleted file",
+        Msg::CmdBan => "Refuse uploads of a user (maintainer)",
+        Msg::CmdUnban => "Allow a banned user again (maintainer)",
+        Msg::CmdForgetMe => "Delete all your files and data",
+        Msg::CmdAlbum => "Create an album or add the replied file to it",
+        Msg::CmdTag => "Tag the replied file, or list your tags",
+        Msg::CmdNamespace => "List your public files at /u/<name>/, off to stop",
+        Msg::CmdToken => "Create, list or revoke your API keys",
+        Msg::CmdLocation => "Show or hide the GPS location of your photos on /view/ pages",
+        Msg::CmdSetup => "Set up this instance, maintainer only",
+    }
+}
+
+fn zh(msg: Msg) -> &'static str {
+    match msg {
+        Msg::ConsentButton => "我同意",
+        Msg::ConsentRequest => "上传文件前，请阅读 {terms} 上的服务条款并点击“我同意”。",
+        Msg::ConsentThanks => "谢谢，现在可以上传文件了。",
+        Msg::ReplyToFile => "请回复已上传的文件或其链接来使用此命令。",
+        Msg::NotYourFiles => "你只能修改自己上传的文件。",
+        Msg::NotYourPreview => "你只能预览自己上传的文件。",
+        Msg::UsageProtect => "用法：/protect <密码>",
+        Msg::UsageRename => "用法：/rename <文件名>",
+        Msg::Unprotected => "已移除 {count} 个文件的密码。",
+        Msg::Protected => "已为 {count} 个文件设置密码，打开时浏览器会要求输入密码。",
+        Msg::Renamed => "{count} 个文件现在下载为 {name}。",
+        Msg::UsageAlias => "用法：/alias <名称>",
+        Msg::AliasInvalid => "别名请使用 1 到 64 个字母、数字、- 或 _。",
+        Msg::AliasReserved => "{name} 是保留名称，请换一个别名。",
+        Msg::AliasSaved => "该文件现在也可以通过 {url} 访问",
+        Msg::UsageAlt => "用法：/alt <图片描述>，最多 {max} 个字符",
+        Msg::AltSaved => "已更新 {count} 个文件的替代文本。",
+        Msg::UsageFetch => "用法：/fetch <不超过 {size} MB 的图片、视频或音频链接>",
+        Msg::FetchInvalidUrl => "这不是 http 或 https 链接。",
+        Msg::FetchFailed => "下载失败：{reason}",
+        Msg::FetchTooLarge => "文件超过了 {size} MB。",
+        Msg::FetchNotMedia => "该链接不是图片、视频或音频，而是 {mime}。",
+        Msg::UsageInfo => "用法：/info <文件 id、unique id 或短 id>，或回复文件 /info",
+        Msg::InfoNotFound => "没有找到文件 {id}。",
+        Msg::InfoSize => "📦 {size}，{mime}",
+        Msg::InfoUploaded => "🕒 上传于 {time} UTC",
+        Msg::InfoUploader => "👤 上传者 {id}",
+        Msg::InfoUploaderUnknown => "👤 未保存上传者",
+        Msg::InfoDownloads => "👁️ 下载 {count} 次",
+        Msg::InfoStored => "💾 已存储副本：{keys}",
+        Msg::InfoNotStored => "💾 没有存储副本，从 Telegram 提供",
+        Msg::InfoDeleted => "🗑️ 删除于 {time} UTC",
+        Msg::InfoBlocked => "⛔ 已停止提供：{reason}",
+        Msg::InfoCamera => "📷 {camera}",
+        Msg::InfoLens => "🔭 {lens}",
+        Msg::InfoExposure => "⚙️ {exposure}",
+        Msg::InfoTaken => "📅 拍摄于 {time}",
+        Msg::InfoLocation => "📍 {location}",
+        Msg::InfoLocationHidden => "📍 位置已隐藏，/location on 显示",
+        Msg::Deleted => "已删除 {count} 个文件，{days} 天内可以用 /undelete <id> 恢复：\n{ids}",
+        Msg::UsageDelete => "回复文件发送 /delete，或者给出 id：/delete <id> <id> ...",
+        Msg::DeleteTooMany => "一次最多删除 {max} 个文件。",
+        Msg::DeleteSkipped => "未删除：\n{lines}",
+        Msg::DeleteNotFound => "未找到",
+        Msg::DeleteAlreadyDeleted => "已经删除",
+        Msg::DeleteForbidden => "不是你的文件",
+        Msg::DeleteDuplicate => "重复",
+        Msg::UsageUndelete => "用法：/undelete <id>",
+        Msg::UndeleteNotFound => "没有找到已删除的文件 {id}，它可能已被彻底删除。",
+        Msg::Undeleted => "{id} 已恢复。",
+        Msg::UsageBan => "用法：/ban <用户 ID>",
+        Msg::UsageUnban => "用法：/unban <用户 ID>",
+        Msg::Banned => "已封禁用户 {id}，其上传将被拒绝。",
+        Msg::AlreadyBanned => "用户 {id} 已经被封禁。",
+        Msg::Unbanned => "用户 {id} 可以重新上传了。",
+        Msg::NotBanned => "用户 {id} 没有被封禁。",
+        Msg::YouAreBanned => "你不能向此机器人上传文件。",
+        Msg::MaintainerOnly => "只有维护者可以运行此命令。",
+        Msg::ForgetMeConfirm => {
+            "这将删除你上传的所有文件、你的相册以及存储的关于你的所有其他数据。链接将失效，且无法撤销。"
+        }
+        Msg::ForgetMeButton => "全部删除",
+        Msg::Forgotten => {
+            "已删除 {files} 个文件、{albums} 个相册和 {objects} 个存储副本。不再保存任何关于你的数据。"
+        }
+        Msg::PrivacyModeOnly => "此机器人不记录文件的上传者，因此只有维护者可以使用此命令。",
+        Msg::UsageAlbum => "用法：/album create <名称>，或回复文件发送 /album add <名称>",
+        Msg::AlbumPrivate => "相册属于用户，请在私聊中运行此命令。",
+        Msg::AlbumCreated => "已创建相册 {name}，分享链接：{url}",
+        Msg::AlbumExists => "你已经有名为 {name} 的相册。",
+        Msg::AlbumNotFound => "你没有名为 {name} 的相册，请先用 /album create {name} 创建",
+        Msg::AlbumAdded => "已将 {count} 个文件添加到 {name}：{url}",
+        Msg::UsageTag => {
+            "回复文件发送 /tag <名称> ... 添加标签，-<名称> 删除标签。单独发送 /tag 列出你的标签。"
+        }
+        Msg::TagInvalid => "{name} 不是有效的标签，最多 {max} 个字母、数字、- 和 _。",
+        Msg::TagTooMany => "一个文件最多有 {max} 个标签。",
+        Msg::TagsSaved => "已更新 {count} 个文件的标签：\n{lines}",
+        Msg::TagNoTags => "没有标签",
+        Msg::TagList => "你的标签：\n{tags}",
+        Msg::TagNone => "你还没有标签，回复文件发送 /tag <名称> 来添加。",
+        Msg::PreviewProtected => "🔒 已设密码，只有知道密码的人可以打开",
+        Msg::PreviewPublic => "🌐 公开，任何拥有链接的人都可以打开",
+        Msg::PreviewNeverExpires => "♾️ 永不过期",
+        Msg::PreviewNoName => "📄 下载时没有文件名",
+        Msg::PreviewDownloadsAs => "📄 下载为 {name}",
+        Msg::PreviewExifStripped => "✅ EXIF 元数据已被 Telegram 移除",
+        Msg::PreviewExifStrippedOnServe => "✅ 提供文件时会移除 EXIF 元数据",
+        Msg::PreviewExifKept => "⚠️ 位置等 EXIF 元数据会按上传时原样公开",
+        Msg::PreviewForwardedFrom => "↪️ 转发自 {from}",
+        Msg::PreviewNoThumbnail => "🖼 没有缩略图",
+        Msg::PreviewThumbnail => "🖼 有缩略图",
+        Msg::SummaryTitle => "最近上传",
+        Msg::NamespacePrivate => "命名空间属于用户，请在私聊中运行此命令。",
+        Msg::NamespaceUsage => "用法：/namespace <名称>，你的公开文件会列在 /u/<名称>/。",
+        Msg::NamespaceCurrent => "你的公开文件列在 {url}",
+        Msg::NamespaceOff => "你的文件不再被列出。",
+        Msg::NamespaceInvalid => "名称请使用 1 到 32 个字母、数字、- 或 _。",
+        Msg::NamespaceTaken => "{name} 已被占用。",
+        Msg::NamespaceSaved => "你未设密码的文件现在列在 {url}",
+        Msg::LocationPrivate => "位置设置属于用户，请在私聊中运行此命令。",
+        Msg::LocationShown => "你的 jpeg 文档的 GPS 位置会显示在 /view/ 页面和 /info 中。",
+        Msg::LocationHidden => "你的 jpeg 文档的 GPS 位置已隐藏。",
+        Msg::LocationUsage => "用法：/location on|off",
+        Msg::TokenPrivate => "API 密钥会显示在聊天中，请在与机器人的私聊中管理。",
+        Msg::UsageToken => {
+            "用法：/token new [upload delete list]、/token list 或 /token revoke <id>"
+        }
+        Msg::TokenCreated => {
+            "密钥 {id}，权限 {scopes}：\n\n{key}\n\n以 Authorization: Bearer <key> 发送。它只显示这一次，复制后请删除这条消息。"
+        }
+        Msg::TokenLimit => "你已经有 {max} 个密钥，请先撤销一个。",
+        Msg::TokenNone => "你还没有 API 密钥，用 /token new 创建一个。",
+        Msg::TokenList => "你的 API 密钥：\n{keys}",
+        Msg::TokenLine => "{id}：{scopes}，最后使用 {used}",
+        Msg::TokenNeverUsed => "从未",
+        Msg::TokenRevoked => "密钥 {id} 已撤销。",
+        Msg::TokenNotFound => "你没有密钥 {id}。",
+        Msg::SetupTitle => "实例设置，请按顺序运行以下步骤：",
+        Msg::SetupStepDb => "初始化数据库",
+        Msg::SetupStepWebhook => "注册 Webhook",
+        Msg::SetupStepR2 => "检查 R2",
+        Msg::SetupStepChannel => "存储频道",
+        Msg::SetupDb => "数据库已初始化。",
+        Msg::SetupWebhook => "Webhook 已注册。",
+        Msg::SetupR2 => "R2 存储桶可以访问。",
+        Msg::SetupR2Kv => {
+            "未绑定 R2，文件存储在 FILE_STORE KV 命名空间中。要存储超过 25 MiB 的文件，请在 wrangler.toml 中添加 R2 绑定。"
+        }
+        Msg::SetupR2Missing => {
+            "未绑定 R2，每次缓存未命中都会从 Telegram 获取文件。请在 wrangler.toml 中添加 R2 绑定来存储文件。"
+        }
+        Msg::SetupChannel => {
+            "创建一个私有频道，将此机器人添加为管理员，\
+            然后在频道中发送 /setup channel 获取 STORAGE_CHAT_ID 所需的 ID。"
+        }
+        Msg::SetupUnknown => "未知的设置步骤。",
+        Msg::SetupChannelId => {
+            "此聊天的 ID 是 {id}，将 STORAGE_CHAT_ID 设置为它，即可把通过 HTTP API 上传的文件保存在这里。"
+        }
+        Msg::SetupMaintainerOnly => "只有维护者可以运行 /setup。",
+        Msg::SetupFailed => "设置步骤 {step} 失败：{error}",
+        Msg::SetupDone => "完成。",
+        Msg::SendFileHint => "发送图片、视频或文件即可托管，/help 查看更多。",
+        Msg::UploadError => "错误：{error}",
+        Msg::TooBig => "{name} 超过 Telegram Bot API 的下载上限 {size} MB，无法托管。",
+        Msg::TooLarge => "{name} 超过此机器人的文件大小上限 {size} MB，未被托管。",
+        Msg::Refused => "{name} 未通过内容审核，不会被托管。",
+        Msg::TypeNotAllowed => "{name} 无法托管，这里不接受 {type} 文件。",
+        Msg::MissingBinding => "{feature} 需要 {binding} 绑定，请将其添加到 wrangler.toml",
+        Msg::Help => {
+            "将图片、视频或文件发送到此聊天，或发送到此机器人担任管理员的频道，\
+            机器人会回复可分享的链接。\n\n\
+            Telegram 会重新编码图片，如需保留原图请以文件形式发送。\
+            最大可托管 {size} MB 的文件。\n\n\
+            命令：\n{commands}"
+        }
+        Msg::CmdHelp => "如何使用此机器人",
+        Msg::CmdPreview => "查看所回复文件的链接会公开哪些内容",
+        Msg::CmdProtect => "为所回复的文件设置密码",
+        Msg::CmdUnprotect => "移除所回复文件的密码",
+        Msg::CmdRename => "设置所回复文件的下载名称",
+        Msg::CmdAlias => "通过 /s/<名称> 提供所回复的文件",
+        Msg::CmdAlt => "为所回复的图片设置供屏幕阅读器使用的描述",
+        Msg::CmdInfo => "查看文件的存储信息",
+        Msg::CmdFetch => "托管从链接下载的文件",
+        Msg::CmdDelete => "删除所回复的文件或给出 id 的文件，一段时间内可以恢复",
+        Msg::CmdUndelete => "恢复已删除的文件",
+        Msg::CmdBan => "拒绝某个用户的上传（维护者）",
+        Msg::CmdUnban => "解除对用户的封禁（维护者）",
+        Msg::CmdForgetMe => "删除你的所有文件和数据",
+        Msg::CmdAlbum => "创建相册或将所回复的文件加入相册",
+        Msg::CmdTag => "给所回复的文件加标签，或列出你的标签",
+        Msg::CmdNamespace => "在 /u/<名称>/ 列出你的公开文件，off 停止",
+        Msg::CmdToken => "创建、列出或撤销你的 API 密钥",
+        Msg::CmdLocation => "在 /view/ 页面显示或隐藏照片的 GPS 位置",
+        Msg::CmdSetup => "设置此实例，仅限维护者",
+    }
+}
+
+fn ja(msg: Msg) -> &'static str {
+    match msg {
+        Msg::ConsentButton => "同意する",
+        Msg::ConsentRequest => {
+            "ファイルをアップロードする前に {terms} の利用規約を読み、「同意する」を押してください。"
+        }
+        Msg::ConsentThanks => {
+            "ありがとうございます。ファイルをアップロードできるようになりました。"
+        }
+        Msg::ReplyToFile => {
+            "アップロードしたファイルまたはそのリンクに返信してこのコマンドを使ってください。"
+        }
+        Msg::NotYourFiles => "変更できるのは自分がアップロードしたファイルだけです。",
+        Msg::NotYourPreview => "プレビューできるのは自分がアップロードしたファイルだけです。",
+        Msg::UsageProtect => "使い方: /protect <パスワード>",
+        Msg::UsageRename => "使い方: /rename <ファイル名>",
+        Msg::Unprotected => "{count} 件のファイルのパスワードを削除しました。",
+        Msg::Protected => {
+            "{count} 件のファイルを保護しました。開くとブラウザがパスワードを求めます。"
+        }
+        Msg::Renamed => "{count} 件のファイルは {name} としてダウンロードされます。",
+        Msg::UsageAlias => "使い方: /alias <名前>",
+        Msg::AliasInvalid => "エイリアスには 1〜64 文字の英数字、- または _ を使ってください。",
+        Msg::AliasReserved => "{name} は予約されています。別のエイリアスを選んでください。",
+        Msg::AliasSaved => "ファイルは {url} からも開けるようになりました",
+        Msg::UsageAlt => "使い方: /alt <画像の説明>、{max} 文字まで",
+        Msg::AltSaved => "{count} 件のファイルの代替テキストを更新しました。",
+        Msg::UsageFetch => "使い方: /fetch <{size} MB までの画像・動画・音声の URL>",
+        Msg::FetchInvalidUrl => "http または https のリンクではありません。",
+        Msg::FetchFailed => "ダウンロードに失敗しました: {reason}",
+        Msg::FetchTooLarge => "ファイルが {size} MB を超えています。",
+        Msg::FetchNotMedia => "リンク先は画像・動画・音声ではなく {mime} です。",
+        Msg::UsageInfo => {
+            "使い方: /info <ファイル ID、unique ID または短縮 ID>、またはファイルに /info で返信"
+        }
+        Msg::InfoNotFound => "ファイル {id} が見つかりません。",
+        Msg::InfoSize => "📦 {size}、{mime}",
+        Msg::InfoUploaded => "🕒 {time} UTC にアップロード",
+        Msg::InfoUploader => "👤 アップロードしたユーザー {id}",
+        Msg::InfoUploaderUnknown => "👤 アップロードしたユーザーは保存されていません",
+        Msg::InfoDownloads => "👁️ ダウンロード {count} 回",
+        Msg::InfoStored => "💾 保存済みのコピー: {keys}",
+        Msg::InfoNotStored => "💾 保存済みのコピーなし、Telegram から配信",
+        Msg::InfoDeleted => "🗑️ {time} UTC に削除",
+        Msg::InfoBlocked => "⛔ 配信停止: {reason}",
+        Msg::InfoCamera => "📷 {camera}",
+        Msg::InfoLens => "🔭 {lens}",
+        Msg::InfoExposure => "⚙️ {exposure}",
+        Msg::InfoTaken => "📅 {time} に撮影",
+        Msg::InfoLocation => "📍 {location}",
+        Msg::InfoLocationHidden => "📍 位置情報は非表示、/location on で表示",
+        Msg::Deleted => {
+            "{count} 件のファイルを削除しました。{days} 日以内なら /undelete <id> で元に戻せます:\n{ids}"
+        }
+        Msg::UsageDelete => {
+            "ファイルに返信して /delete を送るか、id を指定してください: /delete <id> <id> ..."
+        }
+        Msg::DeleteTooMany => "一度に削除できるのは {max} 件までです。",
+        Msg::DeleteSkipped => "削除されなかったもの:\n{lines}",
+        Msg::DeleteNotFound => "見つかりません",
+        Msg::DeleteAlreadyDeleted => "削除済み",
+        Msg::DeleteForbidden => "自分のファイルではありません",
+        Msg::DeleteDuplicate => "重複",
+        Msg::UsageUndelete => "使い方: /undelete <id>",
+        Msg::UndeleteNotFound => {
+            "削除されたファイル {id} は見つかりません。完全に削除された可能性があります。"
+        }
+        Msg::Undeleted => "{id} を復元しました。",
+        Msg::UsageBan => "使い方: /ban <ユーザー ID>",
+        Msg::UsageUnban => "使い方: /unban <ユーザー ID>",
+        Msg::Banned => "ユーザー {id} を禁止しました。アップロードは拒否されます。",
+        Msg::AlreadyBanned => "ユーザー {id} はすでに禁止されています。",
+        Msg::Unbanned => "ユーザー {id} は再びアップロードできます。",
+        Msg::NotBanned => "ユーザー {id} は禁止されていません。",
+        Msg::YouAreBanned => "このボットにはファイルをアップロードできません。",
+        Msg::MaintainerOnly => "このコマンドを実行できるのはメンテナーだけです。",
+        Msg::ForgetMeConfirm => {
+            "アップロードしたすべてのファイル、アルバム、その他あなたについて保存されているすべてを削除します。リンクは使えなくなり、元に戻せません。"
+        }
+        Msg::ForgetMeButton => "すべて削除",
+        Msg::Forgotten => {
+            "{files} 件のファイル、{albums} 件のアルバム、{objects} 件の保存コピーを削除しました。あなたについてのデータはもう保存されていません。"
+        }
+        Msg::PrivacyModeOnly => {
+            "このボットはファイルのアップロード者を保存しないため、このコマンドはメンテナーだけが使えます。"
+        }
+        Msg::UsageAlbum => {
+            "使い方: /album create <名前>、またはファイルに返信して /album add <名前>"
+        }
+        Msg::AlbumPrivate => {
+            "アルバムはユーザーごとのものです。プライベートチャットで実行してください。"
+        }
+        Msg::AlbumCreated => "アルバム {name} を作成しました。共有リンク: {url}",
+        Msg::AlbumExists => "{name} という名前のアルバムはすでにあります。",
+        Msg::AlbumNotFound => {
+            "{name} という名前のアルバムはありません。/album create {name} で作成してください"
+        }
+        Msg::AlbumAdded => "{count} 件のファイルを {name} に追加しました: {url}",
+        Msg::UsageTag => {
+            "ファイルに返信して /tag <名前> ... でタグを付け、-<名前> で外します。/tag だけでタグを一覧表示します。"
+        }
+        Msg::TagInvalid => {
+            "{name} はタグに使えません。{max} 文字までの文字、数字、- と _ を使ってください。"
+        }
+        Msg::TagTooMany => "1 つのファイルに付けられるタグは {max} 個までです。",
+        Msg::TagsSaved => "{count} 件のファイルのタグを更新しました:\n{lines}",
+        Msg::TagNoTags => "タグなし",
+        Msg::TagList => "あなたのタグ:\n{tags}",
+        Msg::TagNone => "まだタグがありません。ファイルに返信して /tag <名前> で追加できます。",
+        Msg::PreviewProtected => "🔒 パスワード保護、パスワードを知っている人だけが開けます",
+        Msg::PreviewPublic => "🌐 公開、リンクを知っている人は誰でも開けます",
+        Msg::PreviewNeverExpires => "♾️ 期限なし",
+        Msg::PreviewNoName => "📄 ファイル名なしでダウンロードされます",
+        Msg::PreviewDownloadsAs => "📄 {name} としてダウンロードされます",
+        Msg::PreviewExifStripped => "✅ EXIF メタデータは Telegram によって削除されました",
+        Msg::PreviewExifStrippedOnServe => "✅ EXIF メタデータは配信時に削除されます",
+        Msg::PreviewExifKept => {
+            "⚠️ 位置情報などの EXIF メタデータはアップロードしたまま公開されます"
+        }
+        Msg::PreviewForwardedFrom => "↪️ {from} から転送",
+        Msg::PreviewNoThumbnail => "🖼 サムネイルなし",
+        Msg::PreviewThumbnail => "🖼 サムネイルあり",
+        Msg::SummaryTitle => "最近のアップロード",
+        Msg::NamespacePrivate => {
+            "ネームスペースはユーザーごとのものです。プライベートチャットで実行してください。"
+        }
+        Msg::NamespaceUsage => {
+            "使い方: /namespace <名前>、公開ファイルは /u/<名前>/ に一覧表示されます。"
+        }
+        Msg::NamespaceCurrent => "公開ファイルは {url} に一覧表示されています",
+        Msg::NamespaceOff => "ファイルは一覧表示されなくなりました。",
+        Msg::NamespaceInvalid => "名前には 1〜32 文字の英数字、- または _ を使ってください。",
+        Msg::NamespaceTaken => "{name} はすでに使われています。",
+        Msg::NamespaceSaved => "パスワードのないファイルが {url} に一覧表示されるようになりました",
+        Msg::LocationPrivate => {
+            "位置情報の設定はユーザーごとです。プライベートチャットで実行してください。"
+        }
+        Msg::LocationShown => {
+            "jpeg ドキュメントの GPS 位置情報を /view/ ページと /info に表示します。"
+        }
+        Msg::LocationHidden => "jpeg ドキュメントの GPS 位置情報は非表示です。",
+        Msg::LocationUsage => "使い方: /location on|off",
+        Msg::TokenPrivate => {
+            "API キーはチャットに表示されるため、ボットとのプライベートチャットで管理してください。"
+        }
+        Msg::UsageToken => {
+            "使い方: /token new [upload delete list]、/token list または /token revoke <id>"
+        }
+        Msg::TokenCreated => {
+            "キー {id}（権限 {scopes}）:\n\n{key}\n\nAuthorization: Bearer <key> として送信してください。表示されるのはこの一度だけです。コピーしたらこのメッセージを削除してください。"
+        }
+        Msg::TokenLimit => "すでに {max} 個のキーがあります。先にどれかを取り消してください。",
+        Msg::TokenNone => "API キーはありません。/token new で作成できます。",
+        Msg::TokenList => "あなたの API キー:\n{keys}",
+        Msg::TokenLine => "{id}: {scopes}、最終使用 {used}",
+        Msg::TokenNeverUsed => "なし",
+        Msg::TokenRevoked => "キー {id} を取り消しました。",
+        Msg::TokenNotFound => "キー {id} はありません。",
+        Msg::SetupTitle => "インスタンスのセットアップ、順番に実行してください:",
+        Msg::SetupStepDb => "データベースを初期化",
+        Msg::SetupStepWebhook => "Webhook を登録",
+        Msg::SetupStepR2 => "R2 を確認",
+        Msg::SetupStepChannel => "ストレージチャンネル",
+        Msg::SetupDb => "データベースを初期化しました。",
+        Msg::SetupWebhook => "Webhook を登録しました。",
+        Msg::SetupR2 => "R2 バケットにアクセスできます。",
+        Msg::SetupR2Kv => {
+            "R2 がバインドされていないため、ファイルは FILE_STORE KV 名前空間に保存されます。25 MiB を超えるファイルには wrangler.toml に R2 バインディングを追加してください。"
+        }
+        Msg::SetupR2Missing => {
+            "R2 がバインドされていないため、キャッシュミスのたびに Telegram からファイルを取得します。保存するには wrangler.toml に R2 バインディングを追加してください。"
+        }
+        Msg::SetupChannel => {
+            "プライベートチャンネルを作成し、このボットを管理者に追加してから、\
+            チャンネルに /setup channel を投稿して STORAGE_CHAT_ID 用の ID を取得してください。"
+        }
+        Msg::SetupUnknown => "不明なセットアップ手順です。",
+        Msg::SetupChannelId => {
+            "このチャットの ID は {id} です。STORAGE_CHAT_ID に設定すると、HTTP API からのアップロードがここに保存されます。"
+        }
+        Msg::SetupMaintainerOnly => "/setup を実行できるのはメンテナーだけです。",
+        Msg::SetupFailed => "セットアップ手順 {step} に失敗しました: {error}",
+        Msg::SetupDone => "完了しました。",
+        Msg::SendFileHint => "写真、動画、ファイルを送るとホストします。詳しくは /help。",
+        Msg::UploadError => "エラー: {error}",
+        Msg::TooBig => {
+            "{name} は Telegram Bot API のダウンロード上限 {size} MB を超えているため、ホストできません。"
+        }
+        Msg::TooLarge => {
+            "{name} はこのボットのファイルサイズ上限 {size} MB を超えているため、ホストされませんでした。"
+        }
+        Msg::Refused => "{name} はコンテンツ審査で拒否されたため、ホストされません。",
+        Msg::TypeNotAllowed => "{name} はホストできません。{type} ファイルは受け付けていません。",
+        Msg::MissingBinding => {
+            "{feature} には {binding} バインディングが必要です。wrangler.toml に追加してください"
+        }
+        Msg::Help => {
+            "写真、動画、ファイルをこのチャット、またはこのボットが管理者のチャンネルに送ると、\
+            共有用のリンクを返信します。\n\n\
+            写真は Telegram によって再エンコードされます。元の画像を保つにはファイルとして送ってください。\
+            {size} MB までのファイルをホストできます。\n\n\
+            コマンド:\n{commands}"
+        }
+        Msg::CmdHelp => "このボットの使い方",
+        Msg::CmdPreview => "返信したファイルのリンクで公開される内容を表示",
+        Msg::CmdProtect => "返信したファイルにパスワードを設定",
+        Msg::CmdUnprotect => "返信したファイルのパスワードを削除",
+        Msg::CmdRename => "返信したファイルのダウンロード名を設定",
+        Msg::CmdAlias => "返信したファイルを /s/<名前> で公開",
+        Msg::CmdAlt => "返信した画像にスクリーンリーダー向けの説明を設定",
+        Msg::CmdInfo => "ファイルについて保存されている情報を表示",
+        Msg::CmdFetch => "リンク先のファイルをダウンロードしてホスト",
+        Msg::CmdDelete => {
+            "返信したファイルまたは指定した id のファイルを削除（しばらくは復元可能）"
+        }
+        Msg::CmdUndelete => "削除したファイルを復元",
+        Msg::CmdBan => "ユーザーのアップロードを拒否（メンテナー）",
+        Msg::CmdUnban => "禁止したユーザーを解除（メンテナー）",
+        Msg::CmdForgetMe => "自分のファイルとデータをすべて削除",
+        Msg::CmdAlbum => "アルバムを作成、または返信したファイルを追加",
+        Msg::CmdTag => "返信したファイルにタグを付ける、またはタグを一覧表示",
+        Msg::CmdNamespace => "公開ファイルを /u/<名前>/ に一覧表示、off で停止",
+        Msg::CmdToken => "API キーを作成、一覧表示、取り消し",
+        Msg::CmdLocation => "/view/ ページで写真の GPS 位置情報を表示または非表示",
+        Msg::CmdSetup => "このインスタンスをセットアップ、メンテナー専用",
+    }
+}
+
+fn ru(msg: Msg) -> &'static str {
+    match msg {
+        Msg::ConsentButton => "Принимаю",
+        Msg::ConsentRequest => {
+            "Прочитайте условия использования на {terms} и нажмите «Принимаю», прежде чем загружать файлы."
+        }
+        Msg::ConsentThanks => "Спасибо, теперь вы можете загружать файлы.",
+        Msg::ReplyToFile => {
+            "Ответьте на загруженный файл или его ссылки, чтобы использовать эту команду."
+        }
+        Msg::NotYourFiles => "Изменять можно только загруженные вами файлы.",
+        Msg::NotYourPreview => "Просматривать можно только загруженные вами файлы.",
+        Msg::UsageProtect => "Использование: /protect <пароль>",
+        Msg::UsageRename => "Использование: /rename <имя файла>",
+        Msg::Unprotected => "Пароль удалён, файлов: {count}.",
+        Msg::Protected => "Защищено файлов: {count}, при открытии браузер запросит пароль.",
+        Msg::Renamed => "Файлы ({count}) теперь скачиваются как {name}.",
+        Msg::UsageAlias => "Использование: /alias <имя>",
+        Msg::AliasInvalid => "Используйте для псевдонима от 1 до 64 латинских букв, цифр, - или _.",
+        Msg::AliasReserved => "Имя {name} зарезервировано, выберите другой псевдоним.",
+        Msg::AliasSaved => "Файл теперь также доступен по адресу {url}",
+        Msg::UsageAlt => "Использование: /alt <описание изображения>, не более {max} символов",
+        Msg::AltSaved => "Альтернативный текст обновлён для файлов: {count}.",
+        Msg::UsageFetch => {
+            "Использование: /fetch <ссылка на изображение, видео или аудио до {size} МБ>"
+        }
+        Msg::FetchInvalidUrl => "Это не ссылка http или https.",
+        Msg::FetchFailed => "Не удалось скачать: {reason}",
+        Msg::FetchTooLarge => "Файл больше {size} МБ.",
+        Msg::FetchNotMedia => "По ссылке не изображение, видео или аудио, а {mime}.",
+        Msg::UsageInfo => {
+            "Использование: /info <id, unique id или короткий id файла> или ответьте /info на файл"
+        }
+        Msg::InfoNotFound => "Файл {id} не найден.",
+        Msg::InfoSize => "📦 {size}, {mime}",
+        Msg::InfoUploaded => "🕒 загружен {time} UTC",
+        Msg::InfoUploader => "👤 загрузил пользователь {id}",
+        Msg::InfoUploaderUnknown => "👤 загрузивший не сохраняется",
+        Msg::InfoDownloads => "👁️ скачиваний: {count}",
+        Msg::InfoStored => "💾 сохранённая копия: {keys}",
+        Msg::InfoNotStored => "💾 сохранённой копии нет, отдаётся из Telegram",
+        Msg::InfoDeleted => "🗑️ удалён {time} UTC",
+        Msg::InfoBlocked => "⛔ не отдаётся: {reason}",
+        Msg::InfoCamera => "📷 {camera}",
+        Msg::InfoLens => "🔭 {lens}",
+        Msg::InfoExposure => "⚙️ {exposure}",
+        Msg::InfoTaken => "📅 снято {time}",
+        Msg::InfoLocation => "📍 {location}",
+        Msg::InfoLocationHidden => "📍 местоположение скрыто, /location on показывает его",
+        Msg::Deleted => {
+            "Удалено файлов: {count}. Восстановить в течение {days} дн. можно командой /undelete <id>:\n{ids}"
+        }
+        Msg::UsageDelete => {
+            "Ответьте на файл командой /delete или укажите id: /delete <id> <id> ..."
+        }
+        Msg::DeleteTooMany => "За раз можно удалить не больше {max} файлов.",
+        Msg::DeleteSkipped => "Не удалены:\n{lines}",
+        Msg::DeleteNotFound => "не найден",
+        Msg::DeleteAlreadyDeleted => "уже удалён",
+        Msg::DeleteForbidden => "не ваш файл",
+        Msg::DeleteDuplicate => "указан дважды",
+        Msg::UsageUndelete => "Использование: /undelete <id>",
+        Msg::UndeleteNotFound => {
+            "Удалённый файл {id} не найден, возможно, он уже удалён окончательно."
+        }
+        Msg::Undeleted => "{id} снова доступен.",
+        Msg::UsageBan => "Использование: /ban <id пользователя>",
+        Msg::UsageUnban => "Использование: /unban <id пользователя>",
+        Msg::Banned => "Пользователь {id} заблокирован, его загрузки отклоняются.",
+        Msg::AlreadyBanned => "Пользователь {id} уже заблокирован.",
+        Msg::Unbanned => "Пользователь {id} снова может загружать файлы.",
+        Msg::NotBanned => "Пользователь {id} не заблокирован.",
+        Msg::YouAreBanned => "Вы не можете загружать файлы в этого бота.",
+        Msg::MaintainerOnly => "Только владелец бота может выполнить эту команду.",
+        Msg::ForgetMeConfirm => {
+            "Будут удалены все загруженные вами файлы, ваши альбомы и всё остальное, что хранится о вас. Ссылки перестанут работать, отменить это нельзя."
+        }
+        Msg::ForgetMeButton => "Удалить всё",
+        Msg::Forgotten => {
+            "Удалено файлов: {files}, альбомов: {albums}, сохранённых копий: {objects}. Данные о вас больше не хранятся."
+        }
+        Msg::PrivacyModeOnly => {
+            "Этот бот не хранит, кто загрузил файл, поэтому эта команда доступна только владельцу бота."
+        }
+        Msg::UsageAlbum => {
+            "Использование: /album create <имя> или ответ на файл с /album add <имя>"
+        }
+        Msg::AlbumPrivate => "Альбомы принадлежат пользователям, выполните команду в личном чате.",
+        Msg::AlbumCreated => "Альбом {name} создан, ссылка: {url}",
+        Msg::AlbumExists => "У вас уже есть альбом {name}.",
+        Msg::AlbumNotFound => {
+            "У вас нет альбома {name}, создайте его командой /album create {name}"
+        }
+        Msg::AlbumAdded => "Файлы ({count}) добавлены в {name}: {url}",
+        Msg::UsageTag => {
+            "Ответьте на файл командой /tag <имя> ..., чтобы добавить теги, -<имя> убирает тег. /tag без аргументов показывает ваши теги."
+        }
+        Msg::TagInvalid => "{name} не подходит для тега, используйте до {max} букв, цифр, - и _.",
+        Msg::TagTooMany => "У файла может быть не больше {max} тегов.",
+        Msg::TagsSaved => "Теги обновлены для файлов: {count}\n{lines}",
+        Msg::TagNoTags => "нет тегов",
+        Msg::TagList => "Ваши теги:\n{tags}",
+        Msg::TagNone => {
+            "У вас пока нет тегов, ответьте на файл командой /tag <имя>, чтобы добавить."
+        }
+        Msg::PreviewProtected => "🔒 защищён паролем, открыть могут только те, кто знает пароль",
+        Msg::PreviewPublic => "🌐 публичный, открыть может любой, у кого есть ссылка",
+        Msg::PreviewNeverExpires => "♾️ бессрочно",
+        Msg::PreviewNoName => "📄 скачивается без имени файла",
+        Msg::PreviewDownloadsAs => "📄 скачивается как {name}",
+        Msg::PreviewExifStripped => "✅ метаданные EXIF удалены Telegram",
+        Msg::PreviewExifStrippedOnServe => "✅ метаданные EXIF удаляются при отдаче файла",
+        Msg::PreviewExifKept => {
+            "⚠️ метаданные EXIF, например местоположение, публикуются как при загрузке"
+        }
+        Msg::PreviewForwardedFrom => "↪️ переслано из {from}",
+        Msg::PreviewNoThumbnail => "🖼 без миниатюры",
+        Msg::PreviewThumbnail => "🖼 есть миниатюра",
+        Msg::SummaryTitle => "Последние загрузки",
+        Msg::NamespacePrivate => {
+            "Пространства имён принадлежат пользователям, выполните команду в личном чате."
+        }
+        Msg::NamespaceUsage => {
+            "Использование: /namespace <имя>, ваши публичные файлы будут перечислены на /u/<имя>/."
+        }
+        Msg::NamespaceCurrent => "Ваши публичные файлы перечислены на {url}",
+        Msg::NamespaceOff => "Ваши файлы больше не перечисляются.",
+        Msg::NamespaceInvalid => "Используйте для имени от 1 до 32 латинских букв, цифр, - или _.",
+        Msg::NamespaceTaken => "Имя {name} уже занято.",
+        Msg::NamespaceSaved => "Ваши файлы без пароля теперь перечислены на {url}",
+        Msg::LocationPrivate => {
+            "Настройка местоположения принадлежит пользователю, выполните команду в личном чате."
+        }
+        Msg::LocationShown => {
+            "GPS-местоположение ваших jpeg-документов показывается на их страницах /view/ и в /info."
+        }
+        Msg::LocationHidden => "GPS-местоположение ваших jpeg-документов скрыто.",
+        Msg::LocationUsage => "Использование: /location on|off",
+        Msg::TokenPrivate => "API-ключи показываются в чате, управляйте ими в личном чате с ботом.",
+        Msg::UsageToken => {
+            "Использование: /token new [upload delete list], /token list или /token revoke <id>"
+        }
+        Msg::TokenCreated => {
+            "Ключ {id} с правами {scopes}:\n\n{key}\n\nОтправляйте его как Authorization: Bearer <key>. Он показывается только один раз, удалите это сообщение после копирования."
+        }
+        Msg::TokenLimit => "У вас уже {max} ключей, сначала отзовите один.",
+        Msg::TokenNone => "У вас нет API-ключей, создайте ключ командой /token new.",
+        Msg::TokenList => "Ваши API-ключи:\n{keys}",
+        Msg::TokenLine => "{id}: {scopes}, последнее использование {used}",
+        Msg::TokenNeverUsed => "никогда",
+        Msg::TokenRevoked => "Ключ {id} отозван.",
+        Msg::TokenNotFound => "У вас нет ключа {id}.",
+        Msg::SetupTitle => "Настройка экземпляра, выполните шаги по порядку:",
+        Msg::SetupStepDb => "Инициализировать базу данных",
+        Msg::SetupStepWebhook => "Зарегистрировать вебхук",
+        Msg::SetupStepR2 => "Проверить R2",
+        Msg::SetupStepChannel => "Канал хранения",
+        Msg::SetupDb => "База данных инициализирована.",
+        Msg::SetupWebhook => "Вебхук зарегистрирован.",
+        Msg::SetupR2 => "Бакет R2 доступен.",
+        Msg::SetupR2Kv => {
+            "R2 не привязан, файлы хранятся в пространстве имён KV FILE_STORE. Для файлов больше 25 МиБ добавьте привязку R2 в wrangler.toml."
+        }
+        Msg::SetupR2Missing => {
+            "R2 не привязан, файлы загружаются из Telegram при каждом промахе кэша. Добавьте привязку R2 в wrangler.toml, чтобы хранить их."
+        }
+        Msg::SetupChannel => {
+            "Создайте приватный канал, добавьте этого бота администратором, \
+            затем отправьте в канал /setup channel, чтобы получить id для STORAGE_CHAT_ID."
+        }
+        Msg::SetupUnknown => "Неизвестный шаг настройки.",
+        Msg::SetupChannelId => {
+            "Id этого чата: {id}, укажите его в STORAGE_CHAT_ID, чтобы хранить здесь файлы, загруженные через http api."
+        }
+        Msg::SetupMaintainerOnly => "Только владелец бота может выполнить /setup.",
+        Msg::SetupFailed => "Шаг настройки {step} не выполнен: {error}",
+        Msg::SetupDone => "Готово.",
+        Msg::SendFileHint => {
+            "Отправьте фото, видео или файл, чтобы разместить его, подробнее в /help."
+        }
+        Msg::UploadError => "Ошибка: {error}",
+        Msg::TooBig => {
+            "{name} больше {size} МБ, лимита скачивания Telegram Bot API, поэтому его нельзя разместить."
+        }
+        Msg::TooLarge => {
+            "{name} больше {size} МБ, лимита размера файлов этого бота, поэтому он не размещён."
+        }
+        Msg::Refused => "{name} отклонён модерацией контента и не будет размещён.",
+        Msg::TypeNotAllowed => "{name} нельзя разместить, файлы {type} здесь не принимаются.",
+        Msg::MissingBinding => {
+            "Для {feature} нужна привязка {binding}, добавьте её в wrangler.toml"
+        }
+        Msg::Help => {
+            "Отправьте фото, видео или файл в этот чат или в канал, где бот является администратором, \
+            и он ответит ссылками, которыми можно поделиться.\n\n\
+            Telegram пережимает фото, отправляйте изображения файлами, чтобы сохранить оригинал. \
+            Можно разместить файлы до {size} МБ.\n\n\
+            Команды:\n{commands}"
+        }
+        Msg::CmdHelp => "Как пользоваться ботом",
+        Msg::CmdPreview => "Показать, что публикуют ссылки на файл из ответа",
+        Msg::CmdProtect => "Задать пароль для файла из ответа",
+        Msg::CmdUnprotect => "Удалить пароль файла из ответа",
+        Msg::CmdRename => "Задать имя для скачивания файла из ответа",
+        Msg::CmdAlias => "Открывать файл из ответа по адресу /s/<имя>",
+        Msg::CmdAlt => "Описать изображение из ответа для экранных дикторов",
+        Msg::CmdInfo => "Показать, что хранится о файле",
+        Msg::CmdFetch => "Разместить файл, скачанный по ссылке",
+        Msg::CmdDelete => "Удалить файл из ответа или по id, какое-то время их можно восстановить",
+        Msg::CmdUndelete => "Восстановить удалённый файл",
+        Msg::CmdBan => "Запретить загрузки пользователя (владелец)",
+        Msg::CmdUnban => "Снять блокировку с пользователя (владелец)",
+        Msg::CmdForgetMe => "Удалить все ваши файлы и данные",
+        Msg::CmdAlbum => "Создать альбом или добавить в него файл из ответа",
+        Msg::CmdTag => "Добавить теги файлу из ответа или показать ваши теги",
+        Msg::CmdNamespace => "Публиковать список ваших файлов на /u/<имя>/, off чтобы выключить",
+        Msg::CmdToken => "Создать, показать или отозвать ваши API-ключи",
+        Msg::CmdLocation => "Показать или скрыть GPS-местоположение фото на страницах /view/",
+        Msg::CmdSetup => "Настроить экземпляр, только для владельца",
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use worker::{ResizeConfig, ResizeFit, ResizeFormat};
+
+use crate::d1::File;
+use crate::error::Error;
+
+// cloudflare refuses larger dimensions
+const MAX_DIMENSION: usize = 12000;
+
+// a scaled or converted variant of an image, made by cloudflare image resizing on the way from telegram
+#[derive(Default, Debug, PartialEq)]
+pub struct Transform {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub fit: Option<String>,
+    // avif or webp, picked from the Accept header
+    pub format: Option<&'static str>,
+}
+
+impl Transform {
+    // ?w=, ?h= and ?fit= of a download link, None without any of them
+    pub fn from_query(query: &HashMap<String, String>) -> Result<Option<Transform>, Error> {
+        let transform = Transform {
+            width: dimension(query, "w")?,
+            height: dimension(query, "h")?,
+            fit: match query.get("fit").map(String::as_str) {
+                None => None,
+                Some(v @ ("scale-down" | "contain" | "cover" | "crop" | "pad")) => {
+                    Some(v.to_string())
+                }
+                Some(v) => {
+                    return Err(Error::BadRequest(format!(
+                        "fit {:?} is not scale-down, contain, cover, crop or pad",
+                        v
+                    )));
+                }
+            },
+            format: None,
+        };
+
+        if transform == Transform::default() {
+            return Ok(None);
+        }
+        Ok(Some(transform))
+    }
+
+    // the same variant always gets the same query, so `?h=1&w=2` and `?w=2&h=1` share a cache entry
+    pub fn query(&self) -> String {
+        let mut params = vec![];
+        if let Some(v) = self.width {
+            params.push(format!("w={}", v));
+        }
+        if let Some(v) = self.height {
+            params.push(format!("h={}", v));
+        }
+        if let Some(v) = &self.fit {
+            params.push(format!("fit={}", v));
+        }
+        if let Some(v) = self.format {
+            params.push(format!("format={}", v));
+        }
+        params.join("&")
+    }
+
+    pub fn resize_config(&self) -> ResizeConfig {
+        ResizeConfig {
+            width: self.width,
+            height: self.height,
+            fit: self.fit.as_deref().map(|v| match v {
+                "contain" => ResizeFit::Contain,
+                "cover" => ResizeFit::Cover,
+                "crop" => ResizeFit::Crop,
+                "pad" => ResizeFit::Pad,
+                _ => ResizeFit::ScaleDown,
+            }),
+            format: self.format.map(|v| match v {
+                "avif" => ResizeFormat::Avif,
+                _ => ResizeFormat::Webp,
+            }),
+            ..ResizeConfig::default()
+        }
+    }
+
+    // of a converted variant
+    pub fn content_type(&self) -> Option<String> {
+        self.format.map(|v| format!("image/{}", v))
+    }
+}
+
+// photos and jpeg or png documents, gifs would lose their animation and svgs can't be converted
+pub fn negotiable(file: &File) -> bool {
+    matches!(file.mime_type.as_str(), "image/jpeg" | "image/png")
+        || (file.mime_type.is_empty() && file.file_name.is_empty())
+}
+
+// the smallest format the client accepts, q values are ignored
+pub fn negotiate(accept: &str) -> Option<&'static str> {
+    let accepts = |mime: &str| {
+        accept
+            .split(',')
+            .any(|v| v.split(';').next().unwrap_or_default().trim() == mime)
+    };
+    if accepts("image/avif") {
+        Some("avif")
+    } else if accepts("image/webp") {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+fn dimension(query: &HashMap<String, String>, key: &str) -> Result<Option<usize>, Error> {
+    match query.get(key) {
+        None => Ok(None),
+        Some(v) => match v.parse::<usize>() {
+            Ok(v) if (1..=MAX_DIMENSION).contains(&v) => Ok(Some(v)),
+            _ => Err(Error::BadRequest(format!(
+                "{} must be a number from 1 to {}",
+                key, MAX_DIMENSION
+            ))),
+        },
+    }
+}
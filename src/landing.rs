@@ -0,0 +1,56 @@
+// the page of `/`. it tells visitors how to use the bot, LANDING_PAGE = "redirect" sends them
+// to the repository instead
+
+use crate::d1::File;
+use crate::listing::{gallery_item, html_escape};
+
+const TEMPLATE: &str = include_str!("landing.html");
+pub const REPOSITORY_URL: &str = "https://github.com/Asutorufa/tg-image-hosting";
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum LandingMode {
+    #[default]
+    Page,
+    Redirect,
+}
+
+impl LandingMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "" | "page" => Some(LandingMode::Page),
+            "redirect" => Some(LandingMode::Redirect),
+            _ => None,
+        }
+    }
+}
+
+// `username` is empty when getMe failed, `recent` are the newest public uploads
+pub fn render(host: &str, username: &str, max_size: u64, recent: &[File]) -> String {
+    let (title, bot) = if username.is_empty() {
+        (host.to_string(), "the bot".to_string())
+    } else {
+        (
+            format!("@{}", username),
+            format!(
+                r#"<a href="https://t.me/{0}">@{0}</a>"#,
+                html_escape(username)
+            ),
+        )
+    };
+
+    let recent = if recent.is_empty() {
+        String::new()
+    } else {
+        let items = recent
+            .iter()
+            .map(|f| gallery_item(host, f))
+            .collect::<String>();
+        format!("<h2>Recent uploads</h2>\n<main>\n{}\n</main>", items)
+    };
+
+    TEMPLATE
+        .replace("{title}", &html_escape(&title))
+        .replace("{bot}", &bot)
+        .replace("{size}", &(max_size / 1024 / 1024).to_string())
+        .replace("{recent}", &recent)
+}
@@ -1,22 +1,82 @@
+// a worker runs on a single thread, the Arcs around runtime handles never cross threads
+#![allow(clippy::arc_with_non_send_sync)]
+
+pub mod apikey;
+pub mod badge;
+pub mod capability;
+pub mod caption;
+pub mod config;
 pub mod consolelog;
+pub mod cors;
+pub mod crypto;
 pub mod d1;
+pub mod digest;
 pub mod error;
+pub mod exif;
+pub mod export;
+pub mod filestore;
+pub mod filetype;
 pub mod handler;
+pub mod hotlink;
+pub mod i18n;
+pub mod image;
+pub mod landing;
+pub mod listing;
+pub mod metrics;
+pub mod moderation;
+pub mod password;
+pub mod privacy;
+pub mod qr;
+pub mod ratelimit;
+pub mod routes;
+pub mod s3;
+pub mod security;
+pub mod state;
+pub mod storage;
+pub mod svg;
+pub mod tag;
+pub mod tasks;
 pub mod tg;
+pub mod turnstile;
+pub mod upload;
+pub mod zip;
 
+use crate::capability::{ANALYTICS, Capabilities, FILE_CACHE, R2_STORAGE, RATE_LIMIT};
+use crate::caption::Captioner;
+use crate::config::Config;
+use crate::cors::Cors;
+use crate::filetype::FileTypes;
 use crate::handler::Handler;
+use crate::hotlink::Hotlink;
+use crate::metrics::Metrics;
+use crate::moderation::Moderator;
+use crate::routes::Routes;
+use crate::security::SecurityHeaders;
 use crate::tg::TgBot;
+use crate::turnstile::Turnstile;
 use log::error;
 use log::info;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use worker::*;
 
-fn get_string_from_env(env: &Env, key: &str) -> String {
-    if let Ok(v) = env.var(key) {
-        return v.to_string();
+// cf-ray is also what cloudflare shows in its own logs, fall back to a random id
+fn request_id(req: Option<&Request>) -> String {
+    if let Some(req) = req
+        && let Ok(Some(v)) = req.headers().get("cf-ray")
+    {
+        return v;
     }
 
-    String::new()
+    let mut id = [0u8; 8];
+    let _ = getrandom::getrandom(&mut id);
+    crypto::hex(&id)
+}
+
+// routes need static patterns, PATH_PREFIX stays the same for the lifetime of the isolate.
+// links with /f/ keep working when it's changed
+fn prefix_route(prefix: &str) -> &'static str {
+    static ROUTE: OnceLock<&'static str> = OnceLock::new();
+    ROUTE.get_or_init(|| Box::leak(format!("/{}/:file_id", prefix).into_boxed_str()))
 }
 
 // Multiple calls to `init` will cause a panic as a tracing subscriber is already set.
@@ -30,46 +90,283 @@ fn start() {
     };
 }
 
-fn init_bot(env: &Env) -> Result<Arc<TgBot>> {
-    let token = get_string_from_env(env, "TELEGRAM_TOKEN");
+// host of links and cache keys: X-Forwarded-Host of a trusted proxy, PUBLIC_HOST or the host of the request.
+// telegram calls the webhook directly, replies use PUBLIC_HOST when the worker is behind a proxy
+fn public_host(req: &Request, config: &Config) -> Option<String> {
+    if config.trust_forwarded_host
+        && let Ok(Some(v)) = req.headers().get("X-Forwarded-Host")
+        && let Some(v) = v.split(',').next().map(str::trim)
+        && config::is_host(v)
+    {
+        return Some(v.to_ascii_lowercase());
+    }
+    if !config.public_host.is_empty() {
+        return Some(config.public_host.clone());
+    }
+    req.url().ok()?.host().map(|v| v.to_string())
+}
 
-    let maintainer_id = get_string_from_env(env, "MAINTAINER_ID")
-        .parse::<i64>()
-        .unwrap_or(0);
+// `/tgbot/<name>` and `/b/<name>/...` belong to the bot of BOTS with that name, the rest of
+// the path is what the router sees
+fn named_bot<'a>(
+    path: &str,
+    bots: &'a [(String, String)],
+) -> Option<(&'a (String, String), String)> {
+    let (name, rest) = if let Some(name) = path.strip_prefix("/tgbot/") {
+        (name, "/tgbot".to_string())
+    } else {
+        let path = path.strip_prefix("/b/")?;
+        match path.split_once('/') {
+            Some((name, rest)) => (name, format!("/{}", rest)),
+            None => (path, "/".to_string()),
+        }
+    };
+    let bot = bots.iter().find(|(v, _)| v == name)?;
+    Some((bot, rest))
+}
 
-    let d1 = d1::D1::new(Arc::new(env.d1("DB")?));
+// the router reads a colon as a parameter, `/api/files:batchDelete` is routed as CUSTOM_METHODS maps it
+static CUSTOM_METHODS: [(&str, &str); 1] = [("/api/files:batchDelete", "/api/batch-delete")];
 
-    Ok(Arc::new(TgBot::new(d1, maintainer_id, token)))
+fn with_path(req: Request, path: &str) -> Result<Request> {
+    let mut url = req.url()?;
+    url.set_path(path);
+    let mut init = RequestInit::new();
+    init.with_method(req.method())
+        .with_headers(req.headers().clone())
+        .with_body(req.inner().body().map(Into::into));
+    Request::new_with_init(url.as_str(), &init)
+}
+
+fn init_bot(env: &Env, config: &Config, bot: Option<&(String, String)>) -> Result<Arc<TgBot>> {
+    let (database, token) = match bot {
+        Some((name, token)) => (config::bot_database(name), token.clone()),
+        None => ("DB".to_string(), config.telegram_token.clone()),
+    };
+    let d1 = d1::D1::new(Arc::new(env.d1(&database)?));
+    let moderator = match env.ai("AI") {
+        Ok(ai) if config.moderation => Some(Moderator::new(
+            ai,
+            config.moderation_model.clone(),
+            config.moderation_review,
+            config.moderation_reject,
+        )),
+        _ => None,
+    };
+    let captioner = match env.ai("AI") {
+        Ok(ai) if config.alt_text => Some(Captioner::new(ai, config.alt_text_model.clone())),
+        _ => None,
+    };
+
+    Ok(Arc::new(
+        TgBot::new(d1, config.maintainer_id, token)
+            .with_storage_chat(config.storage_chat_id)
+            .with_r2(env.bucket(&R2_STORAGE.binding).ok())
+            .with_kv(env.kv(&FILE_CACHE.binding).ok())
+            .with_short_ids(config.short_ids)
+            .with_strip_exif(config.strip_exif)
+            .with_svg_policy(config.svg_policy)
+            .with_max_file_size(config.max_file_size)
+            .with_file_types(FileTypes::new(
+                &config.allowed_mime_types,
+                &config.blocked_extensions,
+            ))
+            .with_moderator(moderator)
+            .with_captioner(captioner)
+            .with_storage(storage::from_env(env))
+            .with_delete_grace_days(config.delete_grace_days)
+            .with_privacy_mode(config.privacy_mode)
+            .with_capabilities(Capabilities::from_env(env))
+            .with_channel_reply_mode(config.channel_reply_mode)
+            .with_summary_size(config.summary_size)
+            .with_reply_template(config.reply_template.clone())
+            .with_lang(config.bot_lang)
+            .with_api_base(config.telegram_api_base.clone())
+            .with_require_consent(config.require_consent),
+    ))
+}
+
+#[event(scheduled)]
+async fn scheduled(_event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
+    consolelog::with_request_id(request_id(None), run_scheduled(env)).await
+}
+
+async fn run_scheduled(env: Env) {
+    let config = Config::from_env(&env);
+    consolelog::set_filter(&config.log_level, &config.log_filter);
+    consolelog::set_json(config.log_json);
+
+    let bots = std::iter::once(None).chain(config.bots.iter().map(Some));
+    for bot in bots {
+        let name = bot.map_or("default", |(v, _)| v.as_str());
+        match init_bot(&env, &config, bot) {
+            Ok(v) => run_tasks(&env, &config, &v).await,
+            Err(e) => error!("Init bot {} failed: {}", name, e),
+        }
+    }
+}
+
+async fn run_tasks(env: &Env, config: &Config, bot: &TgBot) {
+    if let Err(e) = bot.check_webhook().await {
+        error!("Check webhook failed: {}", e);
+    }
+
+    let file_storage = storage::from_env(env);
+    if let Err(e) = tasks::retry_pending(bot, file_storage.as_ref()).await {
+        error!("Retry pending tasks failed: {}", e);
+    }
+
+    if let Err(e) = tasks::purge_deleted(bot, file_storage.as_ref(), config.delete_grace_days).await
+    {
+        error!("Purge deleted files failed: {}", e);
+    }
+
+    if config.daily_digest
+        && let Err(e) = digest::send(bot).await
+    {
+        error!("Send daily digest failed: {}", e);
+    }
 }
 
 #[event(fetch)]
 async fn main(req: Request, env: Env, ctx: Context) -> Result<Response> {
-    if req.method() == Method::Options {
-        return Response::ok("");
+    let request_id = request_id(Some(&req));
+    let config = Config::from_env(&env);
+    let origin = req.headers().get("Origin")?;
+    let resp = consolelog::with_request_id(
+        request_id.clone(),
+        serve(req, env, ctx, &config, request_id.clone()),
+    )
+    .await?;
+
+    // headers of cached responses are immutable, copy them
+    let headers = Headers::new();
+    for (k, v) in resp.headers().entries() {
+        headers.append(&k, &v)?;
     }
+    headers.set("X-Request-Id", &request_id)?;
+    // preflights got theirs from the router
+    if !headers.has("Access-Control-Allow-Methods")? {
+        Cors::new(&config.allowed_origins).apply(origin.as_deref(), &headers)?;
+    }
+    Ok(resp.with_headers(headers))
+}
 
-    let host = if let Ok(v) = req.url()
-        && let Some(v) = v.host()
-    {
-        v.to_string()
-    } else {
-        return Response::error("Host not found", 400);
+async fn serve(
+    mut req: Request,
+    env: Env,
+    ctx: Context,
+    config: &Config,
+    request_id: String,
+) -> Result<Response> {
+    consolelog::set_filter(&config.log_level, &config.log_filter);
+    consolelog::set_json(config.log_json);
+    privacy::set_ip_less(config.ip_less);
+    d1::set_path_prefix(&config.path_prefix);
+
+    let host = match public_host(&req, config) {
+        Some(v) => v,
+        None => return crate::error::Error::BadRequest("host not found".into()).to_response(),
     };
 
-    let bot = match init_bot(&env) {
+    // links of another bot carry its name, so downloads find its database and token
+    let mut named = None;
+    if let Some((v, rest)) = named_bot(&req.path(), &config.bots) {
+        req = with_path(req, &rest)?;
+        named = Some(v);
+    }
+    if let Some((_, path)) = CUSTOM_METHODS.iter().find(|(v, _)| *v == req.path()) {
+        req = with_path(req, path)?;
+    }
+
+    let bot = match init_bot(&env, config, named) {
         Ok(v) => v,
         Err(e) => return Response::ok(format!("Error: {}", e)),
     };
 
-    let handler = Handler::new(host.to_string(), env.bucket("R2").ok(), bot, Arc::new(ctx));
+    let file_storage = storage::from_env(&env);
+    let handler = Handler::new(host, file_storage, bot, Arc::new(ctx))
+        .with_bot_name(named.map(|(v, _)| v.clone()).unwrap_or_default())
+        .with_routing(
+            config.bots.iter().map(|(v, _)| v.clone()).collect(),
+            config.public_host.clone(),
+        )
+        .with_request_id(request_id)
+        .with_api_token(config.api_token.clone())
+        .with_previous_api_token(config.api_token_previous.clone())
+        .with_terms(config.terms.clone())
+        .with_r2_public_base_url(config.r2_public_base_url.clone())
+        .with_cache_control(
+            config.cache_max_age,
+            config.cache_immutable,
+            config.cache_stale_while_revalidate,
+        )
+        .with_edge_cache_ttl(config.edge_cache_ttl)
+        .with_album_zip_max_size(config.album_zip_max_size)
+        .with_image_resizing(config.image_resizing)
+        .with_auto_format(config.auto_format)
+        .with_hide_banned_files(config.hide_banned_files)
+        .with_security_headers(
+            SecurityHeaders::new(
+                config.content_security_policy.clone(),
+                config.referrer_policy.clone(),
+                config.attachment_mode,
+            )
+            .with_svg_policy(config.svg_policy),
+        )
+        .with_landing(config.landing_mode, config.landing_recent)
+        .with_index_files(config.robots_index_files)
+        .with_s3_access_key_id(config.s3_access_key_id.clone())
+        .with_turnstile(
+            (!config.turnstile_site_key.is_empty() && !config.turnstile_secret.is_empty()).then(
+                || {
+                    Turnstile::new(
+                        config.turnstile_site_key.clone(),
+                        config.turnstile_secret.clone(),
+                        config.turnstile_pass_hours,
+                    )
+                    .with_previous_secret(config.turnstile_secret_previous.clone())
+                },
+            ),
+        )
+        .with_hotlink(Hotlink::new(
+            &config.allowed_referers,
+            config.allow_empty_referer,
+            config.hotlink_placeholder.clone(),
+        ))
+        .with_metrics(
+            Metrics::new(env.analytics_engine(&ANALYTICS.binding).ok())
+                .with_counters(config.prometheus_metrics),
+        )
+        .with_rate_limit(
+            env.durable_object(&RATE_LIMIT.binding).ok(),
+            config.rate_limit_per_minute,
+        );
 
-    let router = Router::new()
+    let router = Routes::new()
+        .with_cors(Cors::new(&config.allowed_origins))
         .on_async("/tgbot/register", async |_req: Request, ctx| {
+            // a webhook of a misconfigured bot would fail every update
+            if let Err(e) = config.validate() {
+                return e.to_response();
+            }
             handler.register(_req, ctx).await.map_or_else(
                 |e| e.to_response(),
                 |_| Response::ok("register webhook successful"),
             )
         })
+        .post_async("/tgbot/unregister", async |req, ctx| {
+            handler.unregister(req, ctx).await.map_or_else(
+                |e| e.to_response(),
+                |_| Response::ok("delete webhook successful"),
+            )
+        })
+        .get_async("/tgbot/info", async |req, ctx| {
+            match handler.webhook_info(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
         .on_async("/d1/create_table", async |req, ctx| {
             handler.init_database(req, ctx).await.map_or_else(
                 |e| e.to_response(),
@@ -79,9 +376,66 @@ async fn main(req: Request, env: Env, ctx: Context) -> Result<Response> {
         .post_async("/tgbot", async |req, ctx| {
             match handler.telegram(req, ctx).await {
                 Ok(_) => info!("Update was handled by bot."),
+                // not from telegram, so there's no delivery to acknowledge
+                Err(e @ crate::error::Error::Unauthorized(_)) => return e.to_response(),
                 Err(e) => error!("Update was not handled by bot: {}", e),
             };
-            return Response::ok("ok");
+            Response::ok("ok")
+        })
+        .post_async("/api/upload", async |req, ctx| {
+            match handler.upload(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/api/upload/validate", async |req, ctx| {
+            match handler.validate_upload(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/api/files", async |req, ctx| {
+            match handler.files(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/api/files/:file_id", async |req, ctx| {
+            match handler.update_file(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/api/batch-delete", async |req, ctx| {
+            match handler.batch_delete(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/api/purge/:file_unique_id", async |req, ctx| match handler
+            .purge(req, ctx)
+            .await
+        {
+            Ok(v) => Ok(v),
+            Err(e) => e.to_response(),
+        })
+        .get_async("/api/events", async |req, ctx| {
+            match handler.events(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/api/export", async |req, ctx| {
+            match handler.export(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/api/import", async |req, ctx| {
+            match handler.import(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
         })
         .get_async("/f/:file_id", async |req, ctx| {
             match handler.download(req, ctx).await {
@@ -89,11 +443,281 @@ async fn main(req: Request, env: Env, ctx: Context) -> Result<Response> {
                 Err(e) => e.to_response(),
             }
         })
-        .on("/", Handler::github_page)
-        .or_else_any_method("/*catchall", Handler::github_page);
+        .head_async("/f/:file_id", async |req, ctx| {
+            match handler.head(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/f/:file_id", async |req, ctx| {
+            match handler.pass_challenge(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/view/:file_id", async |req, ctx| {
+            match handler.view(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/view/:file_id", async |req, ctx| {
+            match handler.pass_challenge(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/admin", async |req, ctx| {
+            match handler.admin_page(req, ctx) {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/admin/state", async |req, ctx| {
+            match handler.export_state(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/admin/state/diff", async |req, ctx| {
+            match handler.diff_state(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/admin/files", async |req, ctx| {
+            match handler.admin_files(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .delete_async(
+            "/admin/files/:file_unique_id",
+            async |req, ctx| match handler.admin_delete_file(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            },
+        )
+        .post_async(
+            "/admin/files/:file_unique_id/undelete",
+            async |req, ctx| match handler.admin_undelete_file(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            },
+        )
+        .post_async(
+            "/admin/files/:file_unique_id/takedown",
+            async |req, ctx| match handler.admin_takedown(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            },
+        )
+        .get_async("/admin/users", async |req, ctx| {
+            match handler.admin_users(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/admin/channels", async |req, ctx| {
+            match handler.admin_channels(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .put_async("/admin/channels/:chat_id", async |req, ctx| {
+            match handler.admin_channels(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .delete_async("/admin/channels/:chat_id", async |req, ctx| {
+            match handler.admin_channels(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .put_async("/admin/bans/:user_id", async |req, ctx| {
+            match handler.admin_ban(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .delete_async("/admin/bans/:user_id", async |req, ctx| {
+            match handler.admin_ban(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/admin/stats", async |req, ctx| {
+            match handler.admin_stats(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/admin/cleanup", async |req, ctx| {
+            match handler.admin_cleanup(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/admin/keys", async |req, ctx| {
+            match handler.key_rotation(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/admin/keys/webhook", async |req, ctx| {
+            match handler.rotate_webhook_secret(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/healthz", async |req, ctx| {
+            match handler.health(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/metrics", async |req, ctx| {
+            match handler.metrics(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/badge/uploads.svg", async |req, ctx| {
+            match handler.badge(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/admin/tasks/retry", async |req, ctx| {
+            match handler.retry_tasks(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/admin/drain", async |req, ctx| {
+            match handler.drain_updates(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/u/:name", async |req, ctx| {
+            match handler.listing(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/u/:name/", async |req, ctx| {
+            match handler.listing(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/s/:slug", async |req, ctx| {
+            match handler.slug(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/qr/:file_id", async |req, ctx| {
+            match handler.qr(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/a/:album_id", async |req, ctx| {
+            match handler.album(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/api/sharex-config", async |req, ctx| {
+            match handler.sharex_config(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .put_async("/s3/:bucket/*key", async |req, ctx| {
+            match handler.s3_put(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => s3::error_response(&e),
+            }
+        })
+        .get_async("/delete/:file_unique_id", async |req, ctx| {
+            match handler.delete_page(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/delete/:file_unique_id", async |req, ctx| {
+            match handler.delete(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/report/:file_unique_id", async |req, ctx| {
+            match handler.report_form(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .post_async("/report/:file_unique_id", async |req, ctx| {
+            match handler.report(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/terms", async |req, ctx| {
+            match handler.terms(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .get_async("/robots.txt", async |req, ctx| {
+            match handler.robots(req, ctx) {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .on("/favicon.ico", Handler::<storage::Backend>::favicon)
+        .get_async("/", async |req, ctx| {
+            match handler.landing(req, ctx).await {
+                Ok(v) => Ok(v),
+                Err(e) => e.to_response(),
+            }
+        })
+        .or_else_any_method("/*catchall", Handler::<storage::Backend>::github_page);
+
+    let router = if config.path_prefix == d1::DEFAULT_PATH_PREFIX {
+        router
+    } else {
+        router
+            .get_async(
+                prefix_route(&config.path_prefix),
+                async |req, ctx| match handler.download(req, ctx).await {
+                    Ok(v) => Ok(v),
+                    Err(e) => e.to_response(),
+                },
+            )
+            .head_async(
+                prefix_route(&config.path_prefix),
+                async |req, ctx| match handler.head(req, ctx).await {
+                    Ok(v) => Ok(v),
+                    Err(e) => e.to_response(),
+                },
+            )
+            .post_async(
+                prefix_route(&config.path_prefix),
+                async |req, ctx| match handler.pass_challenge(req, ctx).await {
+                    Ok(v) => Ok(v),
+                    Err(e) => e.to_response(),
+                },
+            )
+    };
 
     Ok(match router.run(req, env).await {
         Ok(v) => v,
-        Err(e) => return Response::error(e.to_string(), 500),
+        Err(e) => return crate::error::Error::from(e).to_response(),
     })
 }
@@ -0,0 +1,305 @@
+use worker::{Date, DateInit};
+
+use crate::d1::{Album, File, ListingField, ListingSort};
+
+// autoindex style page of the public files of a namespace, `tag` is the one the files are filtered by
+pub fn render(
+    host: &str,
+    namespace: &str,
+    files: &[File],
+    sort: &ListingSort,
+    tags: &[(String, usize)],
+    tag: &str,
+) -> String {
+    let namespace = html_escape(namespace);
+    let tag_query = if tag.is_empty() {
+        String::new()
+    } else {
+        format!("&amp;tag={}", html_escape(tag))
+    };
+
+    let header = |title: &str, field: ListingField, param: &str| {
+        // clicking the sorted column again flips the order
+        let order = if sort.field == field && !sort.descending {
+            "desc"
+        } else {
+            "asc"
+        };
+        format!(r#"<th><a href="?sort={param}&amp;order={order}{tag_query}">{title}</a></th>"#)
+    };
+    let chips = tag_chips(tags, tag, &sort.query());
+
+    let rows = files
+        .iter()
+        .map(|f| {
+            format!(
+                r#"<tr><td><a href="{}">{}</a></td><td>{}</td><td>{}</td></tr>"#,
+                html_escape(&f.unique_url(host)),
+                html_escape(&display_name(f)),
+                human_size(f.file_size),
+                date(f.add_time),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Index of /u/{namespace}/</title>
+<style>body {{ font-family: monospace; }} td, th {{ padding: 0 1em 0 0; text-align: left; }} {CHIP_STYLE}</style>
+</head>
+<body>
+<h1>Index of /u/{namespace}/</h1>
+{chips}
+<table>
+<tr>{}{}{}</tr>
+{rows}
+</table>
+</body>
+</html>
+"#,
+        header("Name", ListingField::Name, "name"),
+        header("Size", ListingField::Size, "size"),
+        header("Date", ListingField::Date, "date"),
+    )
+}
+
+// thumbnails of the images of an album, other files are listed by name
+pub fn gallery(
+    host: &str,
+    album: &Album,
+    files: &[File],
+    tags: &[(String, usize)],
+    tag: &str,
+) -> String {
+    let name = html_escape(&album.name);
+    let chips = tag_chips(tags, tag, "");
+
+    let items = files
+        .iter()
+        .map(|f| gallery_item(host, f))
+        .collect::<String>();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{name}</title>
+<style>body {{ font-family: sans-serif; }} main {{ display: flex; flex-wrap: wrap; gap: 8px; }} img {{ height: 200px; max-width: 100%; object-fit: cover; }} .file {{ padding: 1em; border: 1px solid #ccc; }} {CHIP_STYLE}</style>
+</head>
+<body>
+<h1>{name}</h1>
+{chips}
+<main>
+{items}
+</main>
+</body>
+</html>
+"#
+    )
+}
+
+// pills of tag_chips
+const CHIP_STYLE: &str = ".tags { margin: 1em 0; } .tags a { display: inline-block; margin: 0 4px 4px 0; padding: 2px 10px; border: 1px solid #ccc; border-radius: 1em; text-decoration: none; } .tags a.selected { background: #333; color: #fff; }";
+
+// links that filter the page by a tag with how many files have it, `query` is kept in them
+fn tag_chips(tags: &[(String, usize)], tag: &str, query: &str) -> String {
+    if tags.is_empty() {
+        return String::new();
+    }
+
+    let chip = |href: String, label: String, selected: bool| {
+        format!(
+            r#"<a href="{}"{}>{}</a>"#,
+            html_escape(&href),
+            if selected { r#" class="selected""# } else { "" },
+            html_escape(&label)
+        )
+    };
+    let separator = if query.is_empty() { "" } else { "&" };
+    let mut chips = vec![chip(format!("?{}", query), "all".into(), tag.is_empty())];
+    for (name, count) in tags {
+        chips.push(chip(
+            format!("?{}{}tag={}", query, separator, name),
+            format!("#{} {}", name, count),
+            name == tag,
+        ));
+    }
+    format!(r#"<nav class="tags">{}</nav>"#, chips.join(""))
+}
+
+// a thumbnail of images, the name of other files
+pub fn gallery_item(host: &str, f: &File) -> String {
+    let url = html_escape(&f.unique_url(host));
+    let title = html_escape(&display_name(f));
+    if f.is_image() {
+        let alt = if f.alt_text.is_empty() {
+            title.clone()
+        } else {
+            html_escape(&f.alt_text)
+        };
+        format!(
+            r#"<a href="{url}"><img src="{url}" alt="{alt}" title="{title}" loading="lazy"></a>"#
+        )
+    } else {
+        format!(r#"<a class="file" href="{url}">{title}</a>"#)
+    }
+}
+
+// abuse report of a file, posted to the same url
+pub fn report_form(host: &str, file: &File) -> String {
+    let url = html_escape(&file.unique_url(host));
+    let name = html_escape(&display_name(file));
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Report {name}</title>
+<style>body {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; }} textarea {{ width: 100%; }}</style>
+</head>
+<body>
+<h1>Report {name}</h1>
+<p>Tell the maintainer why <a href="{url}">{url}</a> should be taken down.</p>
+<form method="post">
+<textarea name="reason" rows="8" maxlength="2000" required></textarea>
+<p><button type="submit">Send report</button></p>
+</form>
+</body>
+</html>
+"#
+    )
+}
+
+// interstitial of TURNSTILE_SITE_KEY, the solved widget posts the form back to the file url
+pub fn challenge(site_key: &str) -> String {
+    let site_key = html_escape(site_key);
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Checking your browser</title>
+<script src="https://challenges.cloudflare.com/turnstile/v0/api.js" async defer></script>
+<style>body {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; }}</style>
+</head>
+<body>
+<p>Confirm you are not a bot to open the file.</p>
+<form method="post" id="challenge">
+<div class="cf-turnstile" data-sitekey="{site_key}" data-callback="solved"></div>
+<noscript><p><button type="submit">Continue</button></p></noscript>
+</form>
+<script>function solved() {{ document.getElementById("challenge").submit(); }}</script>
+</body>
+</html>
+"#
+    )
+}
+
+// confirmation of a delete url, posted to the same url with the token
+pub fn delete_form(host: &str, file: &File) -> String {
+    let url = html_escape(&file.unique_url(host));
+    let name = html_escape(&display_name(file));
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Delete {name}</title>
+<style>body {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; }}</style>
+</head>
+<body>
+<h1>Delete {name}</h1>
+<p><a href="{url}">{url}</a> stops working for everyone.</p>
+<form method="post">
+<p><button type="submit">Delete</button></p>
+</form>
+</body>
+</html>
+"#
+    )
+}
+
+pub fn deleted(grace_days: u32) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Deleted</title>
+</head>
+<body>
+<p>The file was deleted, the maintainer can restore it for {grace_days} days.</p>
+</body>
+</html>
+"#
+    )
+}
+
+pub fn report_sent() -> String {
+    r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Report sent</title>
+</head>
+<body>
+<p>Thank you, the report was sent to the maintainer.</p>
+</body>
+</html>
+"#
+    .to_string()
+}
+
+pub fn display_name(f: &File) -> String {
+    if !f.download_name.is_empty() {
+        return f.download_name.clone();
+    }
+    if !f.file_name.is_empty() {
+        return f.file_name.clone();
+    }
+    format!("{}{}", f.file_unique_id, f.ext())
+}
+
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "K", "M", "G"];
+
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}{}", size, UNITS[unit])
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+// `2024-01-02 15:04` in utc
+pub fn date(unix: i64) -> String {
+    let iso = Date::new(DateInit::Millis(unix as u64 * 1000)).to_string();
+    iso.get(..16).unwrap_or(&iso).replace('T', " ")
+}
+
+pub fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
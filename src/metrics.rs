@@ -0,0 +1,143 @@
+use log::warn;
+use worker::{AnalyticsEngineDataPointBuilder, AnalyticsEngineDataset};
+
+use crate::d1::Counter;
+
+// counters kept in D1 for /metrics, with their help text
+pub const UPLOADS: &str = "uploads_total";
+pub const DOWNLOADS: &str = "downloads_total";
+pub const CACHE_HITS: &str = "cache_hits_total";
+pub const R2_HITS: &str = "r2_hits_total";
+pub const TELEGRAM_API_ERRORS: &str = "telegram_api_errors_total";
+
+const COUNTERS: [(&str, &str); 5] = [
+    (UPLOADS, "Files uploaded over the http api."),
+    (DOWNLOADS, "Download requests of /f/."),
+    (CACHE_HITS, "Downloads served from the edge cache."),
+    (R2_HITS, "Downloads served from R2."),
+    (
+        TELEGRAM_API_ERRORS,
+        "Downloads and uploads that failed on the Telegram Bot API.",
+    ),
+];
+
+// one data point per download or upload, written to the METRICS analytics engine dataset
+#[derive(Default)]
+pub struct Event {
+    pub kind: &'static str,
+    pub mime_type: String,
+    pub status: u16,
+    // hit, r2, miss or bypass, empty for uploads
+    pub cache: &'static str,
+    pub user_id: u64,
+    pub telegram_error: bool,
+    // the downloaded file, empty when it is not in D1
+    pub file_unique_id: String,
+}
+
+impl Event {
+    pub fn new(kind: &'static str) -> Self {
+        Event {
+            kind,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    dataset: Option<AnalyticsEngineDataset>,
+    // costs a D1 write per request, so only when /metrics is scraped
+    counters: bool,
+}
+
+impl Metrics {
+    pub fn new(dataset: Option<AnalyticsEngineDataset>) -> Self {
+        Metrics {
+            dataset,
+            counters: false,
+        }
+    }
+
+    pub fn with_counters(mut self, counters: bool) -> Self {
+        self.counters = counters;
+        self
+    }
+
+    // file whose download count the event increments
+    pub fn downloaded<'a>(&self, event: &'a Event) -> Option<&'a str> {
+        match event.kind {
+            "download"
+                if self.counters
+                    && !event.file_unique_id.is_empty()
+                    && (200..300).contains(&event.status) =>
+            {
+                Some(event.file_unique_id.as_str())
+            }
+            _ => None,
+        }
+    }
+
+    // counters to increment for the event
+    pub fn counters(&self, event: &Event) -> Vec<&'static str> {
+        let mut counters = vec![];
+        if !self.counters {
+            return counters;
+        }
+
+        match event.kind {
+            "download" => counters.push(DOWNLOADS),
+            "upload" if event.status == 200 => counters.push(UPLOADS),
+            _ => {}
+        }
+        match event.cache {
+            "hit" => counters.push(CACHE_HITS),
+            "r2" => counters.push(R2_HITS),
+            _ => {}
+        }
+        if event.telegram_error {
+            counters.push(TELEGRAM_API_ERRORS);
+        }
+
+        counters
+    }
+
+    pub fn write(&self, event: &Event) {
+        let dataset = match &self.dataset {
+            Some(v) => v,
+            None => return,
+        };
+
+        // blobs: kind, mime type, status, cache, user id; doubles: count
+        let result = AnalyticsEngineDataPointBuilder::new()
+            .indexes([event.kind])
+            .add_blob(event.kind)
+            .add_blob(event.mime_type.as_str())
+            .add_blob(event.status.to_string())
+            .add_blob(event.cache)
+            .add_blob(event.user_id.to_string())
+            .add_double(1)
+            .write_to(dataset);
+
+        if let Err(e) = result {
+            warn!("write metrics failed: {}", e);
+        }
+    }
+}
+
+// prometheus text exposition format, counters that were never incremented are reported as 0
+pub fn prometheus(counters: &[Counter]) -> String {
+    COUNTERS
+        .iter()
+        .map(|(name, help)| {
+            let value = counters
+                .iter()
+                .find(|c| c.name == *name)
+                .map(|c| c.value)
+                .unwrap_or_default();
+            format!(
+                "# HELP tg_image_hosting_{name} {help}\n# TYPE tg_image_hosting_{name} counter\ntg_image_hosting_{name} {value}\n"
+            )
+        })
+        .collect()
+}
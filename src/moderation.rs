@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use worker::{Ai, Fetch, Url};
+
+use crate::d1::File;
+use crate::error::Error;
+
+pub const DEFAULT_MODEL: &str = "@cf/llava-hf/llava-1.5-7b-hf";
+// larger images are left unchecked, the model gets the bytes as a json array
+pub const MAX_IMAGE_SIZE: u64 = 4 * 1024 * 1024;
+const PROMPT: &str = "Rate how sexually explicit, violent or gory this image is, \
+from 0 for harmless to 100 for clearly not safe for work. Answer with the number only.";
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Allow,
+    // kept online, the maintainer is asked to decide
+    Review,
+    // stored as blocked, the links answer 451
+    Reject,
+}
+
+// scores uploads with a vision model of workers ai
+pub struct Moderator {
+    ai: Ai,
+    model: String,
+    // scores from 0 to 1
+    review: f64,
+    reject: f64,
+}
+
+#[derive(Serialize)]
+struct Input<'a> {
+    image: Vec<u8>,
+    prompt: &'a str,
+    max_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct Output {
+    description: String,
+}
+
+impl Moderator {
+    pub fn new(ai: Ai, model: String, review: f64, reject: f64) -> Self {
+        Self {
+            ai,
+            model: if model.is_empty() {
+                DEFAULT_MODEL.to_string()
+            } else {
+                model
+            },
+            review,
+            reject,
+        }
+    }
+
+    // only images within MAX_IMAGE_SIZE can be checked
+    pub fn checks(&self, file: &File) -> bool {
+        file.is_image() && file.file_size <= MAX_IMAGE_SIZE
+    }
+
+    pub fn verdict(&self, score: f64) -> Verdict {
+        if score >= self.reject {
+            Verdict::Reject
+        } else if score >= self.review {
+            Verdict::Review
+        } else {
+            Verdict::Allow
+        }
+    }
+
+    // from 0 to 1, `url` is the telegram download link of the file
+    pub async fn score(&self, url: &str) -> Result<f64, Error> {
+        let image = fetch_image(url).await?;
+
+        let output: Output = self
+            .ai
+            .run(
+                &self.model,
+                Input {
+                    image,
+                    prompt: PROMPT,
+                    max_tokens: 8,
+                },
+            )
+            .await
+            .map_err(|e| Error::Internal(format!("moderation model: {}", e)))?;
+
+        parse_score(&output.description).ok_or(Error::Internal(format!(
+            "moderation model answered {:?}",
+            output.description
+        )))
+    }
+}
+
+// bytes of a telegram download link for the models
+pub async fn fetch_image(url: &str) -> Result<Vec<u8>, Error> {
+    let url = Url::parse(url).map_err(|e| Error::Internal(e.to_string()))?;
+    let mut resp = Fetch::Url(url).send().await?;
+    if resp.status_code() != 200 {
+        return Err(Error::TelegramApi(format!(
+            "download of an image for workers ai failed with {}",
+            resp.status_code()
+        )));
+    }
+    Ok(resp.bytes().await?)
+}
+
+// the first number of the answer, models like to add words around it
+fn parse_score(answer: &str) -> Option<f64> {
+    let start = answer.find(|c: char| c.is_ascii_digit())?;
+    let number = answer[start..]
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()?;
+    let score = number.parse::<f64>().ok()?;
+    Some((score / 100.0).clamp(0.0, 1.0))
+}
@@ -0,0 +1,55 @@
+use crate::crypto::{constant_time_eq, hex, pbkdf2_sha256, sha256_hex};
+use crate::error::Error;
+
+// of new hashes, the count is stored with the hash so it can be raised later. workers bill the
+// cpu time of every protected download, a higher count makes each one slower
+const ITERATIONS: u32 = 100_000;
+const SCHEME: &str = "pbkdf2-sha256";
+
+pub fn hash_password(password: &str) -> Result<(String, String), Error> {
+    let mut salt = [0u8; 16];
+    getrandom::getrandom(&mut salt).map_err(|e| Error::Internal(e.to_string()))?;
+
+    let salt = hex(&salt);
+    let hash = derive(password, &salt, ITERATIONS)?;
+
+    Ok((salt, hash))
+}
+
+// `hash` is `pbkdf2-sha256$<iterations>$<hex>`, or the salted sha-256 of files protected before
+pub fn verify_password(password: &str, salt: &str, hash: &str) -> bool {
+    let computed = match hash.split('$').collect::<Vec<_>>()[..] {
+        [SCHEME, iterations, _] => match iterations.parse() {
+            Ok(iterations) => derive(password, salt, iterations).unwrap_or_default(),
+            Err(_) => return false,
+        },
+        [v] if !v.is_empty() => sha256_hex(format!("{}{}", salt, password).as_bytes()),
+        _ => return false,
+    };
+    !computed.is_empty() && constant_time_eq(computed.as_bytes(), hash.as_bytes())
+}
+
+fn derive(password: &str, salt: &str, iterations: u32) -> Result<String, Error> {
+    let key = pbkdf2_sha256(password.as_bytes(), salt.as_bytes(), iterations)?;
+    Ok(format!("{}${}${}", SCHEME, iterations, hex(&key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_both_schemes() {
+        let hash = derive("secret", "salt", 2).unwrap();
+        assert!(verify_password("secret", "salt", &hash));
+        assert!(!verify_password("Secret", "salt", &hash));
+        assert!(!verify_password("secret", "pepper", &hash));
+
+        let legacy = sha256_hex(b"saltsecret");
+        assert!(verify_password("secret", "salt", &legacy));
+        assert!(!verify_password("other", "salt", &legacy));
+
+        assert!(!verify_password("", "", ""));
+        assert!(!verify_password("secret", "salt", "pbkdf2-sha256$x$00"));
+    }
+}
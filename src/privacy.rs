@@ -0,0 +1,76 @@
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use worker::Request;
+
+use crate::crypto::sha256_hex;
+
+static IP_LESS: AtomicBool = AtomicBool::new(false);
+
+const REDACTED: &str = "[redacted]";
+
+pub fn set_ip_less(enabled: bool) {
+    IP_LESS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_ip_less() -> bool {
+    IP_LESS.load(Ordering::Relaxed)
+}
+
+// the only place that reads the client address, the user agent isn't read at all
+fn connecting_ip(req: &Request) -> Option<String> {
+    req.headers().get("CF-Connecting-IP").ok().flatten()
+}
+
+// the client address for services outside the worker, None when ip-less mode is enabled
+pub fn client_ip(req: &Request) -> Option<String> {
+    if is_ip_less() {
+        return None;
+    }
+    connecting_ip(req)
+}
+
+// hashed client ip, usable as a key without keeping the address itself
+pub fn client_key(req: &Request) -> Option<String> {
+    let ip = connecting_ip(req)?;
+    Some(sha256_hex(ip.as_bytes()))
+}
+
+// replace every ip address in `s` when ip-less mode is enabled
+pub fn redact(s: &str) -> Cow<'_, str> {
+    if !is_ip_less() {
+        return Cow::Borrowed(s);
+    }
+
+    let is_ip_char = |c: char| c.is_ascii_hexdigit() || c == '.' || c == ':';
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find(is_ip_char) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest.find(|c: char| !is_ip_char(c)).unwrap_or(rest.len());
+        let token = &rest[..end];
+        let ip = token.trim_end_matches(['.', ':']);
+        if is_ip(ip) {
+            out.push_str(REDACTED);
+            out.push_str(&token[ip.len()..]);
+        } else {
+            out.push_str(token);
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+
+    Cow::Owned(out)
+}
+
+// rust paths like `Abc::Def` are valid ipv6 too, so require something that looks like a real address
+fn is_ip(s: &str) -> bool {
+    match s.parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => true,
+        Ok(IpAddr::V6(_)) => !s.starts_with(':') && s.matches(':').count() >= 3,
+        Err(_) => false,
+    }
+}
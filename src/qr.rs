@@ -0,0 +1,141 @@
+// qr codes of file links for GET /qr/:file_id. svgs come from the qrcode crate, pngs are
+// written here with stored deflate blocks, a qr code is small enough without compression
+
+use qrcode::render::svg;
+use qrcode::{Color, QrCode};
+
+use crate::error::Error;
+
+// light modules around the code, scanners need them
+const QUIET_ZONE: usize = 4;
+// pixels per module of pngs
+pub const DEFAULT_SCALE: usize = 8;
+pub const MAX_SCALE: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Png,
+    Svg,
+}
+
+impl Format {
+    // from the extension of the link, png without one
+    pub fn parse(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "" | "png" => Some(Format::Png),
+            "svg" => Some(Format::Svg),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Png => "image/png",
+            Format::Svg => "image/svg+xml",
+        }
+    }
+}
+
+pub fn svg(text: &str) -> Result<Vec<u8>, Error> {
+    let code = QrCode::new(text).map_err(|e| Error::BadRequest(e.to_string()))?;
+    Ok(code
+        .render::<svg::Color>()
+        .quiet_zone(true)
+        .min_dimensions(256, 256)
+        .build()
+        .into_bytes())
+}
+
+// black and white, one bit per pixel
+pub fn png(text: &str, scale: usize) -> Result<Vec<u8>, Error> {
+    let code = QrCode::new(text).map_err(|e| Error::BadRequest(e.to_string()))?;
+    let scale = scale.clamp(1, MAX_SCALE);
+    let modules = code.width();
+    let colors = code.to_colors();
+    let size = (modules + 2 * QUIET_ZONE) * scale;
+    let row_len = size.div_ceil(8);
+
+    let mut pixels = Vec::with_capacity((row_len + 1) * size);
+    for y in 0..size {
+        // no filter
+        pixels.push(0);
+        let my = (y / scale).checked_sub(QUIET_ZONE);
+        let mut row = vec![0xffu8; row_len];
+        for x in 0..size {
+            let mx = (x / scale).checked_sub(QUIET_ZONE);
+            let dark = match (mx, my) {
+                (Some(mx), Some(my)) if mx < modules && my < modules => {
+                    colors[my * modules + mx] == Color::Dark
+                }
+                _ => false,
+            };
+            if dark {
+                row[x / 8] &= !(0x80 >> (x % 8));
+            }
+        }
+        pixels.extend_from_slice(&row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(size as u32).to_be_bytes());
+    header.extend_from_slice(&(size as u32).to_be_bytes());
+    // bit depth 1, grayscale, deflate, no filter method, not interlaced
+    header.extend_from_slice(&[1, 0, 0, 0, 0]);
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut out, b"IHDR", &header);
+    chunk(&mut out, b"IDAT", &zlib_stored(&pixels));
+    chunk(&mut out, b"IEND", &[]);
+    Ok(out)
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(if blocks.peek().is_none() { 1 } else { 0 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &v in data {
+        a = (a + v as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
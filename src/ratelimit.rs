@@ -0,0 +1,71 @@
+use std::cell::Cell;
+use worker::*;
+
+const WINDOW_MS: f64 = 60_000.0;
+
+// token bucket per client, the durable object name is the hashed client ip
+#[durable_object]
+pub struct RateLimiter {
+    tokens: Cell<f64>,
+    updated: Cell<f64>,
+}
+
+impl DurableObject for RateLimiter {
+    fn new(_state: State, _env: Env) -> Self {
+        RateLimiter {
+            // clamped to the limit on the first request
+            tokens: Cell::new(f64::INFINITY),
+            updated: Cell::new(Date::now().as_millis() as f64),
+        }
+    }
+
+    async fn fetch(&self, req: Request) -> Result<Response> {
+        let limit = req
+            .url()?
+            .query_pairs()
+            .find(|(k, _)| k == "limit")
+            .and_then(|(_, v)| v.parse::<f64>().ok())
+            .unwrap_or_default();
+
+        if limit <= 0.0 {
+            return Response::ok("ok");
+        }
+
+        let now = Date::now().as_millis() as f64;
+        let refill = (now - self.updated.get()) * limit / WINDOW_MS;
+        let tokens = (self.tokens.get() + refill).min(limit);
+        self.updated.set(now);
+
+        if tokens >= 1.0 {
+            self.tokens.set(tokens - 1.0);
+            return Response::ok("ok");
+        }
+
+        self.tokens.set(tokens);
+
+        let retry_after = ((1.0 - tokens) * WINDOW_MS / limit / 1000.0).ceil();
+        let mut resp = Response::error("too many requests", 429)?;
+        resp.headers_mut()
+            .set("Retry-After", &retry_after.to_string())?;
+        Ok(resp)
+    }
+}
+
+// seconds to wait when the client is over the limit
+pub async fn check(namespace: &ObjectNamespace, key: &str, limit: u32) -> Result<Option<u64>> {
+    let stub = namespace.id_from_name(key)?.get_stub()?;
+    let resp = stub
+        .fetch_with_str(&format!("https://rate-limiter/?limit={}", limit))
+        .await?;
+
+    if resp.status_code() == 429 {
+        let retry_after = resp
+            .headers()
+            .get("Retry-After")?
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(1);
+        return Ok(Some(retry_after));
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use worker::*;
+
+use crate::cors::Cors;
+
+// wraps `Router` to remember which methods every pattern serves, so OPTIONS can answer with them
+pub struct Routes<'a> {
+    router: Router<'a, ()>,
+    methods: BTreeMap<&'static str, Vec<&'static str>>,
+    cors: Cors,
+}
+
+impl Default for Routes<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Routes<'a> {
+    pub fn new() -> Self {
+        Routes {
+            router: Router::new(),
+            methods: BTreeMap::new(),
+            cors: Cors::default(),
+        }
+    }
+
+    pub fn with_cors(mut self, cors: Cors) -> Self {
+        self.cors = cors;
+        self
+    }
+
+    fn allow(&mut self, pattern: &'static str, method: &'static str) {
+        self.methods.entry(pattern).or_default().push(method);
+    }
+
+    pub fn get_async<T>(
+        mut self,
+        pattern: &'static str,
+        func: impl Fn(Request, RouteContext<()>) -> T + 'a,
+    ) -> Self
+    where
+        T: Future<Output = Result<Response>> + 'a,
+    {
+        self.allow(pattern, "GET");
+        self.router = self.router.get_async(pattern, func);
+        self
+    }
+
+    pub fn head_async<T>(
+        mut self,
+        pattern: &'static str,
+        func: impl Fn(Request, RouteContext<()>) -> T + 'a,
+    ) -> Self
+    where
+        T: Future<Output = Result<Response>> + 'a,
+    {
+        self.allow(pattern, "HEAD");
+        self.router = self.router.head_async(pattern, func);
+        self
+    }
+
+    pub fn post_async<T>(
+        mut self,
+        pattern: &'static str,
+        func: impl Fn(Request, RouteContext<()>) -> T + 'a,
+    ) -> Self
+    where
+        T: Future<Output = Result<Response>> + 'a,
+    {
+        self.allow(pattern, "POST");
+        self.router = self.router.post_async(pattern, func);
+        self
+    }
+
+    pub fn put_async<T>(
+        mut self,
+        pattern: &'static str,
+        func: impl Fn(Request, RouteContext<()>) -> T + 'a,
+    ) -> Self
+    where
+        T: Future<Output = Result<Response>> + 'a,
+    {
+        self.allow(pattern, "PUT");
+        self.router = self.router.put_async(pattern, func);
+        self
+    }
+
+    pub fn delete_async<T>(
+        mut self,
+        pattern: &'static str,
+        func: impl Fn(Request, RouteContext<()>) -> T + 'a,
+    ) -> Self
+    where
+        T: Future<Output = Result<Response>> + 'a,
+    {
+        self.allow(pattern, "DELETE");
+        self.router = self.router.delete_async(pattern, func);
+        self
+    }
+
+    // routes registered for every method answer OPTIONS themselves
+    pub fn on_async<T>(
+        mut self,
+        pattern: &'static str,
+        func: impl Fn(Request, RouteContext<()>) -> T + 'a,
+    ) -> Self
+    where
+        T: Future<Output = Result<Response>> + 'a,
+    {
+        self.router = self.router.on_async(pattern, func);
+        self
+    }
+
+    pub fn on(
+        mut self,
+        pattern: &'static str,
+        func: fn(Request, RouteContext<()>) -> Result<Response>,
+    ) -> Self {
+        self.router = self.router.on(pattern, func);
+        self
+    }
+
+    pub fn or_else_any_method(
+        mut self,
+        pattern: &'static str,
+        func: fn(Request, RouteContext<()>) -> Result<Response>,
+    ) -> Self {
+        self.router = self.router.or_else_any_method(pattern, func);
+        self
+    }
+
+    pub async fn run(self, req: Request, env: Env) -> Result<Response> {
+        let mut router = self.router;
+
+        for (pattern, mut methods) in self.methods {
+            methods.push("OPTIONS");
+            let allow = methods.join(", ");
+            let cors = self.cors.clone();
+            router = router.options_async(pattern, move |req, _| {
+                let allow = allow.clone();
+                let cors = cors.clone();
+                async move { preflight(&req, &allow, &cors) }
+            });
+        }
+
+        router.run(req, env).await
+    }
+}
+
+fn preflight(req: &Request, allow: &str, cors: &Cors) -> Result<Response> {
+    let headers = Headers::new();
+    headers.set("Allow", allow)?;
+    cors.apply(req.headers().get("Origin")?.as_deref(), &headers)?;
+    headers.set("Access-Control-Allow-Methods", allow)?;
+    headers.set(
+        "Access-Control-Allow-Headers",
+        &req.headers()
+            .get("Access-Control-Request-Headers")?
+            .unwrap_or("Authorization, Content-Type".to_string()),
+    )?;
+    headers.set("Access-Control-Max-Age", "86400")?;
+
+    Ok(Response::empty()?.with_status(204).with_headers(headers))
+}
@@ -0,0 +1,448 @@
+// the part of the s3 api tools need to upload a file: PUT /s3/:bucket/:key signed with aws
+// signature v4. the access key id is S3_ACCESS_KEY_ID, the secret access key is API_TOKEN.
+// the bucket is only part of the path, every key is uploaded like /api/upload does
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use md5::{Digest, Md5};
+use worker::{Date, Request, Response};
+
+use crate::crypto::{constant_time_eq, hex, hmac_sha256, sha256_hex};
+use crate::error::Error;
+use crate::listing::html_escape;
+
+// requests signed longer ago or later are refused, like s3 does
+const MAX_CLOCK_SKEW_SECS: i64 = 15 * 60;
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+pub const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+// `Credential=<id>/<date>/<region>/s3/aws4_request, SignedHeaders=<a;b>, Signature=<hex>`
+#[derive(Debug, PartialEq)]
+pub struct Authorization {
+    access_key_id: String,
+    date: String,
+    region: String,
+    service: String,
+    signed_headers: Vec<String>,
+    signature: String,
+}
+
+fn parse_authorization(header: &str) -> Option<Authorization> {
+    let params = header.strip_prefix(ALGORITHM)?;
+    let mut credential = None;
+    let mut signed_headers = None;
+    let mut signature = None;
+    for param in params.split(',') {
+        match param.trim().split_once('=')? {
+            ("Credential", v) => credential = Some(v),
+            ("SignedHeaders", v) => signed_headers = Some(v),
+            ("Signature", v) => signature = Some(v),
+            _ => {}
+        }
+    }
+
+    let mut scope = credential?.split('/');
+    let authorization = Authorization {
+        access_key_id: scope.next()?.to_string(),
+        date: scope.next()?.to_string(),
+        region: scope.next()?.to_string(),
+        service: scope.next()?.to_string(),
+        signed_headers: signed_headers?
+            .split(';')
+            .map(|v| v.to_ascii_lowercase())
+            .collect(),
+        signature: signature?.to_string(),
+    };
+    if scope.next() != Some("aws4_request") || scope.next().is_some() {
+        return None;
+    }
+    Some(authorization)
+}
+
+// the checks that only need the headers, so unsigned requests are refused before their body is read
+pub fn authorization(req: &Request, access_key_id: &str) -> Result<Authorization, Error> {
+    let headers = req.headers();
+    let authorization = headers
+        .get("Authorization")?
+        .and_then(|v| parse_authorization(&v))
+        .ok_or_else(|| Error::Unauthorized("missing or malformed sigv4 authorization".into()))?;
+    if authorization.access_key_id != access_key_id {
+        return Err(Error::Unauthorized("unknown access key id".into()));
+    }
+    if !authorization.signed_headers.iter().any(|v| v == "host") {
+        return Err(Error::Unauthorized("the host header must be signed".into()));
+    }
+
+    let amz_date = headers.get("x-amz-date")?.unwrap_or_default();
+    let signed_at = parse_amz_date(&amz_date)
+        .ok_or_else(|| Error::Unauthorized("x-amz-date is missing or malformed".into()))?;
+    let now = (Date::now().as_millis() / 1000) as i64;
+    if (now - signed_at).abs() > MAX_CLOCK_SKEW_SECS {
+        return Err(Error::Unauthorized(
+            "the request time is too skewed from the server time".into(),
+        ));
+    }
+    if !amz_date.starts_with(&authorization.date) {
+        return Err(Error::Unauthorized(
+            "the credential date is not the date of x-amz-date".into(),
+        ));
+    }
+
+    let payload_hash = headers.get("x-amz-content-sha256")?.unwrap_or_default();
+    if payload_hash.starts_with("STREAMING-") {
+        return Err(Error::BadRequest(
+            "chunked uploads are not supported, sign the whole payload".into(),
+        ));
+    }
+    Ok(authorization)
+}
+
+// checks the signature of `req` against every accepted secret, `body` is the payload
+pub fn verify(
+    req: &Request,
+    authorization: &Authorization,
+    secrets: &[&str],
+    body: &[u8],
+) -> Result<(), Error> {
+    let headers = req.headers();
+    let amz_date = headers.get("x-amz-date")?.unwrap_or_default();
+
+    // the hash the client signed, checked against the body unless it chose not to sign it
+    let payload_hash = headers
+        .get("x-amz-content-sha256")?
+        .unwrap_or_else(|| UNSIGNED_PAYLOAD.to_string());
+    if payload_hash != UNSIGNED_PAYLOAD && !payload_hash.eq_ignore_ascii_case(&sha256_hex(body)) {
+        return Err(Error::BadRequest(
+            "x-amz-content-sha256 is not the hash of the body".into(),
+        ));
+    }
+
+    let url = req.url()?;
+    let mut signed = vec![];
+    for name in &authorization.signed_headers {
+        let value = headers.get(name)?.unwrap_or_default();
+        signed.push((name.clone(), value));
+    }
+    let canonical = canonical_request(
+        req.method().as_ref(),
+        url.path(),
+        url.query().unwrap_or_default(),
+        &signed,
+        &payload_hash,
+    );
+    let scope = format!(
+        "{}/{}/{}/aws4_request",
+        authorization.date, authorization.region, authorization.service
+    );
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        amz_date,
+        scope,
+        sha256_hex(canonical.as_bytes())
+    );
+
+    for secret in secrets.iter().filter(|v| !v.is_empty()) {
+        let signature = signature(
+            secret,
+            &authorization.date,
+            &authorization.region,
+            &authorization.service,
+            &string_to_sign,
+        )?;
+        if constant_time_eq(signature.as_bytes(), authorization.signature.as_bytes()) {
+            return Ok(());
+        }
+    }
+    Err(Error::Unauthorized(
+        "the signature does not match, check the secret access key".into(),
+    ))
+}
+
+// the path is used as sent, s3 doesn't normalize it
+fn canonical_request(
+    method: &str,
+    path: &str,
+    query: &str,
+    headers: &[(String, String)],
+    payload_hash: &str,
+) -> String {
+    let mut params = url_pairs(query);
+    params.sort();
+    let query = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", uri_encode(k), uri_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let mut headers = headers
+        .iter()
+        .map(|(k, v)| {
+            (
+                k.to_ascii_lowercase(),
+                v.split_whitespace().collect::<Vec<_>>().join(" "),
+            )
+        })
+        .collect::<Vec<_>>();
+    headers.sort();
+    let canonical_headers = headers
+        .iter()
+        .map(|(k, v)| format!("{}:{}\n", k, v))
+        .collect::<String>();
+    let signed_headers = headers
+        .iter()
+        .map(|(k, _)| k.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, path, query, canonical_headers, signed_headers, payload_hash
+    )
+}
+
+fn signature(
+    secret: &str,
+    date: &str,
+    region: &str,
+    service: &str,
+    string_to_sign: &str,
+) -> Result<String, Error> {
+    let key = hmac_sha256(format!("AWS4{}", secret).as_bytes(), date.as_bytes())?;
+    let key = hmac_sha256(&key, region.as_bytes())?;
+    let key = hmac_sha256(&key, service.as_bytes())?;
+    let key = hmac_sha256(&key, b"aws4_request")?;
+    Ok(hex(&hmac_sha256(&key, string_to_sign.as_bytes())?))
+}
+
+// decoded `key=value` pairs, a key without `=` has an empty value
+fn url_pairs(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            let (k, v) = v.split_once('=').unwrap_or((v, ""));
+            (percent_decode(k), percent_decode(v))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s
+            .get(i + 1..i + 3)
+            .and_then(|v| u8::from_str_radix(v, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(v)) => {
+                out.push(v);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// everything but the unreserved characters, as aws encodes them
+fn uri_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// `20130524T000000Z` as unix seconds
+fn parse_amz_date(v: &str) -> Option<i64> {
+    if v.len() != 16 || !v.is_char_boundary(8) || &v[8..9] != "T" || !v.ends_with('Z') {
+        return None;
+    }
+    let num = |r: std::ops::Range<usize>| v.get(r)?.parse::<i64>().ok();
+    let (year, month, day) = (num(0..4)?, num(4..6)?, num(6..8)?);
+    let (hour, minute, second) = (num(9..11)?, num(11..13)?, num(13..15)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+
+    // days from civil, http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+// the file name of an object key, `photos/2024/cat.png` is `cat.png`
+pub fn file_name(key: &str) -> String {
+    let key = percent_decode(key);
+    key.rsplit('/').next().unwrap_or_default().to_string()
+}
+
+// the quoted md5 s3 answers as ETag, clients compare it with their own
+pub fn etag(body: &[u8]) -> String {
+    format!("\"{}\"", hex(&Md5::digest(body)))
+}
+
+// Content-MD5 is the base64 md5 of the body when a client sends it
+pub fn check_content_md5(req: &Request, body: &[u8]) -> Result<(), Error> {
+    match req.headers().get("Content-MD5")? {
+        Some(v) if v.trim() != BASE64.encode(Md5::digest(body)) => Err(Error::BadRequest(
+            "Content-MD5 is not the md5 of the body".into(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// s3 clients read errors as xml
+pub fn error_response(e: &Error) -> worker::Result<Response> {
+    let (status, code) = match e {
+        Error::Unauthorized(_) => (403, "AccessDenied"),
+        Error::Forbidden(_) => (403, "AccessDenied"),
+        Error::PayloadTooLarge(_) => (400, "EntityTooLarge"),
+        Error::BadRequest(v) if v.starts_with("Content-MD5") => (400, "BadDigest"),
+        Error::BadRequest(_) => (400, "InvalidRequest"),
+        Error::NotFound(_) => (404, "NoSuchKey"),
+        Error::TooManyRequests(_) => (503, "SlowDown"),
+        _ => (500, "InternalError"),
+    };
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>{}</Code><Message>{}</Message></Error>",
+        code,
+        html_escape(&e.to_string())
+    );
+    let mut resp = Response::ok(body)?.with_status(status);
+    resp.headers_mut().set("Content-Type", "application/xml")?;
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the examples of the aws signature v4 documentation for s3
+    const SECRET: &str = "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY";
+    const EMPTY_HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    fn sign(method: &str, path: &str, query: &str, headers: &[(&str, &str)], hash: &str) -> String {
+        let headers = headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>();
+        let canonical = canonical_request(method, path, query, &headers, hash);
+        let string_to_sign = format!(
+            "{}\n20130524T000000Z\n20130524/us-east-1/s3/aws4_request\n{}",
+            ALGORITHM,
+            sha256_hex(canonical.as_bytes())
+        );
+        signature(SECRET, "20130524", "us-east-1", "s3", &string_to_sign).unwrap()
+    }
+
+    #[test]
+    fn get_object() {
+        let headers = [
+            ("Host", "examplebucket.s3.amazonaws.com"),
+            ("Range", "bytes=0-9"),
+            ("x-amz-content-sha256", EMPTY_HASH),
+            ("x-amz-date", "20130524T000000Z"),
+        ];
+        assert_eq!(
+            sign("GET", "/test.txt", "", &headers, EMPTY_HASH),
+            "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41"
+        );
+    }
+
+    #[test]
+    fn put_object() {
+        let hash = sha256_hex(b"Welcome to Amazon S3.");
+        let headers = [
+            ("Host", "examplebucket.s3.amazonaws.com"),
+            ("Date", "Fri, 24 May 2013 00:00:00 GMT"),
+            ("x-amz-date", "20130524T000000Z"),
+            ("x-amz-storage-class", "REDUCED_REDUNDANCY"),
+            ("x-amz-content-sha256", &hash),
+        ];
+        assert_eq!(
+            sign("PUT", "/test%24file.text", "", &headers, &hash),
+            "98ad721746da40c64f1a55b78f14c238d841ea1380cd77a1b5971af0ece108bd"
+        );
+    }
+
+    #[test]
+    fn query_parameters() {
+        let headers = [
+            ("host", "examplebucket.s3.amazonaws.com"),
+            ("x-amz-content-sha256", EMPTY_HASH),
+            ("x-amz-date", "20130524T000000Z"),
+        ];
+        // sorted, and a parameter without a value signs as `lifecycle=`
+        assert_eq!(
+            sign("GET", "/", "prefix=J&max-keys=2", &headers, EMPTY_HASH),
+            "34b48302e7b5fa45bde8084f4b7868a86f0a534bc59db6670ed5711ef69dc6f7"
+        );
+        assert_eq!(
+            sign("GET", "/", "lifecycle", &headers, EMPTY_HASH),
+            "fea454ca298b7da1c68078a5d1bdbfbbe0d65c699e0f91ac7a200a0136783543"
+        );
+    }
+
+    #[test]
+    fn canonical_headers() {
+        let headers = vec![
+            ("X-Amz-Meta-A".to_string(), "  a   b ".to_string()),
+            ("host".to_string(), "h".to_string()),
+        ];
+        assert_eq!(
+            canonical_request("PUT", "/b/k", "a=%7E&b=c%20d", &headers, UNSIGNED_PAYLOAD),
+            "PUT\n/b/k\na=~&b=c%20d\nhost:h\nx-amz-meta-a:a b\n\nhost;x-amz-meta-a\nUNSIGNED-PAYLOAD"
+        );
+    }
+
+    #[test]
+    fn authorization_header() {
+        let header = "AWS4-HMAC-SHA256 Credential=AKID/20130524/us-east-1/s3/aws4_request, SignedHeaders=Host;x-amz-date, Signature=abc";
+        assert_eq!(
+            parse_authorization(header),
+            Some(Authorization {
+                access_key_id: "AKID".into(),
+                date: "20130524".into(),
+                region: "us-east-1".into(),
+                service: "s3".into(),
+                signed_headers: vec!["host".into(), "x-amz-date".into()],
+                signature: "abc".into(),
+            })
+        );
+        assert_eq!(
+            parse_authorization(
+                "AWS4-HMAC-SHA256 Credential=AKID/20130524/us-east-1/s3, SignedHeaders=host, Signature=abc"
+            ),
+            None
+        );
+        assert_eq!(parse_authorization("Bearer abc"), None);
+    }
+
+    #[test]
+    fn amz_date() {
+        assert_eq!(parse_amz_date("20130524T000000Z"), Some(1369353600));
+        assert_eq!(parse_amz_date("20240229T235959Z"), Some(1709251199));
+        assert_eq!(parse_amz_date("20131324T000000Z"), None);
+        assert_eq!(parse_amz_date("2013-05-24T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(file_name("photos/2024/a%20cat.png"), "a cat.png");
+        assert_eq!(uri_encode("a b/~"), "a%20b%2F~");
+    }
+}
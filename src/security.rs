@@ -0,0 +1,135 @@
+// headers of served files. an uploaded html or svg page opened on this origin could run
+// scripts with everything the origin can, so files are never sniffed, run sandboxed and
+// the ones browsers would execute are downloaded instead
+
+// types that can run scripts when opened directly
+const ACTIVE_MIME_TYPES: [&str; 5] = [
+    "text/html",
+    "application/xhtml+xml",
+    "image/svg+xml",
+    "text/xml",
+    "application/xml",
+];
+// nothing is loaded besides the file itself, `sandbox` gives what is left an opaque origin
+pub const DEFAULT_CSP: &str = "default-src 'none'; img-src 'self' data:; media-src 'self'; style-src 'unsafe-inline'; sandbox";
+// links out of files don't tell where they were hosted
+pub const DEFAULT_REFERRER_POLICY: &str = "no-referrer";
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum AttachmentMode {
+    // html, svg and xml documents are downloaded
+    #[default]
+    Active,
+    // everything but images, videos and audio is downloaded
+    NonMedia,
+}
+
+impl AttachmentMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "" | "active" => Some(AttachmentMode::Active),
+            "non-media" => Some(AttachmentMode::NonMedia),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum SvgPolicy {
+    // downloaded like the other active types
+    #[default]
+    Attachment,
+    // shown in the browser, only the csp keeps scripts from running
+    Inline,
+    // shown in the browser after scripts and links out were removed
+    Sanitize,
+}
+
+impl SvgPolicy {
+    pub fn parse(policy: &str) -> Option<Self> {
+        match policy {
+            "" | "attachment" => Some(SvgPolicy::Attachment),
+            "inline" => Some(SvgPolicy::Inline),
+            "sanitize" => Some(SvgPolicy::Sanitize),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders {
+    csp: String,
+    referrer_policy: String,
+    attachment_mode: AttachmentMode,
+    svg_policy: SvgPolicy,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self::new(String::new(), String::new(), AttachmentMode::default())
+    }
+}
+
+impl SecurityHeaders {
+    // empty values are the defaults
+    pub fn new(csp: String, referrer_policy: String, attachment_mode: AttachmentMode) -> Self {
+        SecurityHeaders {
+            csp: if csp.is_empty() {
+                DEFAULT_CSP.to_string()
+            } else {
+                csp
+            },
+            referrer_policy: if referrer_policy.is_empty() {
+                DEFAULT_REFERRER_POLICY.to_string()
+            } else {
+                referrer_policy
+            },
+            attachment_mode,
+            svg_policy: SvgPolicy::default(),
+        }
+    }
+
+    pub fn with_svg_policy(mut self, svg_policy: SvgPolicy) -> Self {
+        self.svg_policy = svg_policy;
+        self
+    }
+
+    // of every file response
+    pub fn headers(&self) -> Vec<(String, String)> {
+        vec![
+            ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+            ("Referrer-Policy".to_string(), self.referrer_policy.clone()),
+            ("Content-Security-Policy".to_string(), self.csp.clone()),
+        ]
+    }
+
+    // files of this type are served with `Content-Disposition: attachment`
+    pub fn is_attachment(&self, mime: &str) -> bool {
+        let mime = essence(mime);
+        if mime == "image/svg+xml" && self.svg_policy != SvgPolicy::Attachment {
+            return false;
+        }
+        match self.attachment_mode {
+            AttachmentMode::Active => is_active(&mime),
+            AttachmentMode::NonMedia => {
+                is_active(&mime)
+                    || !["image/", "video/", "audio/"]
+                        .iter()
+                        .any(|v| mime.starts_with(v))
+            }
+        }
+    }
+}
+
+pub fn is_active(mime: &str) -> bool {
+    ACTIVE_MIME_TYPES.contains(&essence(mime).as_str())
+}
+
+// the type without parameters like charset
+fn essence(mime: &str) -> String {
+    mime.split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
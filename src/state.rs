@@ -0,0 +1,224 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::crypto::{hex, hmac_sha256, verify_hmac_hex};
+use crate::d1::{Digest, Usage};
+use crate::error::Error;
+
+// configuration and counts of an instance, used to prepare a standby deployment
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InstanceState {
+    pub schema_version: usize,
+    pub host: String,
+    pub features: BTreeMap<String, bool>,
+    // names of BOTS, PATH_PREFIX and PUBLIC_HOST, the routes links depend on
+    #[serde(default)]
+    pub bots: Vec<String>,
+    #[serde(default)]
+    pub path_prefix: String,
+    #[serde(default)]
+    pub public_host: String,
+    pub files: u64,
+    pub users: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SignedState {
+    pub state: InstanceState,
+    // hex encoded hmac-sha256 of the json encoded state
+    pub signature: String,
+}
+
+// POST /admin/state/diff, nothing is applied since the settings are wrangler vars
+#[derive(Serialize, Debug)]
+pub struct StateDiff {
+    pub valid: bool,
+    // settings of the exported instance that differ from this one
+    pub differences: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct KeyRotation {
+    // API_TOKEN_PREVIOUS is set and still accepted
+    pub rotating: bool,
+    // unix millis of the last request made with the previous token, 0 if never
+    pub previous_api_token_last_used: u64,
+    // unix millis of the last POST /admin/keys/webhook, 0 if never
+    pub webhook_secret_rotated: u64,
+    // TURNSTILE_SECRET_PREVIOUS is set and passes signed with it are still accepted
+    pub turnstile_rotating: bool,
+}
+
+// GET /admin/stats, what the daily digest tells the maintainer
+#[derive(Serialize, Debug)]
+pub struct Stats {
+    pub files: u64,
+    pub users: u64,
+    pub counters: BTreeMap<String, u64>,
+    // activity of the last 24 hours
+    pub last_day: Digest,
+    pub by_type: Vec<Usage>,
+    // uploads of the last USAGE_DAYS days
+    pub by_day: Vec<Usage>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BanReport {
+    pub user_id: u64,
+    pub banned: bool,
+    // false when the user already was in that state
+    pub changed: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeleteReport {
+    pub file_unique_id: String,
+    pub deleted: bool,
+    // days until the cron removes a deleted file for good
+    pub grace_days: u32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct UndeleteReport {
+    pub file_unique_id: String,
+    // false when the file wasn't deleted
+    pub restored: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteStatus {
+    Deleted,
+    NotFound,
+    AlreadyDeleted,
+    // the file of another user
+    Forbidden,
+    // the id was already in the batch
+    Duplicate,
+}
+
+// one id of a batch delete
+#[derive(Serialize, Debug)]
+pub struct DeleteItem {
+    pub id: String,
+    // empty when the id wasn't found
+    pub file_unique_id: String,
+    pub status: DeleteStatus,
+    pub cache_entries: u32,
+    pub stored_objects: u32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BatchDeleteReport {
+    pub deleted: usize,
+    pub failed: usize,
+    pub grace_days: u32,
+    pub items: Vec<DeleteItem>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CleanupReport {
+    // deleted files removed with their stored copies
+    pub purged: u32,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct PurgeReport {
+    pub cache_entries: u32,
+    pub stored_objects: u32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Health {
+    pub healthy: bool,
+    // "ok", "not bound" or the error of every probed dependency
+    pub checks: BTreeMap<&'static str, String>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Health {
+            healthy: true,
+            checks: BTreeMap::new(),
+        }
+    }
+
+    pub fn check<E: std::fmt::Display>(&mut self, name: &'static str, result: Result<(), E>) {
+        let status = match result {
+            Ok(_) => "ok".to_string(),
+            Err(e) => {
+                self.healthy = false;
+                e.to_string()
+            }
+        };
+        self.checks.insert(name, status);
+    }
+}
+
+impl SignedState {
+    pub fn sign(state: InstanceState, key: &str) -> Result<Self, Error> {
+        let signature = signature(&state, key)?;
+        Ok(SignedState { state, signature })
+    }
+
+    pub fn verify(&self, key: &str) -> Result<bool, Error> {
+        let payload =
+            serde_json::to_vec(&self.state).map_err(|e| Error::Internal(e.to_string()))?;
+        verify_hmac_hex(key.as_bytes(), &payload, &self.signature)
+    }
+}
+
+impl InstanceState {
+    pub fn differences(&self, local: &InstanceState) -> Vec<String> {
+        let mut differences = vec![];
+
+        if self.schema_version != local.schema_version {
+            differences.push(format!(
+                "schema version {} != {}, run /d1/create_table",
+                self.schema_version, local.schema_version
+            ));
+        }
+
+        for (name, enabled) in &self.features {
+            if local.features.get(name) != Some(enabled) {
+                differences.push(format!(
+                    "feature {} is {} on the exported instance",
+                    name,
+                    if *enabled { "enabled" } else { "disabled" }
+                ));
+            }
+        }
+
+        if self.bots != local.bots {
+            differences.push(format!(
+                "BOTS is {:?} on the exported instance",
+                self.bots.join(",")
+            ));
+        }
+        if self.path_prefix != local.path_prefix {
+            differences.push(format!(
+                "PATH_PREFIX is {:?} on the exported instance",
+                self.path_prefix
+            ));
+        }
+        if self.public_host != local.public_host {
+            differences.push(format!(
+                "PUBLIC_HOST is {:?} on the exported instance",
+                self.public_host
+            ));
+        }
+
+        differences
+    }
+}
+
+fn signature(state: &InstanceState, key: &str) -> Result<String, Error> {
+    let payload = serde_json::to_vec(state).map_err(|e| Error::Internal(e.to_string()))?;
+    Ok(hex(&hmac_sha256(key.as_bytes(), &payload)?))
+}
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use web_sys::ReadableStream;
+use worker::kv::KvStore;
+use worker::{Bucket, Env, HttpMetadata, Response, ResponseBody, ResponseBuilder};
+
+use crate::capability::{KV_STORAGE, R2_STORAGE};
+use crate::error::Error;
+
+// a stored copy of a telegram file
+pub struct Object {
+    pub body: ReadableStream,
+    pub http_metadata: HttpMetadata,
+}
+
+// where downloaded files are kept so they aren't fetched from telegram again.
+// workers run on a single thread, the futures don't need to be Send
+#[allow(async_fn_in_trait)]
+pub trait Storage: Clone + 'static {
+    async fn get(&self, key: &str) -> Result<Option<Object>, Error>;
+    async fn put(
+        &self,
+        key: &str,
+        data: ReadableStream,
+        http_metadata: HttpMetadata,
+        custom_metadata: HashMap<String, String>,
+    ) -> Result<(), Error>;
+    async fn delete(&self, key: &str) -> Result<(), Error>;
+    async fn head(&self, key: &str) -> Result<bool, Error>;
+    // custom metadata of an object a public bucket serves, None when it's missing or
+    // the backend can't be public
+    async fn public_metadata(&self, key: &str) -> Result<Option<HashMap<String, String>>, Error>;
+}
+
+// R2 is preferred, FILE_STORE is used by deployments without it
+pub fn from_env(env: &Env) -> Option<Backend> {
+    if let Ok(v) = env.bucket(&R2_STORAGE.binding) {
+        return Some(Backend::R2(R2Storage(v)));
+    }
+    env.kv(&KV_STORAGE.binding)
+        .ok()
+        .map(|v| Backend::Kv(KvStorage(v)))
+}
+
+#[derive(Clone)]
+pub enum Backend {
+    R2(R2Storage),
+    Kv(KvStorage),
+}
+
+impl Storage for Backend {
+    async fn get(&self, key: &str) -> Result<Option<Object>, Error> {
+        match self {
+            Backend::R2(v) => v.get(key).await,
+            Backend::Kv(v) => v.get(key).await,
+        }
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        data: ReadableStream,
+        http_metadata: HttpMetadata,
+        custom_metadata: HashMap<String, String>,
+    ) -> Result<(), Error> {
+        match self {
+            Backend::R2(v) => v.put(key, data, http_metadata, custom_metadata).await,
+            Backend::Kv(v) => v.put(key, data, http_metadata, custom_metadata).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        match self {
+            Backend::R2(v) => v.delete(key).await,
+            Backend::Kv(v) => v.delete(key).await,
+        }
+    }
+
+    async fn head(&self, key: &str) -> Result<bool, Error> {
+        match self {
+            Backend::R2(v) => v.head(key).await,
+            Backend::Kv(v) => v.head(key).await,
+        }
+    }
+
+    async fn public_metadata(&self, key: &str) -> Result<Option<HashMap<String, String>>, Error> {
+        match self {
+            Backend::R2(v) => v.public_metadata(key).await,
+            Backend::Kv(_) => Ok(None),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct R2Storage(pub Bucket);
+
+impl Storage for R2Storage {
+    async fn get(&self, key: &str) -> Result<Option<Object>, Error> {
+        let object = match self.0.get(key).execute().await.map_err(storage_error)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        match object.body().map(|v| v.response_body()) {
+            Some(Ok(ResponseBody::Stream(body))) => Ok(Some(Object {
+                body,
+                http_metadata: object.http_metadata(),
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        data: ReadableStream,
+        http_metadata: HttpMetadata,
+        custom_metadata: HashMap<String, String>,
+    ) -> Result<(), Error> {
+        self.0
+            .put(key, data)
+            .http_metadata(http_metadata)
+            .custom_metadata(custom_metadata)
+            .execute()
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.0.delete(key).await.map_err(storage_error)
+    }
+
+    async fn head(&self, key: &str) -> Result<bool, Error> {
+        Ok(self.0.head(key).await.map_err(storage_error)?.is_some())
+    }
+
+    async fn public_metadata(&self, key: &str) -> Result<Option<HashMap<String, String>>, Error> {
+        match self.0.head(key).await.map_err(storage_error)? {
+            Some(v) => Ok(Some(v.custom_metadata()?)),
+            None => Ok(None),
+        }
+    }
+}
+
+// kv values are limited to 25 MiB, larger files fail to be stored and keep coming from telegram
+#[derive(Clone)]
+pub struct KvStorage(pub KvStore);
+
+// http metadata isn't a kv concept, it's kept in the metadata of the value
+#[derive(Serialize, Deserialize, Default)]
+struct KvMetadata {
+    content_type: Option<String>,
+    content_disposition: Option<String>,
+    custom: HashMap<String, String>,
+}
+
+impl Storage for KvStorage {
+    async fn get(&self, key: &str) -> Result<Option<Object>, Error> {
+        let (bytes, metadata) = self
+            .0
+            .get(key)
+            .bytes_with_metadata::<KvMetadata>()
+            .await
+            .map_err(storage_error)?;
+        let Some(bytes) = bytes else {
+            return Ok(None);
+        };
+        let metadata = metadata.unwrap_or_default();
+
+        let resp: web_sys::Response = Response::from_bytes(bytes)?.into();
+        Ok(resp.body().map(|body| Object {
+            body,
+            http_metadata: HttpMetadata {
+                content_type: metadata.content_type,
+                content_disposition: metadata.content_disposition,
+                ..Default::default()
+            },
+        }))
+    }
+
+    async fn put(
+        &self,
+        key: &str,
+        data: ReadableStream,
+        http_metadata: HttpMetadata,
+        custom_metadata: HashMap<String, String>,
+    ) -> Result<(), Error> {
+        let bytes = ResponseBuilder::new()
+            .body(ResponseBody::Stream(data))
+            .bytes()
+            .await?;
+        let metadata = KvMetadata {
+            content_type: http_metadata.content_type,
+            content_disposition: http_metadata.content_disposition,
+            custom: custom_metadata,
+        };
+
+        self.0
+            .put_bytes(key, &bytes)
+            .and_then(|v| v.metadata(metadata))
+            .map_err(storage_error)?
+            .execute()
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.0.delete(key).await.map_err(storage_error)
+    }
+
+    // kv has no head, listing the key doesn't read the value
+    async fn head(&self, key: &str) -> Result<bool, Error> {
+        let list = self
+            .0
+            .list()
+            .prefix(key.to_string())
+            .limit(1)
+            .execute()
+            .await
+            .map_err(storage_error)?;
+        Ok(list.keys.iter().any(|v| v.name == key))
+    }
+
+    async fn public_metadata(&self, _: &str) -> Result<Option<HashMap<String, String>>, Error> {
+        Ok(None)
+    }
+}
+
+fn storage_error(e: impl std::fmt::Display) -> Error {
+    Error::Storage(e.to_string())
+}
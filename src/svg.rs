@@ -0,0 +1,406 @@
+// removes what can run scripts from svg files: script and foreign elements, event handler
+// attributes and links that aren't fragments or images. the file is buffered, svgs are text
+// and small. anything the parser doesn't understand is dropped instead of passed on
+
+use crate::d1::File;
+
+// larger files are replaced with an empty image
+pub const MAX_SVG_SIZE: usize = 4 * 1024 * 1024;
+pub const EMPTY_SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg"/>"#;
+
+// dropped with their content
+const DROPPED_ELEMENTS: [&str; 7] = [
+    "script",
+    "foreignobject",
+    "iframe",
+    "embed",
+    "object",
+    "handler",
+    "listener",
+];
+// attributes holding links
+const LINK_ATTRIBUTES: [&str; 3] = ["href", "src", "action"];
+
+pub fn applies(file: Option<&File>, ext: &str) -> bool {
+    match file {
+        Some(f) if !f.mime_type.is_empty() => f.mime_type == "image/svg+xml",
+        _ => ext.eq_ignore_ascii_case("svg"),
+    }
+}
+
+pub fn sanitize(data: &[u8]) -> Vec<u8> {
+    if data.len() > MAX_SVG_SIZE {
+        return EMPTY_SVG.as_bytes().to_vec();
+    }
+    let Ok(text) = std::str::from_utf8(data) else {
+        return EMPTY_SVG.as_bytes().to_vec();
+    };
+
+    let mut out = String::with_capacity(text.len());
+    // open elements inside a dropped one, 0 outside of them
+    let mut skip = 0;
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        if skip == 0 {
+            out.push_str(&rest[..start]);
+        }
+        rest = &rest[start..];
+
+        // comments, the doctype with its entities and processing instructions besides the xml declaration
+        if let Some(v) = rest.strip_prefix("<!--") {
+            rest = v.find("-->").map_or("", |end| &v[end + 3..]);
+            continue;
+        }
+        if let Some(v) = rest.strip_prefix("<![CDATA[") {
+            let Some(end) = v.find("]]>") else { break };
+            if skip == 0 {
+                out.push_str(&rest[.."<![CDATA[".len() + end + 3]);
+            }
+            rest = &v[end + 3..];
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            let Some(end) = declaration_end(rest) else {
+                break;
+            };
+            if rest.starts_with("<?xml ") && skip == 0 {
+                out.push_str(&rest[..end + 1]);
+            }
+            rest = &rest[end + 1..];
+            continue;
+        }
+
+        let Some((tag, len)) = parse_tag(rest) else {
+            break;
+        };
+        rest = &rest[len..];
+
+        if skip > 0 {
+            if tag.closing {
+                skip -= 1;
+            } else if !tag.self_closing {
+                skip += 1;
+            }
+            continue;
+        }
+        if is_dropped(&tag) {
+            if !tag.closing && !tag.self_closing {
+                skip = 1;
+            }
+            continue;
+        }
+        out.push_str(&tag.render());
+    }
+    if skip == 0 && !rest.contains('<') {
+        out.push_str(rest);
+    }
+
+    out.into_bytes()
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, &'a str)>,
+    closing: bool,
+    self_closing: bool,
+}
+
+impl Tag<'_> {
+    // the local name without a namespace prefix
+    fn local_name(&self) -> String {
+        let name = self.name.rsplit(':').next().unwrap_or_default();
+        name.to_ascii_lowercase()
+    }
+
+    fn render(&self) -> String {
+        if self.closing {
+            return format!("</{}>", self.name);
+        }
+        let element = self.local_name();
+        let mut out = format!("<{}", self.name);
+        for (name, value) in &self.attributes {
+            if !allowed_attribute(&element, name, value) {
+                continue;
+            }
+            if value.contains('"') {
+                out.push_str(&format!(" {}='{}'", name, value));
+            } else {
+                out.push_str(&format!(" {}=\"{}\"", name, value));
+            }
+        }
+        out.push_str(if self.self_closing { "/>" } else { ">" });
+        out
+    }
+}
+
+fn is_dropped(tag: &Tag) -> bool {
+    let name = tag.local_name();
+    if DROPPED_ELEMENTS.contains(&name.as_str()) {
+        return true;
+    }
+    // animations can turn a harmless link into a script one
+    matches!(name.as_str(), "set" | "animate")
+        && tag.attributes.iter().any(|(k, v)| {
+            k.eq_ignore_ascii_case("attributeName") && {
+                let v = v
+                    .trim()
+                    .rsplit(':')
+                    .next()
+                    .unwrap_or_default()
+                    .to_ascii_lowercase();
+                v.starts_with("on") || LINK_ATTRIBUTES.contains(&v.as_str())
+            }
+        })
+}
+
+// links point into the file, images embed data and anchors open web pages
+fn allowed_attribute(element: &str, name: &str, value: &str) -> bool {
+    let local = name
+        .rsplit(':')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if local.starts_with("on") {
+        return false;
+    }
+    if LINK_ATTRIBUTES.contains(&local.as_str()) {
+        let value = value.trim().to_ascii_lowercase();
+        return value.starts_with('#')
+            || (element == "image" && value.starts_with("data:image/"))
+            || (element == "a" && (value.starts_with("https://") || value.starts_with("http://")));
+    }
+    true
+}
+
+// `<name a="1" b='2'>`, `</name>` or `<name/>` at the start of `text` and its length
+fn parse_tag(text: &str) -> Option<(Tag<'_>, usize)> {
+    let bytes = text.as_bytes();
+    let mut pos = 1;
+    let closing = bytes.get(pos) == Some(&b'/');
+    if closing {
+        pos += 1;
+    }
+
+    let name_start = pos;
+    while pos < bytes.len() && is_name_byte(bytes[pos]) {
+        pos += 1;
+    }
+    if pos == name_start {
+        return None;
+    }
+    let name = &text[name_start..pos];
+
+    let mut attributes = vec![];
+    loop {
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        match bytes.get(pos)? {
+            b'>' => {
+                return Some((
+                    Tag {
+                        name,
+                        attributes,
+                        closing,
+                        self_closing: false,
+                    },
+                    pos + 1,
+                ));
+            }
+            b'/' if bytes.get(pos + 1) == Some(&b'>') && !closing => {
+                return Some((
+                    Tag {
+                        name,
+                        attributes,
+                        closing,
+                        self_closing: true,
+                    },
+                    pos + 2,
+                ));
+            }
+            _ if closing => return None,
+            _ => {}
+        }
+
+        let attr_start = pos;
+        while pos < bytes.len() && is_name_byte(bytes[pos]) {
+            pos += 1;
+        }
+        if pos == attr_start {
+            return None;
+        }
+        let attr = &text[attr_start..pos];
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        // xml has no attributes without a value
+        if bytes.get(pos) != Some(&b'=') {
+            return None;
+        }
+        pos += 1;
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let quote = *bytes.get(pos)?;
+        if quote != b'"' && quote != b'\'' {
+            return None;
+        }
+        let value_start = pos + 1;
+        let value_len = text[value_start..].find(quote as char)?;
+        // a `<` in an attribute isn't well-formed xml
+        let value = &text[value_start..value_start + value_len];
+        if value.contains('<') {
+            return None;
+        }
+        attributes.push((attr, value));
+        pos = value_start + value_len + 1;
+    }
+}
+
+// the `>` closing a declaration, past the quoted values and the `[...]` entities of a doctype
+fn declaration_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut depth = 0;
+    for (i, b) in text.bytes().enumerate() {
+        match (quote, b) {
+            (Some(q), _) if b == q => quote = None,
+            (Some(_), _) => {}
+            (None, b'"' | b'\'') => quote = Some(b),
+            (None, b'[') => depth += 1,
+            (None, b']') => depth -= 1,
+            (None, b'>') if depth <= 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_name_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b':' | b'-' | b'_' | b'.') || b >= 0x80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(svg: &str) -> String {
+        String::from_utf8(sanitize(svg.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn keeps_plain_svg() {
+        let svg = r##"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" width="10"><rect fill="#fff"/><use href="#a"/>text</svg>"##;
+        assert_eq!(clean(svg), svg);
+        // values are written double quoted unless they contain one
+        assert_eq!(
+            clean(r#"<svg width='10' font-family='"a b"'/>"#),
+            r#"<svg width="10" font-family='"a b"'/>"#
+        );
+    }
+
+    #[test]
+    fn drops_scripts_with_their_content() {
+        assert_eq!(
+            clean("<svg><script>alert(1)</script><g/></svg>"),
+            "<svg><g/></svg>"
+        );
+        assert_eq!(
+            clean(
+                "<svg><foreignObject><div><script>alert(1)</script></div></foreignObject><g/></svg>"
+            ),
+            "<svg><g/></svg>"
+        );
+        assert_eq!(
+            clean("<svg><svg:SCRIPT>alert(1)</svg:SCRIPT></svg>"),
+            "<svg></svg>"
+        );
+        // script text the parser doesn't understand ends the output
+        assert_eq!(
+            clean("<svg><script>if (a<b) alert(1)</script></svg>"),
+            "<svg>"
+        );
+    }
+
+    #[test]
+    fn drops_event_handlers() {
+        assert_eq!(
+            clean(r#"<svg onload="alert(1)" OnClick='alert(1)' ev:onfocus="x" id="a"></svg>"#),
+            r#"<svg id="a"></svg>"#
+        );
+    }
+
+    #[test]
+    fn drops_script_links() {
+        assert_eq!(
+            clean(r#"<svg><a xlink:href="javascript:alert(1)">x</a></svg>"#),
+            "<svg><a>x</a></svg>"
+        );
+        assert_eq!(
+            clean(r#"<svg><a href=" JavaScript:alert(1)">x</a></svg>"#),
+            "<svg><a>x</a></svg>"
+        );
+        assert_eq!(
+            clean(
+                r#"<svg><a href="https://example.com">x</a><image href="data:image/png;base64,AA"/></svg>"#
+            ),
+            r#"<svg><a href="https://example.com">x</a><image href="data:image/png;base64,AA"/></svg>"#
+        );
+        assert_eq!(
+            clean(r#"<svg><image href="https://example.com/a.png"/></svg>"#),
+            "<svg><image/></svg>"
+        );
+    }
+
+    #[test]
+    fn drops_animations_of_links_and_handlers() {
+        assert_eq!(
+            clean(r#"<svg><a><set attributeName="href" to="javascript:alert(1)"/>x</a></svg>"#),
+            "<svg><a>x</a></svg>"
+        );
+        assert_eq!(
+            clean(
+                r#"<svg><a><animate attributeName="xlink:HREF" values="javascript:alert(1)"></animate></a></svg>"#
+            ),
+            "<svg><a></a></svg>"
+        );
+        assert_eq!(
+            clean(r#"<svg><a><SET attributeName=" href " to="javascript:alert(1)"/></a></svg>"#),
+            "<svg><a></a></svg>"
+        );
+        assert_eq!(
+            clean(r#"<svg><set attributeName="onmouseover" to="alert(1)"/></svg>"#),
+            "<svg></svg>"
+        );
+        assert_eq!(
+            clean(r#"<svg><set attributeName="fill" to="red"/></svg>"#),
+            r#"<svg><set attributeName="fill" to="red"/></svg>"#
+        );
+    }
+
+    #[test]
+    fn cdata_and_comments() {
+        assert_eq!(
+            clean("<svg><style><![CDATA[a{fill:red}]]></style><!-- <script>x</script> --></svg>"),
+            "<svg><style><![CDATA[a{fill:red}]]></style></svg>"
+        );
+        assert_eq!(
+            clean("<svg><script><![CDATA[</script><script>alert(1)]]></script></svg>"),
+            "<svg></svg>"
+        );
+        assert_eq!(clean("<svg><![CDATA[<script>"), "<svg>");
+        assert_eq!(
+            clean(r#"<!DOCTYPE svg [<!ENTITY x "<script>alert(1)</script>">]><svg/>"#),
+            "<svg/>"
+        );
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert_eq!(clean(r##"<svg><a href="#x"##), "<svg>");
+        assert_eq!(clean("<svg><a onload=alert(1)>x</a></svg>"), "<svg>");
+        assert_eq!(clean(r#"<svg><a title="<script>">x</a></svg>"#), "<svg>");
+        assert_eq!(clean("<svg><!-- x"), "<svg>");
+        assert_eq!(sanitize(&[0xff, 0xfe]), EMPTY_SVG.as_bytes());
+    }
+}
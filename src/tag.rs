@@ -0,0 +1,79 @@
+// tags users put on their files with /tag. a tag belongs to the uploader of the file, so the
+// tags of a user are the same in /tag, their listing and the api
+
+use std::collections::{BTreeMap, HashMap};
+
+pub const MAX_TAG_LENGTH: usize = 32;
+pub const MAX_TAGS_PER_FILE: usize = 20;
+
+// `#Cat` is `cat`, None for names with anything but letters, digits, `-` and `_`
+pub fn normalize(name: &str) -> Option<String> {
+    let name = name.trim_start_matches('#').to_lowercase();
+    if name.is_empty() || name.chars().count() > MAX_TAG_LENGTH {
+        return None;
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return None;
+    }
+    Some(name)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct TagChanges {
+    pub add: Vec<String>,
+    pub remove: Vec<String>,
+}
+
+// `/tag cat -dog`, a leading `-` removes the tag. the first invalid name is the error
+pub fn parse_changes(args: &str) -> Result<TagChanges, String> {
+    let mut changes = TagChanges::default();
+    for v in args.split([',', ' ']).filter(|v| !v.is_empty()) {
+        let (list, name) = match v.strip_prefix('-') {
+            Some(name) => (&mut changes.remove, name),
+            None => (&mut changes.add, v),
+        };
+        let name = normalize(name).ok_or_else(|| v.to_string())?;
+        if !list.contains(&name) {
+            list.push(name);
+        }
+    }
+    Ok(changes)
+}
+
+// the tags of a file after the changes, in order
+pub fn apply(tags: &[String], changes: &TagChanges) -> Vec<String> {
+    let mut tags = tags
+        .iter()
+        .chain(&changes.add)
+        .filter(|v| !changes.remove.contains(v))
+        .cloned()
+        .collect::<Vec<_>>();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+// `cat #tag`: the text and the first valid `#tag` of an inline query
+pub fn split_query(query: &str) -> (String, String) {
+    let mut tag = String::new();
+    let mut words = vec![];
+    for word in query.split_whitespace() {
+        match (word.starts_with('#'), normalize(word)) {
+            (true, Some(v)) if tag.is_empty() => tag = v,
+            _ => words.push(word),
+        }
+    }
+    (words.join(" "), tag)
+}
+
+// how many of the files have each tag, by name
+pub fn counts(tags: &HashMap<String, Vec<String>>) -> Vec<(String, usize)> {
+    let mut counts = BTreeMap::new();
+    for name in tags.values().flatten() {
+        *counts.entry(name.clone()).or_insert(0) += 1;
+    }
+    counts.into_iter().collect()
+}
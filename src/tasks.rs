@@ -0,0 +1,129 @@
+use log::{info, warn};
+use serde::Serialize;
+use worker::Date;
+
+use crate::d1::PendingTask;
+use crate::error::Error;
+use crate::filestore::FileStore;
+use crate::handler::{
+    CLEANED_METADATA, DEFAULT_EDGE_CACHE_TTL, DownloadResult, cleaned, cleaning,
+    delete_stored_copies, download, r2_metadata,
+};
+use crate::storage::Storage;
+use crate::tg::TgBot;
+
+pub const R2_PUT: &str = "r2_put";
+
+// after this many failed retries a task is left in the table for inspection
+const MAX_ATTEMPTS: u32 = 10;
+const BATCH_SIZE: u32 = 20;
+// deleted files removed per run, each one costs a few storage and d1 requests
+const PURGE_BATCH_SIZE: u32 = 50;
+
+#[derive(Serialize, Debug, Default)]
+pub struct RetryReport {
+    pub succeeded: u32,
+    pub failed: u32,
+}
+
+pub async fn retry_pending<S: Storage, D: FileStore>(
+    bot: &TgBot<D>,
+    storage: Option<&S>,
+) -> Result<RetryReport, Error> {
+    let mut report = RetryReport::default();
+
+    for task in bot.d1.pending_tasks(MAX_ATTEMPTS, BATCH_SIZE).await? {
+        match run(bot, storage, &task).await {
+            Ok(_) => {
+                info!("task {} {} succeeded", task.kind, task.key);
+                bot.d1.delete_task(task.id).await?;
+                report.succeeded += 1;
+            }
+            Err(e) => {
+                warn!("task {} {} failed again: {}", task.kind, task.key, e);
+                bot.d1.fail_task(task.id, &e.to_string()).await?;
+                report.failed += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+// removes deleted files whose grace period is over, with their stored copies.
+// the edge cache isn't purged, the links answer 404 from now on and the copies expire
+pub async fn purge_deleted<S: Storage, D: FileStore>(
+    bot: &TgBot<D>,
+    storage: Option<&S>,
+    grace_days: u32,
+) -> Result<u32, Error> {
+    let now = (Date::now().as_millis() / 1000) as i64;
+    let before = now - grace_days as i64 * 86400;
+
+    let mut purged = 0;
+    for file in bot.d1.expired_files(before, PURGE_BATCH_SIZE).await? {
+        if let Some(storage) = storage {
+            delete_stored_copies(storage, &file).await?;
+        }
+        bot.d1.purge_file(&file.file_unique_id).await?;
+        bot.uncache(&file.file_unique_id).await;
+        purged += 1;
+    }
+
+    if purged > 0 {
+        info!("purged {} deleted files", purged);
+    }
+    Ok(purged)
+}
+
+async fn run<S: Storage, D: FileStore>(
+    bot: &TgBot<D>,
+    storage: Option<&S>,
+    task: &PendingTask,
+) -> Result<(), Error> {
+    match task.kind.as_str() {
+        R2_PUT => {
+            let storage = match storage {
+                Some(v) => v,
+                None => return Err(Error::Storage("no storage is bound".into())),
+            };
+
+            // stored by a download since the task was saved, or not wanted anymore
+            if storage.head(&task.key).await? {
+                return Ok(());
+            }
+            if let Some(f) = bot.d1.find(&task.file_id).await?
+                && f.is_deleted()
+            {
+                return Ok(());
+            }
+
+            let (url, _) = bot.get_file_url(&task.file_id, false).await?;
+            let stream = match download(url, DEFAULT_EDGE_CACHE_TTL).await? {
+                DownloadResult::Stream(v) => v,
+                DownloadResult::NotFound => {
+                    let (url, _) = bot.get_file_url(&task.file_id, true).await?;
+                    match download(url, DEFAULT_EDGE_CACHE_TTL).await? {
+                        DownloadResult::Stream(v) => v,
+                        DownloadResult::NotFound => {
+                            return Err(Error::NotFound("file not found".into()));
+                        }
+                    }
+                }
+            };
+
+            let file = bot.d1.find(&task.file_id).await?;
+            let ext = task.key.rsplit('.').next().unwrap_or_default();
+            let stream = cleaned(bot, stream, file.as_ref(), ext)?;
+            let (http_metadata, mut custom_metadata) = r2_metadata(file.as_ref(), ext);
+            custom_metadata.insert(
+                CLEANED_METADATA.to_string(),
+                cleaning(bot, file.as_ref(), ext).to_string(),
+            );
+            storage
+                .put(&task.key, stream, http_metadata, custom_metadata)
+                .await
+        }
+        kind => Err(Error::Internal(format!("unknown task {}", kind))),
+    }
+}
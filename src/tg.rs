@@ -1,122 +1,2883 @@
 use frankenstein::AsyncTelegramApi;
 use frankenstein::client_reqwest::Bot;
-use frankenstein::methods::{GetFileParams, SendMessageParams, SetWebhookParams};
-use frankenstein::types::{ChatId, LinkPreviewOptions, ReplyParameters};
-use frankenstein::updates::UpdateContent;
-use log::info;
+use frankenstein::inline_mode::{
+    InlineQueryResult, InlineQueryResultCachedDocument, InlineQueryResultCachedPhoto,
+    InlineQueryResultCachedVideo,
+};
+use frankenstein::methods::{
+    AnswerCallbackQueryParams, AnswerInlineQueryParams, DeleteMessageParams, DeleteWebhookParams,
+    EditMessageCaptionParams, EditMessageTextParams, GetFileParams, GetUpdatesParams,
+    PinChatMessageParams, SendMessageParams, SetMyCommandsParams, SetWebhookParams,
+};
+use frankenstein::reqwest;
+use frankenstein::reqwest::multipart::{Form, Part};
+use frankenstein::types::AllowedUpdate;
+use frankenstein::types::{
+    BotCommand, ChatId, ChatType, InlineKeyboardButton, InlineKeyboardMarkup, LinkPreviewOptions,
+    Message, MessageEntity, ReplyMarkup, ReplyParameters,
+};
+use frankenstein::updates::{UpdateContent, WebhookInfo};
+use futures_util::StreamExt;
+use futures_util::future::try_join_all;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use worker::kv::KvStore;
+use worker::{Bucket, Cache, Date, Delay, Fetch, Url};
 
-use crate::d1::{D1, File};
+use crate::apikey::{MAX_KEYS_PER_USER, generate, parse_scopes, scope_names};
+use crate::capability::{Capabilities, KV_STORAGE};
+use crate::caption::{Captioner, MAX_ALT_TEXT, clean_alt_text};
+use crate::crypto::{constant_time_eq, random_hex, sha256_hex};
+use crate::d1::{
+    ALIAS_SLUG, BLOCKED_MODERATION, BLOCKED_REPORT, D1, File, REPORT_DISMISSED, REPORT_TAKEN_DOWN,
+    path_prefix,
+};
 use crate::error::Error;
+use crate::exif;
+use crate::filestore::FileStore;
+use crate::filetype::FileTypes;
+use crate::handler::{
+    MAX_BATCH_DELETE, delete_files, delete_stored_copies, purge_copies, stored_copies,
+};
+use crate::i18n::{Lang, Msg, tr};
+use crate::listing::{date, html_escape, human_size};
+use crate::moderation::{Moderator, Verdict};
+use crate::password::hash_password;
+use crate::security::SvgPolicy;
+use crate::state::DeleteStatus;
+use crate::storage::Backend;
+use crate::tag::{MAX_TAG_LENGTH, MAX_TAGS_PER_FILE, apply, parse_changes, split_query};
+use crate::upload::{MAX_DOWNLOAD_SIZE, MAX_UPLOAD_SIZE, UploadMeta, sanitize_file_name};
+
+const DEFAULT_API_BASE: &str = "https://api.telegram.org";
+const CONSENT_CALLBACK: &str = "consent";
+const FORGET_ME_CALLBACK: &str = "forgetme";
+const WEBHOOK_URL_SETTING: &str = "webhook_url";
+// sent by telegram in X-Telegram-Bot-Api-Secret-Token, updates without it are forged
+const WEBHOOK_SECRET_SETTING: &str = "webhook_secret";
+// the secret before the last rotation and when it was rotated, in unix millis
+const WEBHOOK_SECRET_PREVIOUS_SETTING: &str = "webhook_secret_previous";
+const WEBHOOK_SECRET_ROTATED_SETTING: &str = "webhook_secret_rotated";
+// updates telegram sent before a rotation still carry the previous secret
+const WEBHOOK_SECRET_GRACE_MS: u64 = 10 * 60 * 1000;
+const BOT_USERNAME_SETTING: &str = "bot_username";
+// delivery errors younger than this with pending updates count as a broken webhook
+const WEBHOOK_ERROR_WINDOW_SECS: u64 = 60 * 60;
+// telegram rejects longer message texts, counted in utf-16 code units
+const MAX_MESSAGE_LENGTH: usize = 4096;
+const MAX_CAPTION_LENGTH: usize = 1024;
+const SETUP_CALLBACK_PREFIX: &str = "setup:";
+// followed by approve: or block: and the file_unique_id
+const MODERATION_CALLBACK_PREFIX: &str = "moderation:";
+const REPORT_CALLBACK_PREFIX: &str = "report:";
+const SETUP_STEPS: [(&str, Msg); 4] = [
+    ("db", Msg::SetupStepDb),
+    ("webhook", Msg::SetupStepWebhook),
+    ("r2", Msg::SetupStepR2),
+    ("channel", Msg::SetupStepChannel),
+];
+
+// how links of channel posts are published
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum ChannelReplyMode {
+    // a reply to the post
+    #[default]
+    Reply,
+    // appended to the caption of the post
+    Caption,
+    // a reply to the copy in the linked discussion group, shown as a comment
+    Comment,
+}
+
+impl ChannelReplyMode {
+    pub fn parse(mode: &str) -> Self {
+        match mode {
+            "caption" => ChannelReplyMode::Caption,
+            "comment" => ChannelReplyMode::Comment,
+            _ => ChannelReplyMode::Reply,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Command {
+    Start,
+    Help,
+    Preview,
+    Protect,
+    Unprotect,
+    Rename,
+    Alias,
+    Alt,
+    Info,
+    Fetch,
+    Delete,
+    Undelete,
+    Ban,
+    Unban,
+    ForgetMe,
+    Album,
+    Tag,
+    Namespace,
+    Location,
+    Token,
+    Setup,
+}
+
+impl Command {
+    // commands that need to know who uploaded a file or who the user is
+    fn per_user(&self) -> bool {
+        !matches!(
+            self,
+            Command::Start
+                | Command::Help
+                | Command::Preview
+                | Command::Setup
+                | Command::Ban
+                | Command::Unban
+        )
+    }
+}
+
+pub struct CommandInfo {
+    command: Command,
+    pub name: &'static str,
+    pub description: Msg,
+}
+
+// dispatch table of `handle_command`, also shown in the telegram client's autocompletion
+pub const COMMANDS: [CommandInfo; 21] = [
+    CommandInfo {
+        command: Command::Start,
+        name: "start",
+        description: Msg::CmdHelp,
+    },
+    CommandInfo {
+        command: Command::Help,
+        name: "help",
+        description: Msg::CmdHelp,
+    },
+    CommandInfo {
+        command: Command::Preview,
+        name: "preview",
+        description: Msg::CmdPreview,
+    },
+    CommandInfo {
+        command: Command::Protect,
+        name: "protect",
+        description: Msg::CmdProtect,
+    },
+    CommandInfo {
+        command: Command::Unprotect,
+        name: "unprotect",
+        description: Msg::CmdUnprotect,
+    },
+    CommandInfo {
+        command: Command::Rename,
+        name: "rename",
+        description: Msg::CmdRename,
+    },
+    CommandInfo {
+        command: Command::Alias,
+        name: "alias",
+        description: Msg::CmdAlias,
+    },
+    CommandInfo {
+        command: Command::Alt,
+        name: "alt",
+        description: Msg::CmdAlt,
+    },
+    CommandInfo {
+        command: Command::Info,
+        name: "info",
+        description: Msg::CmdInfo,
+    },
+    CommandInfo {
+        command: Command::Fetch,
+        name: "fetch",
+        description: Msg::CmdFetch,
+    },
+    CommandInfo {
+        command: Command::Delete,
+        name: "delete",
+        description: Msg::CmdDelete,
+    },
+    CommandInfo {
+        command: Command::Undelete,
+        name: "undelete",
+        description: Msg::CmdUndelete,
+    },
+    CommandInfo {
+        command: Command::Ban,
+        name: "ban",
+        description: Msg::CmdBan,
+    },
+    CommandInfo {
+        command: Command::Unban,
+        name: "unban",
+        description: Msg::CmdUnban,
+    },
+    CommandInfo {
+        command: Command::ForgetMe,
+        name: "forgetme",
+        description: Msg::CmdForgetMe,
+    },
+    CommandInfo {
+        command: Command::Album,
+        name: "album",
+        description: Msg::CmdAlbum,
+    },
+    CommandInfo {
+        command: Command::Tag,
+        name: "tag",
+        description: Msg::CmdTag,
+    },
+    CommandInfo {
+        command: Command::Namespace,
+        name: "namespace",
+        description: Msg::CmdNamespace,
+    },
+    CommandInfo {
+        command: Command::Location,
+        name: "location",
+        description: Msg::CmdLocation,
+    },
+    CommandInfo {
+        command: Command::Token,
+        name: "token",
+        description: Msg::CmdToken,
+    },
+    CommandInfo {
+        command: Command::Setup,
+        name: "setup",
+        description: Msg::CmdSetup,
+    },
+];
+
+// the updates `handle` understands, telegram doesn't send the others
+const ALLOWED_UPDATES: [AllowedUpdate; 5] = [
+    AllowedUpdate::Message,
+    AllowedUpdate::EditedMessage,
+    AllowedUpdate::ChannelPost,
+    AllowedUpdate::CallbackQuery,
+    AllowedUpdate::InlineQuery,
+];
+
+// telegram shows at most 50 inline results
+const INLINE_RESULTS: u32 = 20;
+
+// getUpdates batches per drain, bounded so a drain fits in one request
+const DRAIN_MAX_BATCHES: u32 = 10;
+const DRAIN_BATCH_SIZE: u32 = 100;
+
+// placeholders are url, unique_url, markdown, html, filename and size
+const DEFAULT_REPLY_TEMPLATE: &str = "{url}\n{unique_url}\n";
+
+// telegram keeps file paths valid for at least an hour
+const FILE_PATH_MAX_AGE_SECS: u64 = 60 * 60;
+
+// at most one error alert to the maintainer per interval
+const ALERT_INTERVAL_SECS: u64 = 10 * 60;
+const LAST_ALERT_SETTING: &str = "last_alert";
+const ALERT_MAX_ERROR_LENGTH: usize = 1000;
+// throttles alerts of this isolate when D1 is the thing that fails
+static LAST_ALERT: AtomicU64 = AtomicU64::new(0);
+
+// links listed by the pinned summary message of a chat
+const MAX_SUMMARY_SIZE: usize = 50;
+const SUMMARY_SETTING_PREFIX: &str = "summary:";
+
+// rows of downloaded files cached in kv in front of d1. ids, short ids and aliases point to
+// the file_unique_id, so a change only drops the row. file paths stay valid for at least an hour
+const FILE_CACHE_TTL_SECS: u64 = 60 * 60;
+const FILE_CACHE_PREFIX: &str = "file:";
+const FILE_ID_CACHE_PREFIX: &str = "id:";
+
+// the pinned summary message of a chat, stored as a setting
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Summary {
+    message_id: i32,
+    // newest first
+    links: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct DrainReport {
+    pub handled: u32,
+    pub failed: u32,
+    // updates left for the webhook because the batch limit was reached
+    pub more_pending: bool,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
 
 #[derive(Clone)]
-pub struct TgBot {
+pub struct TgBot<D: FileStore = D1> {
     pub bot: Bot,
-    pub d1: D1,
+    pub d1: D,
     pub matainer: i64,
     pub bot_token: String,
+    // chat that receives files uploaded over the http api
+    pub storage_chat: i64,
+    // users must accept the terms before their uploads are accepted
+    pub require_consent: bool,
+    pub r2: Option<Bucket>,
+    // bot api server, a local server lifts the 20 MB download limit
+    pub api_base: String,
+    pub capabilities: Capabilities,
+    pub channel_reply_mode: ChannelReplyMode,
+    // latest links kept in a pinned message of groups and channels, 0 disables it
+    pub summary_size: usize,
+    // reply to uploads, rendered once per file
+    pub reply_template: String,
+    // language of chats without a sender and of senders without a catalog
+    pub lang: Lang,
+    // caches file paths of downloads in front of d1
+    pub kv: Option<KvStore>,
+    // links of uploads use short ids instead of telegram file ids
+    pub short_ids: bool,
+    // jpeg documents are served without EXIF, XMP and IPTC
+    pub strip_exif: bool,
+    // what svg documents are served as
+    pub svg_policy: SvgPolicy,
+    // types accepted on upload and served
+    pub file_types: FileTypes,
+    // larger uploads are refused, at most MAX_UPLOAD_SIZE
+    pub max_file_size: u64,
+    // checks image uploads, None without MODERATION
+    moderator: Option<Arc<Moderator>>,
+    // writes alt text of image uploads, None without ALT_TEXT
+    captioner: Option<Arc<Captioner>>,
+    // copies dropped when a reported file is taken down
+    storage: Option<Backend>,
+    // days deleted files can be restored with /undelete
+    pub delete_grace_days: u32,
+    // uploads are saved without the user, commands that need one are left to the maintainer
+    pub privacy_mode: bool,
 }
 
-impl TgBot {
-    pub fn new(d1: D1, matainer: i64, bot_token: String) -> TgBot {
+impl<D: FileStore> TgBot<D> {
+    pub fn new(d1: D, matainer: i64, bot_token: String) -> Self {
         TgBot {
             bot: Bot::new(&bot_token),
             d1,
             matainer,
             bot_token,
+            storage_chat: matainer,
+            require_consent: false,
+            r2: None,
+            api_base: DEFAULT_API_BASE.to_string(),
+            capabilities: Capabilities::default(),
+            channel_reply_mode: ChannelReplyMode::default(),
+            summary_size: 0,
+            reply_template: DEFAULT_REPLY_TEMPLATE.to_string(),
+            lang: Lang::default(),
+            kv: None,
+            short_ids: false,
+            strip_exif: false,
+            svg_policy: SvgPolicy::default(),
+            file_types: FileTypes::default(),
+            max_file_size: MAX_UPLOAD_SIZE,
+            moderator: None,
+            captioner: None,
+            storage: None,
+            delete_grace_days: 0,
+            privacy_mode: false,
+        }
+    }
+
+    pub fn with_lang(mut self, lang: Lang) -> Self {
+        self.lang = lang;
+        self
+    }
+
+    pub fn with_reply_template(mut self, reply_template: String) -> Self {
+        if !reply_template.is_empty() {
+            // dashboard variables can't contain newlines
+            self.reply_template = reply_template.replace("\\n", "\n");
+        }
+        self
+    }
+
+    pub fn with_summary_size(mut self, summary_size: usize) -> Self {
+        self.summary_size = summary_size.min(MAX_SUMMARY_SIZE);
+        self
+    }
+
+    pub fn with_channel_reply_mode(mut self, channel_reply_mode: ChannelReplyMode) -> Self {
+        self.channel_reply_mode = channel_reply_mode;
+        self
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn with_api_base(mut self, api_base: String) -> Self {
+        let api_base = api_base.trim_end_matches('/');
+        if !api_base.is_empty() {
+            self.bot = Bot::new_url(format!("{}/bot{}", api_base, self.bot_token));
+            self.api_base = api_base.to_string();
+        }
+        self
+    }
+
+    pub fn with_r2(mut self, r2: Option<Bucket>) -> Self {
+        self.r2 = r2;
+        self
+    }
+
+    pub fn with_short_ids(mut self, short_ids: bool) -> Self {
+        self.short_ids = short_ids;
+        self
+    }
+
+    pub fn with_strip_exif(mut self, strip_exif: bool) -> Self {
+        self.strip_exif = strip_exif;
+        self
+    }
+
+    pub fn with_svg_policy(mut self, svg_policy: SvgPolicy) -> Self {
+        self.svg_policy = svg_policy;
+        self
+    }
+
+    pub fn with_file_types(mut self, file_types: FileTypes) -> Self {
+        self.file_types = file_types;
+        self
+    }
+
+    // 0 keeps the limit of telegram downloads
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        if max_file_size > 0 {
+            self.max_file_size = max_file_size.min(MAX_UPLOAD_SIZE);
+        }
+        self
+    }
+
+    pub fn with_moderator(mut self, moderator: Option<Moderator>) -> Self {
+        self.moderator = moderator.map(Arc::new);
+        self
+    }
+
+    pub fn with_captioner(mut self, captioner: Option<Captioner>) -> Self {
+        self.captioner = captioner.map(Arc::new);
+        self
+    }
+
+    pub fn with_storage(mut self, storage: Option<Backend>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    pub fn with_delete_grace_days(mut self, delete_grace_days: u32) -> Self {
+        self.delete_grace_days = delete_grace_days;
+        self
+    }
+
+    pub fn with_privacy_mode(mut self, privacy_mode: bool) -> Self {
+        self.privacy_mode = privacy_mode;
+        self
+    }
+
+    pub fn with_kv(mut self, kv: Option<KvStore>) -> Self {
+        self.kv = kv;
+        self
+    }
+
+    pub fn with_require_consent(mut self, require_consent: bool) -> Self {
+        self.require_consent = require_consent;
+        self
+    }
+
+    pub fn with_storage_chat(mut self, storage_chat: i64) -> Self {
+        if storage_chat != 0 {
+            self.storage_chat = storage_chat;
         }
+        self
     }
 
     pub fn matainer_id(&self) -> i64 {
         self.matainer
     }
 
-    pub async fn set_webhook(&self, url: &str) -> Result<(), Error> {
+    pub async fn send_message(&self, params: &SendMessageParams) -> Result<Message, Error> {
+        Ok(retry_unsent(|| self.bot.send_message(params)).await?.result)
+    }
+
+    pub async fn get_file(&self, params: &GetFileParams) -> Result<Option<String>, Error> {
+        Ok(retry(|| self.bot.get_file(params)).await?.result.file_path)
+    }
+
+    async fn delete_message(&self, params: &DeleteMessageParams) -> Result<(), Error> {
+        retry(|| self.bot.delete_message(params)).await?;
+        Ok(())
+    }
+
+    async fn answer_callback_query(&self, params: &AnswerCallbackQueryParams) -> Result<(), Error> {
+        retry(|| self.bot.answer_callback_query(params)).await?;
+        Ok(())
+    }
+
+    // an empty path means the file is over the bot api download limit and can't be hosted
+    async fn file_path(&self, file_id: String) -> Result<String, Error> {
+        match self.get_file(&GetFileParams { file_id }).await {
+            Ok(Some(p)) => Ok(p),
+            Ok(None) => Err(Error::TelegramApi("file path not found".to_string())),
+            Err(Error::TelegramApi(e)) if e.contains("file is too big") => Ok(String::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // re-sent media keeps its file_unique_id, a path saved recently spares the getFile round-trip
+    async fn recent_file_path(
+        &self,
+        file_id: String,
+        file_unique_id: String,
+    ) -> Result<String, Error> {
+        let now = Date::now().as_millis() / 1000;
+        if let Ok(Some(f)) = self.d1.find(&file_unique_id).await
+            && !f.file_path.is_empty()
+            && now < f.update_time.max(0) as u64 + FILE_PATH_MAX_AGE_SECS
+        {
+            info!("reuse file path of {}", file_unique_id);
+            return Ok(f.file_path);
+        }
+
+        self.file_path(file_id).await
+    }
+
+    pub async fn set_webhook(&self, url: &str, drop_pending_updates: bool) -> Result<(), Error> {
         info!("Registering webhook: {}", url);
 
-        self.bot
-            .set_webhook(&SetWebhookParams::builder().url(url).build())
-            .await?;
+        let params = SetWebhookParams::builder()
+            .url(url)
+            .allowed_updates(ALLOWED_UPDATES.to_vec())
+            .drop_pending_updates(drop_pending_updates)
+            .secret_token(self.webhook_secret().await?)
+            .build();
+        retry(|| self.bot.set_webhook(&params)).await?;
+
+        self.d1.set_setting(WEBHOOK_URL_SETTING, url).await?;
+
+        if let Err(e) = self.set_commands().await {
+            warn!("set commands failed: {}", e);
+        }
+
+        self.send_message(
+            &SendMessageParams::builder()
+                .chat_id(ChatId::Integer(self.matainer))
+                .text(format!("register webhook to {} successful", url))
+                .link_preview_options(LinkPreviewOptions::DISABLED)
+                .build(),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    // created on the first registration and kept, so a re-registration doesn't reject updates in flight
+    async fn webhook_secret(&self) -> Result<String, Error> {
+        let secret = self.d1.get_setting(WEBHOOK_SECRET_SETTING).await?;
+        if !secret.is_empty() {
+            return Ok(secret);
+        }
+
+        let secret = random_hex(32)?;
+        self.d1.set_setting(WEBHOOK_SECRET_SETTING, &secret).await?;
+        Ok(secret)
+    }
+
+    // whether an update came from telegram, false until the webhook was registered with a secret
+    pub async fn is_webhook_secret(&self, token: &str) -> Result<bool, Error> {
+        let secret = self.d1.get_setting(WEBHOOK_SECRET_SETTING).await?;
+        if !secret.is_empty() && constant_time_eq(token.as_bytes(), secret.as_bytes()) {
+            return Ok(true);
+        }
+
+        let rotated = self.webhook_secret_rotated().await?;
+        if Date::now().as_millis().saturating_sub(rotated) > WEBHOOK_SECRET_GRACE_MS {
+            return Ok(false);
+        }
+        let previous = self.d1.get_setting(WEBHOOK_SECRET_PREVIOUS_SETTING).await?;
+        Ok(!previous.is_empty() && constant_time_eq(token.as_bytes(), previous.as_bytes()))
+    }
+
+    // registers the webhook again with a new secret, the previous one is accepted for a while
+    pub async fn rotate_webhook_secret(&self) -> Result<(), Error> {
+        let url = self.d1.get_setting(WEBHOOK_URL_SETTING).await?;
+        if url.is_empty() {
+            return Err(Error::BadRequest("no webhook is registered".into()));
+        }
+
+        let secret = self.d1.get_setting(WEBHOOK_SECRET_SETTING).await?;
+        self.d1
+            .set_setting(WEBHOOK_SECRET_PREVIOUS_SETTING, &secret)
+            .await?;
+        self.d1
+            .set_setting(
+                WEBHOOK_SECRET_ROTATED_SETTING,
+                &Date::now().as_millis().to_string(),
+            )
+            .await?;
+        // set_webhook creates the new one
+        self.d1.set_setting(WEBHOOK_SECRET_SETTING, "").await?;
+        self.set_webhook(&url, false).await
+    }
+
+    // unix millis of the last rotation, 0 if it never was
+    pub async fn webhook_secret_rotated(&self) -> Result<u64, Error> {
+        Ok(self
+            .d1
+            .get_setting(WEBHOOK_SECRET_ROTATED_SETTING)
+            .await?
+            .parse::<u64>()
+            .unwrap_or_default())
+    }
+
+    // the default list in the deployment's language, and one per catalog for clients using it
+    pub async fn set_commands(&self) -> Result<(), Error> {
+        let commands = |lang: Lang| {
+            COMMANDS
+                .iter()
+                .map(|c| {
+                    BotCommand::builder()
+                        .command(c.name)
+                        .description(tr(lang, c.description))
+                        .build()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let params = SetMyCommandsParams::builder()
+            .commands(commands(self.lang))
+            .build();
+        retry(|| self.bot.set_my_commands(&params)).await?;
+
+        for lang in Lang::ALL {
+            let params = SetMyCommandsParams::builder()
+                .commands(commands(lang))
+                .language_code(lang.code())
+                .build();
+            retry(|| self.bot.set_my_commands(&params)).await?;
+        }
+        Ok(())
+    }
+
+    // tells the maintainer about unexpected errors, so breakage is noticed without tailing logs
+    pub async fn alert(&self, source: &str, request_id: &str, error: &str) {
+        if self.matainer == 0 {
+            return;
+        }
+
+        let now = Date::now().as_millis() / 1000;
+        if now < LAST_ALERT.load(Ordering::Relaxed) + ALERT_INTERVAL_SECS {
+            return;
+        }
+        LAST_ALERT.store(now, Ordering::Relaxed);
+
+        // shared by all isolates, skipped when D1 is down
+        if let Ok(last) = self.d1.get_setting(LAST_ALERT_SETTING).await
+            && now < last.parse::<u64>().unwrap_or_default() + ALERT_INTERVAL_SECS
+        {
+            return;
+        }
+        if let Err(e) = self
+            .d1
+            .set_setting(LAST_ALERT_SETTING, &now.to_string())
+            .await
+        {
+            warn!("Save last alert time failed: {}", e);
+        }
+
+        // long errors like html error pages of the bot api are cut
+        let error = error
+            .chars()
+            .take(ALERT_MAX_ERROR_LENGTH)
+            .collect::<String>();
+        if let Err(e) = self
+            .send_message(
+                &SendMessageParams::builder()
+                    .chat_id(ChatId::Integer(self.matainer))
+                    .text(format!(
+                        "{} failed, request id {}: {}",
+                        source, request_id, error
+                    ))
+                    .link_preview_options(LinkPreviewOptions::DISABLED)
+                    .build(),
+            )
+            .await
+        {
+            warn!("Send alert failed: {}", e);
+        }
+    }
+
+    // the sender's language, telegram clients send it with every update
+    fn lang_of(&self, code: Option<&str>) -> Lang {
+        Lang::select(code, self.lang)
+    }
+
+    pub async fn delete_webhook(&self, drop_pending_updates: bool) -> Result<(), Error> {
+        info!(
+            "Deleting webhook, drop pending updates: {}",
+            drop_pending_updates
+        );
+
+        let params = DeleteWebhookParams::builder()
+            .drop_pending_updates(drop_pending_updates)
+            .build();
+        retry(|| self.bot.delete_webhook(&params)).await?;
+
+        // otherwise the scheduled check registers it again
+        self.d1.set_setting(WEBHOOK_URL_SETTING, "").await?;
+
+        self.send_message(
+            &SendMessageParams::builder()
+                .chat_id(ChatId::Integer(self.matainer))
+                .text("webhook deleted")
+                .build(),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    // process updates that piled up while the webhook was broken, then register it again
+    pub async fn drain_updates(&self, host: &str) -> Result<DrainReport, Error> {
+        // getUpdates is refused while a webhook is set, pending updates are kept
+        let params = DeleteWebhookParams::builder().build();
+        retry(|| self.bot.delete_webhook(&params)).await?;
+
+        let mut report = DrainReport::default();
+        let result = self.drain(host, &mut report).await;
+
+        self.set_webhook(&format!("https://{}/tgbot", host), false)
+            .await?;
+        result?;
+
+        Ok(report)
+    }
+
+    async fn drain(&self, host: &str, report: &mut DrainReport) -> Result<(), Error> {
+        let mut offset = 0;
+
+        for _ in 0..DRAIN_MAX_BATCHES {
+            let params = GetUpdatesParams::builder()
+                .offset(offset)
+                .limit(DRAIN_BATCH_SIZE)
+                .timeout(0)
+                .build();
+            let updates = retry(|| self.bot.get_updates(&params)).await?.result;
+
+            if updates.is_empty() {
+                return Ok(());
+            }
+
+            for update in updates {
+                offset = i64::from(update.update_id) + 1;
+                match self.handle(host, update).await {
+                    Ok(_) => report.handled += 1,
+                    Err(e) => {
+                        warn!("drained update was not handled: {}", e);
+                        report.failed += 1;
+                    }
+                }
+            }
+        }
+
+        // confirms the last batch, what it returns stays pending for the webhook
+        let params = GetUpdatesParams::builder()
+            .offset(offset)
+            .limit(1)
+            .timeout(0)
+            .build();
+        report.more_pending = !retry(|| self.bot.get_updates(&params))
+            .await?
+            .result
+            .is_empty();
+
+        Ok(())
+    }
+
+    pub async fn webhook_info(&self) -> Result<WebhookInfo, Error> {
+        Ok(retry(|| self.bot.get_webhook_info()).await?.result)
+    }
+
+    pub async fn get_me(&self) -> Result<(), Error> {
+        retry(|| self.bot.get_me()).await?;
+        Ok(())
+    }
+
+    // @username of the bot without the @, asked once and kept in d1
+    pub async fn username(&self) -> Result<String, Error> {
+        let username = self.d1.get_setting(BOT_USERNAME_SETTING).await?;
+        if !username.is_empty() {
+            return Ok(username);
+        }
+
+        let username = retry(|| self.bot.get_me())
+            .await?
+            .result
+            .username
+            .unwrap_or_default();
+        if !username.is_empty() {
+            self.d1.set_setting(BOT_USERNAME_SETTING, &username).await?;
+        }
+        Ok(username)
+    }
+
+    // re-register the webhook when it points somewhere else or keeps failing
+    pub async fn check_webhook(&self) -> Result<(), Error> {
+        let expected = self.d1.get_setting(WEBHOOK_URL_SETTING).await?;
+        if expected.is_empty() {
+            return Ok(());
+        }
+
+        let info = retry(|| self.bot.get_webhook_info()).await?.result;
+        let now = Date::now().as_millis() / 1000;
+
+        let reason = if info.url != expected {
+            format!("webhook points to {:?}", info.url)
+        } else if self
+            .d1
+            .get_setting(WEBHOOK_SECRET_SETTING)
+            .await?
+            .is_empty()
+        {
+            // registered before updates were checked, every update is rejected until then
+            "webhook has no secret token".to_string()
+        } else if info.pending_update_count > 0
+            && let Some(date) = info.last_error_date
+            && now.saturating_sub(date) < WEBHOOK_ERROR_WINDOW_SECS
+        {
+            format!(
+                "webhook delivery is failing: {}",
+                info.last_error_message.unwrap_or_default()
+            )
+        } else {
+            return Ok(());
+        };
+
+        warn!("{}, re-registering {}", reason, expected);
+
+        self.send_message(
+            &SendMessageParams::builder()
+                .chat_id(ChatId::Integer(self.matainer))
+                .text(format!("{}, re-registering {}", reason, expected))
+                .link_preview_options(LinkPreviewOptions::DISABLED)
+                .build(),
+        )
+        .await?;
+
+        self.set_webhook(&expected, false).await
+    }
+
+    // only uploads wait for the terms to be accepted, channel posts have no one to ask.
+    // privacy mode keeps no user ids, so it asks no one
+    async fn needs_consent(&self, user_id: Option<u64>) -> Result<bool, Error> {
+        Ok(match user_id {
+            Some(id) if self.require_consent && !self.privacy_mode => {
+                !self.d1.has_consented(id).await?
+            }
+            _ => false,
+        })
+    }
+
+    async fn request_consent(
+        &self,
+        host: &str,
+        chat_id: i64,
+        msg_id: i32,
+        lang: Lang,
+    ) -> Result<(), Error> {
+        let keyboard = InlineKeyboardMarkup::builder()
+            .inline_keyboard(vec![vec![
+                InlineKeyboardButton::builder()
+                    .text(tr(lang, Msg::ConsentButton))
+                    .callback_data(CONSENT_CALLBACK)
+                    .build(),
+            ]])
+            .build();
+
+        self.send_message(
+            &SendMessageParams::builder()
+                .chat_id(ChatId::Integer(chat_id))
+                .reply_parameters(ReplyParameters::builder().message_id(msg_id).build())
+                .text(trf(
+                    lang,
+                    Msg::ConsentRequest,
+                    &[("terms", format!("https://{}/terms", host))],
+                ))
+                .link_preview_options(LinkPreviewOptions::DISABLED)
+                .reply_markup(ReplyMarkup::InlineKeyboardMarkup(keyboard))
+                .build(),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn reply(&self, chat_id: i64, msg_id: i32, text: String) -> Result<(), Error> {
+        for text in split_message(&text, MAX_MESSAGE_LENGTH) {
+            self.send_message(
+                &SendMessageParams::builder()
+                    .chat_id(ChatId::Integer(chat_id))
+                    .reply_parameters(ReplyParameters::builder().message_id(msg_id).build())
+                    .text(text)
+                    .link_preview_options(LinkPreviewOptions::DISABLED)
+                    .build(),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    // files of the replied message, either the uploaded media itself or the links of the bot reply
+    async fn replied_files(&self, msg: &Message) -> Result<Vec<File>, Error> {
+        let replied = match &msg.reply_to_message {
+            Some(v) => v,
+            None => return Ok(vec![]),
+        };
+
+        let mut ids = vec![];
+        if let Some(doc) = &replied.document {
+            ids.push(doc.file_unique_id.clone());
+        }
+        if let Some(photos) = &replied.photo
+            && let Some(photo) = photos.last()
+        {
+            ids.push(photo.file_unique_id.clone());
+        }
+        if let Some(video) = &replied.video {
+            ids.push(video.file_unique_id.clone());
+        }
+        if let Some(text) = &replied.text {
+            ids.extend(link_ids(text));
+        }
+
+        let mut files: Vec<File> = vec![];
+        for id in ids {
+            if let Some(f) = self.d1.find(&id).await?
+                && !f.is_deleted()
+                && !files.iter().any(|v| v.file_unique_id == f.file_unique_id)
+            {
+                files.push(f);
+            }
+        }
+
+        Ok(files)
+    }
+
+    // uploader of the file or the maintainer. channel posts have no sender, they manage the files
+    // posted in that channel. user id 0 is nobody: channel, anonymized and operator uploads
+    fn can_manage(&self, msg: &Message, file: &File) -> bool {
+        match msg.from.as_ref() {
+            Some(u) => (u.id != 0 && u.id == file.user_id) || u.id as i64 == self.matainer,
+            None => file.user_id == 0 && file.chat_id != 0 && file.chat_id == msg.chat.id,
+        }
+    }
+
+    async fn protect(&self, msg: &Message, password: &str, lang: Lang) -> Result<String, Error> {
+        let files = self.replied_files(msg).await?;
+        if files.is_empty() {
+            return Ok(tr(lang, Msg::ReplyToFile).to_string());
+        }
+
+        let mut count = 0;
+        for f in files.iter().filter(|f| self.can_manage(msg, f)) {
+            let (salt, hash) = if password.is_empty() {
+                (String::new(), String::new())
+            } else {
+                hash_password(password)?
+            };
+            self.d1
+                .save_password(&f.file_unique_id, &hash, &salt)
+                .await?;
+            self.uncache(&f.file_unique_id).await;
+            // a public bucket serves stored copies without asking for the password
+            if !password.is_empty()
+                && let Some(storage) = self.storage.as_ref()
+            {
+                delete_stored_copies(storage, f).await?;
+            }
+            count += 1;
+        }
+
+        if count == 0 {
+            return Ok(tr(lang, Msg::NotYourFiles).to_string());
+        }
+
+        if password.is_empty() {
+            return Ok(trf(lang, Msg::Unprotected, &[("count", count.to_string())]));
+        }
+
+        // don't leave the password in the chat history
+        let _ = self
+            .delete_message(
+                &DeleteMessageParams::builder()
+                    .chat_id(ChatId::Integer(msg.chat.id))
+                    .message_id(msg.message_id)
+                    .build(),
+            )
+            .await;
+
+        Ok(trf(lang, Msg::Protected, &[("count", count.to_string())]))
+    }
+
+    async fn rename(&self, msg: &Message, name: &str, lang: Lang) -> Result<String, Error> {
+        let files = self.replied_files(msg).await?;
+        if files.is_empty() {
+            return Ok(tr(lang, Msg::ReplyToFile).to_string());
+        }
+
+        let name = sanitize_file_name(name);
+        let mut count = 0;
+        for f in files.iter().filter(|f| self.can_manage(msg, f)) {
+            self.d1
+                .save_download_options(&f.file_unique_id, &name, &f.headers())
+                .await?;
+            self.uncache(&f.file_unique_id).await;
+            // stored copies carry the old name, a public bucket serves them as they are
+            if f.download_name != name
+                && let Some(storage) = self.storage.as_ref()
+            {
+                delete_stored_copies(storage, f).await?;
+            }
+            count += 1;
+        }
+
+        if count == 0 {
+            return Ok(tr(lang, Msg::NotYourFiles).to_string());
+        }
+
+        Ok(trf(
+            lang,
+            Msg::Renamed,
+            &[("count", count.to_string()), ("name", name)],
+        ))
+    }
+
+    // tombstones the replied files or the given ids and purges their copies, their links answer 410
+    // until the grace period ends
+    async fn delete(
+        &self,
+        host: &str,
+        msg: &Message,
+        args: &str,
+        lang: Lang,
+    ) -> Result<String, Error> {
+        let mut ids = args
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            ids = self
+                .replied_files(msg)
+                .await?
+                .into_iter()
+                .map(|f| f.file_unique_id)
+                .collect();
+        }
+        if ids.is_empty() {
+            return Ok(tr(lang, Msg::UsageDelete).to_string());
+        }
+        if ids.len() > MAX_BATCH_DELETE {
+            return Ok(trf(
+                lang,
+                Msg::DeleteTooMany,
+                &[("max", MAX_BATCH_DELETE.to_string())],
+            ));
+        }
+
+        let items = delete_files(
+            self,
+            host,
+            &Cache::default(),
+            self.storage.as_ref(),
+            &ids,
+            |f| self.can_manage(msg, f),
+        )
+        .await?;
+        let mut deleted = vec![];
+        let mut skipped = vec![];
+        for item in items {
+            let reason = match item.status {
+                DeleteStatus::Deleted => {
+                    deleted.push(item.file_unique_id);
+                    continue;
+                }
+                DeleteStatus::NotFound => Msg::DeleteNotFound,
+                DeleteStatus::AlreadyDeleted => Msg::DeleteAlreadyDeleted,
+                DeleteStatus::Forbidden => Msg::DeleteForbidden,
+                DeleteStatus::Duplicate => Msg::DeleteDuplicate,
+            };
+            skipped.push(format!("{}: {}", item.id, tr(lang, reason)));
+        }
+
+        let mut text = vec![];
+        if !deleted.is_empty() {
+            text.push(trf(
+                lang,
+                Msg::Deleted,
+                &[
+                    ("count", deleted.len().to_string()),
+                    ("days", self.delete_grace_days.to_string()),
+                    ("ids", deleted.join("\n")),
+                ],
+            ));
+        }
+        if !skipped.is_empty() {
+            text.push(trf(
+                lang,
+                Msg::DeleteSkipped,
+                &[("lines", skipped.join("\n"))],
+            ));
+        }
+        Ok(text.join("\n\n"))
+    }
+
+    // `id` is any id of the file's links
+    async fn undelete(&self, msg: &Message, id: &str, lang: Lang) -> Result<String, Error> {
+        let file = match self.d1.find(id).await? {
+            Some(f) if f.is_deleted() => f,
+            _ => return Ok(trf(lang, Msg::UndeleteNotFound, &[("id", id.to_string())])),
+        };
+        if !self.can_manage(msg, &file) {
+            return Ok(tr(lang, Msg::NotYourFiles).to_string());
+        }
+
+        self.d1.undelete_file(&file.file_unique_id).await?;
+        self.uncache(&file.file_unique_id).await;
+        Ok(trf(lang, Msg::Undeleted, &[("id", file.file_unique_id)]))
+    }
+
+    // nothing is deleted before the user confirms with the button
+    async fn request_forget_me(&self, msg: &Message, lang: Lang) -> Result<(), Error> {
+        let keyboard = InlineKeyboardMarkup::builder()
+            .inline_keyboard(vec![vec![
+                InlineKeyboardButton::builder()
+                    .text(tr(lang, Msg::ForgetMeButton))
+                    .callback_data(FORGET_ME_CALLBACK)
+                    .build(),
+            ]])
+            .build();
+
+        self.send_message(
+            &SendMessageParams::builder()
+                .chat_id(ChatId::Integer(msg.chat.id))
+                .reply_parameters(
+                    ReplyParameters::builder()
+                        .message_id(msg.message_id)
+                        .build(),
+                )
+                .text(tr(lang, Msg::ForgetMeConfirm))
+                .reply_markup(ReplyMarkup::InlineKeyboardMarkup(keyboard))
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    // the copies go first, when the worker stops halfway the rows are still there to run it again
+    async fn forget_me(&self, host: &str, user_id: u64, lang: Lang) -> Result<String, Error> {
+        let files = self.d1.user_files(user_id).await?;
+        let albums = self.d1.count_user_albums(user_id).await?;
+
+        let cache = Cache::default();
+        let mut objects = 0;
+        let mut cache_entries = 0;
+        for f in &files {
+            let purged = purge_copies(host, &cache, self.storage.as_ref(), f).await?;
+            objects += purged.stored_objects;
+            cache_entries += purged.cache_entries;
+        }
+        self.d1.forget_user(user_id).await?;
+        for f in &files {
+            self.uncache(&f.file_unique_id).await;
+        }
+
+        info!(
+            "forgot user: {} files, {} albums, {} stored objects, {} cache entries",
+            files.len(),
+            albums,
+            objects,
+            cache_entries
+        );
+        Ok(trf(
+            lang,
+            Msg::Forgotten,
+            &[
+                ("files", files.len().to_string()),
+                ("albums", albums.to_string()),
+                ("objects", objects.to_string()),
+            ],
+        ))
+    }
+
+    // copies of the files of a banned user, a public bucket would keep serving them
+    pub async fn purge_user_copies(&self, host: &str, user_id: u64) -> Result<u32, Error> {
+        let cache = Cache::default();
+        let mut objects = 0;
+        for f in self.d1.user_files(user_id).await? {
+            objects += purge_copies(host, &cache, self.storage.as_ref(), &f)
+                .await?
+                .stored_objects;
+        }
+        Ok(objects)
+    }
+
+    // uploads of banned users are refused, their files stay online unless HIDE_BANNED_FILES is set
+    async fn ban(&self, host: &str, args: &str, banned: bool, lang: Lang) -> Result<String, Error> {
+        let user_id = match args.trim().parse::<u64>() {
+            Ok(v) if v as i64 != self.matainer => v,
+            _ => {
+                return Ok(tr(
+                    lang,
+                    if banned {
+                        Msg::UsageBan
+                    } else {
+                        Msg::UsageUnban
+                    },
+                )
+                .to_string());
+            }
+        };
+        let values = [("id", user_id.to_string())];
+
+        Ok(match banned {
+            true if self.d1.ban_user(user_id).await? => {
+                self.purge_user_copies(host, user_id).await?;
+                trf(lang, Msg::Banned, &values)
+            }
+            true => trf(lang, Msg::AlreadyBanned, &values),
+            false if self.d1.unban_user(user_id).await? => trf(lang, Msg::Unbanned, &values),
+            false => trf(lang, Msg::NotBanned, &values),
+        })
+    }
+
+    // replaces the generated alt text of the replied files
+    async fn alt(&self, msg: &Message, text: &str, lang: Lang) -> Result<String, Error> {
+        let files = self.replied_files(msg).await?;
+        if files.is_empty() {
+            return Ok(tr(lang, Msg::ReplyToFile).to_string());
+        }
+
+        let alt = clean_alt_text(text);
+        let mut count = 0;
+        for f in files.iter().filter(|f| self.can_manage(msg, f)) {
+            self.d1
+                .save_alt_text(&f.file_unique_id, &alt, false)
+                .await?;
+            count += 1;
+        }
+
+        if count == 0 {
+            return Ok(tr(lang, Msg::NotYourFiles).to_string());
+        }
+
+        Ok(trf(lang, Msg::AltSaved, &[("count", count.to_string())]))
+    }
+
+    // what is stored about the file of `id` or of the replied files
+    async fn info(&self, host: &str, msg: &Message, id: &str, lang: Lang) -> Result<String, Error> {
+        let files = if id.is_empty() {
+            self.replied_files(msg).await?
+        } else {
+            self.d1.find(id).await?.into_iter().collect()
+        };
+        if files.is_empty() {
+            return Ok(match id {
+                "" => tr(lang, Msg::UsageInfo).to_string(),
+                id => trf(lang, Msg::InfoNotFound, &[("id", id.to_string())]),
+            });
+        }
+
+        let mut infos = vec![];
+        for f in files.iter().filter(|f| self.can_manage(msg, f)) {
+            let mut lines = vec![f.unique_url(host)];
+            lines.push(trf(
+                lang,
+                Msg::InfoSize,
+                &[
+                    ("size", human_size(f.file_size)),
+                    (
+                        "mime",
+                        match f.mime_type.as_str() {
+                            // photos are re-encoded by telegram
+                            "" => "image/jpeg".to_string(),
+                            v => v.to_string(),
+                        },
+                    ),
+                ],
+            ));
+            lines.push(trf(lang, Msg::InfoUploaded, &[("time", date(f.add_time))]));
+            lines.push(match f.user_id {
+                0 => tr(lang, Msg::InfoUploaderUnknown).to_string(),
+                id => trf(lang, Msg::InfoUploader, &[("id", id.to_string())]),
+            });
+            lines.push(trf(
+                lang,
+                Msg::InfoDownloads,
+                &[("count", f.downloads.to_string())],
+            ));
+            let stored = match &self.storage {
+                Some(storage) => stored_copies(storage, f).await?,
+                None => vec![],
+            };
+            lines.push(match stored.is_empty() {
+                true => tr(lang, Msg::InfoNotStored).to_string(),
+                false => trf(lang, Msg::InfoStored, &[("keys", stored.join(", "))]),
+            });
+            if f.is_deleted() {
+                lines.push(trf(lang, Msg::InfoDeleted, &[("time", date(f.deleted_at))]));
+            }
+            if f.is_blocked() {
+                lines.push(trf(
+                    lang,
+                    Msg::InfoBlocked,
+                    &[("reason", f.blocked.clone())],
+                ));
+            }
+            match self.exif(f).await {
+                Ok(Some(metadata)) => lines.extend(self.exif_lines(f, &metadata, lang).await?),
+                Ok(None) => {}
+                Err(e) => warn!("Read EXIF of {} failed: {}", f.file_unique_id, e),
+            }
+            infos.push(lines.join("\n"));
+        }
+
+        if infos.is_empty() {
+            return Ok(tr(lang, Msg::NotYourPreview).to_string());
+        }
+        Ok(infos.join("\n\n"))
+    }
+
+    async fn exif_lines(
+        &self,
+        file: &File,
+        metadata: &exif::Metadata,
+        lang: Lang,
+    ) -> Result<Vec<String>, Error> {
+        let mut lines = vec![];
+        for (msg, key, value) in [
+            (Msg::InfoCamera, "camera", &metadata.camera),
+            (Msg::InfoLens, "lens", &metadata.lens),
+            (Msg::InfoExposure, "exposure", &metadata.exposure),
+            (Msg::InfoTaken, "time", &metadata.taken),
+        ] {
+            if !value.is_empty() {
+                lines.push(trf(lang, msg, &[(key, value.clone())]));
+            }
+        }
+        if let Some(location) = metadata.location() {
+            lines.push(match self.d1.shows_location(file.user_id).await? {
+                true => trf(lang, Msg::InfoLocation, &[("location", location)]),
+                false => tr(lang, Msg::InfoLocationHidden).to_string(),
+            });
+        }
+        Ok(lines)
+    }
+
+    // downloads a remote file and sends it to the chat as if the user had uploaded it
+    async fn fetch(
+        &self,
+        host: &str,
+        msg: &Message,
+        url: &str,
+        lang: Lang,
+    ) -> Result<String, Error> {
+        let url = match Url::parse(url.trim()) {
+            Ok(v) if matches!(v.scheme(), "http" | "https") => v,
+            _ => return Ok(tr(lang, Msg::FetchInvalidUrl).to_string()),
+        };
+        let too_large = || {
+            trf(
+                lang,
+                Msg::FetchTooLarge,
+                &[("size", (self.max_file_size / 1024 / 1024).to_string())],
+            )
+        };
+        let failed = |reason: String| trf(lang, Msg::FetchFailed, &[("reason", reason)]);
+
+        let mut resp = match Fetch::Url(url.clone()).send().await {
+            Ok(v) => v,
+            Err(e) => return Ok(failed(e.to_string())),
+        };
+        if resp.status_code() != 200 {
+            return Ok(failed(format!("HTTP {}", resp.status_code())));
+        }
+
+        // only what a link of this worker can show, not whatever a server answers with
+        let mime_type = resp
+            .headers()
+            .get("content-type")?
+            .unwrap_or_default()
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        if !["image/", "video/", "audio/"]
+            .iter()
+            .any(|v| mime_type.starts_with(v))
+        {
+            return Ok(trf(lang, Msg::FetchNotMedia, &[("mime", mime_type)]));
+        }
+        let name = url
+            .path_segments()
+            .and_then(|mut v| v.next_back())
+            .map(sanitize_file_name)
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| "file".to_string());
+        if let Err(e) = self.file_types.check(&mime_type, &name) {
+            return Ok(trf(
+                lang,
+                Msg::TypeNotAllowed,
+                &[("name", name), ("type", e.kind())],
+            ));
+        }
+        if resp
+            .headers()
+            .get("content-length")?
+            .and_then(|v| v.parse::<u64>().ok())
+            .is_some_and(|v| v > self.max_file_size)
+        {
+            return Ok(too_large());
+        }
+
+        // the length header is optional, the body is counted as it arrives
+        let mut data = vec![];
+        let mut body = resp.stream()?;
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(v) => v,
+                Err(e) => return Ok(failed(e.to_string())),
+            };
+            if (data.len() + chunk.len()) as u64 > self.max_file_size {
+                return Ok(too_large());
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        let meta = UploadMeta {
+            size: data.len() as u64,
+            mime_type,
+            hash: sha256_hex(&data),
+        };
+        if let Err(reason) = meta.check_policy(self.max_file_size) {
+            return Ok(failed(reason));
+        }
+
+        if let Some(f) = self.d1.find_by_hash(&meta.hash).await? {
+            info!("fetch deduplicated: {}", f.file_unique_id);
+            return Ok(reply_text(&self.reply_template, host, &f));
+        }
+
+        let mut file = self
+            .send_document(msg.chat.id, Some(msg.message_id), name, &meta, data)
+            .await?;
+        match &msg.from {
+            Some(user) if !self.privacy_mode => file.user_id = user.id,
+            _ => file.anonymize(),
+        }
+
+        let mut files = vec![file];
+        self.d1.save(&files).await?;
+        self.uncache(&files[0].file_unique_id).await;
+        self.assign_short_ids(&mut files).await;
+        let refused = self.moderate(host, &mut files).await;
+        self.describe(&mut files).await;
+
+        if let Some(f) = refused.first() {
+            return Ok(trf(lang, Msg::Refused, &[("name", f.file_name.clone())]));
+        }
+        Ok(files
+            .iter()
+            .map(|f| reply_text(&self.reply_template, host, f))
+            .collect())
+    }
+
+    // a memorable link of the first replied file, slugs and short ids share one table so neither shadows the other
+    async fn alias(
+        &self,
+        host: &str,
+        msg: &Message,
+        slug: &str,
+        lang: Lang,
+    ) -> Result<String, Error> {
+        let slug = slug.to_ascii_lowercase();
+        if !is_slug(&slug) {
+            return Ok(tr(lang, Msg::AliasInvalid).to_string());
+        }
+        if RESERVED_SLUGS.contains(&slug.as_str()) {
+            return Ok(trf(lang, Msg::AliasReserved, &[("name", slug)]));
+        }
+
+        let files = self.replied_files(msg).await?;
+        if files.is_empty() {
+            return Ok(tr(lang, Msg::ReplyToFile).to_string());
+        }
+        let file = match files.iter().find(|f| self.can_manage(msg, f)) {
+            Some(v) => v,
+            None => return Ok(tr(lang, Msg::NotYourFiles).to_string()),
+        };
+
+        if !self
+            .d1
+            .save_alias(&slug, &file.file_unique_id, ALIAS_SLUG)
+            .await?
+        {
+            return Ok(trf(lang, Msg::NamespaceTaken, &[("name", slug)]));
+        }
+
+        Ok(trf(
+            lang,
+            Msg::AliasSaved,
+            &[("url", format!("https://{}/s/{}", host, slug))],
+        ))
+    }
+
+    // `create <name>` and `add <name>`, album names are per user so everyone can have a "holiday"
+    async fn album(
+        &self,
+        host: &str,
+        msg: &Message,
+        args: &str,
+        lang: Lang,
+    ) -> Result<String, Error> {
+        let user = match &msg.from {
+            Some(v) => v,
+            None => return Ok(tr(lang, Msg::AlbumPrivate).to_string()),
+        };
+
+        let (action, name) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let name = name.trim();
+        if !(1..=ALBUM_NAME_MAX).contains(&name.chars().count()) {
+            return Ok(tr(lang, Msg::UsageAlbum).to_string());
+        }
+
+        match action {
+            "create" => match self.d1.create_album(user.id, name).await? {
+                Some(album) => Ok(trf(
+                    lang,
+                    Msg::AlbumCreated,
+                    &[("name", name.to_string()), ("url", album.url(host))],
+                )),
+                None => Ok(trf(lang, Msg::AlbumExists, &[("name", name.to_string())])),
+            },
+            "add" => {
+                let album = match self.d1.find_album(user.id, name).await? {
+                    Some(v) => v,
+                    None => {
+                        return Ok(trf(lang, Msg::AlbumNotFound, &[("name", name.to_string())]));
+                    }
+                };
+
+                let files = self.replied_files(msg).await?;
+                if files.is_empty() {
+                    return Ok(tr(lang, Msg::ReplyToFile).to_string());
+                }
+                let ids = files
+                    .iter()
+                    .filter(|f| self.can_manage(msg, f))
+                    .map(|f| f.file_unique_id.clone())
+                    .collect::<Vec<_>>();
+                if ids.is_empty() {
+                    return Ok(tr(lang, Msg::NotYourFiles).to_string());
+                }
+
+                self.d1.add_album_files(&album.album_id, &ids).await?;
+                Ok(trf(
+                    lang,
+                    Msg::AlbumAdded,
+                    &[
+                        ("count", ids.len().to_string()),
+                        ("name", name.to_string()),
+                        ("url", album.url(host)),
+                    ],
+                ))
+            }
+            _ => Ok(tr(lang, Msg::UsageAlbum).to_string()),
+        }
+    }
+
+    // `/tag a b -c` on a reply changes the tags of the files, `/tag` shows them, or the tags of the user
+    // without a reply
+    async fn tag(&self, msg: &Message, args: &str, lang: Lang) -> Result<String, Error> {
+        let files = self.replied_files(msg).await?;
+        if files.is_empty() {
+            if !args.is_empty() {
+                return Ok(tr(lang, Msg::UsageTag).to_string());
+            }
+            let user_id = msg.from.as_ref().map(|u| u.id).unwrap_or_default();
+            let tags = self.d1.user_tags(user_id).await?;
+            if tags.is_empty() {
+                return Ok(tr(lang, Msg::TagNone).to_string());
+            }
+            let lines = tags
+                .iter()
+                .map(|t| format!("#{} ({})", t.name, t.files))
+                .collect::<Vec<_>>();
+            return Ok(trf(lang, Msg::TagList, &[("tags", lines.join("\n"))]));
+        }
+
+        let changes = match parse_changes(args) {
+            Ok(v) => v,
+            Err(name) => {
+                return Ok(trf(
+                    lang,
+                    Msg::TagInvalid,
+                    &[("name", name), ("max", MAX_TAG_LENGTH.to_string())],
+                ));
+            }
+        };
+        let files = files
+            .into_iter()
+            .filter(|f| self.can_manage(msg, f))
+            .collect::<Vec<_>>();
+        if files.is_empty() {
+            return Ok(tr(lang, Msg::NotYourFiles).to_string());
+        }
+
+        let mut tags = self.d1.files_tags(&files).await?;
+        let changed = !changes.add.is_empty() || !changes.remove.is_empty();
+        if changed {
+            for f in &files {
+                let current = tags.remove(&f.file_unique_id).unwrap_or_default();
+                let new = apply(&current, &changes);
+                if new.len() > MAX_TAGS_PER_FILE {
+                    return Ok(trf(
+                        lang,
+                        Msg::TagTooMany,
+                        &[("max", MAX_TAGS_PER_FILE.to_string())],
+                    ));
+                }
+                tags.insert(f.file_unique_id.clone(), new);
+            }
+            for f in &files {
+                self.d1.save_tags(f, &changes.add, &changes.remove).await?;
+            }
+        }
+
+        let lines = files
+            .iter()
+            .map(|f| {
+                let names = tags.get(&f.file_unique_id).cloned().unwrap_or_default();
+                let names = match names.is_empty() {
+                    true => tr(lang, Msg::TagNoTags).to_string(),
+                    false => names
+                        .iter()
+                        .map(|v| format!("#{}", v))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                };
+                format!("{}: {}", f.file_unique_id, names)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !changed {
+            return Ok(lines);
+        }
+        Ok(trf(
+            lang,
+            Msg::TagsSaved,
+            &[("count", files.len().to_string()), ("lines", lines)],
+        ))
+    }
+
+    // what sharing the links publishes, as a checklist
+    async fn preview(&self, host: &str, msg: &Message, lang: Lang) -> Result<String, Error> {
+        let files = self.replied_files(msg).await?;
+        if files.is_empty() {
+            return Ok(tr(lang, Msg::ReplyToFile).to_string());
+        }
+
+        let previews = files
+            .iter()
+            .filter(|f| self.can_manage(msg, f))
+            .map(|f| {
+                let mut lines = vec![f.unique_url(host)];
+
+                lines.push(if f.is_protected() {
+                    tr(lang, Msg::PreviewProtected).to_string()
+                } else {
+                    tr(lang, Msg::PreviewPublic).to_string()
+                });
+                lines.push(tr(lang, Msg::PreviewNeverExpires).to_string());
+                lines.push(match (f.download_name.as_str(), f.file_name.as_str()) {
+                    ("", "") => tr(lang, Msg::PreviewNoName).to_string(),
+                    ("", name) | (name, _) => {
+                        trf(lang, Msg::PreviewDownloadsAs, &[("name", name.to_string())])
+                    }
+                });
+                for (k, v) in f.headers() {
+                    lines.push(format!("📨 {}: {}", k, v));
+                }
+                // telegram re-encodes photos, other documents are served byte for byte
+                lines.push(if f.mime_type.is_empty() {
+                    tr(lang, Msg::PreviewExifStripped).to_string()
+                } else if self.strip_exif && exif::applies(Some(f), "") {
+                    tr(lang, Msg::PreviewExifStrippedOnServe).to_string()
+                } else {
+                    tr(lang, Msg::PreviewExifKept).to_string()
+                });
+                if !f.forward_from.is_empty() {
+                    let from = trf(
+                        lang,
+                        Msg::PreviewForwardedFrom,
+                        &[("from", f.forward_from.clone())],
+                    );
+                    lines.push(match f.forward_url() {
+                        Some(url) => format!("{}, {}", from, url),
+                        None => from,
+                    });
+                }
+                lines.push(if f.thumbnail_file_id.is_empty() {
+                    tr(lang, Msg::PreviewNoThumbnail).to_string()
+                } else {
+                    tr(lang, Msg::PreviewThumbnail).to_string()
+                });
+
+                lines.join("\n")
+            })
+            .collect::<Vec<_>>();
+
+        if previews.is_empty() {
+            return Ok(tr(lang, Msg::NotYourPreview).to_string());
+        }
+
+        Ok(previews.join("\n\n"))
+    }
+
+    // links of the old file redirect to the new one, its copies in R2 and the edge cache are dropped
+    async fn supersede(&self, host: &str, old: &File, new: &File) -> Result<(), Error> {
+        // an edit back to earlier media of the same uploader, saving keeps its links redirected
+        if let Some(stored) = self.d1.find(&new.file_unique_id).await?
+            && !stored.replaced_by.is_empty()
+            && stored.user_id == old.user_id
+        {
+            self.d1.replace_file(&new.file_unique_id, "").await?;
+            self.uncache(&new.file_unique_id).await;
+        }
+        self.d1
+            .replace_file(&old.file_unique_id, &new.file_id)
+            .await?;
+        self.uncache(&old.file_unique_id).await;
+
+        if let Some(r2) = &self.r2 {
+            r2.delete(format!("{}{}", old.file_unique_id, old.ext()))
+                .await
+                .map_err(|e| Error::Storage(e.to_string()))?;
+        }
+
+        // downloads check replaced_by before the cache, this only frees the space.
+        // the cache api purges the current data center, others expire on their own
+        let cache = Cache::default();
+        for url in [old.url(host), old.unique_url(host)] {
+            cache.delete(url, true).await?;
+        }
+
+        Ok(())
+    }
+
+    // edits the pinned summary of the chat, a new one is sent and pinned when it was deleted
+    async fn update_summary(&self, host: &str, chat_id: i64, files: &[File]) -> Result<(), Error> {
+        let key = format!("{}{}", SUMMARY_SETTING_PREFIX, chat_id);
+        let mut summary: Summary =
+            serde_json::from_str(&self.d1.get_setting(&key).await?).unwrap_or_default();
+
+        let mut links = files.iter().map(|f| f.url(host)).collect::<Vec<_>>();
+        links.extend(summary.links);
+        links.truncate(self.summary_size);
+        summary.links = links;
+
+        // shared by everyone in the chat, so in the deployment's language
+        let text = format!(
+            "{}\n\n{}",
+            tr(self.lang, Msg::SummaryTitle),
+            summary.links.join("\n")
+        );
+
+        let edited = summary.message_id != 0 && {
+            let params = EditMessageTextParams::builder()
+                .chat_id(ChatId::Integer(chat_id))
+                .message_id(summary.message_id)
+                .text(text.clone())
+                .link_preview_options(LinkPreviewOptions::DISABLED)
+                .build();
+            retry(|| self.bot.edit_message_text(&params)).await.is_ok()
+        };
+
+        if !edited {
+            let msg = self
+                .send_message(
+                    &SendMessageParams::builder()
+                        .chat_id(ChatId::Integer(chat_id))
+                        .text(text)
+                        .link_preview_options(LinkPreviewOptions::DISABLED)
+                        .build(),
+                )
+                .await?;
+            summary.message_id = msg.message_id;
+
+            // pinning needs the pin messages admin right, the summary is still edited without it
+            let params = PinChatMessageParams::builder()
+                .chat_id(ChatId::Integer(chat_id))
+                .message_id(msg.message_id)
+                .disable_notification(true)
+                .build();
+            if let Err(e) = retry(|| self.bot.pin_chat_message(&params)).await {
+                warn!("Pin summary of chat {} failed: {}", chat_id, e);
+            }
+        }
+
+        let value = serde_json::to_string(&summary).map_err(|e| Error::Internal(e.to_string()))?;
+        self.d1.set_setting(&key, &value).await
+    }
+
+    // false when the caption would get too long, the links are replied instead
+    async fn append_caption(
+        &self,
+        chat_id: i64,
+        msg_id: i32,
+        caption: String,
+        caption_entities: Option<Vec<MessageEntity>>,
+        links: &str,
+    ) -> Result<bool, Error> {
+        let caption = if caption.is_empty() {
+            links.to_string()
+        } else {
+            format!("{}\n\n{}", caption, links)
+        };
+        if caption.encode_utf16().count() > MAX_CAPTION_LENGTH {
+            return Ok(false);
+        }
+
+        // the links are appended, so the offsets of the existing entities stay valid
+        let params = EditMessageCaptionParams::builder()
+            .chat_id(ChatId::Integer(chat_id))
+            .message_id(msg_id)
+            .caption(caption)
+            .maybe_caption_entities(caption_entities)
+            .build();
+        retry(|| self.bot.edit_message_caption(&params)).await?;
+
+        Ok(true)
+    }
+
+    async fn namespace(
+        &self,
+        host: &str,
+        msg: &Message,
+        name: &str,
+        lang: Lang,
+    ) -> Result<String, Error> {
+        let user = match &msg.from {
+            Some(v) => v,
+            None => return Ok(tr(lang, Msg::NamespacePrivate).to_string()),
+        };
+
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "" => {
+                let current = self.d1.get_namespace(user.id).await?;
+                if current.is_empty() {
+                    return Ok(tr(lang, Msg::NamespaceUsage).to_string());
+                }
+                Ok(trf(
+                    lang,
+                    Msg::NamespaceCurrent,
+                    &[("url", format!("https://{}/u/{}/", host, current))],
+                ))
+            }
+            "off" => {
+                self.d1.save_namespace(user.id, "").await?;
+                Ok(tr(lang, Msg::NamespaceOff).to_string())
+            }
+            _ if !is_namespace(&name) => Ok(tr(lang, Msg::NamespaceInvalid).to_string()),
+            _ => {
+                if !self.d1.save_namespace(user.id, &name).await? {
+                    return Ok(trf(lang, Msg::NamespaceTaken, &[("name", name.clone())]));
+                }
+                Ok(trf(
+                    lang,
+                    Msg::NamespaceSaved,
+                    &[("url", format!("https://{}/u/{}/", host, name))],
+                ))
+            }
+        }
+    }
+
+    // whether /view/ and /info show the GPS location of the user's photos, hidden by default
+    async fn location(&self, msg: &Message, args: &str, lang: Lang) -> Result<String, Error> {
+        let user = match &msg.from {
+            Some(v) => v,
+            None => return Ok(tr(lang, Msg::LocationPrivate).to_string()),
+        };
+
+        let show = match args.to_ascii_lowercase().as_str() {
+            "" => self.d1.shows_location(user.id).await?,
+            "on" => true,
+            "off" => false,
+            _ => return Ok(tr(lang, Msg::LocationUsage).to_string()),
+        };
+        if !args.is_empty() {
+            self.d1.save_show_location(user.id, show).await?;
+        }
+        Ok(match show {
+            true => tr(lang, Msg::LocationShown),
+            false => tr(lang, Msg::LocationHidden),
+        }
+        .to_string())
+    }
+
+    // what the camera recorded of a jpeg document that keeps its EXIF, from the start of the file
+    pub async fn exif(&self, file: &File) -> Result<Option<exif::Metadata>, Error> {
+        if self.strip_exif || !exif::applies(Some(file), "") {
+            return Ok(None);
+        }
+
+        let (url, _) = self.get_file_url(&file.file_id, false).await?;
+        let mut body = reqwest::get(&url).await?.error_for_status()?.bytes_stream();
+        let mut head = vec![];
+        while head.len() < exif::METADATA_PREFIX {
+            match body.next().await {
+                Some(chunk) => head.extend_from_slice(&chunk?),
+                None => break,
+            }
+        }
+        Ok(exif::metadata(&head))
+    }
+
+    // `new [scopes]`, `list` and `revoke <id>`. keys are sent in the chat, so only private chats
+    async fn token(&self, msg: &Message, args: &str, lang: Lang) -> Result<String, Error> {
+        let user = match &msg.from {
+            Some(v) if matches!(msg.chat.type_field, ChatType::Private) => v,
+            _ => return Ok(tr(lang, Msg::TokenPrivate).to_string()),
+        };
+
+        let (action, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let rest = rest.trim();
+        match action {
+            "new" => {
+                let Some(scopes) = parse_scopes(rest) else {
+                    return Ok(tr(lang, Msg::UsageToken).to_string());
+                };
+                if self.d1.user_api_keys(user.id).await?.len() as u64 >= MAX_KEYS_PER_USER {
+                    return Ok(trf(
+                        lang,
+                        Msg::TokenLimit,
+                        &[("max", MAX_KEYS_PER_USER.to_string())],
+                    ));
+                }
+
+                let (key_id, key, hash) = generate()?;
+                let scopes = scope_names(&scopes);
+                self.d1
+                    .save_api_key(&key_id, &hash, user.id, &scopes)
+                    .await?;
+                Ok(trf(
+                    lang,
+                    Msg::TokenCreated,
+                    &[("id", key_id), ("scopes", scopes), ("key", key)],
+                ))
+            }
+            "list" => {
+                let keys = self.d1.user_api_keys(user.id).await?;
+                if keys.is_empty() {
+                    return Ok(tr(lang, Msg::TokenNone).to_string());
+                }
+                let lines = keys
+                    .iter()
+                    .map(|k| {
+                        trf(
+                            lang,
+                            Msg::TokenLine,
+                            &[
+                                ("id", k.key_id.clone()),
+                                ("scopes", k.scopes.clone()),
+                                (
+                                    "used",
+                                    match k.last_used {
+                                        0 => tr(lang, Msg::TokenNeverUsed).to_string(),
+                                        v => date(v),
+                                    },
+                                ),
+                            ],
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Ok(trf(lang, Msg::TokenList, &[("keys", lines)]))
+            }
+            "revoke" if !rest.is_empty() => {
+                let values = [("id", rest.to_string())];
+                Ok(match self.d1.delete_api_key(rest, user.id).await? {
+                    true => trf(lang, Msg::TokenRevoked, &values),
+                    false => trf(lang, Msg::TokenNotFound, &values),
+                })
+            }
+            _ => Ok(tr(lang, Msg::UsageToken).to_string()),
+        }
+    }
+
+    // scores the images of an upload, refused ones are taken out of `files`.
+    // an unavailable model doesn't stop uploads, those files stay unchecked
+    async fn moderate(&self, host: &str, files: &mut Vec<File>) -> Vec<File> {
+        let Some(moderator) = &self.moderator else {
+            return vec![];
+        };
+
+        let mut refused = vec![];
+        for mut f in std::mem::take(files) {
+            if !moderator.checks(&f) {
+                files.push(f);
+                continue;
+            }
+            // files sent again keep their verdict, a new score never undoes a takedown
+            match self.d1.find(&f.file_unique_id).await {
+                Ok(Some(stored)) if stored.moderation_score.is_some() || stored.is_blocked() => {
+                    f.moderation_score = stored.moderation_score;
+                    f.blocked = stored.blocked;
+                    if f.is_blocked() {
+                        refused.push(f);
+                    } else {
+                        files.push(f);
+                    }
+                    continue;
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Find the verdict of {} failed: {}", f.file_unique_id, e),
+            }
+            let score = match moderator.score(&self.file_url(&f.file_path)).await {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("Moderation of {} failed: {}", f.file_unique_id, e);
+                    files.push(f);
+                    continue;
+                }
+            };
+
+            let verdict = moderator.verdict(score);
+            f.moderation_score = Some(score);
+            if verdict == Verdict::Reject {
+                f.blocked = BLOCKED_MODERATION.to_string();
+            }
+            if let Err(e) = self
+                .d1
+                .save_moderation(&f.file_unique_id, score, &f.blocked)
+                .await
+            {
+                warn!("Save moderation of {} failed: {}", f.file_unique_id, e);
+            }
+            self.uncache(&f.file_unique_id).await;
+
+            match verdict {
+                Verdict::Allow => files.push(f),
+                Verdict::Review => {
+                    if let Err(e) = self.request_review(host, &f, score).await {
+                        warn!("Review request of {} failed: {}", f.file_unique_id, e);
+                    }
+                    files.push(f);
+                }
+                Verdict::Reject => refused.push(f),
+            }
+        }
+        refused
+    }
+
+    // alt text of the images of an upload, files keep the text they already have
+    async fn describe(&self, files: &mut [File]) {
+        let Some(captioner) = &self.captioner else {
+            return;
+        };
+
+        for f in files.iter_mut().filter(|f| captioner.checks(f)) {
+            // files sent again aren't described twice
+            if let Ok(Some(stored)) = self.d1.find(&f.file_unique_id).await
+                && !stored.alt_text.is_empty()
+            {
+                f.alt_text = stored.alt_text;
+                continue;
+            }
+            let alt = match captioner.caption(&self.file_url(&f.file_path)).await {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("Caption of {} failed: {}", f.file_unique_id, e);
+                    continue;
+                }
+            };
+            if let Err(e) = self.d1.save_alt_text(&f.file_unique_id, &alt, true).await {
+                warn!("Save alt text of {} failed: {}", f.file_unique_id, e);
+                continue;
+            }
+            f.alt_text = alt;
+        }
+    }
+
+    // files of a channel in channel_collections, posted there or forwarded from it, join its album
+    // and get its tags. the files of a message share their chat and origin
+    async fn collect(&self, files: &[File]) {
+        let Some(first) = files.first() else {
+            return;
+        };
+        let chat_id = match first.forward_chat_id.as_str() {
+            "" => first.chat_id.to_string(),
+            v => v.to_string(),
+        };
+        let collection = match self.d1.channel_collection(&chat_id).await {
+            Ok(Some(v)) => v,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Find the album of channel {} failed: {}", chat_id, e);
+                return;
+            }
+        };
+
+        let ids = files
+            .iter()
+            .map(|f| f.file_unique_id.clone())
+            .collect::<Vec<_>>();
+        if let Err(e) = self.d1.add_album_files(&collection.album_id, &ids).await {
+            warn!(
+                "Add files of channel {} to its album failed: {}",
+                chat_id, e
+            );
+        }
+        let tags = collection.tags();
+        if tags.is_empty() {
+            return;
+        }
+        for f in files {
+            if let Err(e) = self.d1.save_tags(f, &tags, &[]).await {
+                warn!(
+                    "Tag {} of channel {} failed: {}",
+                    f.file_unique_id, chat_id, e
+                );
+            }
+        }
+    }
+
+    // borderline files stay online until the maintainer blocks them
+    async fn request_review(&self, host: &str, file: &File, score: f64) -> Result<(), Error> {
+        let button = |text: &str, action: &str| {
+            InlineKeyboardButton::builder()
+                .text(text)
+                .callback_data(format!(
+                    "{}{}:{}",
+                    MODERATION_CALLBACK_PREFIX, action, file.file_unique_id
+                ))
+                .build()
+        };
+        let keyboard = InlineKeyboardMarkup::builder()
+            .inline_keyboard(vec![vec![
+                button("Approve", "approve"),
+                button("Block", "block"),
+            ]])
+            .build();
+
+        self.send_message(
+            &SendMessageParams::builder()
+                .chat_id(ChatId::Integer(self.matainer))
+                .text(format!(
+                    "Moderation scored {:.2} for {} uploaded by user {}, keep it online?",
+                    score,
+                    file.unique_url(host),
+                    file.user_id
+                ))
+                .reply_markup(ReplyMarkup::InlineKeyboardMarkup(keyboard))
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    // reports come from anyone, only the maintainer gets to act on them
+    pub async fn notify_report(
+        &self,
+        host: &str,
+        file: &File,
+        report_id: i64,
+        reason: &str,
+    ) -> Result<(), Error> {
+        let button = |text: &str, action: &str| {
+            InlineKeyboardButton::builder()
+                .text(text)
+                .callback_data(format!(
+                    "{}{}:{}",
+                    REPORT_CALLBACK_PREFIX, action, file.file_unique_id
+                ))
+                .build()
+        };
+        let keyboard = InlineKeyboardMarkup::builder()
+            .inline_keyboard(vec![vec![
+                button("Take down", "takedown"),
+                button("Dismiss", "dismiss"),
+            ]])
+            .build();
+
+        self.send_message(
+            &SendMessageParams::builder()
+                .chat_id(ChatId::Integer(self.matainer))
+                .text(format!(
+                    "Report {} of {} uploaded by user {}:\n\n{}",
+                    report_id,
+                    file.unique_url(host),
+                    file.user_id,
+                    reason
+                ))
+                .reply_markup(ReplyMarkup::InlineKeyboardMarkup(keyboard))
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    // blocks the file and drops its copies, links answer 451 from now on
+    async fn take_down(&self, host: &str, file_unique_id: &str) -> Result<String, Error> {
+        let file = self.d1.get(file_unique_id).await?;
+        self.d1
+            .save_blocked(&file.file_unique_id, BLOCKED_REPORT)
+            .await?;
+        self.uncache(&file.file_unique_id).await;
+        self.d1
+            .close_reports(&file.file_unique_id, REPORT_TAKEN_DOWN)
+            .await?;
+
+        let purged = purge_copies(host, &Cache::default(), self.storage.as_ref(), &file).await?;
+        Ok(format!(
+            "{} is taken down, {} cache entries and {} stored objects purged.",
+            file.file_unique_id, purged.cache_entries, purged.stored_objects
+        ))
+    }
+
+    fn is_matainer(&self, msg: &Message) -> bool {
+        msg.from.as_ref().map(|u| u.id as i64) == Some(self.matainer)
+    }
+
+    async fn send_setup(&self, msg: &Message, lang: Lang) -> Result<(), Error> {
+        let keyboard = InlineKeyboardMarkup::builder()
+            .inline_keyboard(
+                SETUP_STEPS
+                    .iter()
+                    .map(|(step, text)| {
+                        vec![
+                            InlineKeyboardButton::builder()
+                                .text(tr(lang, *text))
+                                .callback_data(format!("{}{}", SETUP_CALLBACK_PREFIX, step))
+                                .build(),
+                        ]
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .build();
+
+        self.send_message(
+            &SendMessageParams::builder()
+                .chat_id(ChatId::Integer(msg.chat.id))
+                .reply_parameters(
+                    ReplyParameters::builder()
+                        .message_id(msg.message_id)
+                        .build(),
+                )
+                .text(tr(lang, Msg::SetupTitle))
+                .reply_markup(ReplyMarkup::InlineKeyboardMarkup(keyboard))
+                .build(),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn run_setup_step(&self, host: &str, step: &str, lang: Lang) -> Result<String, Error> {
+        let msg = match step {
+            "db" => {
+                self.d1.init().await?;
+                Msg::SetupDb
+            }
+            "webhook" => {
+                self.set_webhook(&format!("https://{}/tgbot", host), false)
+                    .await?;
+                Msg::SetupWebhook
+            }
+            "r2" => match &self.r2 {
+                Some(r2) => {
+                    r2.head("setup-check").await?;
+                    Msg::SetupR2
+                }
+                None if self.capabilities.has(&KV_STORAGE) => Msg::SetupR2Kv,
+                None => Msg::SetupR2Missing,
+            },
+            "channel" => Msg::SetupChannel,
+            _ => Msg::SetupUnknown,
+        };
+        Ok(tr(lang, msg).to_string())
+    }
+
+    async fn handle_command(
+        &self,
+        host: &str,
+        msg: &Message,
+        command: &str,
+        args: &str,
+    ) -> Result<(), Error> {
+        let command = match COMMANDS.iter().find(|c| c.name == command) {
+            Some(v) => v.command,
+            None => return Ok(()),
+        };
+
+        let lang = self.lang_of(msg.from.as_ref().and_then(|u| u.language_code.as_deref()));
 
-        self.bot
-            .send_message(
-                &SendMessageParams::builder()
-                    .chat_id(ChatId::Integer(self.matainer))
-                    .text(format!("register webhook to {} successful", url))
-                    .link_preview_options(LinkPreviewOptions::DISABLED)
-                    .build(),
-            )
-            .await?;
+        // files have no owner to check against and users no rows to keep
+        if self.privacy_mode && command.per_user() && !self.is_matainer(msg) {
+            return self
+                .reply(
+                    msg.chat.id,
+                    msg.message_id,
+                    tr(lang, Msg::PrivacyModeOnly).to_string(),
+                )
+                .await;
+        }
 
-        Ok(())
-    }
+        let text = match command {
+            Command::Start | Command::Help => help(lang, self.max_file_size),
+            Command::Preview => self.preview(host, msg, lang).await?,
+            Command::Protect if args.is_empty() => tr(lang, Msg::UsageProtect).to_string(),
+            Command::Protect => self.protect(msg, args, lang).await?,
+            Command::Unprotect => self.protect(msg, "", lang).await?,
+            Command::Rename if args.is_empty() => tr(lang, Msg::UsageRename).to_string(),
+            Command::Rename => self.rename(msg, args, lang).await?,
+            Command::Alias if args.is_empty() => tr(lang, Msg::UsageAlias).to_string(),
+            Command::Alias => self.alias(host, msg, args, lang).await?,
+            Command::Alt if args.is_empty() => {
+                trf(lang, Msg::UsageAlt, &[("max", MAX_ALT_TEXT.to_string())])
+            }
+            Command::Alt => self.alt(msg, args, lang).await?,
+            Command::Info => self.info(host, msg, args, lang).await?,
+            Command::Fetch if args.is_empty() => trf(
+                lang,
+                Msg::UsageFetch,
+                &[("size", (self.max_file_size / 1024 / 1024).to_string())],
+            ),
+            Command::Fetch if self.needs_consent(msg.from.as_ref().map(|u| u.id)).await? => {
+                return self
+                    .request_consent(host, msg.chat.id, msg.message_id, lang)
+                    .await;
+            }
+            Command::Fetch => self.fetch(host, msg, args, lang).await?,
+            Command::Delete => self.delete(host, msg, args, lang).await?,
+            Command::Undelete if args.is_empty() => tr(lang, Msg::UsageUndelete).to_string(),
+            Command::Undelete => self.undelete(msg, args, lang).await?,
+            Command::Ban | Command::Unban if !self.is_matainer(msg) => {
+                tr(lang, Msg::MaintainerOnly).to_string()
+            }
+            Command::Ban => self.ban(host, args, true, lang).await?,
+            Command::Unban => self.ban(host, args, false, lang).await?,
+            Command::ForgetMe => return self.request_forget_me(msg, lang).await,
+            Command::Album => self.album(host, msg, args, lang).await?,
+            Command::Tag => self.tag(msg, args, lang).await?,
+            Command::Namespace => self.namespace(host, msg, args, lang).await?,
+            Command::Location => self.location(msg, args, lang).await?,
+            Command::Token => self.token(msg, args, lang).await?,
+            // also used inside channels, where messages have no sender
+            Command::Setup if args == "channel" => trf(
+                lang,
+                Msg::SetupChannelId,
+                &[("id", msg.chat.id.to_string())],
+            ),
+            Command::Setup if !self.is_matainer(msg) => {
+                tr(lang, Msg::SetupMaintainerOnly).to_string()
+            }
+            Command::Setup => return self.send_setup(msg, lang).await,
+        };
 
-    fn get_ext(&self, path: &str) -> String {
-        path.rsplit('.')
-            .next()
-            .map(|e| format!(".{}", e))
-            .unwrap_or_default()
+        self.reply(msg.chat.id, msg.message_id, text).await
     }
 
     pub async fn handle(
         &self,
-        host: &String,
+        host: &str,
         update: frankenstein::updates::Update,
     ) -> Result<(), Error> {
+        let edited = matches!(update.content, UpdateContent::EditedMessage(_));
+
         match update.content {
             UpdateContent::Message(msg)
             | UpdateContent::EditedMessage(msg)
             | UpdateContent::ChannelPost(msg) => {
                 let chat_id = msg.chat.id;
                 let msg_id = msg.message_id;
+                let lang = self.lang_of(msg.from.as_ref().and_then(|u| u.language_code.as_deref()));
 
-                let files = File::from_message(msg, async |f| {
-                    let ff = self
-                        .bot
-                        .get_file(&GetFileParams { file_id: f.clone() })
-                        .await?;
-                    match ff.result.file_path {
-                        Some(p) => Ok(p),
-                        None => return Err(Error("File path not found".to_string())),
+                // groups and channels don't learn about the ban
+                if let Some(user) = &msg.from
+                    && user.id as i64 != self.matainer
+                    && self.d1.is_banned(user.id).await?
+                {
+                    if matches!(msg.chat.type_field, ChatType::Private) {
+                        return self
+                            .reply(chat_id, msg_id, tr(lang, Msg::YouAreBanned).to_string())
+                            .await;
                     }
+                    return Ok(());
+                }
+
+                if let Some(text) = &msg.text
+                    && let Some((command, args)) = parse_command(text)
+                {
+                    return match self.handle_command(host, &msg, &command, &args).await {
+                        // tell the user what is missing instead of failing silently
+                        Err(Error::MissingBinding { feature, binding }) => {
+                            let text = trf(
+                                lang,
+                                Msg::MissingBinding,
+                                &[("feature", feature), ("binding", binding)],
+                            );
+                            self.reply(chat_id, msg_id, text).await
+                        }
+                        v => v,
+                    };
+                }
+
+                // channel posts copied into the discussion group, only answered to leave a comment
+                if msg.is_automatic_forward == Some(true)
+                    && self.channel_reply_mode != ChannelReplyMode::Comment
+                {
+                    return Ok(());
+                }
+
+                let private = matches!(msg.chat.type_field, ChatType::Private);
+                let channel = matches!(msg.chat.type_field, ChatType::Channel);
+                let user_id = msg.from.as_ref().map(|u| u.id);
+                let caption = msg.caption.clone().unwrap_or_default();
+                let caption_entities = msg.caption_entities.clone();
+                // before telegram is asked for the paths of files that may never be saved
+                if File::has_files(&msg) && self.needs_consent(user_id).await? {
+                    return self.request_consent(host, chat_id, msg_id, lang).await;
+                }
+                let mut files = File::from_message(msg, async |ids| {
+                    try_join_all(ids.into_iter().map(|(id, unique_id, size)| async move {
+                        // over MAX_FILE_SIZE, telegram isn't asked for a path that won't be used
+                        if size > self.max_file_size {
+                            return Ok(String::new());
+                        }
+                        self.recent_file_path(id, unique_id).await
+                    }))
+                    .await
                 })
                 .await?;
+                if self.privacy_mode {
+                    files.iter_mut().for_each(File::anonymize);
+                }
 
                 if files.is_empty() {
+                    // groups and channels are full of messages that are not meant for the bot
+                    if private {
+                        return self
+                            .reply(chat_id, msg_id, tr(lang, Msg::SendFileHint).to_string())
+                            .await;
+                    }
                     return Ok(());
                 }
 
-                let response = match self.d1.save(&files).await {
+                let (files, not_allowed): (Vec<File>, Vec<File>) = files
+                    .into_iter()
+                    .partition(|f| self.file_types.check_file(f).is_ok());
+
+                // files of the message before an edit that replaced its media
+                let mut replaced = vec![];
+                if edited {
+                    let previous = self.d1.message_files(chat_id, msg_id).await?;
+                    // only the caption changed, the links are still valid
+                    if !previous.is_empty()
+                        && previous
+                            .iter()
+                            .all(|p| files.iter().any(|f| f.file_unique_id == p.file_unique_id))
+                    {
+                        return Ok(());
+                    }
+                    replaced = previous;
+                }
+
+                let (mut files, too_big): (Vec<File>, Vec<File>) =
+                    files.into_iter().partition(|f| !f.file_path.is_empty());
+
+                let saved = self.d1.save(&files).await;
+                if saved.is_ok() {
+                    for f in &files {
+                        self.uncache(&f.file_unique_id).await;
+                    }
+                    self.assign_short_ids(&mut files).await;
+                }
+                let refused = if saved.is_ok() {
+                    self.moderate(host, &mut files).await
+                } else {
+                    vec![]
+                };
+                if saved.is_ok() {
+                    self.describe(&mut files).await;
+                    self.collect(&files).await;
+                }
+
+                if saved.is_ok()
+                    && let Some(new) = files.first()
+                {
+                    for old in replaced
+                        .iter()
+                        .filter(|p| files.iter().all(|f| f.file_unique_id != p.file_unique_id))
+                    {
+                        if let Err(e) = self.supersede(host, old, new).await {
+                            warn!("Supersede file {} failed: {}", old.file_unique_id, e);
+                        }
+                    }
+                }
+
+                if saved.is_ok() && !private && !files.is_empty() && self.summary_size > 0 {
+                    // the upload itself succeeded, a missing summary isn't worth an error reply
+                    if let Err(e) = self.update_summary(host, chat_id, &files).await {
+                        warn!("Update summary of chat {} failed: {}", chat_id, e);
+                    }
+                }
+
+                if channel
+                    && saved.is_ok()
+                    && too_big.is_empty()
+                    && refused.is_empty()
+                    && not_allowed.is_empty()
+                {
+                    match self.channel_reply_mode {
+                        ChannelReplyMode::Reply => {}
+                        ChannelReplyMode::Comment => return Ok(()),
+                        ChannelReplyMode::Caption => {
+                            let links = files
+                                .iter()
+                                .map(|f| f.url(host))
+                                .collect::<Vec<_>>()
+                                .join("\n");
+                            if self
+                                .append_caption(chat_id, msg_id, caption, caption_entities, &links)
+                                .await?
+                            {
+                                return Ok(());
+                            }
+                        }
+                    }
+                }
+
+                let saved_ok = saved.is_ok();
+                let mut response = match saved {
                     Ok(_) => files
                         .iter()
-                        .map(|f| {
-                            let ext = self.get_ext(&f.file_path);
-                            format!(
-                                "https://{}/f/{}{}\nhttps://{}/f/{}{}\n",
-                                host, f.file_id, ext, host, f.file_unique_id, ext
-                            )
-                        })
+                        .map(|f| reply_text(&self.reply_template, host, f))
                         .collect::<String>(),
-                    Err(e) => format!("Error: {}", e),
+                    Err(e) => trf(lang, Msg::UploadError, &[("error", e.to_string())]),
                 };
 
-                self.bot
-                    .send_message(
+                for f in too_big {
+                    let name = if f.file_name.is_empty() {
+                        f.file_unique_id
+                    } else {
+                        f.file_name
+                    };
+                    // the limit of this bot is lower than the one of telegram
+                    let msg = if self.max_file_size < MAX_DOWNLOAD_SIZE {
+                        Msg::TooLarge
+                    } else {
+                        Msg::TooBig
+                    };
+                    response.push_str(&trf(
+                        lang,
+                        msg,
+                        &[
+                            ("name", name),
+                            ("size", (self.max_file_size / 1024 / 1024).to_string()),
+                        ],
+                    ));
+                    response.push('\n');
+                }
+
+                for f in refused {
+                    let name = if f.file_name.is_empty() {
+                        f.file_unique_id
+                    } else {
+                        f.file_name
+                    };
+                    response.push_str(&trf(lang, Msg::Refused, &[("name", name)]));
+                    response.push('\n');
+                }
+
+                for f in not_allowed {
+                    let kind = match self.file_types.check_file(&f) {
+                        Err(v) => v.kind(),
+                        Ok(_) => continue,
+                    };
+                    let name = if f.file_name.is_empty() {
+                        f.file_unique_id
+                    } else {
+                        f.file_name
+                    };
+                    response.push_str(&trf(
+                        lang,
+                        Msg::TypeNotAllowed,
+                        &[("name", name), ("type", kind)],
+                    ));
+                    response.push('\n');
+                }
+
+                let mut blocks = vec![markdown_escape(response.as_str())];
+                if saved_ok {
+                    blocks.extend(files.iter().map(|f| code_block(&snippets(host, f))));
+                }
+
+                // qr codes of the links under the last message, to open them on a phone
+                let keyboard = (saved_ok && !files.is_empty()).then(|| qr_keyboard(host, &files));
+
+                // albums with many files easily go over the message length limit
+                let texts = pack_blocks(&blocks, MAX_MESSAGE_LENGTH);
+                let last = texts.len().saturating_sub(1);
+                for (i, text) in texts.into_iter().enumerate() {
+                    self.send_message(
                         &SendMessageParams::builder()
                             .chat_id(ChatId::Integer(chat_id))
                             .reply_parameters(ReplyParameters::builder().message_id(msg_id).build())
-                            .text(markdown_escape(response.as_str()))
+                            .text(text)
                             .link_preview_options(LinkPreviewOptions::DISABLED)
                             .parse_mode(frankenstein::ParseMode::MarkdownV2)
+                            .maybe_reply_markup(keyboard.clone().filter(|_| i == last))
+                            .build(),
+                    )
+                    .await?;
+                }
+            }
+
+            UpdateContent::InlineQuery(query) => {
+                let (text, tag) = split_query(&query.query);
+                let files = self
+                    .d1
+                    .search_user_files(query.from.id, &text, &tag, INLINE_RESULTS)
+                    .await?;
+
+                let results = files
+                    .iter()
+                    .map(|f| inline_result(host, f))
+                    .collect::<Vec<_>>();
+
+                let params = AnswerInlineQueryParams::builder()
+                    .inline_query_id(query.id.clone())
+                    .results(results)
+                    // results are the sender's own files
+                    .is_personal(true)
+                    .cache_time(0)
+                    .build();
+                retry(|| self.bot.answer_inline_query(&params)).await?;
+            }
+
+            UpdateContent::CallbackQuery(query) => {
+                let data = query.data.clone().unwrap_or_default();
+                let lang = self.lang_of(query.from.language_code.as_deref());
+
+                let text = if data == CONSENT_CALLBACK {
+                    // buttons sent before PRIVACY_MODE was set record nothing
+                    if !self.privacy_mode {
+                        self.d1.save_consent(query.from.id).await?;
+                    }
+                    tr(lang, Msg::ConsentThanks).to_string()
+                } else if data == FORGET_ME_CALLBACK {
+                    // whoever presses the button is forgotten, never the one who sent the command
+                    self.forget_me(host, query.from.id, lang).await?
+                } else if let Some(step) = data.strip_prefix(SETUP_CALLBACK_PREFIX) {
+                    if query.from.id as i64 != self.matainer {
+                        return Err(Error::Forbidden(
+                            "setup is only allowed for the maintainer".to_string(),
+                        ));
+                    }
+
+                    let text = match self.run_setup_step(host, step, lang).await {
+                        Ok(v) => v,
+                        Err(e) => trf(
+                            lang,
+                            Msg::SetupFailed,
+                            &[("step", step.to_string()), ("error", e.to_string())],
+                        ),
+                    };
+
+                    self.send_message(
+                        &SendMessageParams::builder()
+                            .chat_id(ChatId::Integer(self.matainer))
+                            .text(text)
                             .build(),
                     )
                     .await?;
+
+                    tr(lang, Msg::SetupDone).to_string()
+                } else if let Some(decision) = data.strip_prefix(MODERATION_CALLBACK_PREFIX) {
+                    if query.from.id as i64 != self.matainer {
+                        return Err(Error::Forbidden(
+                            "moderation is only allowed for the maintainer".to_string(),
+                        ));
+                    }
+
+                    match decision.split_once(':') {
+                        Some(("approve", id)) => {
+                            self.d1.save_blocked(id, "").await?;
+                            self.uncache(id).await;
+                            format!("{} stays online.", id)
+                        }
+                        Some(("block", id)) => {
+                            self.d1.save_blocked(id, BLOCKED_MODERATION).await?;
+                            self.uncache(id).await;
+                            let file = self.d1.get(id).await?;
+                            purge_copies(host, &Cache::default(), self.storage.as_ref(), &file)
+                                .await?;
+                            format!("{} is blocked.", id)
+                        }
+                        _ => return Err(Error::BadRequest("unknown moderation decision".into())),
+                    }
+                } else if let Some(decision) = data.strip_prefix(REPORT_CALLBACK_PREFIX) {
+                    if query.from.id as i64 != self.matainer {
+                        return Err(Error::Forbidden(
+                            "reports are only handled by the maintainer".to_string(),
+                        ));
+                    }
+
+                    match decision.split_once(':') {
+                        Some(("takedown", id)) => self.take_down(host, id).await?,
+                        Some(("dismiss", id)) => {
+                            self.d1.close_reports(id, REPORT_DISMISSED).await?;
+                            format!("Reports of {} are dismissed.", id)
+                        }
+                        _ => return Err(Error::BadRequest("unknown report decision".into())),
+                    }
+                } else {
+                    return Err(Error::BadRequest("unknown callback query".to_string()));
+                };
+
+                self.answer_callback_query(
+                    &AnswerCallbackQueryParams::builder()
+                        .callback_query_id(query.id)
+                        .text(text)
+                        .build(),
+                )
+                .await?;
             }
 
-            _ => return Err(Error("no message supported".to_string())),
+            _ => return Err(Error::BadRequest("no message supported".to_string())),
         };
         Ok(())
     }
 
+    // frankenstein can only upload files from disk, so the multipart request is built by hand
+    pub async fn upload_document(
+        &self,
+        file_name: String,
+        meta: &UploadMeta,
+        data: Vec<u8>,
+        user_id: u64,
+    ) -> Result<File, Error> {
+        let mut file = self
+            .send_document(self.storage_chat, None, file_name, meta, data)
+            .await?
+            .with_user_id(user_id);
+
+        self.d1.save(&[file.clone()]).await?;
+        self.uncache(&file.file_unique_id).await;
+        self.assign_short_ids(std::slice::from_mut(&mut file)).await;
+
+        Ok(file)
+    }
+
+    // the file of the sent message, not saved yet
+    async fn send_document(
+        &self,
+        chat_id: i64,
+        reply_to: Option<i32>,
+        file_name: String,
+        meta: &UploadMeta,
+        data: Vec<u8>,
+    ) -> Result<File, Error> {
+        let mut document = Part::bytes(data).file_name(file_name);
+        if !meta.mime_type.is_empty() {
+            document = document.mime_str(&meta.mime_type)?;
+        }
+
+        let mut form = Form::new()
+            .text("chat_id", chat_id.to_string())
+            .text("disable_content_type_detection", "true")
+            .part("document", document);
+        if let Some(id) = reply_to {
+            form = form.text(
+                "reply_parameters",
+                format!(
+                    "{{\"message_id\":{},\"allow_sending_without_reply\":true}}",
+                    id
+                ),
+            );
+        }
+
+        let resp = reqwest::Client::new()
+            .post(format!(
+                "{}/bot{}/sendDocument",
+                self.api_base, self.bot_token
+            ))
+            .multipart(form)
+            .send()
+            .await?
+            .json::<ApiResponse<Message>>()
+            .await?;
+
+        let msg = match resp.result {
+            Some(v) if resp.ok => v,
+            _ => {
+                return Err(Error::TelegramApi(
+                    resp.description
+                        .unwrap_or("send document failed".to_string()),
+                ));
+            }
+        };
+
+        let file = File::from_message(Box::new(msg), async |ids| {
+            try_join_all(ids.into_iter().map(|(id, _, _)| self.file_path(id))).await
+        })
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(Error::TelegramApi(
+            "uploaded message contains no file".to_string(),
+        ))?
+        .with_file_hash(meta.hash.clone());
+
+        if file.file_path.is_empty() {
+            return Err(Error::BadRequest(format!(
+                "file is larger than {} MB, the Telegram Bot API download limit",
+                MAX_DOWNLOAD_SIZE / 1024 / 1024
+            )));
+        }
+
+        Ok(file)
+    }
+
+    // a missing short id only makes the links longer
+    async fn assign_short_ids(&self, files: &mut [File]) {
+        if !self.short_ids {
+            return;
+        }
+        for f in files.iter_mut() {
+            match self.d1.short_id(&f.file_unique_id).await {
+                Ok(v) => f.short_id = v,
+                Err(e) => warn!("Assign short id to {} failed: {}", f.file_unique_id, e),
+            }
+        }
+    }
+
     pub async fn get_file_url(
         &self,
         file_id: impl Into<String>,
@@ -125,43 +2886,488 @@ impl TgBot {
         let file_id = file_id.into();
 
         if file_id.is_empty() {
-            return Err(Error("File id is empty".to_string()));
+            return Err(Error::BadRequest("file id is empty".to_string()));
         }
 
-        let file = self.d1.get(&file_id).await?;
+        let file = if no_cache {
+            self.d1.get(&file_id).await?
+        } else {
+            self.find_file(&file_id)
+                .await?
+                .ok_or(Error::NotFound("file not found".to_string()))?
+        };
 
         let mut file_path = file.file_path;
 
-        if no_cache || file_path.is_empty() {
-            if let Some(p) = self
-                .bot
+        if (no_cache || file_path.is_empty())
+            && let Some(p) = self
                 .get_file(&GetFileParams {
                     file_id: file.file_id.clone(),
                 })
                 .await?
-                .result
-                .file_path
-            {
-                self.d1.save_file_path(&file.file_unique_id, &p).await?;
-                file_path = p;
-            }
+        {
+            self.d1.save_file_path(&file.file_unique_id, &p).await?;
+            self.uncache(&file.file_unique_id).await;
+            file_path = p;
         }
 
         if file_path.is_empty() {
-            return Err(Error("File path is empty".to_string()));
+            return Err(Error::TelegramApi("file path is empty".to_string()));
         }
 
         info!("File path: {}", file_path);
 
-        // https://core.telegram.org/bots/api#getfile
-        Ok((
-            format!(
-                "https://api.telegram.org/file/bot{}/{}",
-                self.bot_token, file_path
+        Ok((self.file_url(&file_path), file.file_unique_id))
+    }
+
+    // https://core.telegram.org/bots/api#getfile
+    fn file_url(&self, file_path: &str) -> String {
+        format!("{}/file/bot{}/{}", self.api_base, self.bot_token, file_path)
+    }
+
+    // by file_id, file_unique_id or alias like `find`, from kv when FILE_CACHE is bound
+    pub async fn find_file(&self, id: &str) -> Result<Option<File>, Error> {
+        if let Some(f) = self.cached_file(id).await {
+            return Ok(Some(f));
+        }
+        let file = self.d1.find(id).await?;
+        if let Some(f) = &file {
+            self.cache_file(id, f).await;
+        }
+        Ok(file)
+    }
+
+    // after every change of a file that downloads look at. kv takes up to a minute to
+    // drop it everywhere
+    pub async fn uncache(&self, file_unique_id: &str) {
+        let Some(kv) = &self.kv else {
+            return;
+        };
+        let key = format!("{}{}", FILE_CACHE_PREFIX, file_unique_id);
+        if let Err(e) = kv.delete(&key).await {
+            warn!("uncache file {} failed: {}", file_unique_id, e);
+        }
+    }
+
+    async fn cached_file(&self, id: &str) -> Option<File> {
+        let kv = self.kv.as_ref()?;
+        let key = format!("{}{}", FILE_ID_CACHE_PREFIX, id);
+        let file_unique_id = match kv.get(&key).text().await {
+            Ok(v) => v?,
+            Err(e) => {
+                warn!("get cached id {} failed: {}", id, e);
+                return None;
+            }
+        };
+        let key = format!("{}{}", FILE_CACHE_PREFIX, file_unique_id);
+        match kv.get(&key).json::<File>().await {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("get cached file {} failed: {}", file_unique_id, e);
+                None
+            }
+        }
+    }
+
+    // failures only cost the next download a d1 query
+    async fn cache_file(&self, id: &str, file: &File) {
+        let Some(kv) = &self.kv else {
+            return;
+        };
+
+        let value = match serde_json::to_string(file) {
+            Ok(v) => v,
+            Err(e) => return warn!("encode cached file failed: {}", e),
+        };
+        let entries = [
+            (
+                format!("{}{}", FILE_ID_CACHE_PREFIX, id),
+                file.file_unique_id.clone(),
             ),
-            file.file_unique_id,
-        ))
+            (
+                format!("{}{}", FILE_CACHE_PREFIX, file.file_unique_id),
+                value,
+            ),
+        ];
+        for (key, value) in entries {
+            let result = match kv.put(&key, value) {
+                Ok(v) => v.expiration_ttl(FILE_CACHE_TTL_SECS).execute().await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                return warn!("cache file {} failed: {}", id, e);
+            }
+        }
+    }
+}
+
+const MAX_ATTEMPTS: u32 = 4;
+const INITIAL_BACKOFF_MS: u64 = 500;
+// longer waits would outlive the webhook request
+const MAX_WAIT_MS: u64 = 10_000;
+
+// retries 429 after the `retry_after` telegram asks for, and 5xx/network errors with exponential
+// backoff. only for calls that change nothing or can run twice
+async fn retry<T, F, Fut>(f: F) -> Result<T, frankenstein::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, frankenstein::Error>>,
+{
+    retry_when(true, f).await
+}
+
+// for calls that post something. a 5xx or a network error may come after the message was sent,
+// so only the 429s telegram rejects before sending anything are retried
+async fn retry_unsent<T, F, Fut>(f: F) -> Result<T, frankenstein::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, frankenstein::Error>>,
+{
+    retry_when(false, f).await
+}
+
+async fn retry_when<T, F, Fut>(idempotent: bool, f: F) -> Result<T, frankenstein::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, frankenstein::Error>>,
+{
+    let mut backoff = INITIAL_BACKOFF_MS;
+    let mut attempt = 1;
+
+    loop {
+        let err = match f().await {
+            Ok(v) => return Ok(v),
+            Err(e) => e,
+        };
+
+        let wait = match &err {
+            frankenstein::Error::Api(resp) if resp.error_code == 429 => resp
+                .parameters
+                .as_ref()
+                .and_then(|p| p.retry_after)
+                .map(|s| s as u64 * 1000)
+                .unwrap_or(backoff),
+            frankenstein::Error::Api(resp) if idempotent && resp.error_code >= 500 => backoff,
+            frankenstein::Error::HttpReqwest(_) if idempotent => backoff,
+            _ => return Err(err),
+        };
+
+        if attempt >= MAX_ATTEMPTS || wait > MAX_WAIT_MS {
+            return Err(err);
+        }
+
+        warn!("telegram api error, retry in {}ms: {}", wait, err);
+        Delay::from(Duration::from_millis(wait)).await;
+
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+fn help(lang: Lang, max_file_size: u64) -> String {
+    let commands = COMMANDS
+        .iter()
+        .filter(|c| !matches!(c.command, Command::Start | Command::Help))
+        .map(|c| format!("/{} - {}", c.name, tr(lang, c.description)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    trf(
+        lang,
+        Msg::Help,
+        &[
+            ("size", (max_file_size / 1024 / 1024).to_string()),
+            ("commands", commands),
+        ],
+    )
+}
+
+fn trf(lang: Lang, msg: Msg, values: &[(&str, String)]) -> String {
+    render_template(tr(lang, msg), values)
+}
+
+// cached results reuse the telegram copy, the caption links to the hosted one
+fn inline_result(host: &str, f: &File) -> InlineQueryResult {
+    let title = if f.download_name.is_empty() {
+        f.file_name.clone()
+    } else {
+        f.download_name.clone()
+    };
+    let title = if title.is_empty() {
+        f.file_unique_id.clone()
+    } else {
+        title
+    };
+    let caption = f.unique_url(host);
+
+    // photos are the only uploads stored without a mime type
+    if f.mime_type.is_empty() {
+        return InlineQueryResultCachedPhoto::builder()
+            .id(f.file_unique_id.clone())
+            .photo_file_id(f.file_id.clone())
+            .caption(caption)
+            .build()
+            .into();
+    }
+
+    if f.mime_type.starts_with("video/") {
+        return InlineQueryResultCachedVideo::builder()
+            .id(f.file_unique_id.clone())
+            .video_file_id(f.file_id.clone())
+            .title(title)
+            .caption(caption)
+            .build()
+            .into();
+    }
+
+    InlineQueryResultCachedDocument::builder()
+        .id(f.file_unique_id.clone())
+        .document_file_id(f.file_id.clone())
+        .title(title)
+        .caption(caption)
+        .build()
+        .into()
+}
+
+fn reply_text(template: &str, host: &str, f: &File) -> String {
+    let url = f.url(host);
+    let name = if f.file_name.is_empty() {
+        f.file_unique_id.clone()
+    } else {
+        f.file_name.clone()
+    };
+
+    render_template(
+        template,
+        &[
+            ("url", url.clone()),
+            ("unique_url", f.unique_url(host)),
+            ("markdown", format!("![{}]({})", name, url)),
+            (
+                "html",
+                format!(
+                    "<img src=\"{}\" alt=\"{}\">",
+                    url,
+                    html_escape(if f.alt_text.is_empty() {
+                        &name
+                    } else {
+                        &f.alt_text
+                    })
+                ),
+            ),
+            ("filename", name),
+            ("size", human_size(f.file_size)),
+        ],
+    )
+}
+
+// ready to paste embeds for forums, issues and blogs, links for files that are not images
+fn snippets(host: &str, f: &File) -> String {
+    let url = f.url(host);
+    if f.is_image() {
+        format!(
+            "![{name}]({url})\n<img src=\"{url}\" alt=\"{alt}\">\n[img]{url}[/img]",
+            name = f.file_name,
+            alt = html_escape(if f.alt_text.is_empty() {
+                &f.file_name
+            } else {
+                &f.alt_text
+            }),
+            url = url,
+        )
+    } else {
+        let name = if f.file_name.is_empty() {
+            &f.file_unique_id
+        } else {
+            &f.file_name
+        };
+        format!(
+            "[{name}]({url})\n<a href=\"{url}\">{html}</a>\n[url={url}]{name}[/url]",
+            name = name,
+            html = html_escape(name),
+            url = url,
+        )
+    }
+}
+
+// MarkdownV2 pre block, only ` and \ are escaped inside
+fn code_block(code: &str) -> String {
+    let escaped = code.replace('\\', "\\\\").replace('`', "\\`");
+    format!("```\n{}\n```\n", escaped)
+}
+
+// a url button per file, numbered for albums
+fn qr_keyboard(host: &str, files: &[File]) -> ReplyMarkup {
+    let buttons = files
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            InlineKeyboardButton::builder()
+                .text(if files.len() == 1 {
+                    "QR".to_string()
+                } else {
+                    format!("QR {}", i + 1)
+                })
+                .url(f.qr_url(host))
+                .build()
+        })
+        .collect::<Vec<_>>();
+    ReplyMarkup::InlineKeyboardMarkup(
+        InlineKeyboardMarkup::builder()
+            .inline_keyboard(buttons.chunks(5).map(<[_]>::to_vec).collect())
+            .build(),
+    )
+}
+
+// joins whole blocks into messages, so code blocks are never cut. longer blocks are split by lines
+fn pack_blocks(blocks: &[String], limit: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+    let mut len = 0;
+
+    for block in blocks {
+        let block_len = block.encode_utf16().count();
+
+        if len + block_len > limit && !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+            len = 0;
+        }
+
+        if block_len > limit {
+            chunks.extend(split_message(block, limit));
+            continue;
+        }
+
+        current.push_str(block);
+        len += block_len;
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+// replaces `{name}` with its value, unknown placeholders are kept as they are
+fn render_template(template: &str, values: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let value = after.find('}').and_then(|end| {
+            values
+                .iter()
+                .find(|(name, _)| *name == &after[..end])
+                .map(|(_, v)| (end, v))
+        });
+        match value {
+            Some((end, v)) => {
+                out.push_str(v);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+const ALBUM_NAME_MAX: usize = 64;
+
+// slugs that read like an instruction rather than a file
+const RESERVED_SLUGS: [&str; 8] = [
+    "admin", "api", "help", "login", "new", "random", "settings", "terms",
+];
+
+fn is_slug(slug: &str) -> bool {
+    (1..=64).contains(&slug.len())
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn is_namespace(name: &str) -> bool {
+    (1..=32).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn parse_command(text: &str) -> Option<(String, String)> {
+    let text = text.strip_prefix('/')?;
+    let (command, args) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    // commands in groups are sent as /command@bot_name
+    let command = command.split('@').next().unwrap_or_default();
+    Some((command.to_ascii_lowercase(), args.trim().to_string()))
+}
+
+// file ids of links like https://host/f/<file_id>.jpg, or of the PATH_PREFIX
+fn link_ids(text: &str) -> Vec<String> {
+    let prefix = format!("/{}/", path_prefix());
+    text.split_whitespace()
+        .filter_map(|w| {
+            w.rsplit_once("/f/")
+                .or_else(|| w.rsplit_once(prefix.as_str()))
+        })
+        .filter_map(|(_, name)| name.split('.').next())
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_string())
+        .collect()
+}
+
+// split an escaped text into messages of at most `limit` utf-16 units, on line breaks where possible,
+// never separating an escape from the char it escapes
+fn split_message(text: &str, limit: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+    let mut len = 0;
+
+    for line in text.split_inclusive('\n') {
+        let line_len = line.encode_utf16().count();
+
+        if len + line_len > limit && !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+            len = 0;
+        }
+
+        if line_len <= limit {
+            current.push_str(line);
+            len += line_len;
+            continue;
+        }
+
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            let mut piece = c.to_string();
+            if c == '\\'
+                && let Some(escaped) = chars.next()
+            {
+                piece.push(escaped);
+            }
+
+            let piece_len = piece.encode_utf16().count();
+            if len + piece_len > limit {
+                chunks.push(std::mem::take(&mut current));
+                len = 0;
+            }
+            current.push_str(&piece);
+            len += piece_len;
+        }
     }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
 }
 
 pub(super) const MARKDOWN_ESCAPE_CHARS: [char; 19] = [
@@ -177,3 +3383,98 @@ pub fn markdown_escape(s: &str) -> String {
         s
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filestore::MemoryStore;
+    use crate::filestore::tests::{block_on, file};
+
+    fn message(chat_id: i64, from: Option<u64>) -> Message {
+        let mut msg = serde_json::json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": { "id": chat_id, "type": "supergroup" },
+        });
+        if let Some(id) = from {
+            msg["from"] = serde_json::json!({ "id": id, "is_bot": false, "first_name": "a" });
+        }
+        serde_json::from_value(msg).unwrap()
+    }
+
+    // a reply to the message of the document `file_unique_id`
+    fn reply(chat_id: i64, from: u64, file_unique_id: &str) -> Message {
+        let mut msg = message(chat_id, Some(from));
+        let mut replied = message(chat_id, Some(from));
+        replied.document = Some(Box::new(
+            serde_json::from_value(serde_json::json!({
+                "file_id": format!("id-{file_unique_id}"),
+                "file_unique_id": file_unique_id,
+            }))
+            .unwrap(),
+        ));
+        msg.reply_to_message = Some(Box::new(replied));
+        msg
+    }
+
+    #[test]
+    fn can_manage() {
+        let bot = TgBot::new(MemoryStore::new(), 9, String::new());
+        let mut f = file("a", 1);
+        f.chat_id = -100;
+
+        assert!(bot.can_manage(&message(-100, Some(1)), &f));
+        assert!(bot.can_manage(&message(-5, Some(9)), &f));
+        assert!(!bot.can_manage(&message(-100, Some(2)), &f));
+        // a user's file forwarded to a channel stays theirs
+        assert!(!bot.can_manage(&message(-100, None), &f));
+
+        // channel posts have no sender, only the channel of the file manages it
+        let mut posted = file("c", 0);
+        posted.chat_id = -100;
+        assert!(bot.can_manage(&message(-100, None), &posted));
+        assert!(!bot.can_manage(&message(-5, None), &posted));
+
+        // files saved with privacy mode have no user
+        let anonymous = file("b", 0);
+        assert!(!bot.can_manage(&message(-100, Some(0)), &anonymous));
+        assert!(!bot.can_manage(&message(-100, None), &anonymous));
+    }
+
+    #[test]
+    fn alt_of_own_files() {
+        let store = MemoryStore::new();
+        let bot = TgBot::new(store.clone(), 9, String::new());
+        block_on(store.save(&[file("a", 1)])).unwrap();
+
+        let other = block_on(bot.alt(&reply(-100, 2, "a"), "a cat", Lang::En)).unwrap();
+        assert_eq!(other, tr(Lang::En, Msg::NotYourFiles));
+        assert_eq!(block_on(store.get("a")).unwrap().alt_text, "");
+
+        block_on(bot.alt(&reply(-100, 1, "a"), "a cat", Lang::En)).unwrap();
+        assert_eq!(block_on(store.get("a")).unwrap().alt_text, "a cat");
+    }
+
+    #[test]
+    fn resending_keeps_the_owner() {
+        let store = MemoryStore::new();
+        let bot = TgBot::new(store.clone(), 9, String::new());
+        block_on(store.save(&[file("a", 1)])).unwrap();
+        block_on(store.save(&[file("a", 2)])).unwrap();
+
+        let f = block_on(store.get("a")).unwrap();
+        assert_eq!(f.user_id, 1);
+        assert!(!bot.can_manage(&message(-100, Some(2)), &f));
+        let other = block_on(bot.alt(&reply(-100, 2, "a"), "a dog", Lang::En)).unwrap();
+        assert_eq!(other, tr(Lang::En, Msg::NotYourFiles));
+    }
+
+    #[test]
+    fn split_counts_utf16() {
+        // an emoji is two utf-16 units
+        let chunks = split_message(&"\u{1F600}".repeat(3), 4);
+        assert_eq!(chunks, ["\u{1F600}\u{1F600}", "\u{1F600}"]);
+        let chunks = pack_blocks(&["ab\n".to_string(), "\u{1F600}\n".to_string()], 4);
+        assert_eq!(chunks, ["ab\n", "\u{1F600}\n"]);
+    }
+}
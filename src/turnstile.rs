@@ -0,0 +1,144 @@
+// a cloudflare turnstile challenge in front of downloads. visitors without a pass get an
+// interstitial page, solving it sets a cookie signed with the secret and bound to their ip
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use worker::{Date, Fetch, Headers, Method, Request, RequestInit};
+
+use crate::crypto::{hex, hmac_sha256, verify_hmac_hex};
+use crate::error::Error;
+use crate::privacy::{client_ip, client_key};
+
+const SITEVERIFY_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+pub const COOKIE: &str = "tg_pass";
+// form field of the widget
+pub const RESPONSE_FIELD: &str = "cf-turnstile-response";
+pub const DEFAULT_PASS_HOURS: u64 = 24;
+
+#[derive(Clone, Debug)]
+pub struct Turnstile {
+    pub site_key: String,
+    secret: String,
+    // passes signed before the secret was rotated, empty when it isn't
+    previous_secret: String,
+    // seconds a solved challenge is valid
+    pass_ttl: u64,
+}
+
+#[derive(Serialize)]
+struct Verify<'a> {
+    secret: &'a str,
+    response: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remoteip: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Verdict {
+    success: bool,
+}
+
+impl Turnstile {
+    pub fn new(site_key: String, secret: String, pass_hours: u64) -> Self {
+        Turnstile {
+            site_key,
+            secret,
+            previous_secret: String::new(),
+            pass_ttl: pass_hours.max(1) * 3600,
+        }
+    }
+
+    pub fn with_previous_secret(mut self, previous_secret: String) -> Self {
+        self.previous_secret = previous_secret;
+        self
+    }
+
+    pub fn rotating(&self) -> bool {
+        !self.previous_secret.is_empty()
+    }
+
+    // a pass of this ip that hasn't expired
+    pub fn passed(&self, req: &Request) -> bool {
+        let cookies = req
+            .headers()
+            .get("Cookie")
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let Some(pass) = cookies
+            .split(';')
+            .filter_map(|v| v.trim().split_once('='))
+            .find(|(k, _)| *k == COOKIE)
+            .map(|(_, v)| v)
+        else {
+            return false;
+        };
+        let Some((expires, signature)) = pass.split_once('.') else {
+            return false;
+        };
+        match expires.parse::<u64>() {
+            Ok(v) if v > Date::now().as_millis() / 1000 => [&self.secret, &self.previous_secret]
+                .iter()
+                .filter(|k| !k.is_empty())
+                .any(|k| {
+                    verify_hmac_hex(k.as_bytes(), &signed_data(req, v), signature).unwrap_or(false)
+                }),
+            _ => false,
+        }
+    }
+
+    // Set-Cookie of a solved challenge
+    pub fn pass_cookie(&self, req: &Request) -> Result<String, Error> {
+        let expires = Date::now().as_millis() / 1000 + self.pass_ttl;
+        Ok(format!(
+            "{}={}.{}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+            COOKIE,
+            expires,
+            self.signature(req, expires)?,
+            self.pass_ttl
+        ))
+    }
+
+    // asks turnstile whether the token of the widget was solved on this site
+    pub async fn verify(&self, req: &Request, token: &str) -> Result<bool, Error> {
+        let body = serde_json::to_string(&Verify {
+            secret: &self.secret,
+            response: token,
+            remoteip: client_ip(req),
+        })
+        .map_err(|e| Error::Internal(e.to_string()))?;
+
+        let headers = Headers::new();
+        headers.set("Content-Type", "application/json")?;
+        let request = Request::new_with_init(
+            SITEVERIFY_URL,
+            &RequestInit {
+                method: Method::Post,
+                headers,
+                body: Some(JsValue::from_str(&body)),
+                ..RequestInit::default()
+            },
+        )?;
+
+        let mut resp = Fetch::Request(request).send().await?;
+        if resp.status_code() != 200 {
+            return Err(Error::Internal(format!(
+                "turnstile siteverify answered {}",
+                resp.status_code()
+            )));
+        }
+        Ok(resp.json::<Verdict>().await?.success)
+    }
+
+    // hex encoded hmac-sha256 of the expiry and the hashed ip
+    fn signature(&self, req: &Request, expires: u64) -> Result<String, Error> {
+        Ok(hex(&hmac_sha256(
+            self.secret.as_bytes(),
+            &signed_data(req, expires),
+        )?))
+    }
+}
+
+fn signed_data(req: &Request, expires: u64) -> Vec<u8> {
+    format!("{}:{}", expires, client_key(req).unwrap_or_default()).into_bytes()
+}
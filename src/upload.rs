@@ -0,0 +1,220 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::crypto::{hex, hmac_sha256, verify_hmac_hex};
+use crate::d1::File;
+use crate::error::Error;
+
+// https://core.telegram.org/bots/api#getfile
+pub const MAX_DOWNLOAD_SIZE: u64 = 20 * 1024 * 1024;
+
+// bots can upload 50 MB, but files over the download limit could never be served
+pub const MAX_UPLOAD_SIZE: u64 = MAX_DOWNLOAD_SIZE;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UploadMeta {
+    pub size: u64,
+    #[serde(default)]
+    pub mime_type: String,
+    // hex encoded sha256 of the file content
+    #[serde(default)]
+    pub hash: String,
+}
+
+impl UploadMeta {
+    // `max_size` is MAX_FILE_SIZE, at most MAX_UPLOAD_SIZE
+    pub fn check_policy(&self, max_size: u64) -> Result<(), String> {
+        if self.size == 0 {
+            return Err("file is empty".to_string());
+        }
+
+        if self.size > max_size {
+            return Err(format!(
+                "file size {} exceeds the limit of {} bytes",
+                self.size, max_size
+            ));
+        }
+
+        if !self.mime_type.is_empty() && !self.mime_type.contains('/') {
+            return Err(format!("invalid mime type: {}", self.mime_type));
+        }
+
+        if !self.hash.is_empty() && !is_sha256_hex(&self.hash) {
+            return Err("hash is not a hex encoded sha256 digest".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Validation {
+    pub accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub deduplicated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl Validation {
+    pub fn accepted() -> Self {
+        Validation {
+            accepted: true,
+            ..Validation::default()
+        }
+    }
+
+    pub fn rejected(reason: String) -> Self {
+        Validation {
+            reason: Some(reason),
+            ..Validation::default()
+        }
+    }
+
+    pub fn deduplicated(url: String) -> Self {
+        Validation {
+            accepted: true,
+            deduplicated: true,
+            url: Some(url),
+            ..Validation::default()
+        }
+    }
+}
+
+// `delete_url` and `thumbnail_url` are what sharex and picgo uploaders read besides `url`
+#[derive(Serialize, Debug)]
+pub struct Uploaded {
+    pub deduplicated: bool,
+    pub file_unique_id: String,
+    pub url: String,
+    pub unique_url: String,
+    // opens a page that deletes the file, empty without API_TOKEN
+    pub delete_url: String,
+    // the link itself or a scaled one for images, empty for other files
+    pub thumbnail_url: String,
+}
+
+impl Uploaded {
+    pub fn new(file: &File, host: &str, deduplicated: bool) -> Self {
+        Uploaded {
+            deduplicated,
+            file_unique_id: file.file_unique_id.clone(),
+            url: file.url(host),
+            unique_url: file.unique_url(host),
+            delete_url: String::new(),
+            thumbnail_url: if file.is_image() {
+                file.url(host)
+            } else {
+                String::new()
+            },
+        }
+    }
+
+    pub fn with_delete_url(mut self, delete_url: String) -> Self {
+        self.delete_url = delete_url;
+        self
+    }
+
+    pub fn with_thumbnail_url(mut self, thumbnail_url: String) -> Self {
+        self.thumbnail_url = thumbnail_url;
+        self
+    }
+}
+
+// an entry of GET /api/files
+#[derive(Serialize, Debug)]
+pub struct ListedFile {
+    pub file_unique_id: String,
+    pub file_name: String,
+    pub file_size: u64,
+    pub mime_type: String,
+    pub add_time: i64,
+    pub url: String,
+    pub tags: Vec<String>,
+}
+
+impl ListedFile {
+    pub fn new(file: &File, host: &str, tags: &HashMap<String, Vec<String>>) -> Self {
+        ListedFile {
+            file_unique_id: file.file_unique_id.clone(),
+            file_name: file.file_name.clone(),
+            file_size: file.file_size,
+            mime_type: file.mime_type.clone(),
+            add_time: file.add_time,
+            url: file.url(host),
+            tags: tags.get(&file.file_unique_id).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+// response of POST /api/upload?format=picgo, the shape of the picgo server
+#[derive(Serialize, Debug)]
+pub struct PicGoUploaded {
+    pub success: bool,
+    pub result: Vec<String>,
+}
+
+// hex encoded hmac-sha256 of the file with the api token, the proof of a delete url
+pub fn delete_token(key: &str, file_unique_id: &str) -> Result<String, Error> {
+    let data = format!("delete:{}", file_unique_id);
+    Ok(hex(&hmac_sha256(key.as_bytes(), data.as_bytes())?))
+}
+
+pub fn verify_delete_token(key: &str, file_unique_id: &str, token: &str) -> Result<bool, Error> {
+    let data = format!("delete:{}", file_unique_id);
+    verify_hmac_hex(key.as_bytes(), data.as_bytes(), token)
+}
+
+fn is_sha256_hex(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// body and response of POST /api/files/:file_id, missing fields are left unchanged
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DownloadOptions {
+    pub download_name: Option<String>,
+    pub headers: Option<BTreeMap<String, String>>,
+}
+
+// json body of POST /api/files:batchDelete, any ids of the files' links
+#[derive(Deserialize, Debug)]
+pub struct BatchDeleteRequest {
+    pub ids: Vec<String>,
+}
+
+// json body of PUT /admin/channels/:chat_id
+#[derive(Deserialize, Debug)]
+pub struct ChannelCollectionRequest {
+    pub album_id: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+// json body of POST /report/:file_unique_id, the html form sends the same field
+#[derive(Deserialize, Debug)]
+pub struct ReportRequest {
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Reported {
+    pub report_id: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Imported {
+    pub imported: usize,
+    // already known to this instance, left unchanged
+    pub skipped: usize,
+}
+
+// file names chosen by users end up in Content-Disposition
+pub fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_control() && *c != '/' && *c != '\\')
+        .take(255)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
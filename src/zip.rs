@@ -0,0 +1,307 @@
+// writes zip archives as their entries stream through, without buffering a whole file.
+// entries are stored uncompressed, images are compressed already and deflate would cost
+// cpu time the workers don't have. sizes and crcs follow each entry in a data descriptor
+// because they are only known once its body was read. no zip64, archives stay below 4 GiB
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const DATA_DESCRIPTOR: u32 = 0x08074b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+
+const VERSION: u16 = 20;
+// sizes in the data descriptor, utf-8 names
+const FLAGS: u16 = (1 << 3) | (1 << 11);
+const STORED: u16 = 0;
+
+pub const MAX_ARCHIVE_SIZE: u64 = u32::MAX as u64;
+
+struct Entry {
+    name: String,
+    time: u16,
+    date: u16,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+#[derive(Default)]
+pub struct ZipWriter {
+    entries: Vec<Entry>,
+    // bytes handed out so far
+    offset: u64,
+    crc: Crc32,
+    size: u64,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // local header of the next entry, its body is passed through `write`
+    pub fn start_entry(&mut self, name: &str, unix_time: i64) -> Vec<u8> {
+        let (time, date) = dos_time(unix_time);
+
+        let mut out = vec![];
+        put_u32(&mut out, LOCAL_HEADER);
+        put_u16(&mut out, VERSION);
+        put_u16(&mut out, FLAGS);
+        put_u16(&mut out, STORED);
+        put_u16(&mut out, time);
+        put_u16(&mut out, date);
+        // crc and sizes, see the data descriptor
+        put_u32(&mut out, 0);
+        put_u32(&mut out, 0);
+        put_u32(&mut out, 0);
+        put_u16(&mut out, name.len() as u16);
+        put_u16(&mut out, 0);
+        out.extend_from_slice(name.as_bytes());
+
+        self.entries.push(Entry {
+            name: name.to_string(),
+            time,
+            date,
+            crc: 0,
+            size: 0,
+            offset: self.offset as u32,
+        });
+        self.crc = Crc32::default();
+        self.size = 0;
+        self.offset += out.len() as u64;
+        out
+    }
+
+    // a chunk of the current entry, it's written unchanged
+    pub fn write(&mut self, data: &[u8]) {
+        self.crc.update(data);
+        self.size += data.len() as u64;
+        self.offset += data.len() as u64;
+    }
+
+    pub fn end_entry(&mut self) -> Vec<u8> {
+        let crc = self.crc.finish();
+        let size = self.size as u32;
+        if let Some(entry) = self.entries.last_mut() {
+            entry.crc = crc;
+            entry.size = size;
+        }
+
+        let mut out = vec![];
+        put_u32(&mut out, DATA_DESCRIPTOR);
+        put_u32(&mut out, crc);
+        put_u32(&mut out, size);
+        put_u32(&mut out, size);
+        self.offset += out.len() as u64;
+        out
+    }
+
+    // the central directory, the last bytes of the archive
+    pub fn finish(&mut self) -> Vec<u8> {
+        let mut out = vec![];
+        for entry in &self.entries {
+            put_u32(&mut out, CENTRAL_HEADER);
+            put_u16(&mut out, VERSION);
+            put_u16(&mut out, VERSION);
+            put_u16(&mut out, FLAGS);
+            put_u16(&mut out, STORED);
+            put_u16(&mut out, entry.time);
+            put_u16(&mut out, entry.date);
+            put_u32(&mut out, entry.crc);
+            put_u32(&mut out, entry.size);
+            put_u32(&mut out, entry.size);
+            put_u16(&mut out, entry.name.len() as u16);
+            // extra field, comment, disk, internal and external attributes
+            put_u16(&mut out, 0);
+            put_u16(&mut out, 0);
+            put_u16(&mut out, 0);
+            put_u16(&mut out, 0);
+            put_u32(&mut out, 0);
+            put_u32(&mut out, entry.offset);
+            out.extend_from_slice(entry.name.as_bytes());
+        }
+
+        let entries = self.entries.len() as u16;
+        let directory_size = out.len() as u32;
+        put_u32(&mut out, END_OF_CENTRAL_DIRECTORY);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, entries);
+        put_u16(&mut out, entries);
+        put_u32(&mut out, directory_size);
+        put_u32(&mut out, self.offset as u32);
+        put_u16(&mut out, 0);
+
+        self.offset += out.len() as u64;
+        out
+    }
+
+    // bytes handed out so far
+    pub fn written(&self) -> u64 {
+        self.offset
+    }
+}
+
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+// ms-dos time and date of a unix timestamp in utc, dates before 1980 can't be represented
+fn dos_time(unix: i64) -> (u16, u16) {
+    let days = unix.div_euclid(86400);
+    let secs = unix.rem_euclid(86400);
+
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    if !(1980..=2107).contains(&year) {
+        return (0, (1 << 5) | 1);
+    }
+
+    let time = ((secs / 3600) << 11) | ((secs % 3600 / 60) << 5) | ((secs % 60) / 2);
+    let date = ((year - 1980) << 9) | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+// crc-32 of zip
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+struct Crc32(u32);
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32(0xffffffff)
+    }
+}
+
+impl Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        for b in data {
+            self.0 = CRC_TABLE[((self.0 ^ *b as u32) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(data: &[u8], pos: usize) -> u16 {
+        u16::from_le_bytes([data[pos], data[pos + 1]])
+    }
+
+    fn u32_at(data: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn crc32() {
+        let mut crc = Crc32::default();
+        crc.update(b"12345");
+        crc.update(b"6789");
+        assert_eq!(crc.finish(), 0xcbf43926);
+        assert_eq!(Crc32::default().finish(), 0);
+    }
+
+    #[test]
+    fn dos_times() {
+        // 2024-02-29 13:45:58 utc
+        let (time, date) = dos_time(1709214358);
+        assert_eq!(time, (13 << 11) | (45 << 5) | 29);
+        assert_eq!(date, (44 << 9) | (2 << 5) | 29);
+        assert_eq!(dos_time(0), (0, (1 << 5) | 1));
+    }
+
+    #[test]
+    fn archive() {
+        let mut zip = ZipWriter::new();
+        let mut data = vec![];
+        for (name, chunks) in [("a.txt", vec![&b"hello "[..], b"world"]), ("é.png", vec![])] {
+            data.extend(zip.start_entry(name, 1709214358));
+            for chunk in chunks {
+                zip.write(chunk);
+                data.extend_from_slice(chunk);
+            }
+            data.extend(zip.end_entry());
+        }
+        let directory_offset = data.len();
+        data.extend(zip.finish());
+        assert_eq!(zip.written(), data.len() as u64);
+
+        // end of central directory
+        let end = data.len() - 22;
+        assert_eq!(u32_at(&data, end), END_OF_CENTRAL_DIRECTORY);
+        assert_eq!(u16_at(&data, end + 10), 2);
+        assert_eq!(u32_at(&data, end + 12) as usize, end - directory_offset);
+        assert_eq!(u32_at(&data, end + 16) as usize, directory_offset);
+
+        // central headers point at local headers with the same name
+        let mut pos = directory_offset;
+        let mut entries = vec![];
+        while pos < end {
+            assert_eq!(u32_at(&data, pos), CENTRAL_HEADER);
+            let crc = u32_at(&data, pos + 16);
+            let size = u32_at(&data, pos + 20);
+            let name_len = u16_at(&data, pos + 28) as usize;
+            let offset = u32_at(&data, pos + 42) as usize;
+            let name = &data[pos + 46..pos + 46 + name_len];
+
+            assert_eq!(u32_at(&data, offset), LOCAL_HEADER);
+            assert_eq!(u16_at(&data, offset + 6), FLAGS);
+            assert_eq!(&data[offset + 30..offset + 30 + name_len], name);
+            let body = offset + 30 + name_len;
+            let descriptor = body + size as usize;
+            assert_eq!(u32_at(&data, descriptor), DATA_DESCRIPTOR);
+            assert_eq!(u32_at(&data, descriptor + 4), crc);
+            assert_eq!(u32_at(&data, descriptor + 8), size);
+
+            entries.push((
+                String::from_utf8(name.to_vec()).unwrap(),
+                data[body..descriptor].to_vec(),
+            ));
+            pos += 46 + name_len;
+        }
+        assert_eq!(
+            entries,
+            vec![
+                ("a.txt".to_string(), b"hello world".to_vec()),
+                ("é.png".to_string(), vec![]),
+            ]
+        );
+    }
+}